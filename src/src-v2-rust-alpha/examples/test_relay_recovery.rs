@@ -14,10 +14,12 @@ fn main() {
     
     // Test 2: Create device with custom recovery config
     println!("\n2. Creating device with custom recovery config...");
-    let mut custom_config = RecoveryConfig::default();
-    custom_config.max_retry_attempts = 10;
-    custom_config.health_check_interval_secs = 60;
-    custom_config.enable_system_reboot = false;
+    let custom_config = RecoveryConfig {
+        max_retry_attempts: 10,
+        health_check_interval_secs: 60,
+        enable_system_reboot: false,
+        ..Default::default()
+    };
     
     let custom_device = QwiicRelayDevice::new_with_config(0x26, custom_config.clone());
     println!("   Custom device created with ID: 0x{:02X}", custom_device.id);
@@ -45,8 +47,10 @@ fn main() {
     }
     
     println!("\n5. Testing all relays off...");
-    device.all_off();
-    println!("   All relays off command sent");
+    match device.all_off() {
+        Ok(_) => println!("   All relays off command sent"),
+        Err(e) => println!("   Expected error (no hardware): {}", e),
+    }
     
     println!("\n✓ All tests completed successfully!");
     println!("Note: Hardware-specific operations will fail without actual Qwiic relay connected.");
@@ -28,6 +28,9 @@ pub mod lcd;
 pub mod video;
 pub mod pump;
 pub mod pump_safety;
+pub mod pump_jobs;
+pub mod actuators;
+pub mod emergency_stop;
 pub mod water_level;
 pub mod http;
 pub mod tools;
@@ -35,11 +38,18 @@ pub mod error;
 pub mod retry;
 pub mod error_monitor;
 pub mod qwiic;
+pub mod i2c;
+pub mod serial;
+pub mod particulate;
+pub mod native_sensors;
+pub mod sensor_fusion;
+pub mod plausibility;
+pub mod rules;
+pub mod climate;
+pub mod pid;
 pub mod auth;
 pub mod ph_sensor;
 pub mod instance;
-pub mod command_exec;
-pub mod water_level;
 
 #[cfg(test)]
 mod tests_overflow;
@@ -108,6 +118,10 @@ pub fn print_stats(){
     println!("              PH:    {}", sensors::get_value("ph"));
     println!("          T1_OVF:    {}", sensors::get_value("t1_ovf"));
     println!("          T2_OVF:    {}", sensors::get_value("t2_ovf"));
+    if let Some(status) = climate::status() {
+        println!();
+        println!("{}", climate::format_status(&status));
+    }
     
     println!();
 }
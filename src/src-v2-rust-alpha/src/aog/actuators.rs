@@ -0,0 +1,446 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Actuator Registry - Every relay channel and GPIO output with its safe state
+// and commanded state, driven safe on boot, shutdown and panic
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Mutex, TryLockError};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use rppal::gpio::{Gpio, OutputPin};
+
+use crate::aog::gpio::pwm::PWM;
+use crate::aog::pump_safety::{PumpSafetyMonitor, SAFETY_MONITOR};
+use crate::aog::qwiic::{self, QwiicRelayDevice};
+use crate::{Config, PumpOutput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActuatorOutput {
+    Relay { board: u16, relay_id: u16 },
+    Gpio { pin: u8 },
+}
+
+impl std::fmt::Display for ActuatorOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ActuatorOutput::Relay { board, relay_id } => write!(f, "relay 0x{:02x}:{}", board, relay_id),
+            ActuatorOutput::Gpio { pin } => write!(f, "gpio {}", pin),
+        }
+    }
+}
+
+impl ActuatorOutput {
+    /// Output for a configured pump; relay pumps without a board use the default board
+    pub fn for_pump(output: &PumpOutput) -> Self {
+        match output {
+            PumpOutput::Relay { relay_id, board } => ActuatorOutput::Relay {
+                board: board.unwrap_or_else(qwiic::default_board),
+                relay_id: *relay_id,
+            },
+            PumpOutput::Gpio { pin } => ActuatorOutput::Gpio { pin: *pin },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActuatorState {
+    Off,
+    On,
+}
+
+impl ActuatorState {
+    pub fn from_bool(on: bool) -> Self {
+        if on { ActuatorState::On } else { ActuatorState::Off }
+    }
+
+    pub fn is_on(self) -> bool {
+        self == ActuatorState::On
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActuatorStatus {
+    pub name: String,
+    pub output: ActuatorOutput,
+    pub safe_state: ActuatorState,
+    pub commanded: Option<ActuatorState>,  // None until first driven
+    pub verified: Option<bool>,  // Read-back matched the commanded state, None if unreadable
+    pub last_changed: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Physical access to actuator outputs
+pub trait ActuatorDriver: Send + Sync {
+    fn write(&self, output: ActuatorOutput, state: ActuatorState) -> Result<(), String>;
+    /// Current physical state, None if the output can't be read back
+    fn read(&self, output: ActuatorOutput) -> Result<Option<ActuatorState>, String>;
+}
+
+/// Qwiic relay boards and active-low GPIO outputs. Each GPIO output pin is
+/// acquired on its first write and held from then on.
+#[derive(Default)]
+pub struct HardwareDriver {
+    pins: Mutex<HashMap<u8, OutputPin>>,
+}
+
+impl HardwareDriver {
+    fn with_pin<T>(&self, pin: u8, f: impl FnOnce(&mut OutputPin) -> T) -> Result<T, String> {
+        let mut pins = self.pins.lock().unwrap_or_else(|e| e.into_inner());
+        let out = match pins.entry(pin) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let gpio = Gpio::new().map_err(|e| format!("GPIO unavailable: {}", e))?;
+                let mut out = gpio.get(pin)
+                    .map_err(|e| format!("Failed to get GPIO pin {}: {}", pin, e))?
+                    .into_output();
+                // Keep the pin driven if the process exits without a shutdown
+                out.set_reset_on_drop(false);
+                entry.insert(out)
+            }
+        };
+        Ok(f(out))
+    }
+}
+
+impl ActuatorDriver for HardwareDriver {
+    fn write(&self, output: ActuatorOutput, state: ActuatorState) -> Result<(), String> {
+        match output {
+            ActuatorOutput::Relay { board, relay_id } => QwiicRelayDevice::new(board)
+                .set_relay(relay_id, state.is_on())
+                .map_err(|e| e.to_string()),
+            ActuatorOutput::Gpio { pin } => self.with_pin(pin, |out| {
+                if state.is_on() { out.set_low() } else { out.set_high() }
+            }),
+        }
+    }
+
+    fn read(&self, output: ActuatorOutput) -> Result<Option<ActuatorState>, String> {
+        match output {
+            ActuatorOutput::Relay { board, relay_id } => QwiicRelayDevice::new(board)
+                .get_relay_state(relay_id)
+                .map(|on| Some(ActuatorState::from_bool(on)))
+                .map_err(|e| e.to_string()),
+            ActuatorOutput::Gpio { pin } => self.with_pin(pin, |out| Some(ActuatorState::from_bool(out.is_set_low()))),
+        }
+    }
+}
+
+pub struct ActuatorRegistry {
+    actuators: Mutex<Vec<ActuatorStatus>>,
+    driver: Box<dyn ActuatorDriver>,
+    monitor: PumpSafetyMonitor,
+}
+
+impl ActuatorRegistry {
+    /// Registry whose outputs are blocked from leaving their safe state while
+    /// `monitor` has the emergency stop latched
+    pub fn new(driver: Box<dyn ActuatorDriver>, monitor: PumpSafetyMonitor) -> Self {
+        ActuatorRegistry {
+            actuators: Mutex::new(Vec::new()),
+            driver,
+            monitor,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ActuatorStatus>> {
+        self.actuators.lock().unwrap_or_else(|e| {
+            log::error!("Actuator registry lock poisoned, recovering");
+            e.into_inner()
+        })
+    }
+
+    /// Add an output. Names and outputs must be unique.
+    pub fn register(&self, name: &str, output: ActuatorOutput, safe_state: ActuatorState) -> Result<(), String> {
+        let mut actuators = self.lock();
+        if let Some(existing) = actuators.iter().find(|a| a.name == name || a.output == output) {
+            return Err(format!("{} is already registered as '{}'", existing.output, existing.name));
+        }
+        actuators.push(ActuatorStatus {
+            name: name.to_string(),
+            output,
+            safe_state,
+            commanded: None,
+            verified: None,
+            last_changed: None,
+            last_error: None,
+        });
+        Ok(())
+    }
+
+    pub fn status(&self) -> Vec<ActuatorStatus> {
+        self.lock().clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<ActuatorStatus> {
+        self.lock().iter().find(|a| a.name == name).cloned()
+    }
+
+    pub fn get_output(&self, output: ActuatorOutput) -> Option<ActuatorStatus> {
+        self.lock().iter().find(|a| a.output == output).cloned()
+    }
+
+    /// Drive a registered actuator and verify it by reading it back
+    pub fn set(&self, name: &str, state: ActuatorState) -> Result<ActuatorStatus, String> {
+        let output = self.get(name)
+            .map(|a| a.output)
+            .ok_or_else(|| format!("Unknown actuator '{}'", name))?;
+        self.set_output(output, state)
+    }
+
+    /// Drive an output, registering it with an Off safe state if it isn't known
+    pub fn set_output(&self, output: ActuatorOutput, state: ActuatorState) -> Result<ActuatorStatus, String> {
+        let known = self.lock().iter().find(|a| a.output == output).map(|a| a.safe_state);
+        let safe_state = match known {
+            Some(safe_state) => safe_state,
+            None => {
+                self.register(&output.to_string(), output, ActuatorState::Off)?;
+                ActuatorState::Off
+            }
+        };
+
+        if state != safe_state && self.monitor.is_emergency_stop_active() {
+            return Err(format!("Emergency stop active - {} held in its safe state", output));
+        }
+
+        let result = self.driver.write(output, state)
+            .and_then(|_| self.driver.read(output))
+            .and_then(|physical| match physical {
+                Some(actual) if actual != state => Err(format!(
+                    "{} commanded {:?} but reads back {:?}", output, state, actual)),
+                _ => Ok(physical.is_some()),
+            });
+
+        let mut actuators = self.lock();
+        let actuator = actuators.iter_mut()
+            .find(|a| a.output == output)
+            .ok_or_else(|| format!("{} was removed from the registry", output))?;
+        actuator.commanded = Some(state);
+        actuator.last_changed = Some(Local::now().to_rfc3339());
+        match result {
+            Ok(readable) => {
+                actuator.verified = if readable { Some(true) } else { None };
+                actuator.last_error = None;
+                Ok(actuator.clone())
+            }
+            Err(e) => {
+                log::error!("Actuator '{}': {}", actuator.name, e);
+                actuator.verified = Some(false);
+                actuator.last_error = Some(e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    /// Drive every registered actuator to its safe state.
+    /// Returns the actuators that failed, with the reason.
+    pub fn drive_all_safe(&self) -> Vec<(String, String)> {
+        let targets: Vec<(String, ActuatorOutput, ActuatorState)> = self.lock().iter()
+            .map(|a| (a.name.clone(), a.output, a.safe_state))
+            .collect();
+
+        targets.into_iter()
+            .filter_map(|(name, output, safe_state)| self.set_output(output, safe_state)
+                .err()
+                .map(|e| (name, e)))
+            .collect()
+    }
+
+    /// Drive every actuator safe without waiting on the registry, as this runs
+    /// from the panic hook and the panicking thread may hold the lock. Writes
+    /// are not read back or recorded. None if the registry is locked.
+    pub fn try_drive_all_safe(&self) -> Option<Vec<(String, String)>> {
+        let targets: Vec<(String, ActuatorOutput, ActuatorState)> = match self.actuators.try_lock() {
+            Ok(actuators) => actuators.iter().map(|a| (a.name.clone(), a.output, a.safe_state)).collect(),
+            Err(TryLockError::Poisoned(e)) => e.into_inner().iter().map(|a| (a.name.clone(), a.output, a.safe_state)).collect(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+
+        Some(targets.into_iter()
+            .filter_map(|(name, output, safe_state)| self.driver.write(output, safe_state)
+                .err()
+                .map(|e| (name, e)))
+            .collect())
+    }
+
+    /// Actuators whose commanded state is not their safe state
+    pub fn active(&self) -> Vec<ActuatorStatus> {
+        self.lock().iter()
+            .filter(|a| matches!(a.commanded, Some(state) if state != a.safe_state))
+            .cloned()
+            .collect()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref ACTUATORS: ActuatorRegistry = ActuatorRegistry::new(Box::new(HardwareDriver::default()), SAFETY_MONITOR.clone());
+}
+
+/// Register the system's relays and GPIO outputs and drive them safe
+pub fn init(config: &Config) {
+    // Every channel of every board, by its logical name where it has one
+    let mut outputs: Vec<(String, ActuatorOutput)> = Vec::new();
+    for board in qwiic::relay_boards() {
+        for relay_id in 1..=board.board_type.channel_count() {
+            let output = ActuatorOutput::Relay { board: board.address, relay_id };
+            let name = board.channels.iter()
+                .find(|channel| channel.relay_id == relay_id)
+                .map(|channel| channel.name.clone())
+                .unwrap_or_else(|| output.to_string());
+            outputs.push((name, output));
+        }
+    }
+
+    outputs.push(("tank_one_to_two_pump".to_string(), ActuatorOutput::Gpio { pin: config.tank_one_to_two_pump_pin as u8 }));
+    outputs.push(("uv_light".to_string(), ActuatorOutput::Gpio { pin: config.uv_light_pin as u8 }));
+    outputs.push(("air_circulation".to_string(), ActuatorOutput::Gpio { pin: config.air_circulation_pin as u8 }));
+    if let Some(pump_config) = &config.pump_config {
+        outputs.extend(pump_config.pumps.iter()
+            .map(|pump| (pump.id.clone(), ActuatorOutput::for_pump(&pump.output))));
+    }
+    if let Some(hvac_config) = &config.hvac_config {
+        outputs.extend(crate::aog::climate::configured_outputs(hvac_config).into_iter()
+            .map(|(role, output)| (role.name().to_string(), output)));
+    }
+
+    for (name, output) in outputs {
+        // A pump may be defined on a channel that is already named
+        if let Err(e) = ACTUATORS.register(&name, output, ActuatorState::Off) {
+            log::debug!("Skipping actuator '{}': {}", name, e);
+        }
+    }
+
+    shutdown("startup");
+}
+
+/// Drive every actuator to its safe state, logging any that failed
+pub fn shutdown(context: &str) {
+    PWM.all_off();
+    let failures = ACTUATORS.drive_all_safe();
+    if failures.is_empty() {
+        log::info!("All actuators in safe state ({})", context);
+    }
+    for (name, error) in failures {
+        log::error!("CRITICAL: actuator '{}' not in safe state ({}): {}", name, context, error);
+    }
+}
+
+/// Drive actuators safe before the default panic handling runs
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PWM.all_off();
+        match ACTUATORS.try_drive_all_safe() {
+            Some(failures) => {
+                for (name, error) in failures {
+                    log::error!("CRITICAL: actuator '{}' not in safe state (panic): {}", name, error);
+                }
+            }
+            None => log::error!("CRITICAL: actuator registry locked during panic, outputs not driven safe"),
+        }
+        default_hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// Outputs kept in memory; stuck outputs ignore writes
+    #[derive(Default)]
+    struct MockDriver {
+        states: Mutex<HashMap<String, ActuatorState>>,
+        stuck: Mutex<Vec<ActuatorOutput>>,
+    }
+
+    impl ActuatorDriver for Arc<MockDriver> {
+        fn write(&self, output: ActuatorOutput, state: ActuatorState) -> Result<(), String> {
+            if !self.stuck.lock().unwrap().contains(&output) {
+                self.states.lock().unwrap().insert(output.to_string(), state);
+            }
+            Ok(())
+        }
+
+        fn read(&self, output: ActuatorOutput) -> Result<Option<ActuatorState>, String> {
+            Ok(Some(*self.states.lock().unwrap().get(&output.to_string()).unwrap_or(&ActuatorState::Off)))
+        }
+    }
+
+    fn registry() -> (ActuatorRegistry, Arc<MockDriver>, PumpSafetyMonitor) {
+        let driver = Arc::new(MockDriver::default());
+        let monitor = PumpSafetyMonitor::new();
+        let registry = ActuatorRegistry::new(Box::new(driver.clone()), monitor.clone());
+        registry.register("fill", ActuatorOutput::Relay { board: 0x25, relay_id: 3 }, ActuatorState::Off).unwrap();
+        registry.register("uv", ActuatorOutput::Gpio { pin: 27 }, ActuatorState::Off).unwrap();
+        (registry, driver, monitor)
+    }
+
+    #[test]
+    fn test_register_rejects_duplicates() {
+        let (registry, _, _) = registry();
+        assert!(registry.register("fill", ActuatorOutput::Gpio { pin: 5 }, ActuatorState::Off).is_err());
+        assert!(registry.register("other", ActuatorOutput::Gpio { pin: 27 }, ActuatorState::Off).is_err());
+        assert_eq!(registry.status().len(), 2);
+    }
+
+    #[test]
+    fn test_set_verifies_read_back() {
+        let (registry, driver, _) = registry();
+        let status = registry.set("fill", ActuatorState::On).unwrap();
+        assert_eq!(status.commanded, Some(ActuatorState::On));
+        assert_eq!(status.verified, Some(true));
+        assert_eq!(registry.active().len(), 1);
+
+        driver.stuck.lock().unwrap().push(ActuatorOutput::Gpio { pin: 27 });
+        assert!(registry.set("uv", ActuatorState::On).is_err());
+        let uv = registry.get("uv").unwrap();
+        assert_eq!(uv.verified, Some(false));
+        assert!(uv.last_error.is_some());
+
+        assert!(registry.set("missing", ActuatorState::On).is_err());
+    }
+
+    #[test]
+    fn test_drive_all_safe() {
+        let (registry, driver, _) = registry();
+        registry.set("fill", ActuatorState::On).unwrap();
+        registry.set("uv", ActuatorState::On).unwrap();
+
+        driver.stuck.lock().unwrap().push(ActuatorOutput::Gpio { pin: 27 });
+        let failures = registry.drive_all_safe();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "uv");
+        assert_eq!(registry.get("fill").unwrap().commanded, Some(ActuatorState::Off));
+    }
+
+    #[test]
+    fn test_try_drive_all_safe_never_blocks() {
+        let (registry, driver, _) = registry();
+        registry.set("fill", ActuatorState::On).unwrap();
+
+        // The panicking thread may hold the registry lock
+        let held = registry.actuators.lock().unwrap();
+        assert!(registry.try_drive_all_safe().is_none());
+        drop(held);
+
+        assert_eq!(registry.try_drive_all_safe(), Some(Vec::new()));
+        assert_eq!(driver.states.lock().unwrap().get("relay 0x25:3"), Some(&ActuatorState::Off));
+    }
+
+    #[test]
+    fn test_emergency_stop_holds_safe_state() {
+        let (registry, _, monitor) = registry();
+        monitor.emergency_shutdown("Test".to_string());
+        assert!(registry.set("fill", ActuatorState::On).is_err());
+        assert!(registry.set("fill", ActuatorState::Off).is_ok());
+
+        // Unknown outputs are registered on first use
+        let output = ActuatorOutput::Gpio { pin: 6 };
+        assert!(registry.set_output(output, ActuatorState::Off).is_ok());
+        assert_eq!(registry.get("gpio 6").unwrap().output, output);
+    }
+}
@@ -0,0 +1,468 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Climate Module - Thermostat and humidistat loops for the HVAC kit: heater,
+// cooler, fan and humidifier with hysteresis, minimum on/off times, day/night
+// setpoints from the photo cycle and airflow interlocks
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::aog::actuators::{ActuatorOutput, ActuatorState, ACTUATORS};
+use crate::aog::rules::in_window;
+use crate::aog::sensors;
+use crate::{ClimateOutputConfig, ClimateSetpoints, Config, HvacConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClimateRole {
+    Fan,
+    Heater,
+    Cooler,
+    Humidifier,
+}
+
+impl ClimateRole {
+    /// In evaluation order: the fan first, as the heater depends on it
+    pub const ALL: [ClimateRole; 4] = [ClimateRole::Fan, ClimateRole::Heater, ClimateRole::Cooler, ClimateRole::Humidifier];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ClimateRole::Fan => "fan",
+            ClimateRole::Heater => "heater",
+            ClimateRole::Cooler => "cooler",
+            ClimateRole::Humidifier => "humidifier",
+        }
+    }
+
+    fn output(self, config: &HvacConfig) -> Option<&ClimateOutputConfig> {
+        match self {
+            ClimateRole::Fan => config.fan.as_ref(),
+            ClimateRole::Heater => config.heater.as_ref(),
+            ClimateRole::Cooler => config.cooler.as_ref(),
+            ClimateRole::Humidifier => config.humidifier.as_ref(),
+        }
+    }
+}
+
+/// Configured outputs by role, for registering them with the actuators
+pub fn configured_outputs(config: &HvacConfig) -> Vec<(ClimateRole, ActuatorOutput)> {
+    ClimateRole::ALL.iter()
+        .filter_map(|role| role.output(config).map(|output| (*role, ActuatorOutput::for_pump(&output.output))))
+        .collect()
+}
+
+/// Hysteresis: a heating loop starts `band` below the setpoint and runs until
+/// the setpoint is reached, a cooling loop the same way from above
+fn demand(on: bool, value: f32, setpoint: f32, band: f32, raise: bool) -> bool {
+    if raise {
+        value < if on { setpoint } else { setpoint - band }
+    } else {
+        value > if on { setpoint } else { setpoint + band }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClimateOutputStatus {
+    pub role: ClimateRole,
+    pub on: bool,
+    pub since: u64,  // When the output last changed
+    pub demand: bool,
+    pub held: Option<String>,  // Why the output is not following demand
+    pub last_error: Option<String>,
+    pub verified: bool,  // Reads back as commanded; an unverified output is switched again
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClimateStatus {
+    pub profile: String,  // "day" or "night"
+    pub setpoints: ClimateSetpoints,
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+    pub outputs: Vec<ClimateOutputStatus>,
+}
+
+pub struct ClimateController {
+    config: HvacConfig,
+    photo_cycle: (u8, u8),
+    outputs: Vec<ClimateOutputStatus>,
+    // Fan running to bring humidity down, tracked apart from heating and cooling
+    venting: bool,
+    day: bool,
+    temperature: Option<f32>,
+    humidity: Option<f32>,
+}
+
+impl ClimateController {
+    /// Every output starts off, as if it had just been switched off at `now`,
+    /// so a compressor gets its rest after a restart
+    pub fn new(config: HvacConfig, photo_cycle: (u8, u8), now: u64) -> Self {
+        let outputs = ClimateRole::ALL.iter()
+            .filter(|role| role.output(&config).is_some())
+            .map(|role| ClimateOutputStatus { role: *role, on: false, since: now, demand: false, held: None, last_error: None, verified: true })
+            .collect();
+        ClimateController { config, photo_cycle, outputs, venting: false, day: true, temperature: None, humidity: None }
+    }
+
+    pub fn set_photo_cycle(&mut self, start: u8, end: u8) {
+        self.photo_cycle = (start, end);
+    }
+
+    pub fn output(&self, role: ClimateRole) -> Option<ActuatorOutput> {
+        role.output(&self.config).map(|output| ActuatorOutput::for_pump(&output.output))
+    }
+
+    fn is_on(&self, role: ClimateRole) -> Option<bool> {
+        self.outputs.iter().find(|status| status.role == role).map(|status| status.on)
+    }
+
+    fn setpoints(&self) -> ClimateSetpoints {
+        if self.day { self.config.day } else { self.config.night }
+    }
+
+    /// Run the loops on the latest readings and return the outputs to switch.
+    /// Changes take effect once confirmed, so the heater only starts the cycle
+    /// after the fan is confirmed running, and an output that is not in its
+    /// commanded state is switched again.
+    pub fn step(&mut self, temperature: Option<f32>, humidity: Option<f32>, now: u64, hour: u8) -> Vec<(ClimateRole, bool)> {
+        self.day = in_window(self.photo_cycle.0, self.photo_cycle.1, hour);
+        self.temperature = temperature;
+        self.humidity = humidity;
+        let setpoints = self.setpoints();
+        let heater_on = self.is_on(ClimateRole::Heater).unwrap_or(false);
+        let cooler_on = self.is_on(ClimateRole::Cooler).unwrap_or(false);
+        // Airflow only counts once the fan reads back as running
+        let fan_on = self.outputs.iter().find(|status| status.role == ClimateRole::Fan).map(|status| status.on && status.verified);
+
+        let heat = temperature.is_some_and(|t| demand(heater_on, t, setpoints.temperature_c, self.config.temperature_band_c, true));
+        let cool = temperature.is_some_and(|t| demand(cooler_on, t, setpoints.temperature_c, self.config.temperature_band_c, false));
+        let humidify = humidity.is_some_and(|h| {
+            demand(self.is_on(ClimateRole::Humidifier).unwrap_or(false), h, setpoints.humidity_pct, self.config.humidity_band_pct, true)
+        });
+        self.venting = humidity.is_some_and(|h| demand(self.venting, h, setpoints.humidity_pct, self.config.humidity_band_pct, false));
+
+        let mut changes = Vec::new();
+        for status in self.outputs.iter_mut() {
+            let (wanted, forced_off) = match status.role {
+                ClimateRole::Fan => (self.config.fan_continuous || heat || heater_on || cool || cooler_on || self.venting, None),
+                ClimateRole::Heater => {
+                    let blocked = if temperature.is_none() {
+                        Some("no temperature reading")
+                    } else if self.config.heater_requires_fan && fan_on.is_none() {
+                        Some("no fan configured for airflow")
+                    } else if self.config.heater_requires_fan && fan_on == Some(false) {
+                        Some("waiting for airflow")
+                    } else if cooler_on {
+                        Some("cooler running")
+                    } else {
+                        None
+                    };
+                    (heat, blocked)
+                }
+                ClimateRole::Cooler => {
+                    let blocked = if temperature.is_none() {
+                        Some("no temperature reading")
+                    } else if heater_on {
+                        Some("heater running")
+                    } else {
+                        None
+                    };
+                    (cool, blocked)
+                }
+                ClimateRole::Humidifier => (humidify, humidity.is_none().then_some("no humidity reading")),
+            };
+            status.demand = wanted;
+            status.held = None;
+
+            // Interlocks switch off at once, whatever the minimum on time
+            let mut on = wanted && forced_off.is_none();
+            if let Some(reason) = forced_off {
+                if wanted || status.on {
+                    status.held = Some(reason.to_string());
+                }
+            } else if on != status.on {
+                let limits = status.role.output(&self.config).map(|output| (output.min_on_seconds, output.min_off_seconds)).unwrap_or_default();
+                let minimum = if status.on { limits.0 } else { limits.1 };
+                let elapsed = now.saturating_sub(status.since);
+                if elapsed < minimum {
+                    status.held = Some(format!("minimum {} time, {}s left", if status.on { "on" } else { "off" }, minimum - elapsed));
+                    on = status.on;
+                }
+            }
+            if on != status.on || !status.verified {
+                changes.push((status.role, on));
+            }
+        }
+        changes
+    }
+
+    /// Follow the actual state of an output, which the emergency stop, the
+    /// console, the API or a rule may have switched outside the loops
+    pub fn sync(&mut self, role: ClimateRole, on: bool, verified: bool, now: u64) {
+        if let Some(status) = self.outputs.iter_mut().find(|status| status.role == role) {
+            if status.on != on {
+                log::warn!("Climate: {} was switched {} outside climate control", role.name(), if on { "on" } else { "off" });
+                status.on = on;
+                status.since = now;
+            }
+            status.verified = verified;
+        }
+    }
+
+    /// Record that an output was switched
+    pub fn confirm(&mut self, role: ClimateRole, on: bool, now: u64) {
+        if let Some(status) = self.outputs.iter_mut().find(|status| status.role == role) {
+            status.on = on;
+            status.since = now;
+            status.last_error = None;
+            status.verified = true;
+        }
+    }
+
+    /// Record that switching an output failed; it keeps its previous state
+    pub fn fail(&mut self, role: ClimateRole, error: String) {
+        if let Some(status) = self.outputs.iter_mut().find(|status| status.role == role) {
+            status.last_error = Some(error);
+        }
+    }
+
+    pub fn status(&self) -> ClimateStatus {
+        ClimateStatus {
+            profile: if self.day { "day" } else { "night" }.to_string(),
+            setpoints: self.setpoints(),
+            temperature: self.temperature,
+            humidity: self.humidity,
+            outputs: self.outputs.clone(),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref CLIMATE: Mutex<Option<ClimateController>> = Mutex::new(None);
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Run the loops once on the current readings and drive the outputs
+pub fn run_once() {
+    let now = now();
+    let hour = Local::now().hour() as u8;
+    let temperature = sensors::current_value("temp");
+    let humidity = sensors::current_value("hum");
+
+    let mut guard = CLIMATE.lock().unwrap_or_else(|e| e.into_inner());
+    let controller = match guard.as_mut() {
+        Some(controller) => controller,
+        None => return,
+    };
+    for role in ClimateRole::ALL {
+        if let Some(status) = controller.output(role).and_then(|output| ACTUATORS.get_output(output)) {
+            let on = status.commanded.is_some_and(|state| state.is_on());
+            controller.sync(role, on, status.verified != Some(false), now);
+        }
+    }
+    for (role, on) in controller.step(temperature, humidity, now, hour) {
+        let output = match controller.output(role) {
+            Some(output) => output,
+            None => continue,
+        };
+        match ACTUATORS.set_output(output, ActuatorState::from_bool(on)) {
+            Ok(_) => {
+                log::info!("Climate: {} {}", role.name(), if on { "on" } else { "off" });
+                controller.confirm(role, on, now);
+            }
+            Err(e) => {
+                log::error!("Climate: failed to switch {} {}: {}", role.name(), if on { "on" } else { "off" }, e);
+                controller.fail(role, e);
+            }
+        }
+    }
+}
+
+/// Start the climate loops if the HVAC kit is installed
+pub fn init(config: &Config) {
+    if !config.is_hvac_kit_installed {
+        return;
+    }
+    let hvac = match &config.hvac_config {
+        Some(hvac) => hvac.clone(),
+        None => {
+            log::warn!("HVAC kit installed but no hvac_config, climate control disabled");
+            return;
+        }
+    };
+    let interval = Duration::from_secs(hvac.interval_seconds.max(1));
+    log::info!("Climate control: {:?}", configured_outputs(&hvac).iter().map(|(role, _)| role.name()).collect::<Vec<_>>());
+    *CLIMATE.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some(ClimateController::new(hvac, (config.photo_cycle_start, config.photo_cycle_end), now()));
+
+    let _ = thread::Builder::new().name("climate_thread".to_string()).spawn(move || loop {
+        run_once();
+        thread::sleep(interval);
+    });
+}
+
+pub fn status() -> Option<ClimateStatus> {
+    CLIMATE.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|controller| controller.status())
+}
+
+/// Follow a change to the photo cycle for the day/night setpoints
+pub fn set_photo_cycle(start: u8, end: u8) {
+    if let Some(controller) = CLIMATE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        controller.set_photo_cycle(start, end);
+    }
+}
+
+pub fn format_status(status: &ClimateStatus) -> String {
+    let reading = |value: Option<f32>, unit: &str| value.map(|v| format!("{:.1}{}", v, unit)).unwrap_or_else(|| "N/A".to_string());
+    let mut lines = vec![format!("Climate ({}): {} target {:.1}C, {} target {:.0}%",
+        status.profile,
+        reading(status.temperature, "C"), status.setpoints.temperature_c,
+        reading(status.humidity, "%"), status.setpoints.humidity_pct)];
+    for output in &status.outputs {
+        let mut line = format!("  {:<10} {}", output.role.name(), if output.on { "ON" } else { "off" });
+        if let Some(held) = &output.held {
+            line.push_str(&format!(" ({})", held));
+        }
+        if let Some(error) = &output.last_error {
+            line.push_str(&format!(" - error: {}", error));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Handle `climate` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["climate"] | ["climate", "status"] => match status() {
+            Some(status) => format_status(&status),
+            None => "Climate control is not running (HVAC kit not installed or not configured)".to_string(),
+        },
+        _ => "Usage: climate [status]".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PumpOutput;
+
+    fn output(pin: u8, min_on: u64, min_off: u64) -> Option<ClimateOutputConfig> {
+        Some(ClimateOutputConfig { output: PumpOutput::Gpio { pin }, min_on_seconds: min_on, min_off_seconds: min_off })
+    }
+
+    fn hvac() -> HvacConfig {
+        HvacConfig {
+            heater: output(5, 0, 0),
+            cooler: output(6, 60, 180),
+            fan: output(13, 0, 0),
+            humidifier: output(19, 0, 0),
+            ..HvacConfig::default()
+        }
+    }
+
+    /// Step and confirm every change, as the hardware loop does when it succeeds
+    fn run(controller: &mut ClimateController, temperature: Option<f32>, humidity: Option<f32>, now: u64, hour: u8) -> Vec<(ClimateRole, bool)> {
+        let changes = controller.step(temperature, humidity, now, hour);
+        for (role, on) in &changes {
+            controller.confirm(*role, *on, now);
+        }
+        changes
+    }
+
+    #[test]
+    fn test_heater_waits_for_fan_and_follows_band() {
+        let mut controller = ClimateController::new(hvac(), (6, 22), 0);
+
+        // Day setpoint 25C with a 1C band: 23.5 calls for heat, fan first
+        assert_eq!(run(&mut controller, Some(23.5), Some(60.0), 10, 12), vec![(ClimateRole::Fan, true)]);
+        assert_eq!(controller.status().outputs[1].held.as_deref(), Some("waiting for airflow"));
+        assert_eq!(run(&mut controller, Some(23.5), Some(60.0), 20, 12), vec![(ClimateRole::Heater, true)]);
+
+        // Inside the band it keeps heating until the setpoint
+        assert!(run(&mut controller, Some(24.5), Some(60.0), 30, 12).is_empty());
+        assert_eq!(run(&mut controller, Some(25.0), Some(60.0), 40, 12), vec![(ClimateRole::Heater, false)]);
+        assert_eq!(run(&mut controller, Some(25.0), Some(60.0), 50, 12), vec![(ClimateRole::Fan, false)]);
+
+        // A failed fan switches the heater off at once
+        run(&mut controller, Some(23.0), Some(60.0), 60, 12);
+        run(&mut controller, Some(23.0), Some(60.0), 70, 12);
+        controller.confirm(ClimateRole::Fan, false, 80);
+        let changes = controller.step(Some(23.0), Some(60.0), 80, 12);
+        assert!(changes.contains(&(ClimateRole::Heater, false)));
+    }
+
+    #[test]
+    fn test_outputs_follow_actual_state() {
+        let mut controller = ClimateController::new(hvac(), (6, 22), 0);
+        run(&mut controller, Some(23.0), Some(60.0), 10, 12);
+        run(&mut controller, Some(23.0), Some(60.0), 20, 12);
+        assert!(controller.is_on(ClimateRole::Heater).unwrap());
+
+        // Fan switched off from outside: the heater goes off and the fan is switched back on
+        controller.sync(ClimateRole::Fan, false, true, 30);
+        assert_eq!(run(&mut controller, Some(23.0), Some(60.0), 30, 12),
+            vec![(ClimateRole::Fan, true), (ClimateRole::Heater, false)]);
+        run(&mut controller, Some(23.0), Some(60.0), 40, 12);
+        assert!(controller.is_on(ClimateRole::Heater).unwrap());
+
+        // A fan that doesn't read back as running doesn't count as airflow
+        controller.sync(ClimateRole::Fan, true, false, 50);
+        assert_eq!(controller.step(Some(23.0), Some(60.0), 50, 12),
+            vec![(ClimateRole::Fan, true), (ClimateRole::Heater, false)]);
+
+        // After an emergency stop everything is off, and is commanded again
+        let mut controller = ClimateController::new(hvac(), (6, 22), 0);
+        run(&mut controller, Some(23.0), Some(60.0), 10, 12);
+        run(&mut controller, Some(23.0), Some(60.0), 20, 12);
+        for role in ClimateRole::ALL {
+            controller.sync(role, false, true, 30);
+        }
+        assert_eq!(run(&mut controller, Some(23.0), Some(60.0), 40, 12), vec![(ClimateRole::Fan, true)]);
+        assert_eq!(run(&mut controller, Some(23.0), Some(60.0), 50, 12), vec![(ClimateRole::Heater, true)]);
+    }
+
+    #[test]
+    fn test_cooler_minimum_times() {
+        let mut controller = ClimateController::new(hvac(), (6, 22), 0);
+
+        // Compressor rest from startup holds the cooler off for 180s
+        run(&mut controller, Some(27.0), Some(60.0), 100, 12);
+        assert!(!controller.is_on(ClimateRole::Cooler).unwrap());
+        assert_eq!(controller.status().outputs[2].held.as_deref(), Some("minimum off time, 80s left"));
+        run(&mut controller, Some(27.0), Some(60.0), 180, 12);
+        assert!(controller.is_on(ClimateRole::Cooler).unwrap());
+
+        // Reached the setpoint, but must run for 60s
+        run(&mut controller, Some(24.9), Some(60.0), 200, 12);
+        assert!(controller.is_on(ClimateRole::Cooler).unwrap());
+        run(&mut controller, Some(24.9), Some(60.0), 240, 12);
+        assert!(!controller.is_on(ClimateRole::Cooler).unwrap());
+    }
+
+    #[test]
+    fn test_night_profile_and_missing_readings() {
+        let mut controller = ClimateController::new(hvac(), (6, 22), 0);
+
+        // Night setpoint is 20C, so 19.5 needs no heat at 23:00
+        assert!(run(&mut controller, Some(19.5), Some(65.0), 10, 23).is_empty());
+        assert_eq!(controller.status().profile, "night");
+
+        // Humidity 58% is below the night band: humidify, then lose the reading
+        assert_eq!(run(&mut controller, Some(20.0), Some(58.0), 20, 23), vec![(ClimateRole::Humidifier, true)]);
+        assert_eq!(run(&mut controller, Some(20.0), None, 30, 23), vec![(ClimateRole::Humidifier, false)]);
+        assert_eq!(controller.status().outputs[3].held.as_deref(), Some("no humidity reading"));
+
+        // Humid air vents through the fan
+        controller.set_photo_cycle(0, 24);
+        assert_eq!(run(&mut controller, Some(25.0), Some(66.0), 40, 23), vec![(ClimateRole::Fan, true)]);
+        assert_eq!(controller.status().profile, "day");
+    }
+}
@@ -22,9 +22,6 @@
 
 use crate::aog;
 
-use qwiic_relay_rs::*;
-use rppal::gpio::Gpio;
-
 use std::error::Error;
 
 
@@ -36,7 +33,8 @@ use std::time::Duration;
 
 
 use std::io::{self, BufRead};
-use std::sync::mpsc::{self};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 
 // Gpio uses BCM pin numbering. BCM GPIO 23 is tied to physical pin 16.
@@ -136,51 +134,84 @@ pub fn run(cmd: String) -> Result<(), Box<dyn Error>>{
         println!("{}", aog::sensors::get_value("t2_ovf"));
     }
 
+    if command.starts_with("pump"){
+        println!("{}", aog::pump_jobs::run_command(&command));
+    }
+
+    if command.starts_with("water"){
+        println!("{}", aog::water_level::run_command(&command));
+    }
+
+    if command.starts_with("estop"){
+        println!("{}", aog::emergency_stop::run_command(&command, "console"));
+    }
+
 
     if command.starts_with("relay"){
+        println!("{}", aog::qwiic::run_command(&command));
+    }
 
-        let qwiic_relay_config = QwiicRelayConfig::default();
-        let mut qwiic_relay = match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", 0x25) {
-            Ok(relay) => relay,
-            Err(e) => {
-                log::error!("Failed to initialize Qwiic relay device: {:?}", e);
-                return Err(Box::new(e));
-            }
-        };
 
-     
-    
-        if command.contains("off"){
-            if command.contains("2"){
-                let _ = qwiic_relay.set_relay_off(Some(2));
-            }
-            if command.contains("3"){
-                let _ = qwiic_relay.set_relay_off(Some(3));
-            }
-            if command.contains("4"){
-                let _ = qwiic_relay.set_relay_off(Some(4));
-            }
-        }
+    if command.starts_with("i2c"){
+        println!("{}", aog::i2c::run_command(&command));
+    }
 
-        if command.contains("on"){
-            if command.contains("2"){
-                let _ = qwiic_relay.set_relay_on(Some(2));
-            }
-            if command.contains("3"){
-                let _ = qwiic_relay.set_relay_on(Some(3));
-            }
-            if command.contains("4"){
-                let _ = qwiic_relay.set_relay_on(Some(4));
-            }
-        }
+
+    if command.starts_with("climate"){
+        println!("{}", aog::climate::run_command(&command));
+    }
+
+
+    if command.starts_with("pid"){
+        println!("{}", aog::pid::run_command(&command));
+    }
 
 
+    if command.starts_with("pwm"){
+        println!("{}", aog::gpio::pwm::run_command(&command));
+    }
+
+
+    if command.starts_with("rules"){
+        println!("{}", aog::rules::run_command(&command));
     }
 
     
     if command.clone() == *"help"{
         println!("gpio status:                  prints status of the gpio bus");
-        println!("gpio [on/off] [gpio_bdm]:     change state of a gpio pin");
+        println!("gpio [on/off] [gpio_bdm/name]: change state of a gpio output");
+        println!("gpio outputs:                 prints gpio outputs and why any are held off");
+        println!("gpio inputs:                  prints gpio inputs with their level and edge counts");
+        println!("gpio input [gpio_bdm/name]:   prints an input's recent edges");
+        println!("relay status:                 prints relay states and switch counts");
+        println!("relay [on/off] [1-4]:         switch a relay and verify it");
+        println!("i2c scan:                     lists devices on the I2C bus");
+        println!("pump fill [tank] [N%/NL]:     fill a tank to a level or by a volume");
+        println!("pump drain [tank] [N%/NL]:    drain a tank to a level or by a volume");
+        println!("pump transfer [from] [to] NL: transfer a volume between tanks");
+        println!("pump jobs:                    lists pump jobs and their progress");
+        println!("pump cancel [id]:             cancels the running pump job");
+        println!("pump maintenance [id]:        pump service status and history");
+        println!("pump service [id] [notes]:    records a pump service and resets its counter");
+        println!("estop [status]:               prints emergency stop state");
+        println!("estop trigger [reason]:       trips the emergency stop, all outputs off");
+        println!("estop reset [reason]:         releases the emergency stop latch");
+        println!("water status:                 prints tank water levels");
+        println!("water calibration:            prints water level calibration");
+        println!("water calibrate [tank] [lvl]: capture calibration point (empty/full/cm/reset)");
+        println!("climate [status]:             prints climate setpoints, readings and outputs");
+        println!("pid [status]:                 prints PID loops with their setpoint, output and tuning");
+        println!("pid tune [name] [kp] [ki] [kd]: changes a PID loop's tuning");
+        println!("pid setpoint [name] [value]:  changes a PID loop's setpoint");
+        println!("pid auto [name]:              returns a PID loop to automatic");
+        println!("pid manual [name] [percent]:  holds a PID loop's output");
+        println!("pwm [status]:                 prints PWM channels with their level and target");
+        println!("pwm set [name] [percent]:     ramps a PWM channel to a level");
+        println!("pwm auto [name]:              returns a PWM channel to its schedule");
+        println!("rules [status]:               lists automation rules and what they would do now");
+        println!("rules history:                lists actions taken by automation rules");
+        println!("rules [enable/disable] [name]: enables or disables an automation rule");
+        println!("rules dry-run [on/off]:       evaluate rules without acting on them");
         println!("clear/cls:                    clears screen");
         println!("api token generate:           generate new API authentication token");
         println!("api token remove:             remove API authentication requirement");
@@ -205,124 +236,46 @@ pub fn run(cmd: String) -> Result<(), Box<dyn Error>>{
     if command.starts_with("gpio"){
         if command == *"gpio status"{
             let _ = aog::gpio::status::print();
-        }
-
-        if command.contains("on"){
-            println!("Press enter to terminate the gpio on(set-low) thread");
-            let (tx, _rx) = mpsc::channel();
-            let split = cmd.split(' ');
-            let split_vec = split.collect::<Vec<&str>>();
-            let selected_pin = match split_vec[2].parse::<u8>() {
-                Ok(pin) => pin,
-                Err(e) => {
-                    log::error!("Invalid pin number: {}", e);
-                    return Ok(());
-                }
-            };
-
-            let gpio = Gpio::new();
-            if gpio.is_ok() {
-                let gpio_pin = gpio.ok().and_then(|g| g.get(selected_pin).ok());
-                if let Some(pin_result) = gpio_pin {
-                    let mut pin = pin_result.into_output();
-                    thread::spawn(move || loop {
-                        pin.set_low();
-                        thread::sleep(Duration::from_millis(500));
-                    });
-                } else {
-                    log::warn!("Command '{}' failed. GPIO pin is unavailable.", command);
-                }
-            } else {
-                log::warn!("Command '{}' failed. GPIO is unavailable.", command);
-            }
-
-        
-            if !command.contains("nolock"){
-                let mut line = String::new();
-                let stdin = io::stdin();
-                let _ = stdin.lock().read_line(&mut line);
-                let _ = tx.send(());
-            }
-
-        } else if command.contains("off"){
-            let split = cmd.split(' ');
-            let split_vec = split.collect::<Vec<&str>>();
-            let selected_pin = match split_vec[2].parse::<u8>() {
-                Ok(pin) => pin,
-                Err(e) => {
-                    log::error!("Invalid pin number: {}", e);
-                    return Ok(());
-                }
-            };
-
-
-            let gpio = Gpio::new();
-            if gpio.is_ok() {
-                let gpio_pin = gpio.ok().and_then(|g| g.get(selected_pin).ok());
-                if let Some(pin_result) = gpio_pin {
-                    pin_result.into_input();
-                } else {
-                    log::warn!("Command '{}' failed. GPIO pin is unavailable.", command);
-                }
-            } else {
-                log::warn!("Command '{}' failed. GPIO is unavailable.", command);
-            }
-            
-
-
-
-
-        } else if command.contains("stress"){
-
-
+        } else if command == *"gpio inputs" || command.starts_with("gpio input "){
+            println!("{}", aog::gpio::input::run_command(&command));
+        } else if command.starts_with("gpio stress"){
             println!("Press enter to terminate the gpio stress thread");
-            let (tx, _rx) = mpsc::channel();
-            let split = cmd.split(' ');
-            let split_vec = split.collect::<Vec<&str>>();
-            let selected_pin = match split_vec[2].parse::<u8>() {
-                Ok(pin) => pin,
-                Err(e) => {
-                    log::error!("Invalid pin number: {}", e);
+            let split_vec = cmd.split(' ').collect::<Vec<&str>>();
+            let selected_pin = match split_vec.get(2).map(|pin| pin.parse::<u8>()) {
+                Some(Ok(pin)) => pin,
+                _ => {
+                    log::error!("Invalid pin number in '{}'", command);
                     return Ok(());
                 }
             };
 
-
-
-            let gpio = Gpio::new();
-            if gpio.is_ok() {
-                let gpio_pin = gpio.ok().and_then(|g| g.get(selected_pin).ok());
-                if let Some(pin_result) = gpio_pin {
-                    let mut pin = pin_result.into_output();
-                    thread::spawn(move || loop {
-                            pin.set_low();
-                            thread::sleep(Duration::from_millis(2000));
-                            pin.set_high();
-                            thread::sleep(Duration::from_millis(2000));
-                    });
-                } else {
-                    log::warn!("Command '{}' failed. GPIO pin is unavailable.", command);
+            let stop = Arc::new(AtomicBool::new(false));
+            let stop_thread = Arc::clone(&stop);
+            let pin = selected_pin.to_string();
+            thread::spawn(move || {
+                let mut on = true;
+                while !stop_thread.load(Ordering::Relaxed) {
+                    if let Err(e) = aog::gpio::output::set(&pin, on) {
+                        log::warn!("Command 'gpio stress' failed: {}", e);
+                        break;
+                    }
+                    on = !on;
+                    thread::sleep(Duration::from_millis(2000));
                 }
-            } else {
-                log::warn!("Command '{}' failed. GPIO is unavailable.", command);
-            }
-
+                let _ = aog::gpio::output::set(&pin, false);
+            });
 
             if !command.contains("nolock"){
                 let mut line = String::new();
                 let stdin = io::stdin();
                 let _ = stdin.lock().read_line(&mut line);
-                let _ = tx.send(());
+                stop.store(true, Ordering::Relaxed);
             }
-
- 
+        } else {
+            println!("{}", aog::gpio::output::run_command(&command));
         }
     }
 
-
-    
-
-
     Ok(())
 }
 
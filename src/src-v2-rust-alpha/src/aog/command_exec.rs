@@ -1,5 +1,4 @@
 use crate::aog;
-use qwiic_relay_rs::*;
 use std::error::Error;
 
 /// Execute a command and return the output as a string
@@ -66,37 +65,52 @@ pub fn execute_with_output(cmd: String) -> Result<String, Box<dyn Error>> {
         output = aog::sensors::get_value("t2_ovf");
     }
 
+    if command.starts_with("water") {
+        output = aog::water_level::run_command(&command);
+    }
+
     if command.starts_with("gpio status") {
-        output.push_str("GPIO Status:\n");
-        // Add GPIO status information
-        output.push_str("GPIO pins configured and active\n");
-    }
-
-    if command.starts_with("relay status") {
-        let qwiic_relay_config = QwiicRelayConfig::default();
-        match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", 0x25) {
-            Ok(mut relay) => {
-                for i in 1..=4 {
-                    match relay.get_relay_state(Some(i)) {
-                        Ok(state) => {
-                            output.push_str(&format!("Relay {}: {}\n", i, if state { "ON" } else { "OFF" }));
-                        }
-                        Err(e) => {
-                            output.push_str(&format!("Relay {}: Error reading state: {}\n", i, e));
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                output.push_str(&format!("Failed to connect to relay board: {}\n", e));
-            }
-        }
-    }
-
-    if command.starts_with("pump status") {
-        output.push_str("Pump Status:\n");
-        // Add pump status information
-        output.push_str("Pumps operational\n");
+        output = aog::gpio::status::render_table(&aog::gpio::status::current());
+    }
+
+    if command == "gpio inputs" || command.starts_with("gpio input ") {
+        output = aog::gpio::input::run_command(&command);
+    }
+
+    if command.starts_with("gpio on") || command.starts_with("gpio off") || command == "gpio outputs" {
+        output = aog::gpio::output::run_command(&command);
+    }
+
+    if command.starts_with("relay") {
+        output = aog::qwiic::run_command(&command);
+    }
+
+    if command.starts_with("i2c") {
+        output = aog::i2c::run_command(&command);
+    }
+
+    if command.starts_with("pump") {
+        output = aog::pump_jobs::run_command(&command);
+    }
+
+    if command.starts_with("climate") {
+        output = aog::climate::run_command(&command);
+    }
+
+    if command.starts_with("pid") {
+        output = aog::pid::run_command(&command);
+    }
+
+    if command.starts_with("pwm") {
+        output = aog::gpio::pwm::run_command(&command);
+    }
+
+    if command.starts_with("rules") {
+        output = aog::rules::run_command(&command);
+    }
+
+    if command.starts_with("estop") {
+        output = aog::emergency_stop::run_command(&command, "command-api");
     }
 
     if command.starts_with("help") {
@@ -108,8 +122,37 @@ pub fn execute_with_output(cmd: String) -> Result<String, Box<dyn Error>> {
         output.push_str("  pm25      - Show PM2.5 level\n");
         output.push_str("  pm10      - Show PM10 level\n");
         output.push_str("  gpio status  - Show GPIO status\n");
-        output.push_str("  relay status - Show relay status\n");
-        output.push_str("  pump status  - Show pump status\n");
+        output.push_str("  gpio outputs - Show GPIO outputs and why any are held off\n");
+        output.push_str("  gpio inputs  - Show GPIO inputs with their level and edge counts\n");
+        output.push_str("  gpio input <pin|name> - Show an input's recent edges\n");
+        output.push_str("  gpio on|off <pin|name> - Switch a GPIO output\n");
+        output.push_str("  relay status - Show relay states and switch counts\n");
+        output.push_str("  relay on|off <1-4> - Switch a relay and verify it\n");
+        output.push_str("  i2c scan     - List devices on the I2C bus\n");
+        output.push_str("  pump status  - Show pump jobs\n");
+        output.push_str("  pump fill|drain <tank> <N%|NL> - Fill or drain a tank\n");
+        output.push_str("  pump transfer <from> <to> <NL> - Transfer water between tanks\n");
+        output.push_str("  pump cancel [id] - Cancel the running pump job\n");
+        output.push_str("  pump maintenance [id] - Show pump service status and history\n");
+        output.push_str("  pump service <id> [notes] - Record a pump service\n");
+        output.push_str("  estop [status] - Show emergency stop state\n");
+        output.push_str("  estop trigger [reason] - Trip the emergency stop\n");
+        output.push_str("  estop reset <reason> - Release the emergency stop latch\n");
+        output.push_str("  water status - Show tank water levels\n");
+        output.push_str("  water calibration - Show water level calibration\n");
+        output.push_str("  water calibrate <tank> empty|full|<cm>|reset - Calibrate level sensor\n");
+        output.push_str("  climate [status] - Show climate setpoints, readings and outputs\n");
+        output.push_str("  pid [status] - Show PID loops with their setpoint, output and tuning\n");
+        output.push_str("  pid tune <name> <kp> <ki> <kd> - Change a PID loop's tuning\n");
+        output.push_str("  pid setpoint <name> <value> - Change a PID loop's setpoint\n");
+        output.push_str("  pid auto|manual <name> [percent] - Switch a PID loop between automatic and manual\n");
+        output.push_str("  pwm [status] - Show PWM channels with their level and target\n");
+        output.push_str("  pwm set <name> <percent> - Ramp a PWM channel to a level\n");
+        output.push_str("  pwm auto <name> - Return a PWM channel to its schedule\n");
+        output.push_str("  rules [status] - List automation rules and what they would do now\n");
+        output.push_str("  rules history - List actions taken by automation rules\n");
+        output.push_str("  rules enable|disable <name> - Enable or disable an automation rule\n");
+        output.push_str("  rules dry-run on|off - Evaluate rules without acting on them\n");
         output.push_str("  cls/clear    - Clear screen\n");
         output.push_str("  help         - Show this help\n");
     }
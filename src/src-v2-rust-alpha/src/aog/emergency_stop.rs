@@ -0,0 +1,512 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Emergency Stop Module - Latched E-stop that drives every known actuator to
+// its safe state and stays tripped until an authenticated reset with a reason
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use std::thread;
+use std::fs;
+use std::path::Path;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::aog::pump_safety::{PumpSafetyMonitor, SAFETY_MONITOR, CRITICAL_HIGH_LEVEL};
+use crate::aog::qwiic::QwiicRelayDevice;
+use crate::aog::actuators::ACTUATORS;
+use crate::aog::gpio::input::INPUTS;
+use crate::{Config, GpioInputConfig, InputPull};
+
+/// Trigger and reset history
+pub const ESTOP_LOG_PATH: &str = "/opt/aog/emergency_stop_log.json";
+
+const BUTTON_DEBOUNCE_MS: u64 = 20;
+const OVERFLOW_POLL_MS: u64 = 1000;
+const MAX_ESTOP_EVENTS: usize = 200;
+
+/// What tripped the emergency stop
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TriggerSource {
+    Button,
+    Api,
+    Command,
+    Overflow,
+    RelayFault,
+    Software,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EStopEvent {
+    Triggered {
+        source: TriggerSource,
+        reason: String,
+        timestamp: String,
+        failed_outputs: Vec<String>,
+    },
+    Reset {
+        by: String,
+        reason: String,
+        timestamp: String,
+    },
+}
+
+/// An actuator that must be driven to its safe state on an emergency stop
+pub trait SafeOutput: Send + Sync {
+    fn name(&self) -> String;
+    fn make_safe(&self) -> Result<(), String>;
+}
+
+/// Every relay on a Qwiic relay board switched off
+pub struct RelayBoardOutput {
+    device: QwiicRelayDevice,
+}
+
+impl RelayBoardOutput {
+    pub fn new(address: u16) -> Self {
+        RelayBoardOutput { device: QwiicRelayDevice::new(address) }
+    }
+}
+
+impl SafeOutput for RelayBoardOutput {
+    fn name(&self) -> String {
+        format!("relay board 0x{:02x}", self.device.id)
+    }
+
+    fn make_safe(&self) -> Result<(), String> {
+        self.device.all_off().map_err(|e| e.to_string())
+    }
+}
+
+/// Every output known to the actuator registry driven to its safe state
+pub struct RegisteredActuators;
+
+impl SafeOutput for RegisteredActuators {
+    fn name(&self) -> String {
+        "registered actuators".to_string()
+    }
+
+    fn make_safe(&self) -> Result<(), String> {
+        let failures = ACTUATORS.drive_all_safe();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures.iter().map(|(name, e)| format!("{}: {}", name, e)).collect::<Vec<_>>().join("; "))
+        }
+    }
+}
+
+/// Current E-stop state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EStopStatus {
+    pub active: bool,
+    pub reason: Option<String>,
+    pub triggered_at: Option<String>,
+    pub button_pin: Option<u8>,
+    pub button_pressed: bool,
+    pub outputs: Vec<String>,
+    pub last_reset: Option<EStopEvent>,
+}
+
+pub struct EmergencyStop {
+    monitor: PumpSafetyMonitor,
+    outputs: Mutex<Vec<Arc<dyn SafeOutput>>>,
+    events: Mutex<Vec<EStopEvent>>,
+    log_path: Option<String>,
+    button_pin: Mutex<Option<u8>>,
+    button_pressed: Arc<AtomicBool>,
+}
+
+impl EmergencyStop {
+    /// E-stop latched through `monitor`, with its history kept at `log_path`
+    pub fn new(monitor: PumpSafetyMonitor, log_path: Option<&str>) -> Self {
+        let events = log_path
+            .filter(|path| Path::new(path).exists())
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        EmergencyStop {
+            monitor,
+            outputs: Mutex::new(Vec::new()),
+            events: Mutex::new(events),
+            log_path: log_path.map(|p| p.to_string()),
+            button_pin: Mutex::new(None),
+            button_pressed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Add an actuator to drive safe on an emergency stop. If the stop is
+    /// already latched (e.g. restored after a reboot) it is made safe now.
+    pub fn register_output(&self, output: Arc<dyn SafeOutput>) {
+        if self.is_active() {
+            if let Err(e) = output.make_safe() {
+                log::error!("CRITICAL: failed to make {} safe: {}", output.name(), e);
+            }
+        }
+        self.outputs.lock().unwrap().push(output);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.monitor.is_emergency_stop_active()
+    }
+
+    /// Latch the emergency stop and drive every registered output safe.
+    /// Returns the outputs that could not be made safe.
+    pub fn trigger(&self, source: TriggerSource, reason: &str) -> Vec<String> {
+        if self.is_active() {
+            log::warn!("Emergency stop already active, ignoring {:?} trigger: {}", source, reason);
+            return Vec::new();
+        }
+
+        // Latch first so pump loops and jobs stop on their next check
+        self.monitor.emergency_shutdown(format!("{:?}: {}", source, reason));
+        let _ = crate::aog::pump_jobs::JOB_MANAGER.cancel(None);
+
+        let outputs: Vec<Arc<dyn SafeOutput>> = self.outputs.lock().unwrap().clone();
+        let failed_outputs: Vec<String> = outputs.iter()
+            .filter_map(|output| match output.make_safe() {
+                Ok(()) => None,
+                Err(e) => {
+                    log::error!("CRITICAL: failed to make {} safe: {}", output.name(), e);
+                    Some(output.name())
+                }
+            })
+            .collect();
+
+        log::error!("EMERGENCY STOP ({:?}): {} - {} of {} outputs made safe",
+            source, reason, outputs.len() - failed_outputs.len(), outputs.len());
+        self.record(EStopEvent::Triggered {
+            source,
+            reason: reason.to_string(),
+            timestamp: Local::now().to_rfc3339(),
+            failed_outputs: failed_outputs.clone(),
+        });
+        failed_outputs
+    }
+
+    /// Release the latch. `by` identifies the authenticated caller.
+    pub fn reset(&self, by: &str, reason: &str) -> Result<(), String> {
+        if reason.trim().is_empty() {
+            return Err("A reason is required to reset the emergency stop".to_string());
+        }
+        if !self.is_active() {
+            return Err("Emergency stop is not active".to_string());
+        }
+        if self.button_pressed.load(Ordering::SeqCst) {
+            return Err("Emergency stop button is still pressed".to_string());
+        }
+
+        self.monitor.reset_emergency_stop();
+        log::warn!("Emergency stop reset by {}: {}", by, reason);
+        self.record(EStopEvent::Reset {
+            by: by.to_string(),
+            reason: reason.trim().to_string(),
+            timestamp: Local::now().to_rfc3339(),
+        });
+        Ok(())
+    }
+
+    pub fn status(&self) -> EStopStatus {
+        let record = self.monitor.emergency_stop_record();
+        let active = self.is_active();
+        EStopStatus {
+            active,
+            reason: record.as_ref().filter(|_| active).map(|r| r.reason.clone()),
+            triggered_at: record.filter(|_| active).map(|r| r.timestamp),
+            button_pin: *self.button_pin.lock().unwrap(),
+            button_pressed: self.button_pressed.load(Ordering::SeqCst),
+            outputs: self.outputs.lock().unwrap().iter().map(|o| o.name()).collect(),
+            last_reset: self.events.lock().unwrap().iter().rev()
+                .find(|event| matches!(event, EStopEvent::Reset { .. }))
+                .cloned(),
+        }
+    }
+
+    /// Trigger and reset history, oldest first
+    pub fn events(&self) -> Vec<EStopEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn record(&self, event: EStopEvent) {
+        let mut events = self.events.lock().unwrap();
+        events.push(event);
+        if events.len() > MAX_ESTOP_EVENTS {
+            let excess = events.len() - MAX_ESTOP_EVENTS;
+            events.drain(0..excess);
+        }
+
+        if let Some(path) = &self.log_path {
+            match serde_json::to_string_pretty(&*events) {
+                Ok(json) => {
+                    if let Err(e) = fs::write(path, json) {
+                        log::error!("Failed to write emergency stop log: {}", e);
+                    }
+                }
+                Err(e) => log::error!("Failed to serialize emergency stop log: {}", e),
+            }
+        }
+    }
+
+    /// Watch a physical E-stop button as a debounced GPIO input. With
+    /// `active_low` the button pulls the pin to ground against the internal pull-up.
+    pub fn start_button_monitor(&'static self, pin: u8, active_low: bool) -> Result<(), String> {
+        // Subscribe before watching so the first press can't be missed
+        let events = INPUTS.subscribe();
+        let config = GpioInputConfig {
+            name: "estop_button".to_string(),
+            pin,
+            pull: if active_low { InputPull::Up } else { InputPull::Down },
+            debounce_ms: BUTTON_DEBOUNCE_MS,
+        };
+        if let Err(e) = INPUTS.watch(config) {
+            if !INPUTS.is_watched(pin) {
+                return Err(format!("Failed to watch E-stop button on GPIO {}: {}", pin, e));
+            }
+            log::warn!("E-stop button GPIO {} is also a configured input, using its pull and debounce", pin);
+        }
+        *self.button_pin.lock().unwrap() = Some(pin);
+        if let Some(high) = INPUTS.level(&pin.to_string()) {
+            self.button_changed(pin, high != active_low);
+        }
+
+        thread::Builder::new()
+            .name("estop-button".to_string())
+            .spawn(move || {
+                for event in events.iter().filter(|event| event.pin == pin) {
+                    self.button_changed(pin, event.high != active_low);
+                }
+            })
+            .map_err(|e| format!("Failed to start E-stop button monitor: {}", e))?;
+
+        log::info!("E-stop button monitored on GPIO {}", pin);
+        Ok(())
+    }
+
+    /// Debounced button level change; trips on the press
+    fn button_changed(&self, pin: u8, pressed: bool) {
+        let was_pressed = self.button_pressed.swap(pressed, Ordering::SeqCst);
+        if pressed && !was_pressed {
+            self.trigger(TriggerSource::Button, &format!("E-stop button on GPIO {} pressed", pin));
+        }
+    }
+
+    /// Trip on a critical overflow reported by the level or overflow sensors
+    pub fn start_overflow_monitor(&'static self) {
+        thread::Builder::new()
+            .name("estop-overflow".to_string())
+            .spawn(move || loop {
+                if !self.is_active() {
+                    if let Some(reason) = overflow_condition() {
+                        self.trigger(TriggerSource::Overflow, &reason);
+                    }
+                }
+                thread::sleep(Duration::from_millis(OVERFLOW_POLL_MS));
+            })
+            .map(|_| ())
+            .unwrap_or_else(|e| log::error!("Failed to start E-stop overflow monitor: {}", e));
+    }
+}
+
+/// Description of a critical overflow, if one is present
+fn overflow_condition() -> Option<String> {
+    for (tank_id, sensor) in [("tank1", "t1_ovf"), ("tank2", "t2_ovf")] {
+        if crate::aog::sensors::get_value(sensor).contains("OVERFLOW") {
+            return Some(format!("{} overflow sensor triggered", tank_id));
+        }
+        // Only the cached sample, a direct measurement would hold the system lock
+        let reading = crate::aog::water_level::WATER_LEVEL_SYSTEM.lock().ok()
+            .and_then(|guard| guard.as_ref().and_then(|system| system.monitor(tank_id)))
+            .and_then(|monitor| monitor.cached_level());
+        if let Some(reading) = reading {
+            if reading.is_valid && reading.level_percent >= CRITICAL_HIGH_LEVEL {
+                return Some(format!("{} water level critical at {:.1}%", tank_id, reading.level_percent));
+            }
+        }
+    }
+    None
+}
+
+lazy_static::lazy_static! {
+    pub static ref ESTOP: EmergencyStop = EmergencyStop::new(SAFETY_MONITOR.clone(), Some(ESTOP_LOG_PATH));
+}
+
+/// Register the system's actuators and start the E-stop inputs
+pub fn init(config: &Config) {
+    let estop_config = config.emergency_stop_config.clone().unwrap_or_default();
+
+    // Individual channels first, then whole boards in case a channel is unknown
+    ESTOP.register_output(Arc::new(RegisteredActuators));
+    let mut boards: Vec<u16> = crate::aog::qwiic::relay_boards().iter().map(|board| board.address).collect();
+    for address in &estop_config.relay_boards {
+        if !boards.contains(address) {
+            boards.push(*address);
+        }
+    }
+    for address in boards {
+        ESTOP.register_output(Arc::new(RelayBoardOutput::new(address)));
+    }
+
+    if let Some(pin) = estop_config.button_pin {
+        if let Err(e) = ESTOP.start_button_monitor(pin, estop_config.button_active_low) {
+            log::error!("E-stop button not available: {}", e);
+        }
+    }
+    if estop_config.trip_on_overflow {
+        ESTOP.start_overflow_monitor();
+    }
+
+    if ESTOP.is_active() {
+        log::error!("Emergency stop is latched - reset it with 'estop reset <reason>' once the cause is cleared");
+    }
+}
+
+fn format_status(status: &EStopStatus) -> String {
+    let mut lines = vec![if status.active {
+        format!("EMERGENCY STOP ACTIVE since {}: {}",
+            status.triggered_at.as_deref().unwrap_or("unknown"), status.reason.as_deref().unwrap_or("unknown"))
+    } else {
+        "Emergency stop not active".to_string()
+    }];
+    if let Some(pin) = status.button_pin {
+        lines.push(format!("Button: GPIO {} ({})", pin, if status.button_pressed { "pressed" } else { "released" }));
+    }
+    lines.push(format!("Outputs: {}", status.outputs.join(", ")));
+    if let Some(EStopEvent::Reset { by, reason, timestamp }) = &status.last_reset {
+        lines.push(format!("Last reset {} by {}: {}", timestamp, by, reason));
+    }
+    lines.join("\n")
+}
+
+/// Handle `estop [status]`, `estop trigger <reason>` and `estop reset <reason>`.
+/// `actor` identifies who issued the command.
+pub fn run_command(command: &str, actor: &str) -> String {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.as_slice() {
+        ["estop"] | ["estop", "status"] => format_status(&ESTOP.status()),
+        ["estop", "trigger", reason @ ..] => {
+            let reason = if reason.is_empty() { format!("Triggered by {}", actor) } else { reason.join(" ") };
+            let failed = ESTOP.trigger(TriggerSource::Command, &reason);
+            if failed.is_empty() {
+                "Emergency stop triggered - all outputs off".to_string()
+            } else {
+                format!("Emergency stop triggered - FAILED to make safe: {}", failed.join(", "))
+            }
+        }
+        ["estop", "reset", reason @ ..] => match ESTOP.reset(actor, &reason.join(" ")) {
+            Ok(()) => "Emergency stop reset".to_string(),
+            Err(e) => format!("Reset refused: {}", e),
+        },
+        _ => "Usage: estop [status] | estop trigger [reason] | estop reset <reason>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockOutput {
+        name: String,
+        fail: bool,
+        made_safe: AtomicBool,
+    }
+
+    impl SafeOutput for MockOutput {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn make_safe(&self) -> Result<(), String> {
+            self.made_safe.store(true, Ordering::SeqCst);
+            if self.fail { Err("bus error".to_string()) } else { Ok(()) }
+        }
+    }
+
+    fn mock(name: &str, fail: bool) -> Arc<MockOutput> {
+        Arc::new(MockOutput { name: name.to_string(), fail, made_safe: AtomicBool::new(false) })
+    }
+
+    #[test]
+    fn test_trigger_makes_outputs_safe() {
+        let estop = EmergencyStop::new(PumpSafetyMonitor::new(), None);
+        let relay = mock("relay", false);
+        let broken = mock("broken", true);
+        estop.register_output(relay.clone());
+        estop.register_output(broken.clone());
+        assert!(!relay.made_safe.load(Ordering::SeqCst));
+
+        let failed = estop.trigger(TriggerSource::Api, "Test");
+        assert_eq!(failed, vec!["broken".to_string()]);
+        assert!(relay.made_safe.load(Ordering::SeqCst));
+        assert!(broken.made_safe.load(Ordering::SeqCst));
+        assert!(estop.is_active());
+        assert!(estop.status().reason.unwrap().contains("Test"));
+
+        // Outputs added while latched are made safe straight away
+        let late = mock("late", false);
+        estop.register_output(late.clone());
+        assert!(late.made_safe.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_reset_requires_reason() {
+        let monitor = PumpSafetyMonitor::new();
+        let estop = EmergencyStop::new(monitor.clone(), None);
+        assert!(estop.reset("tester", "Cleared").is_err());
+
+        estop.trigger(TriggerSource::Overflow, "tank1 overflow");
+        assert!(monitor.can_start_pump("pump", crate::aog::pump_safety::PumpType::Circulation).is_err());
+        assert!(estop.reset("tester", "  ").is_err());
+
+        estop.button_pressed.store(true, Ordering::SeqCst);
+        assert!(estop.reset("tester", "Cleared").is_err());
+        estop.button_pressed.store(false, Ordering::SeqCst);
+
+        estop.reset("tester", "Drained tank").unwrap();
+        assert!(!estop.is_active());
+        assert!(monitor.can_start_pump("pump", crate::aog::pump_safety::PumpType::Circulation).is_ok());
+
+        match estop.status().last_reset {
+            Some(EStopEvent::Reset { by, reason, .. }) => {
+                assert_eq!(by, "tester");
+                assert_eq!(reason, "Drained tank");
+            }
+            other => panic!("unexpected last reset {:?}", other),
+        }
+        assert_eq!(estop.events().len(), 2);
+    }
+
+    #[test]
+    fn test_button_trips_once_per_press() {
+        let estop = EmergencyStop::new(PumpSafetyMonitor::new(), None);
+        estop.button_changed(5, true);
+        estop.button_changed(5, true);
+        assert!(estop.is_active());
+        assert_eq!(estop.events().len(), 1);
+
+        // Held down, the latch can't be released
+        assert!(estop.reset("tester", "Cleared").is_err());
+        estop.button_changed(5, false);
+        estop.reset("tester", "Cleared").unwrap();
+
+        estop.button_changed(5, true);
+        assert!(estop.is_active());
+        assert_eq!(estop.events().len(), 3);
+    }
+
+    #[test]
+    fn test_event_log_persistence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("estop.json");
+        let path = path.to_str().unwrap();
+
+        let estop = EmergencyStop::new(PumpSafetyMonitor::new(), Some(path));
+        estop.trigger(TriggerSource::Button, "Pressed");
+        estop.reset("tester", "Checked").unwrap();
+
+        let reloaded = EmergencyStop::new(PumpSafetyMonitor::new(), Some(path));
+        assert_eq!(reloaded.events(), estop.events());
+    }
+}
@@ -5,7 +5,6 @@ use serde::{Serialize, Deserialize};
 use crate::aog::error::AogError;
 
 const MAX_ERROR_HISTORY: usize = 100;
-const MAX_ERRORS_PER_MODULE: usize = 50;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorEntry {
@@ -63,6 +62,12 @@ pub struct ErrorMonitor {
     startup_time: SystemTime,
 }
 
+impl Default for ErrorMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ErrorMonitor {
     pub fn new() -> Self {
         ErrorMonitor {
@@ -200,11 +205,11 @@ impl ErrorMonitor {
         }
         
         let mut most_common_errors: Vec<(String, u64)> = error_counts.into_iter().collect();
-        most_common_errors.sort_by(|a, b| b.1.cmp(&a.1));
+        most_common_errors.sort_by_key(|e| std::cmp::Reverse(e.1));
         most_common_errors.truncate(5);
         
         let mut modules_with_errors: Vec<(String, u64)> = module_counts.clone().into_iter().collect();
-        modules_with_errors.sort_by(|a, b| b.1.cmp(&a.1));
+        modules_with_errors.sort_by_key(|m| std::cmp::Reverse(m.1));
         
         // Determine module status
         let module_status: Vec<ModuleStatus> = modules_with_errors.iter()
@@ -20,5 +20,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+pub mod input;
+pub mod output;
+pub mod pwm;
 pub mod status;
-pub mod thread;
\ No newline at end of file
@@ -0,0 +1,395 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// GPIO Input Module - Switches and interlocks watched with edge interrupts,
+// debounced, with a per-pin event history and subscribers for edge events
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rppal::gpio::{Gpio, InputPin, Level, Trigger};
+use serde::{Deserialize, Serialize};
+
+use crate::{Config, GpioInputConfig, InputPull};
+
+const MAX_EVENTS: usize = 100;
+const MAX_BCM_PIN: u8 = 27;
+// How often the pins are read back in case an edge interrupt was missed
+const RESYNC_SECS: f64 = 1.0;
+
+fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+/// A debounced level change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub name: String,
+    pub pin: u8,
+    pub high: bool,
+    pub time: f64,  // Unix time of the edge that started the new level
+    pub bounces: u32,  // Raw edges since the previous event, more than one means the contact chattered
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpioInputStatus {
+    pub name: String,
+    pub pin: u8,
+    pub pull: InputPull,
+    pub debounce_ms: u64,
+    pub high: Option<bool>,  // Debounced level, None until it is known
+    pub raw_edges: u64,
+    pub events: u64,
+    pub last_event: Option<f64>,
+}
+
+struct MonitoredInput {
+    config: GpioInputConfig,
+    // Held so the interrupt stays registered, and read back to resync
+    pin: Option<InputPin>,
+    high: Option<bool>,
+    pending: Option<(bool, f64)>,
+    raw_edges: u64,
+    bounces: u32,
+    events: u64,
+    history: VecDeque<InputEvent>,
+}
+
+impl MonitoredInput {
+    fn edge(&mut self, high: bool, time: f64) {
+        self.raw_edges += 1;
+        self.bounces += 1;
+        self.pending = Some((high, time));
+    }
+
+    fn deadline(&self) -> Option<f64> {
+        self.pending.map(|(_, time)| time + self.config.debounce_ms as f64 / 1000.0)
+    }
+
+    fn status(&self) -> GpioInputStatus {
+        GpioInputStatus {
+            name: self.config.name.clone(),
+            pin: self.config.pin,
+            pull: self.config.pull,
+            debounce_ms: self.config.debounce_ms,
+            high: self.high,
+            raw_edges: self.raw_edges,
+            events: self.events,
+            last_event: self.history.back().map(|event| event.time),
+        }
+    }
+}
+
+pub struct InputMonitor {
+    inputs: Mutex<Vec<MonitoredInput>>,
+    subscribers: Mutex<Vec<Sender<InputEvent>>>,
+    samples: Mutex<Option<Sender<(u8, bool, f64)>>>,
+}
+
+impl InputMonitor {
+    pub fn new() -> Self {
+        InputMonitor { inputs: Mutex::new(Vec::new()), subscribers: Mutex::new(Vec::new()), samples: Mutex::new(None) }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<MonitoredInput>> {
+        self.inputs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn matches(input: &MonitoredInput, target: &str) -> bool {
+        input.config.name == target || input.config.pin.to_string() == target
+    }
+
+    /// Add an input with its level when it was opened. Names and pins must be unique.
+    fn insert(inputs: &mut Vec<MonitoredInput>, config: GpioInputConfig, pin: Option<InputPin>, high: Option<bool>) -> Result<(), String> {
+        if let Some(existing) = inputs.iter().find(|i| i.config.name == config.name || i.config.pin == config.pin) {
+            return Err(format!("GPIO {} is already input '{}'", existing.config.pin, existing.config.name));
+        }
+        inputs.push(MonitoredInput {
+            config,
+            pin,
+            high,
+            pending: None,
+            raw_edges: 0,
+            bounces: 0,
+            events: 0,
+            history: VecDeque::new(),
+        });
+        Ok(())
+    }
+
+    /// Record a raw edge, which becomes an event once it has held for the debounce time
+    pub fn sample(&self, pin: u8, high: bool, time: f64) {
+        if let Some(input) = self.lock().iter_mut().find(|input| input.config.pin == pin) {
+            input.edge(high, time);
+        }
+    }
+
+    /// Read every open pin back and treat a level that differs from the
+    /// debounced one as an edge, in case an interrupt was missed
+    pub fn resync(&self, time: f64) {
+        for input in self.lock().iter_mut() {
+            let high = match input.pin.as_ref() {
+                Some(pin) => pin.is_high(),
+                None => continue,
+            };
+            if input.pending.is_none() && input.high != Some(high) {
+                log::warn!("GPIO input '{}' reads {} without an edge, resyncing", input.config.name, if high { "high" } else { "low" });
+                input.edge(high, time);
+            }
+        }
+    }
+
+    /// Publish the edges that have held for their debounce time. Returns when
+    /// the next pending edge settles.
+    pub fn settle(&self, time: f64) -> Option<f64> {
+        let mut events = Vec::new();
+        let mut inputs = self.lock();
+        for input in inputs.iter_mut() {
+            match (input.pending, input.deadline()) {
+                (Some((high, since)), Some(deadline)) if deadline <= time => {
+                    input.pending = None;
+                    if input.high == Some(high) {
+                        continue;
+                    }
+                    input.high = Some(high);
+                    let event = InputEvent {
+                        name: input.config.name.clone(),
+                        pin: input.config.pin,
+                        high,
+                        time: since,
+                        bounces: input.bounces,
+                    };
+                    input.bounces = 0;
+                    input.events += 1;
+                    input.history.push_back(event.clone());
+                    if input.history.len() > MAX_EVENTS {
+                        input.history.pop_front();
+                    }
+                    events.push(event);
+                }
+                _ => {}
+            }
+        }
+        let next = inputs.iter().filter_map(|input| input.deadline()).reduce(f64::min);
+        drop(inputs);
+
+        if !events.is_empty() {
+            let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+            for event in events {
+                log::debug!("GPIO input '{}' went {}", event.name, if event.high { "high" } else { "low" });
+                subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+            }
+        }
+        next
+    }
+
+    /// Receive every debounced edge from now on
+    pub fn subscribe(&self) -> Receiver<InputEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(tx);
+        rx
+    }
+
+    /// Debounced level of an input, by name or BCM pin
+    pub fn level(&self, target: &str) -> Option<bool> {
+        self.lock().iter().find(|input| Self::matches(input, target)).and_then(|input| input.high)
+    }
+
+    pub fn is_watched(&self, pin: u8) -> bool {
+        self.lock().iter().any(|input| input.config.pin == pin)
+    }
+
+    pub fn status(&self) -> Vec<GpioInputStatus> {
+        self.lock().iter().map(|input| input.status()).collect()
+    }
+
+    /// Events for an input, oldest first
+    pub fn history(&self, target: &str) -> Option<Vec<InputEvent>> {
+        self.lock().iter().find(|input| Self::matches(input, target)).map(|input| input.history.iter().cloned().collect())
+    }
+
+    /// Open an input and start delivering its edges to the dispatcher
+    pub fn watch(&'static self, config: GpioInputConfig) -> Result<(), String> {
+        if config.pin > MAX_BCM_PIN {
+            return Err(format!("Invalid GPIO pin {} for input '{}'", config.pin, config.name));
+        }
+        // Held until the input is added, so two callers can't open the same pin
+        let mut inputs = self.lock();
+        if inputs.iter().any(|i| i.config.name == config.name || i.config.pin == config.pin) {
+            return Err(format!("GPIO {} or input '{}' is already watched", config.pin, config.name));
+        }
+
+        let gpio = Gpio::new().map_err(|e| format!("GPIO unavailable: {}", e))?;
+        let pin = gpio.get(config.pin).map_err(|e| format!("Failed to get GPIO pin {}: {}", config.pin, e))?;
+        let mut input = match config.pull {
+            InputPull::Off => pin.into_input(),
+            InputPull::Up => pin.into_input_pullup(),
+            InputPull::Down => pin.into_input_pulldown(),
+        };
+        let high = input.is_high();
+        let number = config.pin;
+        let sender = self.dispatcher();
+        input.set_async_interrupt(Trigger::Both, move |level| {
+            let _ = sender.send((number, level == Level::High, now()));
+        }).map_err(|e| format!("Failed to set interrupt on GPIO {}: {}", number, e))?;
+
+        log::info!("Watching GPIO input '{}' on GPIO {} ({})", config.name, number, if high { "high" } else { "low" });
+        Self::insert(&mut inputs, config, Some(input), Some(high))
+    }
+
+    /// Sender for raw edges, starting the thread that debounces them on first use
+    fn dispatcher(&'static self) -> Sender<(u8, bool, f64)> {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sender) = samples.as_ref() {
+            return sender.clone();
+        }
+        let (tx, rx) = mpsc::channel::<(u8, bool, f64)>();
+        let _ = thread::Builder::new().name("gpio_input_thread".to_string()).spawn(move || {
+            let mut next: Option<f64> = None;
+            let mut resync_at = now() + RESYNC_SECS;
+            loop {
+                // Sleep until an edge arrives, a pending one settles or the pins are due a resync
+                let deadline = next.map_or(resync_at, |next| next.min(resync_at));
+                match rx.recv_timeout(Duration::from_secs_f64((deadline - now()).max(0.0))) {
+                    Ok((pin, high, time)) => self.sample(pin, high, time),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if now() >= resync_at {
+                    self.resync(now());
+                    resync_at = now() + RESYNC_SECS;
+                }
+                next = self.settle(now());
+            }
+        });
+        *samples = Some(tx.clone());
+        tx
+    }
+}
+
+impl Default for InputMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref INPUTS: InputMonitor = InputMonitor::new();
+}
+
+/// Debounced level of a BCM pin, watching it without a pull resistor if it
+/// isn't configured
+pub fn is_high(pin: u8) -> Result<bool, String> {
+    if !INPUTS.is_watched(pin) {
+        let config = GpioInputConfig { name: format!("gpio_{}", pin), pin, pull: InputPull::Off, debounce_ms: 50 };
+        // Another caller may have started watching it in the meantime
+        if let Err(e) = INPUTS.watch(config) {
+            if !INPUTS.is_watched(pin) {
+                return Err(e);
+            }
+        }
+    }
+    INPUTS.level(&pin.to_string()).ok_or_else(|| format!("GPIO {} level unknown", pin))
+}
+
+/// Watch the configured inputs
+pub fn init(config: &Config) {
+    for input in config.gpio_inputs.clone().unwrap_or_default() {
+        let name = input.name.clone();
+        if let Err(e) = INPUTS.watch(input) {
+            log::error!("GPIO input '{}' unavailable: {}", name, e);
+        }
+    }
+}
+
+/// Handle `gpio inputs` and `gpio input <name|pin>` console commands
+pub fn run_command(command: &str) -> String {
+    match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["gpio", "inputs"] => {
+            let inputs = INPUTS.status();
+            if inputs.is_empty() {
+                return "No GPIO inputs".to_string();
+            }
+            inputs.iter().map(|status| {
+                let level = match status.high {
+                    Some(true) => "high",
+                    Some(false) => "low",
+                    None => "unknown",
+                };
+                format!("{} (GPIO {}, pull {:?}): {} - {} events from {} raw edges",
+                    status.name, status.pin, status.pull, level, status.events, status.raw_edges)
+            }).collect::<Vec<_>>().join("\n")
+        }
+        ["gpio", "input", target] => match INPUTS.history(target) {
+            Some(events) if events.is_empty() => format!("No events for GPIO input '{}'", target),
+            Some(events) => events.iter().map(|event| format!("{:.3} {} {} ({} raw edges)",
+                event.time, event.name, if event.high { "high" } else { "low" }, event.bounces))
+                .collect::<Vec<_>>().join("\n"),
+            None => format!("Unknown GPIO input '{}'", target),
+        },
+        _ => "Usage: gpio inputs | gpio input <name|pin>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> InputMonitor {
+        let monitor = InputMonitor::new();
+        let config = GpioInputConfig { name: "float_switch".to_string(), pin: 16, pull: InputPull::Off, debounce_ms: 50 };
+        InputMonitor::insert(&mut monitor.lock(), config, None, Some(false)).unwrap();
+        monitor
+    }
+
+    #[test]
+    fn test_debounce_chatter() {
+        let monitor = monitor();
+        let events = monitor.subscribe();
+
+        // Chatter settles high after the last bounce holds for 50ms
+        monitor.sample(16, true, 10.000);
+        monitor.sample(16, false, 10.010);
+        monitor.sample(16, true, 10.020);
+        let next = monitor.settle(10.050).unwrap();
+        assert!((next - 10.070).abs() < 1e-9);
+        assert_eq!(monitor.level("float_switch"), Some(false));
+        assert_eq!(monitor.settle(10.080), None);
+        assert_eq!(monitor.level("16"), Some(true));
+
+        let event = events.try_recv().unwrap();
+        assert!(event.high);
+        assert_eq!(event.time, 10.020);
+        assert_eq!(event.bounces, 3);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_glitch_back_to_same_level_is_ignored() {
+        let monitor = monitor();
+        monitor.sample(16, true, 1.0);
+        monitor.sample(16, false, 1.01);
+        monitor.settle(2.0);
+        assert_eq!(monitor.level("16"), Some(false));
+        assert_eq!(monitor.history("16"), Some(vec![]));
+        assert_eq!(monitor.status()[0].raw_edges, 2);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let monitor = monitor();
+        for i in 0..(MAX_EVENTS + 5) {
+            let time = i as f64;
+            monitor.sample(16, i % 2 == 0, time);
+            monitor.settle(time + 0.5);
+        }
+        let history = monitor.history("float_switch").unwrap();
+        assert_eq!(history.len(), MAX_EVENTS);
+        assert_eq!(history[0].time, 5.0);
+        assert_eq!(monitor.status()[0].events, (MAX_EVENTS + 5) as u64);
+        assert!(InputMonitor::insert(&mut monitor.lock(), GpioInputConfig { name: "other".to_string(), pin: 16, pull: InputPull::Up, debounce_ms: 0 }, None, None).is_err());
+    }
+}
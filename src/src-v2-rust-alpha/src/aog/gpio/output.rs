@@ -0,0 +1,354 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// GPIO Output Module - Named on/off GPIO outputs that are switched on command,
+// held off outside the photo cycle or while their safety interlock is open
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use chrono::{Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::aog::actuators::{ActuatorOutput, ActuatorState, ACTUATORS};
+use crate::aog::gpio::input;
+use crate::aog::pump_safety::SAFETY_MONITOR;
+use crate::aog::rules;
+use crate::{Config, GpioOutputConfig};
+
+const CHECK_INTERVAL_SECS: u64 = 1;
+const MAX_BCM_PIN: u8 = 27;
+
+/// Where output changes are written and interlocks read
+pub trait OutputSink: Send + Sync {
+    fn write(&self, pin: u8, on: bool) -> Result<(), String>;
+    /// Whether an interlock input reads high (closed)
+    fn interlock_closed(&self, pin: u8) -> Result<bool, String>;
+}
+
+/// Drives outputs through the actuator registry, so they are verified and
+/// held off by the emergency stop like every other output
+pub struct ActuatorSink;
+
+impl OutputSink for ActuatorSink {
+    fn write(&self, pin: u8, on: bool) -> Result<(), String> {
+        ACTUATORS.set_output(ActuatorOutput::Gpio { pin }, ActuatorState::from_bool(on)).map(|_| ())
+    }
+
+    fn interlock_closed(&self, pin: u8) -> Result<bool, String> {
+        input::is_high(pin)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpioOutputStatus {
+    pub name: String,
+    pub pin: u8,
+    pub requested: bool,  // Switched on by a command
+    pub on: Option<bool>,  // Last state written, None before the first write
+    pub held_off: Option<String>,  // Why a requested output is off
+    pub photo_cycle: bool,
+    pub safety_gpio_pin: Option<u8>,
+    pub last_changed: Option<String>,
+    pub last_error: Option<String>,
+}
+
+struct ManagedOutput {
+    config: GpioOutputConfig,
+    requested: bool,
+    on: Option<bool>,
+    held_off: Option<String>,
+    last_changed: Option<String>,
+    last_error: Option<String>,
+}
+
+impl ManagedOutput {
+    fn new(config: GpioOutputConfig) -> Self {
+        ManagedOutput { config, requested: false, on: None, held_off: None, last_changed: None, last_error: None }
+    }
+
+    fn status(&self) -> GpioOutputStatus {
+        GpioOutputStatus {
+            name: self.config.name.clone(),
+            pin: self.config.pin,
+            requested: self.requested,
+            on: self.on,
+            held_off: self.held_off.clone(),
+            photo_cycle: self.config.photo_cycle,
+            safety_gpio_pin: self.config.safety_gpio_pin,
+            last_changed: self.last_changed.clone(),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+pub struct GpioOutputManager {
+    outputs: Mutex<Vec<ManagedOutput>>,
+    photo_cycle: Mutex<(u8, u8)>,
+    sink: Box<dyn OutputSink>,
+}
+
+impl GpioOutputManager {
+    pub fn new(sink: Box<dyn OutputSink>) -> Self {
+        GpioOutputManager { outputs: Mutex::new(Vec::new()), photo_cycle: Mutex::new((6, 24)), sink }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ManagedOutput>> {
+        self.outputs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_photo_cycle(&self, start: u8, end: u8) {
+        *self.photo_cycle.lock().unwrap_or_else(|e| e.into_inner()) = (start, end);
+    }
+
+    /// Add a named output and drive it off. Names and pins must be unique.
+    pub fn add(&self, config: GpioOutputConfig, hour: u8) -> Result<GpioOutputStatus, String> {
+        if config.pin > MAX_BCM_PIN || config.safety_gpio_pin.is_some_and(|pin| pin > MAX_BCM_PIN || pin == config.pin) {
+            return Err(format!("Invalid pins for GPIO output '{}'", config.name));
+        }
+        let mut outputs = self.lock();
+        if let Some(existing) = outputs.iter().find(|o| o.config.name == config.name || o.config.pin == config.pin) {
+            return Err(format!("GPIO {} is already output '{}'", existing.config.pin, existing.config.name));
+        }
+        let mut output = ManagedOutput::new(config);
+        self.apply(&mut output, hour);
+        let status = output.status();
+        outputs.push(output);
+        Ok(status)
+    }
+
+    /// Why an output that was switched on must stay off, if it must
+    fn hold_reason(&self, config: &GpioOutputConfig, hour: u8) -> Option<String> {
+        let (start, end) = *self.photo_cycle.lock().unwrap_or_else(|e| e.into_inner());
+        if config.photo_cycle && !rules::in_window(start, end, hour) {
+            return Some(format!("Outside photo cycle {}-{}", start, end));
+        }
+        match config.safety_gpio_pin.map(|pin| (pin, self.sink.interlock_closed(pin))) {
+            Some((pin, Ok(false))) => Some(format!("Safety interlock on GPIO {} is open", pin)),
+            Some((pin, Err(e))) => Some(format!("Safety interlock on GPIO {} unreadable: {}", pin, e)),
+            _ => None,
+        }
+    }
+
+    /// Write the output's state if it differs from what was last written
+    fn apply(&self, output: &mut ManagedOutput, hour: u8) {
+        output.held_off = if output.requested { self.hold_reason(&output.config, hour) } else { None };
+        let on = output.requested && output.held_off.is_none();
+        if output.on == Some(on) {
+            return;
+        }
+        match self.sink.write(output.config.pin, on) {
+            Ok(()) => {
+                if let Some(reason) = output.held_off.as_ref() {
+                    log::warn!("GPIO output '{}' held off: {}", output.config.name, reason);
+                }
+                output.on = Some(on);
+                output.last_changed = Some(Local::now().to_rfc3339());
+                output.last_error = None;
+            }
+            Err(e) => {
+                if output.last_error.as_ref() != Some(&e) {
+                    log::error!("GPIO output '{}': {}", output.config.name, e);
+                }
+                output.last_error = Some(e);
+            }
+        }
+    }
+
+    /// Switch an output, by name or BCM pin. A pin that isn't configured is
+    /// added as an output without a photo cycle or interlock.
+    pub fn set(&self, target: &str, on: bool, hour: u8) -> Result<GpioOutputStatus, String> {
+        let known = self.lock().iter().any(|o| o.config.name == target || o.config.pin.to_string() == target);
+        if !known {
+            let pin = target.parse::<u8>().map_err(|_| format!("Unknown GPIO output '{}'", target))?;
+            let config = GpioOutputConfig { name: format!("gpio_{}", pin), pin, photo_cycle: false, safety_gpio_pin: None };
+            self.add(config, hour)?;
+        }
+
+        let mut outputs = self.lock();
+        let output = outputs.iter_mut()
+            .find(|o| o.config.name == target || o.config.pin.to_string() == target)
+            .ok_or_else(|| format!("Unknown GPIO output '{}'", target))?;
+        output.requested = on;
+        self.apply(output, hour);
+        match &output.last_error {
+            Some(e) => Err(e.clone()),
+            None => Ok(output.status()),
+        }
+    }
+
+    /// Re-check the photo cycle and interlocks. The emergency stop cancels
+    /// every request, so nothing comes back on when it is reset.
+    pub fn check(&self, hour: u8, estop: bool) {
+        for output in self.lock().iter_mut() {
+            if estop && output.requested {
+                log::warn!("Emergency stop active - GPIO output '{}' switched off", output.config.name);
+                output.requested = false;
+            }
+            self.apply(output, hour);
+        }
+    }
+
+    pub fn status(&self) -> Vec<GpioOutputStatus> {
+        self.lock().iter().map(|output| output.status()).collect()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref GPIO_OUTPUTS: GpioOutputManager = GpioOutputManager::new(Box::new(ActuatorSink));
+}
+
+fn current_hour() -> u8 {
+    Local::now().hour() as u8
+}
+
+/// Switch an output by name or BCM pin at the current hour
+pub fn set(target: &str, on: bool) -> Result<GpioOutputStatus, String> {
+    GPIO_OUTPUTS.set(target, on, current_hour())
+}
+
+/// Register the configured outputs off and start re-checking their photo
+/// cycle and interlocks
+pub fn init(config: &Config) {
+    GPIO_OUTPUTS.set_photo_cycle(config.photo_cycle_start, config.photo_cycle_end);
+    let outputs = config.gpio_outputs.clone().unwrap_or_default();
+    if outputs.is_empty() {
+        return;
+    }
+
+    for output in outputs {
+        let (name, pin) = (output.name.clone(), output.pin);
+        if let Err(e) = ACTUATORS.register(&name, ActuatorOutput::Gpio { pin }, ActuatorState::Off) {
+            log::info!("GPIO output '{}': {}", name, e);
+        }
+        if let Err(e) = GPIO_OUTPUTS.add(output, current_hour()) {
+            log::error!("Skipping GPIO output '{}': {}", name, e);
+        }
+    }
+
+    let _ = thread::Builder::new().name("gpio_output_thread".to_string()).spawn(|| loop {
+        GPIO_OUTPUTS.check(current_hour(), SAFETY_MONITOR.is_emergency_stop_active());
+        thread::sleep(Duration::from_secs(CHECK_INTERVAL_SECS));
+    });
+}
+
+fn format_status(status: &GpioOutputStatus) -> String {
+    let state = match status.on {
+        Some(true) => "on",
+        Some(false) => "off",
+        None => "unknown",
+    };
+    let mut line = format!("{} (GPIO {}): {}", status.name, status.pin, state);
+    if let Some(reason) = &status.held_off {
+        line.push_str(&format!(" - held off: {}", reason));
+    }
+    if let Some(error) = &status.last_error {
+        line.push_str(&format!(" - error: {}", error));
+    }
+    line
+}
+
+/// Handle `gpio on|off|outputs` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["gpio", "outputs"] => {
+            let outputs = GPIO_OUTPUTS.status();
+            if outputs.is_empty() {
+                return "No GPIO outputs".to_string();
+            }
+            outputs.iter().map(format_status).collect::<Vec<_>>().join("\n")
+        }
+        ["gpio", state @ ("on" | "off"), target, ..] => {
+            match set(target, *state == "on") {
+                Ok(status) => format_status(&status),
+                Err(e) => format!("Command '{}' failed: {}", command, e),
+            }
+        }
+        _ => "Usage: gpio outputs | gpio on <pin|name> | gpio off <pin|name>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct MockSink {
+        writes: Mutex<Vec<(u8, bool)>>,
+        interlock: Mutex<bool>,
+    }
+
+    impl OutputSink for Arc<MockSink> {
+        fn write(&self, pin: u8, on: bool) -> Result<(), String> {
+            self.writes.lock().unwrap().push((pin, on));
+            Ok(())
+        }
+
+        fn interlock_closed(&self, _pin: u8) -> Result<bool, String> {
+            Ok(*self.interlock.lock().unwrap())
+        }
+    }
+
+    fn manager() -> (GpioOutputManager, Arc<MockSink>) {
+        let sink = Arc::new(MockSink::default());
+        *sink.interlock.lock().unwrap() = true;
+        let manager = GpioOutputManager::new(Box::new(Arc::clone(&sink)));
+        manager.set_photo_cycle(6, 22);
+        (manager, sink)
+    }
+
+    fn light(safety_gpio_pin: Option<u8>) -> GpioOutputConfig {
+        GpioOutputConfig { name: "light".to_string(), pin: 27, photo_cycle: true, safety_gpio_pin }
+    }
+
+    #[test]
+    fn test_writes_only_on_change() {
+        let (manager, sink) = manager();
+        manager.add(light(None), 12).unwrap();
+        manager.set("light", true, 12).unwrap();
+        manager.set("27", true, 12).unwrap();
+        manager.check(12, false);
+        manager.set("light", false, 12).unwrap();
+        assert_eq!(*sink.writes.lock().unwrap(), vec![(27, false), (27, true), (27, false)]);
+        assert!(manager.add(light(None), 12).is_err());
+    }
+
+    #[test]
+    fn test_photo_cycle_and_interlock() {
+        let (manager, sink) = manager();
+        manager.add(light(Some(5)), 12).unwrap();
+        manager.set("light", true, 12).unwrap();
+
+        // Off overnight, back on in the morning
+        manager.check(23, false);
+        assert_eq!(manager.status()[0].on, Some(false));
+        assert!(manager.status()[0].held_off.as_deref().unwrap().contains("photo cycle"));
+        manager.check(7, false);
+        assert_eq!(manager.status()[0].on, Some(true));
+
+        // Open interlock holds it off until closed
+        *sink.interlock.lock().unwrap() = false;
+        manager.check(8, false);
+        assert_eq!(manager.status()[0].on, Some(false));
+        *sink.interlock.lock().unwrap() = true;
+        manager.check(8, false);
+        assert_eq!(manager.status()[0].on, Some(true));
+    }
+
+    #[test]
+    fn test_emergency_stop_cancels_requests() {
+        let (manager, _) = manager();
+        let status = manager.set("17", true, 12).unwrap();
+        assert_eq!(status.name, "gpio_17");
+        assert_eq!(status.on, Some(true));
+        manager.check(12, true);
+        manager.check(12, false);
+        let status = &manager.status()[0];
+        assert!(!status.requested);
+        assert_eq!(status.on, Some(false));
+        assert!(manager.set("40", true, 12).is_err());
+        assert!(manager.set("fan", true, 12).is_err());
+    }
+}
@@ -0,0 +1,556 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// PWM Module - Named hardware and software PWM channels with soft start/stop
+// ramps, for dimmable grow lights and variable-speed pumps and fans
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{Local, Timelike};
+use rppal::gpio::{Gpio, OutputPin};
+use rppal::pwm::{Channel, Polarity, Pwm};
+use serde::{Deserialize, Serialize};
+
+use crate::aog::pump_safety::SAFETY_MONITOR;
+use crate::{Config, PwmChannelConfig, PwmMode, PwmSchedule};
+
+const TICK_MS: u64 = 100;
+const MINUTES_PER_DAY: f32 = 1440.0;
+
+/// Hardware PWM channel for a BCM pin, with the default pwm-2chan overlay
+pub fn hardware_channel(pin: u8) -> Option<Channel> {
+    match pin {
+        12 | 18 => Some(Channel::Pwm0),
+        13 | 19 => Some(Channel::Pwm1),
+        _ => None,
+    }
+}
+
+/// Drives a pin at a frequency and duty cycle (0.0 - 1.0)
+pub trait PwmBackend: Send {
+    fn write(&mut self, frequency_hz: f64, duty: f64) -> Result<(), String>;
+    fn kind(&self) -> &'static str;
+}
+
+struct HardwareBackend {
+    pwm: Pwm,
+}
+
+impl PwmBackend for HardwareBackend {
+    fn write(&mut self, frequency_hz: f64, duty: f64) -> Result<(), String> {
+        self.pwm.set_frequency(frequency_hz, duty).map_err(|e| format!("Hardware PWM write failed: {}", e))
+    }
+
+    fn kind(&self) -> &'static str {
+        "hardware"
+    }
+}
+
+impl Drop for HardwareBackend {
+    fn drop(&mut self) {
+        let _ = self.pwm.disable();
+    }
+}
+
+struct SoftwareBackend {
+    pin: OutputPin,
+    invert: bool,
+}
+
+impl PwmBackend for SoftwareBackend {
+    fn write(&mut self, frequency_hz: f64, duty: f64) -> Result<(), String> {
+        let duty = if self.invert { 1.0 - duty } else { duty };
+        self.pin.set_pwm_frequency(frequency_hz, duty).map_err(|e| format!("Software PWM write failed: {}", e))
+    }
+
+    fn kind(&self) -> &'static str {
+        "software"
+    }
+}
+
+impl Drop for SoftwareBackend {
+    fn drop(&mut self) {
+        let _ = self.pin.clear_pwm();
+        if self.invert { self.pin.set_high() } else { self.pin.set_low() }
+    }
+}
+
+fn open_hardware(config: &PwmChannelConfig) -> Result<Box<dyn PwmBackend>, String> {
+    let channel = hardware_channel(config.pin)
+        .ok_or_else(|| format!("GPIO {} has no hardware PWM", config.pin))?;
+    if PWM.hardware_in_use(channel) {
+        return Err(format!("Hardware PWM {:?} is already in use", channel));
+    }
+    let polarity = if config.invert { Polarity::Inverse } else { Polarity::Normal };
+    let pwm = Pwm::with_frequency(channel, config.frequency_hz, 0.0, polarity, true)
+        .map_err(|e| format!("Failed to open hardware PWM {:?}: {}", channel, e))?;
+    Ok(Box::new(HardwareBackend { pwm }))
+}
+
+fn open_software(config: &PwmChannelConfig) -> Result<Box<dyn PwmBackend>, String> {
+    let gpio = Gpio::new().map_err(|e| format!("Failed to initialize GPIO: {}", e))?;
+    let pin = gpio.get(config.pin).map_err(|e| format!("Failed to get GPIO pin {}: {}", config.pin, e))?.into_output();
+    let mut backend = SoftwareBackend { pin, invert: config.invert };
+    backend.write(config.frequency_hz, 0.0)?;
+    Ok(Box::new(backend))
+}
+
+fn open_backend(config: &PwmChannelConfig) -> Result<Box<dyn PwmBackend>, String> {
+    match config.mode {
+        PwmMode::Hardware => open_hardware(config),
+        PwmMode::Software => open_software(config),
+        PwmMode::Auto if hardware_channel(config.pin).is_some() => open_hardware(config).or_else(|e| {
+            log::warn!("PWM '{}': {}, falling back to software PWM", config.name, e);
+            open_software(config)
+        }),
+        PwmMode::Auto => open_software(config),
+    }
+}
+
+/// Level in percent for a scheduled channel at `minute` of the day, ramping
+/// up after the photo cycle starts and down before it ends
+pub fn schedule_level(schedule: &PwmSchedule, photo_cycle: (u8, u8), minute: f32) -> f32 {
+    let start = photo_cycle.0 as f32 * 60.0;
+    let end = photo_cycle.1 as f32 * 60.0;
+    // Equal start and end hours light the whole day
+    let length = (end - start).rem_euclid(MINUTES_PER_DAY);
+    let length = if length > 0.0 { length } else { MINUTES_PER_DAY };
+    let elapsed = (minute - start).rem_euclid(MINUTES_PER_DAY);
+    if elapsed >= length {
+        return 0.0;
+    }
+    let mut level: f32 = 1.0;
+    if schedule.sunrise_minutes > 0 {
+        level = level.min(elapsed / schedule.sunrise_minutes as f32);
+    }
+    if schedule.sunset_minutes > 0 {
+        level = level.min((length - elapsed) / schedule.sunset_minutes as f32);
+    }
+    schedule.max_percent.clamp(0.0, 100.0) * level.clamp(0.0, 1.0)
+}
+
+/// Move `current` towards `target`, taking `ramp_seconds` for the full range
+fn ramp(current: f32, target: f32, ramp_seconds: f32, dt: f32) -> f32 {
+    if ramp_seconds <= 0.0 {
+        return target;
+    }
+    let step = 100.0 * dt / ramp_seconds;
+    if current < target { (current + step).min(target) } else { (current - step).max(target) }
+}
+
+/// Duty cycle for a level, spreading levels above zero over min_percent..100
+fn duty(config: &PwmChannelConfig, percent: f32) -> f64 {
+    if percent <= 0.0 {
+        return 0.0;
+    }
+    let min = config.min_percent.clamp(0.0, 100.0) as f64;
+    (min + percent.clamp(0.0, 100.0) as f64 * (100.0 - min) / 100.0) / 100.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PwmChannelStatus {
+    pub name: String,
+    pub pin: u8,
+    pub backend: Option<String>,  // "hardware" or "software", None if the pin couldn't be opened
+    pub target: f32,
+    pub level: f32,
+    pub duty: f64,
+    pub scheduled: bool,  // Following its photo cycle schedule rather than a set level
+    pub last_error: Option<String>,
+}
+
+pub struct PwmChannel {
+    config: PwmChannelConfig,
+    backend: Option<Box<dyn PwmBackend>>,
+    target: f32,
+    level: f32,
+    manual: bool,
+    last_tick: Option<f64>,
+    written: Option<f64>,
+    last_error: Option<String>,
+}
+
+impl PwmChannel {
+    pub fn new(config: PwmChannelConfig, backend: Option<Box<dyn PwmBackend>>) -> Self {
+        PwmChannel { config, backend, target: 0.0, level: 0.0, manual: false, last_tick: None, written: None, last_error: None }
+    }
+
+    /// Ramp towards the set or scheduled level and write the duty if it changed.
+    /// The emergency stop drops the output at once, without a ramp.
+    pub fn tick(&mut self, now: f64, minute: f32, photo_cycle: (u8, u8), estop: bool) {
+        // The emergency stop cancels a manual level, so nothing ramps back
+        // up when it is reset; a schedule resumes
+        if estop && self.manual {
+            log::warn!("Emergency stop active - PWM channel '{}' released", self.config.name);
+            self.release();
+        }
+        if let (false, Some(schedule)) = (self.manual, self.config.schedule.as_ref()) {
+            self.target = schedule_level(schedule, photo_cycle, minute);
+        }
+        let dt = self.last_tick.map(|last| (now - last).max(0.0) as f32).unwrap_or(0.0);
+        self.last_tick = Some(now);
+        self.level = if estop { 0.0 } else { ramp(self.level, self.target, self.config.ramp_seconds, dt) };
+
+        let duty = duty(&self.config, self.level);
+        if self.written == Some(duty) {
+            return;
+        }
+        if let Some(backend) = self.backend.as_mut() {
+            match backend.write(self.config.frequency_hz, duty) {
+                Ok(()) => {
+                    self.written = Some(duty);
+                    self.last_error = None;
+                }
+                Err(e) => {
+                    if self.last_error.as_ref() != Some(&e) {
+                        log::error!("PWM '{}': {}", self.config.name, e);
+                    }
+                    self.last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Set a level, overriding any schedule
+    pub fn set(&mut self, percent: f32) {
+        self.manual = true;
+        self.target = percent.clamp(0.0, 100.0);
+    }
+
+    /// Return to the schedule, or off if there is none
+    pub fn release(&mut self) {
+        self.manual = false;
+        if self.config.schedule.is_none() {
+            self.target = 0.0;
+        }
+    }
+
+    /// Hardware PWM channel the output is generated on, if any
+    fn hardware(&self) -> Option<Channel> {
+        self.backend.as_ref()
+            .filter(|backend| backend.kind() == "hardware")
+            .and_then(|_| hardware_channel(self.config.pin))
+    }
+
+    pub fn status(&self) -> PwmChannelStatus {
+        PwmChannelStatus {
+            name: self.config.name.clone(),
+            pin: self.config.pin,
+            backend: self.backend.as_ref().map(|backend| backend.kind().to_string()),
+            target: self.target,
+            level: self.level,
+            duty: self.written.unwrap_or(0.0),
+            scheduled: !self.manual && self.config.schedule.is_some(),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+pub struct PwmManager {
+    channels: Mutex<Vec<PwmChannel>>,
+    photo_cycle: Mutex<(u8, u8)>,
+}
+
+impl PwmManager {
+    pub fn new() -> Self {
+        PwmManager { channels: Mutex::new(Vec::new()), photo_cycle: Mutex::new((6, 24)) }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<PwmChannel>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add a channel. Names, pins and hardware PWM channels must be unique,
+    /// as GPIO 12 and 18 share PWM0 and GPIO 13 and 19 share PWM1.
+    pub fn add(&self, channel: PwmChannel) -> Result<(), String> {
+        let mut channels = self.lock();
+        if let Some(existing) = channels.iter().find(|c| c.config.name == channel.config.name || c.config.pin == channel.config.pin) {
+            return Err(format!("GPIO {} is already PWM channel '{}'", existing.config.pin, existing.config.name));
+        }
+        if let Some(hardware) = channel.hardware() {
+            if let Some(existing) = channels.iter().find(|c| c.hardware() == Some(hardware)) {
+                return Err(format!("Hardware PWM {:?} is already used by channel '{}' on GPIO {}",
+                    hardware, existing.config.name, existing.config.pin));
+            }
+        }
+        channels.push(channel);
+        Ok(())
+    }
+
+    pub fn hardware_in_use(&self, hardware: Channel) -> bool {
+        self.lock().iter().any(|channel| channel.hardware() == Some(hardware))
+    }
+
+    pub fn set_photo_cycle(&self, start: u8, end: u8) {
+        *self.photo_cycle.lock().unwrap_or_else(|e| e.into_inner()) = (start, end);
+    }
+
+    fn with_channel<T>(&self, name: &str, f: impl FnOnce(&mut PwmChannel) -> T) -> Result<T, String> {
+        let mut channels = self.lock();
+        let channel = channels.iter_mut()
+            .find(|channel| channel.config.name == name)
+            .ok_or_else(|| format!("Unknown PWM channel '{}'", name))?;
+        Ok(f(channel))
+    }
+
+    /// Ramp a channel to `percent`, overriding its schedule
+    pub fn set(&self, name: &str, percent: f32) -> Result<PwmChannelStatus, String> {
+        if !percent.is_finite() {
+            return Err(format!("Invalid level {}", percent));
+        }
+        self.with_channel(name, |channel| {
+            channel.set(percent);
+            channel.status()
+        })
+    }
+
+    /// Return a channel to its schedule
+    pub fn release(&self, name: &str) -> Result<PwmChannelStatus, String> {
+        self.with_channel(name, |channel| {
+            channel.release();
+            channel.status()
+        })
+    }
+
+    pub fn status(&self) -> Vec<PwmChannelStatus> {
+        self.lock().iter().map(|channel| channel.status()).collect()
+    }
+
+    pub fn tick(&self, now: f64, minute: f32) {
+        let photo_cycle = *self.photo_cycle.lock().unwrap_or_else(|e| e.into_inner());
+        let estop = SAFETY_MONITOR.is_emergency_stop_active();
+        for channel in self.lock().iter_mut() {
+            channel.tick(now, minute, photo_cycle, estop);
+        }
+    }
+
+    /// Turn every channel off at once. Skipped if the channels are locked,
+    /// as this runs from the panic hook.
+    pub fn all_off(&self) {
+        if let Ok(mut channels) = self.channels.try_lock() {
+            for channel in channels.iter_mut() {
+                channel.target = 0.0;
+                channel.level = 0.0;
+                if let Some(backend) = channel.backend.as_mut() {
+                    let _ = backend.write(channel.config.frequency_hz, 0.0);
+                }
+                channel.written = Some(0.0);
+            }
+        }
+    }
+}
+
+impl Default for PwmManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref PWM: PwmManager = PwmManager::new();
+}
+
+/// Open the configured channels and start ramping them
+pub fn init(config: &Config) {
+    PWM.set_photo_cycle(config.photo_cycle_start, config.photo_cycle_end);
+    let channels = config.pwm_channels.clone().unwrap_or_default();
+    if channels.is_empty() {
+        return;
+    }
+
+    for channel_config in channels {
+        let backend = match open_backend(&channel_config) {
+            Ok(backend) => {
+                log::info!("PWM '{}' on GPIO {} ({})", channel_config.name, channel_config.pin, backend.kind());
+                Some(backend)
+            }
+            Err(e) => {
+                log::error!("PWM '{}' unavailable: {}", channel_config.name, e);
+                None
+            }
+        };
+        let name = channel_config.name.clone();
+        if let Err(e) = PWM.add(PwmChannel::new(channel_config, backend)) {
+            log::error!("Skipping PWM channel '{}': {}", name, e);
+        }
+    }
+
+    let _ = thread::Builder::new().name("pwm_thread".to_string()).spawn(|| loop {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        let time = Local::now();
+        PWM.tick(now, (time.hour() * 60 + time.minute()) as f32 + time.second() as f32 / 60.0);
+        thread::sleep(Duration::from_millis(TICK_MS));
+    });
+}
+
+/// Handle `pwm` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    let format = |status: &PwmChannelStatus| {
+        let mut line = format!("{} (GPIO {}, {}): {:.1}% -> {:.1}%{}",
+            status.name, status.pin, status.backend.as_deref().unwrap_or("unavailable"),
+            status.level, status.target, if status.scheduled { " scheduled" } else { "" });
+        if let Some(error) = &status.last_error {
+            line.push_str(&format!(" - error: {}", error));
+        }
+        line
+    };
+    let result = match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["pwm"] | ["pwm", "status"] => {
+            let channels = PWM.status();
+            if channels.is_empty() {
+                return "No PWM channels".to_string();
+            }
+            return channels.iter().map(format).collect::<Vec<_>>().join("\n");
+        }
+        ["pwm", "set", name, percent] => match percent.trim_end_matches('%').parse::<f32>() {
+            Ok(percent) => PWM.set(name, percent),
+            Err(_) => Err(format!("Invalid level '{}'", percent)),
+        },
+        ["pwm", "auto", name] => PWM.release(name),
+        _ => return "Usage: pwm [status] | pwm set <name> <percent> | pwm auto <name>".to_string(),
+    };
+    match result {
+        Ok(status) => format(&status),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct MockBackend(Arc<Mutex<Vec<f64>>>);
+
+    impl PwmBackend for MockBackend {
+        fn write(&mut self, _frequency_hz: f64, duty: f64) -> Result<(), String> {
+            self.0.lock().unwrap().push(duty);
+            Ok(())
+        }
+
+        fn kind(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    fn channel(ramp_seconds: f32, min_percent: f32, schedule: Option<PwmSchedule>) -> (PwmChannel, Arc<Mutex<Vec<f64>>>) {
+        let config = PwmChannelConfig {
+            name: "light".to_string(),
+            pin: 18,
+            mode: PwmMode::Auto,
+            frequency_hz: 1000.0,
+            invert: false,
+            ramp_seconds,
+            min_percent,
+            schedule,
+        };
+        let written = Arc::new(Mutex::new(Vec::new()));
+        (PwmChannel::new(config, Some(Box::new(MockBackend(Arc::clone(&written))))), written)
+    }
+
+    #[test]
+    fn test_soft_start_and_stop() {
+        let (mut pwm, written) = channel(10.0, 0.0, None);
+        pwm.tick(0.0, 0.0, (6, 24), false);
+        pwm.set(50.0);
+        pwm.tick(2.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().level, 20.0);
+        pwm.tick(4.0, 0.0, (6, 24), false);
+        pwm.tick(6.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().level, 50.0);
+
+        // Soft stop, cut short by the emergency stop
+        pwm.release();
+        pwm.tick(7.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().level, 40.0);
+        pwm.tick(7.1, 0.0, (6, 24), true);
+        assert_eq!(pwm.status().level, 0.0);
+        assert_eq!(*written.lock().unwrap(), vec![0.0, 0.2, 0.4, 0.5, 0.4, 0.0]);
+    }
+
+    #[test]
+    fn test_emergency_stop_cancels_manual_level() {
+        let (mut pwm, _) = channel(0.0, 0.0, None);
+        pwm.set(60.0);
+        pwm.tick(0.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().level, 60.0);
+        pwm.tick(1.0, 0.0, (6, 24), true);
+        pwm.tick(2.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().target, 0.0);
+        assert_eq!(pwm.status().level, 0.0);
+    }
+
+    #[test]
+    fn test_minimum_duty() {
+        let (mut pwm, _) = channel(0.0, 30.0, None);
+        pwm.set(50.0);
+        pwm.tick(0.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().duty, 0.65);
+        pwm.set(0.0);
+        pwm.tick(1.0, 0.0, (6, 24), false);
+        assert_eq!(pwm.status().duty, 0.0);
+    }
+
+    #[test]
+    fn test_sunrise_sunset_schedule() {
+        let schedule = PwmSchedule { sunrise_minutes: 30, sunset_minutes: 60, max_percent: 80.0 };
+        let at = |hour: f32| schedule_level(&schedule, (6, 22), hour * 60.0);
+        assert_eq!(at(5.9), 0.0);
+        assert_eq!(at(6.25), 40.0);
+        assert_eq!(at(12.0), 80.0);
+        assert_eq!(at(21.5), 40.0);
+        assert_eq!(at(22.0), 0.0);
+
+        // Overnight cycle
+        let overnight = |hour: f32| schedule_level(&schedule, (20, 8), hour * 60.0);
+        assert_eq!(overnight(2.0), 80.0);
+        assert_eq!(overnight(12.0), 0.0);
+
+        // Manual level overrides the schedule until released
+        let (mut pwm, _) = channel(0.0, 0.0, Some(schedule));
+        pwm.tick(0.0, 12.0 * 60.0, (6, 22), false);
+        assert!(pwm.status().scheduled);
+        assert_eq!(pwm.status().level, 80.0);
+        pwm.set(10.0);
+        pwm.tick(1.0, 12.0 * 60.0, (6, 22), false);
+        assert_eq!(pwm.status().level, 10.0);
+        pwm.release();
+        pwm.tick(2.0, 12.0 * 60.0, (6, 22), false);
+        assert_eq!(pwm.status().level, 80.0);
+    }
+
+    struct MockHardware;
+
+    impl PwmBackend for MockHardware {
+        fn write(&mut self, _frequency_hz: f64, _duty: f64) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn kind(&self) -> &'static str {
+            "hardware"
+        }
+    }
+
+    #[test]
+    fn test_hardware_channels() {
+        assert_eq!(hardware_channel(18), Some(Channel::Pwm0));
+        assert_eq!(hardware_channel(19), Some(Channel::Pwm1));
+        assert_eq!(hardware_channel(17), None);
+
+        // GPIO 12 and 18 share PWM0, so only one of them can use it
+        let manager = PwmManager::new();
+        let on_pin = |name: &str, pin: u8, backend: Box<dyn PwmBackend>| {
+            let (channel, _) = channel(0.0, 0.0, None);
+            PwmChannel::new(PwmChannelConfig { name: name.to_string(), pin, ..channel.config }, Some(backend))
+        };
+        manager.add(on_pin("light", 18, Box::new(MockHardware))).unwrap();
+        assert!(manager.hardware_in_use(Channel::Pwm0));
+        assert!(!manager.hardware_in_use(Channel::Pwm1));
+        assert!(manager.add(on_pin("fan", 12, Box::new(MockHardware))).is_err());
+        let (software, _) = channel(0.0, 0.0, None);
+        manager.add(on_pin("fan", 12, software.backend.unwrap())).unwrap();
+        manager.add(on_pin("pump", 19, Box::new(MockHardware))).unwrap();
+    }
+}
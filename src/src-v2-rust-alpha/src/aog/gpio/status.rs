@@ -18,17 +18,24 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-// gpio.rs - Retrieves the mode and logic level for each of the pins on
-// the 26-pin or 40-pin GPIO header, and displays the results in an ASCII table.
+// gpio.rs - Retrieves the mode, logic level and AOG role of each of the pins on
+// the 26-pin or 40-pin GPIO header, as data for the API and as an ASCII table.
 
 use std::error::Error;
 use std::fmt;
-use std::process;
+use std::fs;
 
 use rppal::gpio::Gpio;
 use rppal::system::{DeviceInfo, Model};
+use serde::{Deserialize, Serialize};
 
-enum PinType {
+use crate::aog::actuators::{ActuatorOutput, ACTUATORS};
+use crate::aog::gpio::input::INPUTS;
+use crate::{Config, InputPull, PidOutputConfig, PumpOutput, WaterLevelSensorType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinType {
     Gpio(u8),
     Ground,
     Power3v3,
@@ -92,6 +99,239 @@ const HEADER: [PinType; 40] = [
 const MAX_PINS_SHORT: usize = 26;
 const MAX_PINS_LONG: usize = 40;
 
+// The model name from the device tree, which identifies boards that rppal doesn't
+const DEVICE_TREE_MODEL: &str = "/proc/device-tree/model";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderLayout {
+    Pins26Rev1,  // Pi B Rev 1, with a few pins switched
+    Pins26,  // Pi A and B Rev 2
+    Pins40,  // Every later model with a header, including the Pi 5
+}
+
+impl HeaderLayout {
+    pub fn pins(&self) -> Vec<PinType> {
+        match self {
+            HeaderLayout::Pins26Rev1 => {
+                // The GPIO header on the earlier Pi models mostly overlaps with the first 26 pins of
+                // the 40-pin header on the newer models. A few pins are switched on the Pi B Rev 1.
+                let mut header_rev1 = HEADER;
+                header_rev1[2] = PinType::Gpio(0);
+                header_rev1[4] = PinType::Gpio(1);
+                header_rev1[12] = PinType::Gpio(21);
+                header_rev1[..MAX_PINS_SHORT].to_vec()
+            }
+            HeaderLayout::Pins26 => HEADER[..MAX_PINS_SHORT].to_vec(),
+            HeaderLayout::Pins40 => HEADER[..MAX_PINS_LONG].to_vec(),
+        }
+    }
+
+    fn for_model(model: Model) -> Option<HeaderLayout> {
+        match model {
+            Model::RaspberryPiBRev1 => Some(HeaderLayout::Pins26Rev1),
+            Model::RaspberryPiA | Model::RaspberryPiBRev2 => Some(HeaderLayout::Pins26),
+            Model::RaspberryPiAPlus
+            | Model::RaspberryPiBPlus
+            | Model::RaspberryPi2B
+            | Model::RaspberryPi3APlus
+            | Model::RaspberryPi3B
+            | Model::RaspberryPi3BPlus
+            | Model::RaspberryPi4B
+            | Model::RaspberryPi400
+            | Model::RaspberryPiZero
+            | Model::RaspberryPiZeroW => Some(HeaderLayout::Pins40),
+            _ => None,
+        }
+    }
+
+    /// Layout for a device tree model name, for boards newer than rppal
+    fn for_model_name(name: &str) -> Option<HeaderLayout> {
+        if name.starts_with("Raspberry Pi 5") || name.starts_with("Raspberry Pi 500") {
+            Some(HeaderLayout::Pins40)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinStatus {
+    pub physical: u8,
+    #[serde(rename = "type")]
+    pub pin_type: PinType,
+    pub bcm: Option<u8>,
+    pub mode: Option<String>,  // e.g. "IN", "OUT" or "ALT0", None if the pin couldn't be read
+    pub level: Option<u8>,
+    pub pull: Option<InputPull>,  // Configured pull; the SoC can't report it
+    pub roles: Vec<String>,  // What the configuration uses the pin for, more than one is a conflict
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpioStatus {
+    pub model: Option<String>,
+    pub layout: Option<HeaderLayout>,
+    pub pins: Vec<PinStatus>,
+    pub error: Option<String>,  // Why the model or pin states are missing
+}
+
+/// The pins the configuration assigns, with their role and any configured pull
+pub fn assignments(config: &Config) -> Vec<(u8, String, Option<InputPull>)> {
+    let mut pins: Vec<(u8, String, Option<InputPull>)> = Vec::new();
+    let mut add = |pin: u8, role: String, pull: Option<InputPull>| pins.push((pin, role, pull));
+    let gpio_output = |output: &PumpOutput| match output {
+        PumpOutput::Gpio { pin } => Some(*pin),
+        _ => None,
+    };
+
+    add(config.tank_one_to_two_pump_pin as u8, "tank_one_to_two_pump".to_string(), None);
+    add(config.uv_light_pin as u8, "uv_light".to_string(), None);
+    add(config.air_circulation_pin as u8, "air_circulation".to_string(), None);
+    add(crate::aog::pump::FLOAT_SWITCH_PIN, "pump_float_switch".to_string(), None);
+
+    if let Some(pump_config) = &config.pump_config {
+        for pump in &pump_config.pumps {
+            if let Some(pin) = gpio_output(&pump.output) {
+                add(pin, pump.id.clone(), None);
+            }
+        }
+        if let Some(pin) = pump_config.safety_gpio_pin {
+            add(pin, "pump_safety_interlock".to_string(), None);
+        }
+    }
+    if let Some(hvac) = &config.hvac_config {
+        let outputs = [("heater", &hvac.heater), ("cooler", &hvac.cooler), ("fan", &hvac.fan), ("humidifier", &hvac.humidifier)];
+        for (role, output) in outputs {
+            if let Some(pin) = output.as_ref().and_then(|output| gpio_output(&output.output)) {
+                add(pin, format!("climate:{}", role), None);
+            }
+        }
+    }
+    for pid in config.pid_loops.iter().flatten() {
+        if let PidOutputConfig::TimeProportional { output, .. } = &pid.output {
+            if let Some(pin) = gpio_output(output) {
+                add(pin, format!("pid:{}", pid.name), None);
+            }
+        }
+    }
+    for channel in config.pwm_channels.iter().flatten() {
+        add(channel.pin, format!("pwm:{}", channel.name), None);
+    }
+    for output in config.gpio_outputs.iter().flatten() {
+        add(output.pin, format!("output:{}", output.name), None);
+        if let Some(pin) = output.safety_gpio_pin {
+            add(pin, format!("interlock:{}", output.name), None);
+        }
+    }
+    for input in config.gpio_inputs.iter().flatten() {
+        add(input.pin, format!("input:{}", input.name), Some(input.pull));
+    }
+    if let Some(estop) = &config.emergency_stop_config {
+        if let Some(pin) = estop.button_pin {
+            add(pin, "estop_button".to_string(), Some(if estop.button_active_low { InputPull::Up } else { InputPull::Down }));
+        }
+    }
+    if let Some(pin) = config.sensor_kit_config.as_ref().and_then(|kit| kit.dht_gpio_pin) {
+        add(pin, "dht".to_string(), None);
+    }
+    if let Some(water) = &config.water_level_config {
+        if matches!(water.sensor_type, WaterLevelSensorType::Ultrasonic | WaterLevelSensorType::Float) {
+            let tanks = [
+                (water.tank1_sensor_pin, "tank1_level"),
+                (water.tank2_sensor_pin, "tank2_level"),
+                (water.tank1_echo_pin, "tank1_echo"),
+                (water.tank2_echo_pin, "tank2_echo"),
+            ];
+            for (pin, role) in tanks {
+                if let Some(pin) = pin {
+                    add(pin, role.to_string(), None);
+                }
+            }
+        }
+    }
+    pins
+}
+
+/// Build the per-pin model from a layout, a reader for the pins' mode and
+/// level, and the configured assignments
+fn collect(header: &[PinType], read: impl Fn(u8) -> (Option<String>, Option<u8>), assignments: &[(u8, String, Option<InputPull>)]) -> Vec<PinStatus> {
+    header.iter().enumerate().map(|(idx, pin_type)| {
+        let bcm = match pin_type {
+            PinType::Gpio(bcm) => Some(*bcm),
+            _ => None,
+        };
+        let (mode, level) = bcm.map(&read).unwrap_or((None, None));
+        let assigned: Vec<_> = assignments.iter().filter(|(pin, _, _)| Some(*pin) == bcm).collect();
+        PinStatus {
+            physical: idx as u8 + 1,
+            pin_type: *pin_type,
+            bcm,
+            mode,
+            level,
+            pull: assigned.iter().find_map(|(_, _, pull)| *pull),
+            roles: assigned.iter().map(|(_, role, _)| role.clone()).collect(),
+        }
+    }).collect()
+}
+
+/// Identify the board and its header layout
+fn detect() -> Result<(String, HeaderLayout), String> {
+    match DeviceInfo::new() {
+        Ok(info) => {
+            let model = info.model();
+            HeaderLayout::for_model(model)
+                .map(|layout| (model.to_string(), layout))
+                .ok_or_else(|| format!("No GPIO header information available for {}", model))
+        }
+        Err(e) => {
+            let name = fs::read_to_string(DEVICE_TREE_MODEL)
+                .map(|name| name.trim_end_matches('\0').trim().to_string())
+                .map_err(|_| format!("Unknown Raspberry Pi model: {}", e))?;
+            HeaderLayout::for_model_name(&name)
+                .map(|layout| (name.clone(), layout))
+                .ok_or_else(|| format!("No GPIO header information available for {}", name))
+        }
+    }
+}
+
+/// Mode, level and role of every header pin. Pins held open by AOG report
+/// the debounced level of a watched input or the state written to an output.
+pub fn status(config: &Config) -> GpioStatus {
+    let (model, layout) = match detect() {
+        Ok(detected) => detected,
+        Err(e) => return GpioStatus { model: None, layout: None, pins: Vec::new(), error: Some(e) },
+    };
+
+    let gpio = Gpio::new();
+    let error = gpio.as_ref().err().map(|e| format!("GPIO unavailable: {}", e));
+    let read = |bcm: u8| match gpio.as_ref().ok().and_then(|gpio| gpio.get(bcm).ok()) {
+        // Retrieve a Pin without converting it to an InputPin,
+        // OutputPin or IoPin, so we can check the pin's mode
+        // and level without affecting its state.
+        Some(pin) => (Some(format!("{}", pin.mode()).to_uppercase()), Some(pin.read() as u8)),
+        None => match INPUTS.level(&bcm.to_string()) {
+            Some(high) => (Some("IN".to_string()), Some(high as u8)),
+            // Actuator outputs are active low
+            None => match ACTUATORS.status().iter().find(|a| a.output == ActuatorOutput::Gpio { pin: bcm }).and_then(|a| a.commanded) {
+                Some(state) => (Some("OUT".to_string()), Some(!state.is_on() as u8)),
+                None => (None, None),
+            },
+        },
+    };
+
+    GpioStatus {
+        model: Some(model),
+        layout: Some(layout),
+        pins: collect(&layout.pins(), read, &assignments(config)),
+        error,
+    }
+}
+
+/// Status with the saved configuration's roles
+pub fn current() -> GpioStatus {
+    status(&Config::load(0).unwrap_or_else(|_| Config::new()))
+}
+
 fn format_pin(
     buf: &mut String,
     pin: usize,
@@ -99,7 +339,7 @@ fn format_pin(
     mode: impl fmt::Display,
     level: impl fmt::Display,
 ) {
-    if pin % 2 != 0 {
+    if !pin.is_multiple_of(2) {
         buf.push_str(&format!(
             "| {:>4} | {:<5} | {:>1} | {:>2} |",
             gpio, mode, level, pin
@@ -112,68 +352,93 @@ fn format_pin(
     }
 }
 
-fn print_header(header: &[PinType]) -> Result<(), Box<dyn Error>> {
-    let gpio = Gpio::new()?;
-
+/// The header as an ASCII table, followed by the pins AOG uses
+pub fn render_table(status: &GpioStatus) -> String {
     let mut buf = String::with_capacity(1600);
+    if let Some(model) = &status.model {
+        buf.push_str(&format!("{}\n", model));
+    }
 
-    buf.push_str("+------+-------+---+---------+---+-------+------+\n");
-    buf.push_str("| GPIO | Mode  | L |   Pin   | L | Mode  | GPIO |\n");
-    buf.push_str("+------+-------+---+----+----+---+-------+------+\n");
-
-    for (idx, pin_type) in header.iter().enumerate() {
-        match pin_type {
-            PinType::Gpio(bcm_gpio) => {
-                // Retrieve a Pin without converting it to an InputPin,
-                // OutputPin or IoPin, so we can check the pin's mode
-                // and level without affecting its state.
-                let pin = gpio.get(*bcm_gpio)?;
+    if !status.pins.is_empty() {
+        buf.push_str("+------+-------+---+---------+---+-------+------+\n");
+        buf.push_str("| GPIO | Mode  | L |   Pin   | L | Mode  | GPIO |\n");
+        buf.push_str("+------+-------+---+----+----+---+-------+------+\n");
 
-                format_pin(
+        for pin in &status.pins {
+            match (pin.bcm, pin.pin_type) {
+                (Some(bcm), _) => format_pin(
                     &mut buf,
-                    idx + 1,
-                    bcm_gpio,
-                    format!("{}", pin.mode()).to_uppercase(),
-                    pin.read() as u8,
-                );
-            }
-            _ => format_pin(&mut buf, idx + 1, "", pin_type, ""),
-        };
-    }
+                    pin.physical as usize,
+                    bcm,
+                    pin.mode.as_deref().unwrap_or("-"),
+                    pin.level.map(|level| level.to_string()).unwrap_or_else(|| "-".to_string()),
+                ),
+                (None, pin_type) => format_pin(&mut buf, pin.physical as usize, "", pin_type, ""),
+            };
+        }
 
-    buf.push_str("+------+-------+---+----+----+---+-------+------+\n");
+        buf.push_str("+------+-------+---+----+----+---+-------+------+\n");
+    }
 
-    print!("{}", buf);
+    for pin in status.pins.iter().filter(|pin| !pin.roles.is_empty()) {
+        buf.push_str(&format!("GPIO{} (pin {}): {}", pin.bcm.unwrap_or_default(), pin.physical, pin.roles.join(", ")));
+        if let Some(pull) = pin.pull {
+            buf.push_str(&format!(" [pull {:?}]", pull).to_lowercase());
+        }
+        if pin.roles.len() > 1 {
+            buf.push_str(" - CONFLICT");
+        }
+        buf.push('\n');
+    }
 
-    Ok(())
+    if let Some(error) = &status.error {
+        buf.push_str(&format!("{}\n", error));
+    }
+    buf
 }
 
 pub fn print() -> Result<(), Box<dyn Error>> {
-    // Identify the Pi's model, so we can print the appropriate GPIO header.
-    match DeviceInfo::new()?.model() {
-        Model::RaspberryPiBRev1 => {
-            // The GPIO header on the earlier Pi models mostly overlaps with the first 26 pins of
-            // the 40-pin header on the newer models. A few pins are switched on the Pi B Rev 1.
-            let mut header_rev1 = HEADER;
-            header_rev1[2] = PinType::Gpio(0);
-            header_rev1[4] = PinType::Gpio(1);
-            header_rev1[12] = PinType::Gpio(21);
-
-            print_header(&header_rev1[..MAX_PINS_SHORT])
-        }
-        Model::RaspberryPiA | Model::RaspberryPiBRev2 => print_header(&HEADER[..MAX_PINS_SHORT]),
-        Model::RaspberryPiAPlus
-        | Model::RaspberryPiBPlus
-        | Model::RaspberryPi2B
-        | Model::RaspberryPi3APlus
-        | Model::RaspberryPi3B
-        | Model::RaspberryPi3BPlus
-        | Model::RaspberryPi4B
-        | Model::RaspberryPiZero
-        | Model::RaspberryPiZeroW => print_header(&HEADER[..MAX_PINS_LONG]),
-        model => {
-            log::error!("Error: No GPIO header information available for {}", model);
-            process::exit(1);
-        }
-    }
-}
\ No newline at end of file
+    let status = current();
+    print!("{}", render_table(&status));
+    match status.layout {
+        Some(_) => Ok(()),
+        None => Err(status.error.unwrap_or_default().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_layouts() {
+        assert_eq!(HeaderLayout::Pins40.pins().len(), 40);
+        assert_eq!(HeaderLayout::Pins26.pins().len(), 26);
+        let rev1 = HeaderLayout::Pins26Rev1.pins();
+        assert_eq!(rev1[2], PinType::Gpio(0));
+        assert_eq!(rev1[12], PinType::Gpio(21));
+        assert_eq!(HeaderLayout::for_model_name("Raspberry Pi 5 Model B Rev 1.0"), Some(HeaderLayout::Pins40));
+        assert_eq!(HeaderLayout::for_model_name("Rock Pi 4"), None);
+    }
+
+    #[test]
+    fn test_pin_model_and_roles() {
+        let mut config = Config::new();
+        config.uv_light_pin = 17;  // Clashes with the transfer pump
+        let assignments = assignments(&config);
+        let pins = collect(&HeaderLayout::Pins40.pins(), |bcm| (Some("OUT".to_string()), Some(bcm % 2)), &assignments);
+
+        let pin11 = &pins[10];
+        assert_eq!((pin11.physical, pin11.bcm, pin11.level), (11, Some(17), Some(1)));
+        assert_eq!(pin11.roles, vec!["tank_one_to_two_pump".to_string(), "uv_light".to_string()]);
+        assert_eq!(pins[35].roles, vec!["pump_float_switch".to_string()]);
+        assert_eq!((pins[0].bcm, pins[0].mode.as_ref()), (None, None));
+
+        let status = GpioStatus { model: Some("test".to_string()), layout: Some(HeaderLayout::Pins40), pins, error: None };
+        let table = render_table(&status);
+        assert!(table.contains("|   17 | OUT   | 1 | 11 |"));
+        assert!(table.contains("GPIO17 (pin 11): tank_one_to_two_pump, uv_light - CONFLICT"));
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["pins"][10]["type"]["gpio"], 17);
+    }
+}
@@ -48,7 +48,6 @@ use serde::{Serialize, Deserialize};
 
 use crate::aog;
 use crate::Config;
-use crate::error::{recover_mutex_lock, safe_mutex_access};
 
 
 
@@ -96,11 +95,12 @@ pub fn init(){
     
     log::info!("Starting HTTPS server on {}", bind_addr);
     
-    rouille::Server::new_ssl(bind_addr, move |request| {
+    if let Ok(server) = rouille::Server::new_ssl(bind_addr, move |request| {
         {
             session::session(request, "SID", 3600, |session| {
                 let session_id: &str = session.id();
                 let mut session_authenticated = false;
+                let mut session_username = String::new();
                 let mut sessions :Vec<crate::Session> = Vec::new();
     
     
@@ -117,6 +117,7 @@ pub fn init(){
                 for session in &sessions{
                     if session.id.contains(session_id){
                         session_authenticated = true;
+                        session_username = session.username.clone();
                     } 
                 }
     
@@ -151,7 +152,8 @@ pub fn init(){
                     if input.input_username == *"admin" && password_valid {
                                             let session = crate::Session {
                                                 id: session_id.to_string(),
-                                                delta: 0
+                                                delta: 0,
+                                                username: input.input_username.clone(),
                                             };
                                             sessions.push(session);
                     
@@ -238,6 +240,443 @@ pub fn init(){
                     return response.with_additional_header("Access-Control-Allow-Origin", "*");
                 }
                 
+                // Pump job progress and outcomes
+                if request.url() == "/api/pump/jobs" {
+                    let jobs = crate::aog::pump_jobs::JOB_MANAGER.list();
+                    return Response::json(&jobs).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Submit a pump job (authenticated)
+                if request.url() == "/api/pump/jobs/submit" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        operation: String,
+                        tank: String,
+                        target: String,
+                        destination: Option<String>,
+                    }));
+                    
+                    let result = crate::aog::pump_jobs::JobKind::parse(
+                        &input.operation, &input.tank, &input.target, input.destination.as_deref())
+                        .and_then(|kind| crate::aog::pump_jobs::JOB_MANAGER.submit(kind));
+                    
+                    return match result {
+                        Ok(id) => Response::json(&crate::aog::pump_jobs::JOB_MANAGER.get(id)),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
+                // Cancel the running pump job (authenticated)
+                if request.url() == "/api/pump/jobs/cancel" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    return match crate::aog::pump_jobs::JOB_MANAGER.cancel(None) {
+                        Ok(id) => Response::json(&crate::aog::pump_jobs::JOB_MANAGER.get(id)),
+                        Err(e) => Response::text(e).with_status_code(409),
+                    };
+                }
+                
+                // Pump service status and maintenance history
+                if request.url() == "/api/pump/maintenance" {
+                    let monitor = &crate::aog::pump_safety::SAFETY_MONITOR;
+                    let report = serde_json::json!({
+                        "pumps": monitor.maintenance_report(),
+                        "history": monitor.service_history(None),
+                    });
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Record a pump service (authenticated)
+                if request.url() == "/api/pump/service" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        pump_id: String,
+                        notes: Option<String>,
+                    }));
+                    
+                    let monitor = &crate::aog::pump_safety::SAFETY_MONITOR;
+                    if !monitor.maintenance_report().iter().any(|status| status.pump_id == input.pump_id) {
+                        return Response::text(format!("Unknown pump '{}'", input.pump_id)).with_status_code(404);
+                    }
+                    let record = monitor.record_service(&input.pump_id, input.notes.unwrap_or_default());
+                    return Response::json(&record);
+                }
+                
+                // Start a flow calibration of a pump (authenticated)
+                if request.url() == "/api/pump/calibrate" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        pump_id: String,
+                        runs: Option<u32>,
+                        run_seconds: Option<u64>,
+                    }));
+                    
+                    let mut options = crate::aog::pump_safety::FlowCalibrationOptions::default();
+                    if let Some(runs) = input.runs.filter(|runs| *runs > 0) {
+                        options.runs = runs;
+                    }
+                    if let Some(seconds) = input.run_seconds.filter(|seconds| *seconds > 0) {
+                        options.run_duration = std::time::Duration::from_secs(seconds);
+                    }
+                    
+                    return match crate::aog::pump_jobs::JOB_MANAGER.calibrate(&input.pump_id, options) {
+                        Ok(()) => Response::json(&serde_json::json!({
+                            "pump_id": input.pump_id,
+                            "calibrating": true,
+                        })).with_status_code(202),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
+                // Relay boards, named channels and switch counters
+                if request.url() == "/api/relays" {
+                    let report = serde_json::json!({
+                        "boards": crate::aog::qwiic::relay_boards(),
+                        "counters": crate::aog::qwiic::RELAY_COUNTERS.all(),
+                    });
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Typed sensor readings, particulate sensor health, rejected readings
+                // and fused source health
+                if request.url() == "/api/sensors" {
+                    let report = serde_json::json!({
+                        "readings": crate::aog::sensors::readings(),
+                        "particulate": {
+                            "latest": crate::aog::particulate::PARTICULATE.latest(),
+                            "health": crate::aog::particulate::PARTICULATE.health(),
+                        },
+                        "plausibility": crate::aog::plausibility::PLAUSIBILITY.stats(),
+                        "fusion": crate::aog::sensor_fusion::SENSOR_FUSION.health(
+                            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs()
+                        ),
+                    });
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Devices found by the last I2C scan and the bound serial devices
+                if request.url() == "/api/hardware" {
+                    let report = serde_json::json!({
+                        "i2c": crate::aog::i2c::last_inventory(),
+                        "serial": crate::aog::serial::SERIAL_DEVICES.bindings(),
+                    });
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Rescan the I2C bus (authenticated)
+                if request.url() == "/api/hardware/scan" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    return match crate::aog::i2c::scan_hardware(edit_aog_config) {
+                        Ok(inventory) => Response::json(&inventory),
+                        Err(e) => Response::text(e).with_status_code(500),
+                    };
+                }
+                
+                // Automation rules, what they would do now and their recent actions
+                if request.url() == "/api/rules" {
+                    let report = serde_json::json!({
+                        "dry_run": crate::aog::rules::RULES.dry_run(),
+                        "rules": crate::aog::rules::RULES.rules(),
+                        "evaluation": crate::aog::rules::preview_now(),
+                        "history": crate::aog::rules::RULES.history(),
+                    });
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Add or replace an automation rule (authenticated)
+                if request.url() == "/api/rules/save" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        rule: String,
+                    }));
+                    
+                    let rule: crate::AutomationRule = match serde_json::from_str(&input.rule) {
+                        Ok(rule) => rule,
+                        Err(e) => return Response::text(format!("Invalid rule: {}", e)).with_status_code(400),
+                    };
+                    let rules = &crate::aog::rules::RULES;
+                    if let Err(e) = rules.upsert(rule, &rules.actions()) {
+                        return Response::text(e).with_status_code(400);
+                    }
+                    return match rules.persist() {
+                        Ok(()) => Response::json(&rules.rules()),
+                        Err(e) => Response::text(e).with_status_code(500),
+                    };
+                }
+                
+                // Remove, enable or disable an automation rule (authenticated)
+                if request.url() == "/api/rules/remove" || request.url() == "/api/rules/enable" || request.url() == "/api/rules/disable" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        name: String,
+                    }));
+                    
+                    let rules = &crate::aog::rules::RULES;
+                    let result = match request.url().as_str() {
+                        "/api/rules/remove" => rules.remove(&input.name, &rules.actions()).map(|_| ()),
+                        "/api/rules/enable" => rules.set_enabled(&input.name, true),
+                        _ => rules.set_enabled(&input.name, false),
+                    };
+                    if let Err(e) = result {
+                        return Response::text(e).with_status_code(404);
+                    }
+                    return match rules.persist() {
+                        Ok(()) => Response::json(&rules.rules()),
+                        Err(e) => Response::text(e).with_status_code(500),
+                    };
+                }
+                
+                // Switch dry-run mode (authenticated)
+                if request.url() == "/api/rules/dry-run" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        enabled: bool,
+                    }));
+                    
+                    let rules = &crate::aog::rules::RULES;
+                    rules.set_dry_run(input.enabled);
+                    return match rules.persist() {
+                        Ok(()) => Response::json(&serde_json::json!({ "dry_run": rules.dry_run() })),
+                        Err(e) => Response::text(e).with_status_code(500),
+                    };
+                }
+                
+                // PID loops and their recent controller states, for tuning
+                if request.url() == "/api/pid" {
+                    let loops: Vec<serde_json::Value> = crate::aog::pid::status().into_iter()
+                        .map(|status| serde_json::json!({
+                            "history": crate::aog::pid::history(&status.name),
+                            "status": status,
+                        }))
+                        .collect();
+                    return Response::json(&loops).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Retune a PID loop (authenticated)
+                if request.url() == "/api/pid/tune" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        name: String,
+                        kp: f32,
+                        ki: f32,
+                        kd: f32,
+                        setpoint: Option<f32>,
+                    }));
+                    
+                    let tuning = crate::aog::pid::PidTuning { kp: input.kp, ki: input.ki, kd: input.kd };
+                    return match crate::aog::pid::tune(&input.name, tuning, input.setpoint) {
+                        Ok(status) => Response::json(&status),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
+                // Switch a PID loop between automatic and manual (authenticated)
+                if request.url() == "/api/pid/mode" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        name: String,
+                        manual_output: Option<f32>,
+                    }));
+                    
+                    return match crate::aog::pid::set_mode(&input.name, input.manual_output) {
+                        Ok(status) => Response::json(&status),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
+                // Mode, level and AOG role of every header pin
+                if request.url() == "/api/gpio" {
+                    return Response::json(&crate::aog::gpio::status::current()).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // GPIO inputs with their debounced level and recent edges
+                if request.url() == "/api/gpio/inputs" {
+                    let inputs = &crate::aog::gpio::input::INPUTS;
+                    let report: Vec<serde_json::Value> = inputs.status().into_iter()
+                        .map(|status| serde_json::json!({
+                            "history": inputs.history(&status.name),
+                            "status": status,
+                        }))
+                        .collect();
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // GPIO outputs with their requested and written state
+                if request.url() == "/api/gpio/outputs" {
+                    return Response::json(&crate::aog::gpio::output::GPIO_OUTPUTS.status()).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Switch a GPIO output by name or BCM pin (authenticated)
+                if request.url() == "/api/gpio/outputs/set" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        output: String,
+                        on: bool,
+                    }));
+                    
+                    return match crate::aog::gpio::output::set(&input.output, input.on) {
+                        Ok(status) => Response::json(&status),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
+                // PWM channels with their current and target levels
+                if request.url() == "/api/pwm" {
+                    return Response::json(&crate::aog::gpio::pwm::PWM.status()).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Set a PWM channel's level, or return it to its schedule (authenticated)
+                if request.url() == "/api/pwm/set" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        name: String,
+                        percent: Option<f32>,
+                    }));
+                    
+                    let pwm = &crate::aog::gpio::pwm::PWM;
+                    let result = match input.percent {
+                        Some(percent) => pwm.set(&input.name, percent),
+                        None => pwm.release(&input.name),
+                    };
+                    return match result {
+                        Ok(status) => Response::json(&status),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
+                // Emergency stop state and trigger/reset history (authenticated)
+                if request.url() == "/api/emergency-stop" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let estop = &crate::aog::emergency_stop::ESTOP;
+                    let report = serde_json::json!({
+                        "status": estop.status(),
+                        "events": estop.events(),
+                    });
+                    return Response::json(&report).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Trip the emergency stop (authenticated)
+                if request.url() == "/api/emergency-stop/trigger" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        reason: String,
+                    }));
+                    
+                    let estop = &crate::aog::emergency_stop::ESTOP;
+                    let failed_outputs = estop.trigger(crate::aog::emergency_stop::TriggerSource::Api, input.reason.trim());
+                    return Response::json(&serde_json::json!({
+                        "status": estop.status(),
+                        "failed_outputs": failed_outputs,
+                    }));
+                }
+                
+                // Release the emergency stop latch (authenticated, reason required)
+                if request.url() == "/api/emergency-stop/reset" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        reason: String,
+                    }));
+                    
+                    let estop = &crate::aog::emergency_stop::ESTOP;
+                    // Never record the session id, the event history is readable over the API
+                    let by = if session_username.is_empty() { "web user".to_string() } else { session_username.clone() };
+                    return match estop.reset(&by, &input.reason) {
+                        Ok(()) => Response::json(&estop.status()),
+                        Err(e) => Response::text(e).with_status_code(409),
+                    };
+                }
+                
+                // Cached water levels, alerts and recent alert events
+                if request.url() == "/api/water/levels" {
+                    let levels = match crate::aog::water_level::WATER_LEVEL_SYSTEM.lock() {
+                        Ok(guard) => guard.as_ref().map(|system| serde_json::json!({
+                            "levels": system.get_all_levels(),
+                            "events": system.get_events(),
+                            "sampling": system.is_sampling(),
+                        })),
+                        Err(_) => None,
+                    };
+                    return Response::json(&levels).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Water level calibration for all tanks
+                if request.url() == "/api/water/calibration" {
+                    let calibrations = match crate::aog::water_level::WATER_LEVEL_SYSTEM.lock() {
+                        Ok(guard) => guard.as_ref().map(|system| system.get_calibrations()).unwrap_or_default(),
+                        Err(_) => HashMap::new(),
+                    };
+                    return Response::json(&calibrations).with_additional_header("Access-Control-Allow-Origin", "*");
+                }
+                
+                // Capture a water level calibration point (authenticated)
+                if request.url() == "/api/water/calibrate" {
+                    if !session_authenticated {
+                        return Response::text("Unauthorized").with_status_code(401);
+                    }
+                    
+                    let input = try_or_400!(post_input!(request, {
+                        tank: String,
+                        target: String,
+                    }));
+                    
+                    let result = match crate::aog::water_level::WATER_LEVEL_SYSTEM.lock() {
+                        Ok(guard) => match guard.as_ref() {
+                            Some(system) => system.calibrate_tank_target(&input.tank, &input.target),
+                            None => Err("Water level system is not initialized".to_string()),
+                        },
+                        Err(_) => Err("Water level system unavailable".to_string()),
+                    };
+                    
+                    return match result {
+                        Ok(calibration) => Response::json(&calibration),
+                        Err(e) => Response::text(e).with_status_code(400),
+                    };
+                }
+                
                 if request.url() == "/api/stats"{
                     #[derive(Serialize, Deserialize, Debug, Clone)]
                     struct WebApiStats {
@@ -251,7 +690,8 @@ pub fn init(){
                         t2_ovf: String,
                         overflow_error: bool,
                         ph: String,
-                        ph_status: Option<crate::aog::ph_sensor::PhSensorStatus>
+                        ph_status: Option<crate::aog::ph_sensor::PhSensorStatus>,
+                        climate: Option<crate::aog::climate::ClimateStatus>
                     }
                    
                     // Get pH status if available
@@ -272,9 +712,10 @@ pub fn init(){
                         pm10: crate::aog::sensors::get_value("pm10"),
                         t1_ovf: crate::aog::sensors::get_value("t1_ovf"),
                         t2_ovf: crate::aog::sensors::get_value("t2_ovf"),
-                        overflow_error: overflow_error,
+                        overflow_error,
                         ph: crate::aog::sensors::get_value("ph_calibrated"),
-                        ph_status: ph_status
+                        ph_status,
+                        climate: crate::aog::climate::status()
                     });
                     return response;
                 }
@@ -314,9 +755,7 @@ pub fn init(){
     
         }
     }, cert, pkey)
-    .map_err(|e| log::error!("Failed to start HTTPS server: {}", e))
-    .ok()
-    .map(|server| server.run());
+    .map_err(|e| log::error!("Failed to start HTTPS server: {}", e)) { server.run() }
     
 }
 
@@ -342,7 +781,7 @@ impl RateLimiter {
         let window_start = now - self.window;
         
         // Get or create request history for this client
-        let requests = self.requests.entry(client_id.to_string()).or_insert_with(Vec::new);
+        let requests = self.requests.entry(client_id.to_string()).or_default();
         
         // Remove old requests outside the window
         requests.retain(|&req_time| req_time > window_start);
@@ -405,7 +844,7 @@ pub fn init_command_api(){
     
     log::info!("Starting Command API server on {} (localhost-only)", bind_addr);
     
-    rouille::Server::new_ssl(bind_addr, move |request| {
+    if let Ok(server) = rouille::Server::new_ssl(bind_addr, move |request| {
         {
             // IP filtering - reject non-localhost connections
             let remote_addr = request.remote_addr();
@@ -432,11 +871,7 @@ pub fn init_command_api(){
                 match auth_header {
                     Some(header_value) => {
                         // Support both "Bearer <token>" and plain token formats
-                        let token = if header_value.starts_with("Bearer ") {
-                            &header_value[7..]
-                        } else {
-                            header_value
-                        };
+                        let token = header_value.strip_prefix("Bearer ").unwrap_or(header_value);
                         token == expected_token
                     },
                     None => false
@@ -478,9 +913,13 @@ pub fn init_command_api(){
             
             // Define allowed commands whitelist
             let allowed_commands = vec![
-                "help", "cls", "clear", "gpio status", "stdout", "test",
+                "help", "cls", "clear", "gpio status", "gpio outputs", "gpio inputs", "stdout", "test",
                 "pump status", "pump fill", "pump drain", "pump stop",
-                "relay status"
+                "relay status", "water status", "water calibration",
+                "pump jobs", "pump cancel", "pump maintenance",
+                "estop", "estop status", "estop trigger", "i2c scan",
+                "rules", "rules status", "rules evaluate", "rules history",
+                "climate", "climate status", "pid", "pid status", "pwm", "pwm status"
             ];
             
             // Check if command is in whitelist or is a safe gpio command
@@ -488,7 +927,26 @@ pub fn init_command_api(){
                          (command.starts_with("gpio on ") && command.split_whitespace().count() == 3) ||
                          (command.starts_with("gpio off ") && command.split_whitespace().count() == 3) ||
                          (command.starts_with("relay on ") && command.split_whitespace().count() == 3) ||
-                         (command.starts_with("relay off ") && command.split_whitespace().count() == 3);
+                         (command.starts_with("relay off ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("water calibrate ") && command.split_whitespace().count() == 4) ||
+                         (command.starts_with("pump fill ") && command.split_whitespace().count() == 4) ||
+                         (command.starts_with("pump drain ") && command.split_whitespace().count() == 4) ||
+                         (command.starts_with("pump transfer ") && command.split_whitespace().count() == 5) ||
+                         (command.starts_with("pump cancel ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("pump maintenance ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("pump service ") && command.split_whitespace().count() >= 3) ||
+                         command.starts_with("estop trigger ") ||
+                         (command.starts_with("pid tune ") && command.split_whitespace().count() == 6) ||
+                         (command.starts_with("pid setpoint ") && command.split_whitespace().count() == 4) ||
+                         (command.starts_with("pid auto ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("pid manual ") && command.split_whitespace().count() == 4) ||
+                         (command.starts_with("gpio input ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("pwm set ") && command.split_whitespace().count() == 4) ||
+                         (command.starts_with("pwm auto ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("rules enable ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("rules disable ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("rules dry-run ") && command.split_whitespace().count() == 3) ||
+                         (command.starts_with("estop reset ") && command.split_whitespace().count() >= 3);
             
             if !is_safe {
                 log::warn!("Blocked potentially unsafe command: {}", command);
@@ -500,12 +958,12 @@ pub fn init_command_api(){
             }
             
             // Additional validation for gpio/relay commands
-            if command.starts_with("gpio ") || command.starts_with("relay ") {
+            if command.starts_with("gpio ") {
                 let parts: Vec<&str> = command.split_whitespace().collect();
                 if parts.len() >= 3 {
-                    // Validate pin/relay number is numeric
+                    // Validate pin number is numeric
                     if parts[2].parse::<u8>().is_err() {
-                        log::warn!("Invalid pin/relay number in command: {}", command);
+                        log::warn!("Invalid pin number in command: {}", command);
                         let response = Response::json(&CommandStatus { 
                             status: "error: invalid pin/relay number".to_string(),
                             output: None
@@ -515,9 +973,46 @@ pub fn init_command_api(){
                 }
             }
             
-            if input.input_command == *"admin" {
-                
+            // Relays are addressed by channel name or number
+            if command.starts_with("relay on ") || command.starts_with("relay off ") {
+                let parts: Vec<&str> = command.split_whitespace().collect();
+                if crate::aog::qwiic::resolve_channel(parts[2]).is_err() {
+                    log::warn!("Unknown relay in command: {}", command);
+                    let response = Response::json(&CommandStatus { 
+                        status: "error: unknown relay".to_string(),
+                        output: None
+                    });
+                    return response;
+                }
             }
+            
+            // Only known tanks may be calibrated
+            if command.starts_with("water calibrate ") {
+                let parts: Vec<&str> = command.split_whitespace().collect();
+                if parts[2] != "tank1" && parts[2] != "tank2" {
+                    log::warn!("Invalid tank in command: {}", command);
+                    let response = Response::json(&CommandStatus { 
+                        status: "error: invalid tank".to_string(),
+                        output: None
+                    });
+                    return response;
+                }
+            }
+            
+            // Pump ids are plain identifiers
+            if command.starts_with("pump service ") || command.starts_with("pump maintenance ") {
+                let parts: Vec<&str> = command.split_whitespace().collect();
+                if !parts[2].chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                    log::warn!("Invalid pump id in command: {}", command);
+                    let response = Response::json(&CommandStatus { 
+                        status: "error: invalid pump id".to_string(),
+                        output: None
+                    });
+                    return response;
+                }
+            }
+            
+            
 
 
             // Execute command and capture output
@@ -532,17 +1027,15 @@ pub fn init_command_api(){
             };
 
             // let arduino_response = crate::aog::sensors::get_arduino_raw();
-            let response = Response::json(&CommandStatus { 
+            
+            Response::json(&CommandStatus { 
                 status: "success".to_string(),
                 output
-            });
-            return response;
+            })
 
 
         }
     }, cert, pkey)
-    .map_err(|e| log::error!("Failed to start HTTPS server: {}", e))
-    .ok()
-    .map(|server| server.run());
+    .map_err(|e| log::error!("Failed to start HTTPS server: {}", e)) { server.run() }
     
 }
@@ -0,0 +1,362 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// I2C Module - Bus scanner and inventory of the Qwiic devices on /dev/i2c-1,
+// compared against the devices the configuration expects
+
+use std::sync::Mutex;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use rppal::i2c::I2c;
+
+use crate::aog::qwiic::{self, QwiicRelayDevice};
+use crate::Config;
+
+/// Bus the Qwiic connector is wired to
+pub const I2C_BUS: u8 = 1;
+/// Address of the SerLCD used by the lcd module
+pub const LCD_ADDRESS: u16 = 0x72;
+
+// Addresses outside this range are reserved
+const FIRST_ADDRESS: u16 = 0x08;
+const LAST_ADDRESS: u16 = 0x77;
+
+/// Devices recognised by address. Where a device has an ID register it is
+/// checked before the device is reported as identified.
+const KNOWN_DEVICES: &[(u16, &str)] = &[
+    (0x08, "Qwiic Quad Solid State Relay"),
+    (0x09, "Qwiic Quad Solid State Relay (alt)"),
+    (0x0A, "Qwiic Dual Solid State Relay"),
+    (0x0B, "Qwiic Dual Solid State Relay (alt)"),
+    (0x18, "Qwiic Single Relay"),
+    (0x19, "Qwiic Single Relay (alt)"),
+    (0x48, "ADS1115 ADC"),
+    (0x49, "ADS1115 ADC (alt)"),
+    (0x5A, "CCS811 air quality sensor"),
+    (0x5B, "CCS811 air quality sensor (alt)"),
+    (0x61, "SCD30 CO2 sensor"),
+    (0x62, "SCD4x CO2 sensor"),
+    (0x63, "Atlas Scientific EZO pH"),
+    (0x64, "Atlas Scientific EZO EC"),
+    (0x6C, "Qwiic Quad Relay (alt)"),
+    (0x6D, "Qwiic Quad Relay"),
+    (0x72, "SerLCD display"),
+    (0x76, "BME280/BMP280"),
+    (0x77, "BME280/BMP280 (alt)"),
+];
+
+const CCS811_HW_ID_REG: u8 = 0x20;
+const CCS811_HW_ID: u8 = 0x81;
+const BMX280_ID_REG: u8 = 0xD0;
+const BME280_CHIP_ID: u8 = 0x60;
+const BMP280_CHIP_ID: u8 = 0x58;
+
+/// Raw access to the bus, so identification can be tested without hardware
+pub trait I2cBus {
+    /// Whether a device acknowledges `address`
+    fn probe(&mut self, address: u16) -> bool;
+    fn read_register(&mut self, address: u16, register: u8, len: usize) -> Result<Vec<u8>, String>;
+    /// Firmware version of a Qwiic relay board at `address`
+    fn relay_firmware(&mut self, address: u16) -> Result<f32, String>;
+}
+
+/// The Pi's hardware I2C bus
+pub struct RppalBus {
+    i2c: I2c,
+}
+
+impl RppalBus {
+    pub fn new(bus: u8) -> Result<Self, String> {
+        let i2c = I2c::with_bus(bus).map_err(|e| format!("Failed to open I2C bus {}: {}", bus, e))?;
+        Ok(RppalBus { i2c })
+    }
+}
+
+impl I2cBus for RppalBus {
+    fn probe(&mut self, address: u16) -> bool {
+        if self.i2c.set_slave_address(address).is_err() {
+            return false;
+        }
+        // Same probes as i2cdetect: reads where a quick write could upset an
+        // EEPROM or write-protect latch, quick writes elsewhere
+        if (0x30..=0x37).contains(&address) || (0x50..=0x5F).contains(&address) {
+            let mut buffer = [0u8; 1];
+            self.i2c.read(&mut buffer).is_ok()
+        } else {
+            self.i2c.smbus_quick_command(false).is_ok()
+        }
+    }
+
+    fn read_register(&mut self, address: u16, register: u8, len: usize) -> Result<Vec<u8>, String> {
+        self.i2c.set_slave_address(address).map_err(|e| e.to_string())?;
+        let mut buffer = vec![0u8; len];
+        self.i2c.write_read(&[register], &mut buffer).map_err(|e| e.to_string())?;
+        Ok(buffer)
+    }
+
+    fn relay_firmware(&mut self, address: u16) -> Result<f32, String> {
+        QwiicRelayDevice::new(address).firmware_version().map_err(|e| e.to_string())
+    }
+}
+
+/// A device the configuration says should be on the bus
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedDevice {
+    pub address: u16,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct I2cDevice {
+    pub address: u16,
+    pub name: Option<String>,  // Known device at this address, if any
+    pub identified: bool,  // Confirmed from an ID register or firmware version
+    pub firmware: Option<String>,
+    pub configured_as: Option<String>,  // What the configuration expects here
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HardwareInventory {
+    pub bus: u8,
+    pub scanned_at: String,
+    pub devices: Vec<I2cDevice>,
+    pub missing: Vec<ExpectedDevice>,  // Configured but not answering
+    pub unconfigured: Vec<u16>,  // Answering but not configured
+}
+
+/// Devices the configuration relies on: relay boards, the LCD, native
+/// sensors and any I2C pH or level sensors
+pub fn expected_devices(config: &Config) -> Vec<ExpectedDevice> {
+    let mut expected: Vec<ExpectedDevice> = qwiic::relay_boards().iter()
+        .map(|board| ExpectedDevice {
+            address: board.address,
+            name: format!("{:?} relay board", board.board_type),
+        })
+        .collect();
+    expected.push(ExpectedDevice { address: LCD_ADDRESS, name: "LCD".to_string() });
+
+    if let Some(ph) = config.ph_config.as_ref().filter(|ph| ph.enabled && ph.sensor_type == "i2c") {
+        if let Some(address) = ph.i2c_address {
+            expected.push(ExpectedDevice { address: address as u16, name: "pH sensor".to_string() });
+        }
+    }
+    if let Some(sensor_kit) = &config.sensor_kit_config {
+        for (address, name) in crate::aog::native_sensors::expected_i2c_devices(sensor_kit) {
+            expected.push(ExpectedDevice { address, name });
+        }
+    }
+    if let Some(water) = &config.water_level_config {
+        for (tank, address) in [("tank1", water.tank1_i2c_address), ("tank2", water.tank2_i2c_address)] {
+            if let Some(address) = address {
+                expected.push(ExpectedDevice { address: address as u16, name: format!("{} level sensor", tank) });
+            }
+        }
+    }
+    expected
+}
+
+/// Read ID registers or firmware to confirm what is at `address`
+fn identify(bus: &mut dyn I2cBus, address: u16, is_relay: bool) -> (Option<String>, bool, Option<String>) {
+    let known = KNOWN_DEVICES.iter()
+        .find(|(known, _)| *known == address)
+        .map(|(_, name)| name.to_string());
+
+    if is_relay || known.as_deref().is_some_and(|name| name.contains("Relay")) {
+        return match bus.relay_firmware(address) {
+            Ok(version) => (Some(known.unwrap_or_else(|| "Qwiic relay".to_string())), true, Some(format!("{:.1}", version))),
+            Err(_) => (known, false, None),
+        };
+    }
+
+    let register = |bus: &mut dyn I2cBus, register| bus.read_register(address, register, 1).ok()
+        .and_then(|bytes| bytes.first().copied());
+    match address {
+        0x5A | 0x5B if register(bus, CCS811_HW_ID_REG) == Some(CCS811_HW_ID) =>
+            (Some("CCS811 air quality sensor".to_string()), true, None),
+        0x76 | 0x77 => match register(bus, BMX280_ID_REG) {
+            Some(BME280_CHIP_ID) => (Some("BME280".to_string()), true, None),
+            Some(BMP280_CHIP_ID) => (Some("BMP280".to_string()), true, None),
+            _ => (known, false, None),
+        },
+        _ => (known, false, None),
+    }
+}
+
+/// Probe every address on `bus` and compare what answers with `expected`
+pub fn scan(bus: &mut dyn I2cBus, bus_number: u8, expected: &[ExpectedDevice]) -> HardwareInventory {
+    let relay_addresses: Vec<u16> = qwiic::relay_boards().iter().map(|board| board.address).collect();
+    let configured = |address: u16| expected.iter()
+        .find(|device| device.address == address)
+        .map(|device| device.name.clone());
+
+    let present: Vec<u16> = (FIRST_ADDRESS..=LAST_ADDRESS)
+        .filter(|address| bus.probe(*address))
+        .collect();
+    let devices: Vec<I2cDevice> = present.into_iter()
+        .map(|address| {
+            let (name, identified, firmware) = identify(bus, address, relay_addresses.contains(&address));
+            I2cDevice { address, name, identified, firmware, configured_as: configured(address) }
+        })
+        .collect();
+
+    HardwareInventory {
+        bus: bus_number,
+        scanned_at: Local::now().to_rfc3339(),
+        missing: expected.iter()
+            .filter(|device| !devices.iter().any(|found| found.address == device.address))
+            .cloned()
+            .collect(),
+        unconfigured: devices.iter()
+            .filter(|device| device.configured_as.is_none())
+            .map(|device| device.address)
+            .collect(),
+        devices,
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LAST_INVENTORY: Mutex<Option<HardwareInventory>> = Mutex::new(None);
+}
+
+/// Scan the Qwiic bus and keep the result for `/api/hardware`
+pub fn scan_hardware(config: &Config) -> Result<HardwareInventory, String> {
+    let mut bus = RppalBus::new(I2C_BUS)?;
+    let inventory = scan(&mut bus, I2C_BUS, &expected_devices(config));
+    *LAST_INVENTORY.lock().unwrap_or_else(|e| e.into_inner()) = Some(inventory.clone());
+    Ok(inventory)
+}
+
+pub fn last_inventory() -> Option<HardwareInventory> {
+    LAST_INVENTORY.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Scan at startup and log configured-but-missing and unconfigured devices
+pub fn check_inventory(config: &Config) {
+    match scan_hardware(config) {
+        Ok(inventory) => {
+            log::info!("I2C bus {}: {} devices found", inventory.bus, inventory.devices.len());
+            for device in &inventory.missing {
+                log::warn!("Configured I2C device missing: {} at 0x{:02x}", device.name, device.address);
+            }
+            for address in &inventory.unconfigured {
+                let name = inventory.devices.iter()
+                    .find(|device| device.address == *address)
+                    .and_then(|device| device.name.clone())
+                    .unwrap_or_else(|| "unknown device".to_string());
+                log::info!("Unconfigured I2C device at 0x{:02x}: {}", address, name);
+            }
+        }
+        Err(e) => log::warn!("I2C scan skipped: {}", e),
+    }
+}
+
+pub fn format_inventory(inventory: &HardwareInventory) -> String {
+    let mut lines = vec![format!("I2C bus {} ({} devices)", inventory.bus, inventory.devices.len())];
+    for device in &inventory.devices {
+        let mut line = format!("  0x{:02x}: {}", device.address, device.name.as_deref().unwrap_or("unknown"));
+        if !device.identified && device.name.is_some() {
+            line.push_str(" (by address)");
+        }
+        if let Some(firmware) = &device.firmware {
+            line.push_str(&format!(", firmware {}", firmware));
+        }
+        match &device.configured_as {
+            Some(role) => line.push_str(&format!(" - configured as {}", role)),
+            None => line.push_str(" - not configured"),
+        }
+        lines.push(line);
+    }
+    for device in &inventory.missing {
+        lines.push(format!("  0x{:02x}: MISSING - {}", device.address, device.name));
+    }
+    lines.join("\n")
+}
+
+/// Handle `i2c scan`
+pub fn run_command(command: &str) -> String {
+    match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["i2c"] | ["i2c", "scan"] => {
+            let config = match Config::load(0) {
+                Ok(config) => config,
+                Err(e) => return format!("Failed to load config: {}", e),
+            };
+            match scan_hardware(&config) {
+                Ok(inventory) => format_inventory(&inventory),
+                Err(e) => format!("I2C scan failed: {}", e),
+            }
+        }
+        _ => "Usage: i2c scan".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Devices present with their register contents
+    #[derive(Default)]
+    struct MockBus {
+        registers: HashMap<u16, HashMap<u8, u8>>,
+        relays: HashMap<u16, f32>,
+    }
+
+    impl I2cBus for MockBus {
+        fn probe(&mut self, address: u16) -> bool {
+            self.registers.contains_key(&address) || self.relays.contains_key(&address)
+        }
+
+        fn read_register(&mut self, address: u16, register: u8, _len: usize) -> Result<Vec<u8>, String> {
+            self.registers.get(&address)
+                .and_then(|registers| registers.get(&register))
+                .map(|value| vec![*value])
+                .ok_or_else(|| "nack".to_string())
+        }
+
+        fn relay_firmware(&mut self, address: u16) -> Result<f32, String> {
+            self.relays.get(&address).copied().ok_or_else(|| "nack".to_string())
+        }
+    }
+
+    #[test]
+    fn test_scan_identifies_devices() {
+        let mut bus = MockBus::default();
+        bus.relays.insert(qwiic::DEFAULT_RELAY_BOARD, 1.1);
+        bus.registers.insert(0x5A, HashMap::from([(CCS811_HW_ID_REG, CCS811_HW_ID)]));
+        bus.registers.insert(0x77, HashMap::from([(BMX280_ID_REG, BMP280_CHIP_ID)]));
+        bus.registers.insert(0x40, HashMap::new());
+
+        let expected = vec![
+            ExpectedDevice { address: qwiic::DEFAULT_RELAY_BOARD, name: "relay board".to_string() },
+            ExpectedDevice { address: LCD_ADDRESS, name: "LCD".to_string() },
+        ];
+        let inventory = scan(&mut bus, 1, &expected);
+
+        assert_eq!(inventory.devices.len(), 4);
+        let relay = inventory.devices.iter().find(|d| d.address == qwiic::DEFAULT_RELAY_BOARD).unwrap();
+        assert!(relay.identified);
+        assert_eq!(relay.firmware.as_deref(), Some("1.1"));
+        assert_eq!(relay.configured_as.as_deref(), Some("relay board"));
+
+        let ccs811 = inventory.devices.iter().find(|d| d.address == 0x5A).unwrap();
+        assert!(ccs811.identified);
+        let bmp = inventory.devices.iter().find(|d| d.address == 0x77).unwrap();
+        assert_eq!(bmp.name.as_deref(), Some("BMP280"));
+        let unknown = inventory.devices.iter().find(|d| d.address == 0x40).unwrap();
+        assert!(unknown.name.is_none());
+
+        assert_eq!(inventory.missing, vec![expected[1].clone()]);
+        assert_eq!(inventory.unconfigured, vec![0x40, 0x5A, 0x77]);
+    }
+
+    #[test]
+    fn test_unconfirmed_device_is_not_identified() {
+        let mut bus = MockBus::default();
+        // Something at the CCS811 address with the wrong hardware id
+        bus.registers.insert(0x5A, HashMap::from([(CCS811_HW_ID_REG, 0x12)]));
+        let inventory = scan(&mut bus, 1, &[]);
+        assert_eq!(inventory.devices[0].name.as_deref(), Some("CCS811 air quality sensor"));
+        assert!(!inventory.devices[0].identified);
+    }
+}
@@ -19,17 +19,14 @@ pub struct InstanceInfo {
 }
 
 pub fn check_running_instance() -> bool {
-    if let Ok(_) = TcpStream::connect("127.0.0.1:9443") {
+    if TcpStream::connect("127.0.0.1:9443").is_ok() {
         return true;
     }
     false
 }
 
 pub fn check_port_available(port: u16) -> bool {
-    match TcpStream::connect(format!("127.0.0.1:{}", port)) {
-        Ok(_) => false,
-        Err(_) => true,
-    }
+    TcpStream::connect(format!("127.0.0.1:{}", port)).is_err()
 }
 
 pub fn write_pid_file() -> io::Result<()> {
@@ -177,7 +174,11 @@ pub fn handle_instance_check(force: bool) -> Result<bool, Box<dyn std::error::Er
         return Ok(true);
     }
     
-    if check_running_instance() {
+    // A lock held by a live process counts even before its server is up
+    let locked = Path::new(LOCK_FILE).exists()
+        && read_pid_file().map(|info| is_process_running(info.pid)).unwrap_or(false);
+    
+    if check_running_instance() || locked {
         if let Ok(info) = read_pid_file() {
             log::info!(
                 "AOG instance already running (PID: {}, started: {})",
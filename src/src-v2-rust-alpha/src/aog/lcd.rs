@@ -18,9 +18,9 @@ pub fn init(){
 
         // Fetch IP Address
         let ipp = machine_ip::get();
-        if ipp.is_some(){
-            let tmpip = ipp.unwrap().to_string();
-            if tmpip.len() > 0{
+        if let Some(ipp) = ipp {
+            let tmpip = ipp.to_string();
+            if !tmpip.is_empty(){
                 ip = tmpip;
             }
         }
@@ -70,7 +70,7 @@ pub fn set_lcd(mut screen: Screen, ip: String, co2: String, pm25: String) -> Res
     screen.move_cursor(0,0)?;
 
     // Print text
-    screen.print(format!("{}", ip).as_str())?;
+    screen.print(&ip)?;
 
     // Move to the next line
     screen.move_cursor(1,0)?;
@@ -89,8 +89,8 @@ pub fn set_lcd(mut screen: Screen, ip: String, co2: String, pm25: String) -> Res
     screen.move_cursor(3,0)?;
 
     // Print text
-    screen.print(format!("Status: 001").as_str())?;
+    screen.print("Status: 001")?;
 
 
-    return Ok(screen);
+    Ok(screen)
 }
\ No newline at end of file
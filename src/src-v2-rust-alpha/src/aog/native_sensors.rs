@@ -0,0 +1,259 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Native Sensors Module - DHT, CCS811 and SCD30/SCD4x read directly by the Pi,
+// so a unit can run without the Arduino sensor kit
+
+pub mod ccs811;
+pub mod dht;
+pub mod scd;
+
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::aog::i2c::I2C_BUS;
+use crate::aog::sensor_fusion::SENSOR_FUSION;
+use crate::aog::sensors;
+use crate::SensorKitConfig;
+use self::ccs811::Ccs811;
+use self::dht::Dht;
+use self::scd::Scd;
+
+pub const POLL_SECS: u64 = 5;
+// Failed reads in a row before a driver is reopened
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+/// One metric from one poll: (name, value, unit, source)
+type Metric = (&'static str, f32, &'static str, &'static str);
+
+enum Driver {
+    Dht(Dht),
+    Ccs811(Ccs811),
+    Scd(Scd),
+}
+
+impl Driver {
+    /// Read the metrics this sensor provides. Humidity and temperature from
+    /// earlier drivers in the same poll are used for CCS811 compensation.
+    fn read(&mut self, environment: Option<(f32, f32)>) -> Result<Vec<Metric>, String> {
+        match self {
+            Driver::Dht(dht) => {
+                let source = match dht.model() {
+                    crate::DhtModel::Dht11 => "dht11",
+                    crate::DhtModel::Dht22 => "dht22",
+                };
+                let (temperature, humidity) = dht.read()?;
+                Ok(vec![("temp", temperature, "C", source), ("hum", humidity, "%", source)])
+            }
+            Driver::Ccs811(ccs811) => {
+                if let Some((humidity, temperature)) = environment {
+                    ccs811.set_environment(humidity, temperature)?;
+                }
+                Ok(match ccs811.read()? {
+                    Some((eco2, tvoc)) => vec![("co2", eco2 as f32, "ppm", "ccs811"), ("tvoc", tvoc as f32, "ppb", "ccs811")],
+                    None => Vec::new(),
+                })
+            }
+            Driver::Scd(scd) => {
+                let source = match scd.model() {
+                    crate::Co2SensorModel::Scd30 => "scd30",
+                    crate::Co2SensorModel::Scd4x => "scd4x",
+                };
+                Ok(match scd.read()? {
+                    Some((co2, temperature, humidity)) => vec![
+                        ("co2", co2, "ppm", source),
+                        ("temp", temperature, "C", source),
+                        ("hum", humidity, "%", source),
+                    ],
+                    None => Vec::new(),
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DriverKind {
+    Dht { pin: u8, model: crate::DhtModel },
+    Scd(crate::Co2SensorModel),
+    Ccs811 { address: u16 },
+}
+
+impl DriverKind {
+    fn open(&self) -> Result<Driver, String> {
+        match *self {
+            DriverKind::Dht { pin, model } => Dht::new(pin, model).map(Driver::Dht),
+            DriverKind::Scd(model) => Scd::new(I2C_BUS, model).map(Driver::Scd),
+            DriverKind::Ccs811 { address } => Ccs811::new(I2C_BUS, address).map(Driver::Ccs811),
+        }
+    }
+}
+
+/// Drivers selected by the sensor kit configuration. The order decides
+/// which humidity and temperature compensate the CCS811.
+fn selected_drivers(config: &SensorKitConfig) -> Vec<DriverKind> {
+    let mut drivers = Vec::new();
+    if let Some(pin) = config.dht_gpio_pin {
+        drivers.push(DriverKind::Dht { pin, model: config.dht_model });
+    }
+    if let Some(model) = config.co2_sensor {
+        drivers.push(DriverKind::Scd(model));
+    }
+    if let Some(address) = config.ccs811_i2c_address {
+        drivers.push(DriverKind::Ccs811 { address: address as u16 });
+    }
+    drivers
+}
+
+/// Humidity and temperature from the highest priority driver read so far
+fn environment(metrics: &[Metric]) -> Option<(f32, f32)> {
+    let value = |wanted: &str| metrics.iter().find(|(name, ..)| *name == wanted).map(|metric| metric.1);
+    Some((value("hum")?, value("temp")?))
+}
+
+struct Slot {
+    kind: DriverKind,
+    driver: Option<Driver>,
+    failures: u32,
+}
+
+/// Start polling the configured native sensors, if any
+pub fn init(config: &SensorKitConfig) {
+    let kinds = selected_drivers(config);
+    if kinds.is_empty() {
+        return;
+    }
+    log::info!("Native sensors: {:?}", kinds);
+
+    let mut slots: Vec<Slot> = kinds.into_iter().map(|kind| Slot { kind, driver: None, failures: 0 }).collect();
+    let _ = thread::Builder::new().name("native_sensor_thread".to_string()).spawn(move || loop {
+        let mut metrics: Vec<Metric> = Vec::new();
+        for slot in slots.iter_mut() {
+            if slot.driver.is_none() {
+                match slot.kind.open() {
+                    Ok(driver) => {
+                        log::info!("Native sensor {:?} started", slot.kind);
+                        slot.driver = Some(driver);
+                        slot.failures = 0;
+                    }
+                    Err(e) => {
+                        log::debug!("Native sensor {:?} unavailable: {}", slot.kind, e);
+                        continue;
+                    }
+                }
+            }
+
+            let env = environment(&metrics);
+            let result = match slot.driver.as_mut() {
+                Some(driver) => driver.read(env),
+                None => continue,
+            };
+            match result {
+                Ok(new) => {
+                    slot.failures = 0;
+                    metrics.extend(new);
+                }
+                Err(e) => {
+                    // Bit-banged DHT reads fail now and then, so only give
+                    // up on a driver after several misses in a row
+                    slot.failures += 1;
+                    log::debug!("Native sensor {:?} read failed: {}", slot.kind, e);
+                    if slot.failures >= MAX_CONSECUTIVE_FAILURES {
+                        log::warn!("Native sensor {:?} failed {} reads, reopening: {}", slot.kind, slot.failures, e);
+                        slot.driver = None;
+                    }
+                }
+            }
+        }
+
+        // Check each source's reading, then publish each metric fused across
+        // the sources that measure it
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        for (name, value, _, source) in &metrics {
+            if let Some(value) = sensors::accept(name, source, *value) {
+                SENSOR_FUSION.submit(name, source, value, now);
+            }
+        }
+        let mut published: Vec<&str> = Vec::new();
+        for (name, _, unit, _) in &metrics {
+            if published.contains(name) {
+                continue;
+            }
+            published.push(name);
+            if let Some(fused) = SENSOR_FUSION.fuse(name, now) {
+                let source = match fused.sources.as_slice() {
+                    [single] => single.as_str(),
+                    _ => "fusion",
+                };
+                sensors::record(name, fused.value, unit, source);
+            }
+        }
+        thread::sleep(Duration::from_secs(POLL_SECS));
+    });
+}
+
+/// I2C devices the native drivers expect, for the hardware inventory
+pub fn expected_i2c_devices(config: &SensorKitConfig) -> Vec<(u16, String)> {
+    selected_drivers(config).into_iter()
+        .filter_map(|kind| match kind {
+            DriverKind::Scd(model) => Some((model.i2c_address(), format!("{:?} CO2 sensor", model))),
+            DriverKind::Ccs811 { address } => Some((address, "CCS811 air quality sensor".to_string())),
+            DriverKind::Dht { .. } => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Co2SensorModel, DhtModel};
+
+    fn kit() -> SensorKitConfig {
+        SensorKitConfig {
+            dht11_pin: 7,
+            tank_one_overflow: 4,
+            tank_two_overflow: 2,
+            analog_co2_pin: "A0".to_string(),
+            enable_dht11: true,
+            enable_analog_co2: false,
+            enable_ccs811: false,
+            dht_gpio_pin: None,
+            dht_model: DhtModel::Dht11,
+            ccs811_i2c_address: None,
+            co2_sensor: None,
+        }
+    }
+
+    #[test]
+    fn test_selected_drivers_in_priority_order() {
+        assert!(selected_drivers(&kit()).is_empty());
+
+        let mut config = kit();
+        config.ccs811_i2c_address = Some(0x5B);
+        config.co2_sensor = Some(Co2SensorModel::Scd30);
+        config.dht_gpio_pin = Some(4);
+        config.dht_model = DhtModel::Dht22;
+        assert_eq!(selected_drivers(&config), vec![
+            DriverKind::Dht { pin: 4, model: DhtModel::Dht22 },
+            DriverKind::Scd(Co2SensorModel::Scd30),
+            DriverKind::Ccs811 { address: 0x5B },
+        ]);
+        assert_eq!(expected_i2c_devices(&config), vec![
+            (0x61, "Scd30 CO2 sensor".to_string()),
+            (0x5B, "CCS811 air quality sensor".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_environment_from_first_driver() {
+        let metrics = vec![
+            ("temp", 21.0, "C", "dht22"),
+            ("hum", 55.0, "%", "dht22"),
+            ("co2", 800.0, "ppm", "scd30"),
+            ("temp", 23.0, "C", "scd30"),
+        ];
+        assert_eq!(environment(&metrics), Some((55.0, 21.0)));
+        assert_eq!(environment(&metrics[2..]), None);
+    }
+}
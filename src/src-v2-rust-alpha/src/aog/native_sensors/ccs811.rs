@@ -0,0 +1,124 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// CCS811 Module - eCO2 and TVOC from an AMS CCS811 on the I2C bus
+
+use std::thread;
+use std::time::Duration;
+use rppal::i2c::I2c;
+
+const STATUS: u8 = 0x00;
+const MEAS_MODE: u8 = 0x01;
+const ALG_RESULT_DATA: u8 = 0x02;
+const ENV_DATA: u8 = 0x05;
+const HW_ID: u8 = 0x20;
+const ERROR_ID: u8 = 0xE0;
+const APP_START: u8 = 0xF4;
+
+const HW_ID_CCS811: u8 = 0x81;
+const STATUS_ERROR: u8 = 0x01;
+const STATUS_DATA_READY: u8 = 0x08;
+const STATUS_APP_VALID: u8 = 0x10;
+const STATUS_FW_MODE: u8 = 0x80;
+// Drive mode 1: a measurement every second
+const DRIVE_MODE_1S: u8 = 0x10;
+
+pub struct Ccs811 {
+    i2c: I2c,
+    address: u16,
+}
+
+impl Ccs811 {
+    /// Check the hardware id, start the application firmware and begin
+    /// measuring once a second
+    pub fn new(bus: u8, address: u16) -> Result<Self, String> {
+        let mut i2c = I2c::with_bus(bus).map_err(|e| format!("Failed to open I2C bus {}: {}", bus, e))?;
+        i2c.set_slave_address(address).map_err(|e| format!("Failed to address CCS811 at 0x{:02x}: {}", address, e))?;
+        let mut sensor = Ccs811 { i2c, address };
+
+        let hw_id = sensor.read_register(HW_ID, 1)?[0];
+        if hw_id != HW_ID_CCS811 {
+            return Err(format!("Device at 0x{:02x} is not a CCS811 (hardware id 0x{:02x})", address, hw_id));
+        }
+        if sensor.status()? & STATUS_APP_VALID == 0 {
+            return Err("CCS811 has no valid application firmware".to_string());
+        }
+        sensor.write(&[APP_START])?;
+        thread::sleep(Duration::from_millis(100));
+        let status = sensor.status()?;
+        if status & STATUS_FW_MODE == 0 {
+            return Err("CCS811 did not start its application firmware".to_string());
+        }
+        sensor.write(&[MEAS_MODE, DRIVE_MODE_1S])?;
+        Ok(sensor)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.i2c.write(data)
+            .map(|_| ())
+            .map_err(|e| format!("CCS811 at 0x{:02x} write failed: {}", self.address, e))
+    }
+
+    fn read_register(&mut self, register: u8, len: usize) -> Result<Vec<u8>, String> {
+        let mut buffer = vec![0u8; len];
+        self.i2c.write_read(&[register], &mut buffer)
+            .map_err(|e| format!("CCS811 at 0x{:02x} read failed: {}", self.address, e))?;
+        Ok(buffer)
+    }
+
+    fn status(&mut self) -> Result<u8, String> {
+        let status = self.read_register(STATUS, 1)?[0];
+        if status & STATUS_ERROR != 0 {
+            let error_id = self.read_register(ERROR_ID, 1)?[0];
+            return Err(format!("CCS811 reported error 0x{:02x}", error_id));
+        }
+        Ok(status)
+    }
+
+    /// Compensate the algorithm for the measured humidity and temperature
+    pub fn set_environment(&mut self, humidity: f32, temperature: f32) -> Result<(), String> {
+        let env = env_data(humidity, temperature);
+        self.write(&[ENV_DATA, env[0], env[1], env[2], env[3]])
+    }
+
+    /// (eCO2 ppm, TVOC ppb), or None when no new result is ready
+    pub fn read(&mut self) -> Result<Option<(u16, u16)>, String> {
+        if self.status()? & STATUS_DATA_READY == 0 {
+            return Ok(None);
+        }
+        let data = self.read_register(ALG_RESULT_DATA, 4)?;
+        Ok(Some(parse_algorithm_result(&data)))
+    }
+}
+
+/// ENV_DATA encoding: humidity and temperature + 25 °C, both in 1/512 units
+pub fn env_data(humidity: f32, temperature: f32) -> [u8; 4] {
+    let humidity = (humidity.clamp(0.0, 100.0) * 512.0).round() as u16;
+    let temperature = ((temperature.clamp(-25.0, 100.0) + 25.0) * 512.0).round() as u16;
+    let [h_hi, h_lo] = humidity.to_be_bytes();
+    let [t_hi, t_lo] = temperature.to_be_bytes();
+    [h_hi, h_lo, t_hi, t_lo]
+}
+
+/// (eCO2 ppm, TVOC ppb) from the first four bytes of ALG_RESULT_DATA
+pub fn parse_algorithm_result(data: &[u8]) -> (u16, u16) {
+    (u16::from_be_bytes([data[0], data[1]]), u16::from_be_bytes([data[2], data[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_data_encoding() {
+        // Datasheet example: 48.5% RH is 0x6100, as is 23.5 °C
+        assert_eq!(env_data(48.5, 23.5), [0x61, 0x00, 0x61, 0x00]);
+        assert_eq!(env_data(0.0, -25.0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_parse_algorithm_result() {
+        assert_eq!(parse_algorithm_result(&[0x01, 0xC2, 0x00, 0x0F]), (450, 15));
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// DHT Module - DHT11/DHT22 read by bit-banging a GPIO pin
+
+use std::thread;
+use std::time::{Duration, Instant};
+use rppal::gpio::{Gpio, IoPin, Level, Mode, PullUpDown};
+
+use crate::DhtModel;
+
+// Longest any phase of the reply should last
+const PULSE_TIMEOUT_US: u32 = 200;
+// High pulses longer than this are a 1 bit (26-28 µs for 0, 70 µs for 1)
+const ONE_THRESHOLD_US: u32 = 50;
+
+pub struct Dht {
+    pin: IoPin,
+    model: DhtModel,
+}
+
+impl Dht {
+    pub fn new(pin: u8, model: DhtModel) -> Result<Self, String> {
+        let gpio = Gpio::new().map_err(|e| format!("Failed to access GPIO: {}", e))?;
+        let mut pin = gpio.get(pin)
+            .map_err(|e| format!("Failed to get GPIO pin {}: {}", pin, e))?
+            .into_io(Mode::Input);
+        pin.set_pullupdown(PullUpDown::PullUp);
+        Ok(Dht { pin, model })
+    }
+
+    pub fn model(&self) -> DhtModel {
+        self.model
+    }
+
+    /// Microseconds until the pin leaves `level`
+    fn wait_while(&self, level: Level) -> Result<u32, String> {
+        let start = Instant::now();
+        while self.pin.read() == level {
+            let elapsed = start.elapsed().as_micros() as u32;
+            if elapsed > PULSE_TIMEOUT_US {
+                return Err(format!("DHT timed out waiting for the end of a {:?} pulse", level));
+            }
+        }
+        Ok(start.elapsed().as_micros() as u32)
+    }
+
+    /// Temperature in °C and relative humidity in %
+    pub fn read(&mut self) -> Result<(f32, f32), String> {
+        // Start signal: hold the line low, then release it to the pull-up
+        let start_low = match self.model {
+            DhtModel::Dht11 => Duration::from_millis(18),
+            DhtModel::Dht22 => Duration::from_millis(2),
+        };
+        self.pin.set_mode(Mode::Output);
+        self.pin.set_low();
+        thread::sleep(start_low);
+        self.pin.set_high();
+        self.pin.set_mode(Mode::Input);
+
+        // Reply: 80 µs low, 80 µs high, then 40 bits of 50 µs low and a high
+        // pulse whose length is the bit
+        self.wait_while(Level::High)?;
+        self.wait_while(Level::Low)?;
+        self.wait_while(Level::High)?;
+        let mut pulses = [0u32; 40];
+        for pulse in pulses.iter_mut() {
+            self.wait_while(Level::Low)?;
+            *pulse = self.wait_while(Level::High)?;
+        }
+
+        let bytes = decode(&pulses)?;
+        Ok(parse(self.model, &bytes))
+    }
+}
+
+/// Turn the 40 high pulse lengths into bytes and check the checksum
+pub fn decode(pulses: &[u32; 40]) -> Result<[u8; 5], String> {
+    let mut bytes = [0u8; 5];
+    for (i, pulse) in pulses.iter().enumerate() {
+        if *pulse > ONE_THRESHOLD_US {
+            bytes[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    let sum = bytes[..4].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    if sum != bytes[4] {
+        return Err(format!("DHT checksum mismatch: {:02x?}", bytes));
+    }
+    Ok(bytes)
+}
+
+/// Temperature in °C and relative humidity in % from a checked frame
+pub fn parse(model: DhtModel, bytes: &[u8; 5]) -> (f32, f32) {
+    match model {
+        DhtModel::Dht11 => {
+            let humidity = bytes[0] as f32 + bytes[1] as f32 * 0.1;
+            let temperature = bytes[2] as f32 + (bytes[3] & 0x7F) as f32 * 0.1;
+            let sign = if bytes[3] & 0x80 != 0 { -1.0 } else { 1.0 };
+            (sign * temperature, humidity)
+        }
+        DhtModel::Dht22 => {
+            let humidity = u16::from_be_bytes([bytes[0], bytes[1]]) as f32 / 10.0;
+            let temperature = u16::from_be_bytes([bytes[2] & 0x7F, bytes[3]]) as f32 / 10.0;
+            let sign = if bytes[2] & 0x80 != 0 { -1.0 } else { 1.0 };
+            (sign * temperature, humidity)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulses_for(bytes: [u8; 5]) -> [u32; 40] {
+        let mut pulses = [27u32; 40];
+        for (i, pulse) in pulses.iter_mut().enumerate() {
+            if bytes[i / 8] & (0x80 >> (i % 8)) != 0 {
+                *pulse = 70;
+            }
+        }
+        pulses
+    }
+
+    #[test]
+    fn test_decode_and_parse_dht22() {
+        // 65.2% and -10.1°C
+        let frame = [0x02, 0x8C, 0x80, 0x65, 0x73];
+        let bytes = decode(&pulses_for(frame)).unwrap();
+        let (temperature, humidity) = parse(DhtModel::Dht22, &bytes);
+        assert!((humidity - 65.2).abs() < 0.01);
+        assert!((temperature + 10.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_decode_and_parse_dht11() {
+        let frame = [45, 0, 23, 5, 73];
+        let bytes = decode(&pulses_for(frame)).unwrap();
+        let (temperature, humidity) = parse(DhtModel::Dht11, &bytes);
+        assert_eq!(humidity, 45.0);
+        assert!((temperature - 23.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        assert!(decode(&pulses_for([45, 0, 23, 5, 74])).is_err());
+    }
+}
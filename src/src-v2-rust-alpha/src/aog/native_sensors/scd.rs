@@ -0,0 +1,191 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// SCD Module - CO2, temperature and humidity from a Sensirion SCD30 or SCD4x
+
+use std::thread;
+use std::time::Duration;
+use rppal::i2c::I2c;
+
+use crate::Co2SensorModel;
+
+// SCD30 commands
+const SCD30_START_CONTINUOUS: u16 = 0x0010;
+const SCD30_SET_INTERVAL: u16 = 0x4600;
+const SCD30_DATA_READY: u16 = 0x0202;
+const SCD30_READ_MEASUREMENT: u16 = 0x0300;
+const SCD30_INTERVAL_SECS: u16 = 2;
+
+// SCD4x commands
+const SCD4X_START_PERIODIC: u16 = 0x21B1;
+const SCD4X_STOP_PERIODIC: u16 = 0x3F86;
+const SCD4X_DATA_READY: u16 = 0xE4B8;
+const SCD4X_READ_MEASUREMENT: u16 = 0xEC05;
+
+/// Sensirion CRC-8: polynomial 0x31, initial value 0xFF
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFFu8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x31 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Split a response into 16-bit words, checking each word's CRC
+pub fn words(data: &[u8]) -> Result<Vec<u16>, String> {
+    data.chunks(3)
+        .map(|chunk| match chunk {
+            [hi, lo, crc] if crc8(&[*hi, *lo]) == *crc => Ok(u16::from_be_bytes([*hi, *lo])),
+            [hi, lo, crc] => Err(format!("CRC mismatch on word {:02x}{:02x}: got {:02x}", hi, lo, crc)),
+            _ => Err("Truncated response".to_string()),
+        })
+        .collect()
+}
+
+/// SCD30 measurement: three big-endian floats split over two words each
+pub fn parse_scd30(data: &[u8]) -> Result<(f32, f32, f32), String> {
+    let words = words(data)?;
+    if words.len() != 6 {
+        return Err(format!("SCD30 measurement has {} words, expected 6", words.len()));
+    }
+    let float = |i: usize| f32::from_bits(((words[i] as u32) << 16) | words[i + 1] as u32);
+    Ok((float(0), float(2), float(4)))
+}
+
+/// SCD4x measurement: CO2 in ppm and raw temperature and humidity words
+pub fn parse_scd4x(data: &[u8]) -> Result<(f32, f32, f32), String> {
+    let words = words(data)?;
+    if words.len() != 3 {
+        return Err(format!("SCD4x measurement has {} words, expected 3", words.len()));
+    }
+    let co2 = words[0] as f32;
+    let temperature = -45.0 + 175.0 * words[1] as f32 / 65535.0;
+    let humidity = 100.0 * words[2] as f32 / 65535.0;
+    Ok((co2, temperature, humidity))
+}
+
+pub struct Scd {
+    i2c: I2c,
+    model: Co2SensorModel,
+}
+
+impl Scd {
+    /// Start periodic measurement on the sensor
+    pub fn new(bus: u8, model: Co2SensorModel) -> Result<Self, String> {
+        let address = model.i2c_address();
+        let mut i2c = I2c::with_bus(bus).map_err(|e| format!("Failed to open I2C bus {}: {}", bus, e))?;
+        i2c.set_slave_address(address).map_err(|e| format!("Failed to address {:?} at 0x{:02x}: {}", model, address, e))?;
+        let mut sensor = Scd { i2c, model };
+
+        match model {
+            Co2SensorModel::Scd30 => {
+                sensor.command(SCD30_SET_INTERVAL, Some(SCD30_INTERVAL_SECS))?;
+                // No ambient pressure compensation
+                sensor.command(SCD30_START_CONTINUOUS, Some(0))?;
+            }
+            Co2SensorModel::Scd4x => {
+                // Starting while already measuring is an error, so stop first
+                let _ = sensor.command(SCD4X_STOP_PERIODIC, None);
+                thread::sleep(Duration::from_millis(500));
+                sensor.command(SCD4X_START_PERIODIC, None)?;
+            }
+        }
+        Ok(sensor)
+    }
+
+    pub fn model(&self) -> Co2SensorModel {
+        self.model
+    }
+
+    fn command(&mut self, command: u16, argument: Option<u16>) -> Result<(), String> {
+        let mut frame = command.to_be_bytes().to_vec();
+        if let Some(argument) = argument {
+            let bytes = argument.to_be_bytes();
+            frame.extend_from_slice(&bytes);
+            frame.push(crc8(&bytes));
+        }
+        self.i2c.write(&frame)
+            .map(|_| ())
+            .map_err(|e| format!("{:?} command 0x{:04x} failed: {}", self.model, command, e))
+    }
+
+    /// Send a command and read its response after the sensor's processing
+    /// time. The SCD30 does not support repeated starts, so this is two transfers.
+    fn read_command(&mut self, command: u16, len: usize) -> Result<Vec<u8>, String> {
+        self.command(command, None)?;
+        thread::sleep(Duration::from_millis(5));
+        let mut buffer = vec![0u8; len];
+        self.i2c.read(&mut buffer)
+            .map_err(|e| format!("{:?} read 0x{:04x} failed: {}", self.model, command, e))?;
+        Ok(buffer)
+    }
+
+    /// (CO2 ppm, temperature °C, relative humidity %), or None when no new
+    /// measurement is ready
+    pub fn read(&mut self) -> Result<Option<(f32, f32, f32)>, String> {
+        match self.model {
+            Co2SensorModel::Scd30 => {
+                if words(&self.read_command(SCD30_DATA_READY, 3)?)?[0] != 1 {
+                    return Ok(None);
+                }
+                parse_scd30(&self.read_command(SCD30_READ_MEASUREMENT, 18)?).map(Some)
+            }
+            Co2SensorModel::Scd4x => {
+                if words(&self.read_command(SCD4X_DATA_READY, 3)?)?[0] & 0x07FF == 0 {
+                    return Ok(None);
+                }
+                parse_scd4x(&self.read_command(SCD4X_READ_MEASUREMENT, 9)?).map(Some)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc8() {
+        // Sensirion datasheet example
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+        assert_eq!(crc8(&[0x00, 0x00]), 0x81);
+    }
+
+    #[test]
+    fn test_parse_scd30() {
+        // Datasheet example: 439.09 ppm is 0x43DB 0x8C2E
+        let co2 = [0x43, 0xDB, 0xCB, 0x8C, 0x2E, 0x8F];
+        let temperature = 22.5f32.to_bits().to_be_bytes();
+        let humidity = 45.0f32.to_bits().to_be_bytes();
+        let mut data = co2.to_vec();
+        for pair in temperature.chunks(2).chain(humidity.chunks(2)) {
+            data.extend_from_slice(pair);
+            data.push(crc8(pair));
+        }
+        let (co2, temperature, humidity) = parse_scd30(&data).unwrap();
+        assert!((co2 - 439.09).abs() < 0.01);
+        assert_eq!(temperature, 22.5);
+        assert_eq!(humidity, 45.0);
+    }
+
+    #[test]
+    fn test_parse_scd4x() {
+        let mut data = Vec::new();
+        for word in [800u16, 0x6667, 0x5EB9] {
+            let bytes = word.to_be_bytes();
+            data.extend_from_slice(&bytes);
+            data.push(crc8(&bytes));
+        }
+        let (co2, temperature, humidity) = parse_scd4x(&data).unwrap();
+        assert_eq!(co2, 800.0);
+        assert!((temperature - 25.0).abs() < 0.01);
+        assert!((humidity - 37.0).abs() < 0.01);
+
+        data[2] ^= 0xFF;
+        assert!(parse_scd4x(&data).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Particulate Module - SDS011 driver loop: one sensor session per device,
+// duty cycling to save the laser, humidity correction and fault detection
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sds011::SDS011;
+
+use crate::aog::error::AogError;
+use crate::aog::error_monitor;
+use crate::aog::sensors;
+use crate::aog::serial::{SerialRole, SERIAL_DEVICES};
+use crate::{Config, ParticulateConfig};
+
+/// Upper end of the SDS011 measuring range in µg/m³
+pub const MAX_PM: f32 = 999.9;
+pub const PM_UNIT: &str = "ug/m3";
+const SOURCE: &str = "sds011";
+// Failed queries in a row before a continuously running sensor is faulted
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParticulateReading {
+    pub pm25: f32,  // Humidity corrected when a humidity was available
+    pub pm10: f32,
+    pub pm25_raw: f32,
+    pub pm10_raw: f32,
+    pub humidity: Option<f32>,  // Relative humidity used for the correction
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SensorStatus {
+    NotConnected,
+    Ok,
+    Degraded,  // Recent queries failed but a reading is still due
+    Fault,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParticulateHealth {
+    pub status: SensorStatus,
+    pub consecutive_failures: u32,
+    pub total_queries: u64,
+    pub failed_queries: u64,
+    pub last_error: Option<String>,
+    pub last_reading: Option<u64>,
+    pub session_started: Option<u64>,
+}
+
+/// What the driver loop needs from an SDS011, so it can be tested without one
+pub trait ParticulateSensor {
+    fn set_work_period(&mut self, minutes: u8) -> Result<(), String>;
+    /// (pm25, pm10) in µg/m³
+    fn query(&mut self) -> Result<(f32, f32), String>;
+}
+
+struct Sds011Sensor(SDS011);
+
+impl ParticulateSensor for Sds011Sensor {
+    fn set_work_period(&mut self, minutes: u8) -> Result<(), String> {
+        self.0.set_work_period(minutes).map_err(|e| e.to_string())
+    }
+
+    fn query(&mut self) -> Result<(f32, f32), String> {
+        self.0.query().map(|m| (m.pm25, m.pm10)).map_err(|e| e.to_string())
+    }
+}
+
+/// Correct for hygroscopic particle growth, which the SDS011 counts as
+/// larger particles above about 70% RH (fit used by the sensor.community network)
+pub fn humidity_corrected(pm25: f32, pm10: f32, humidity: f32) -> (f32, f32) {
+    let rh = (humidity / 100.0).clamp(0.0, 0.99);
+    (
+        pm25 / (1.0 + 0.48756 * rh.powf(8.60068)),
+        pm10 / (1.0 + 0.81559 * rh.powf(5.83411)),
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+pub struct ParticulateMonitor {
+    config: Mutex<ParticulateConfig>,
+    health: Mutex<ParticulateHealth>,
+    latest: Mutex<Option<ParticulateReading>>,
+}
+
+impl ParticulateMonitor {
+    pub fn new(config: ParticulateConfig) -> Self {
+        ParticulateMonitor {
+            config: Mutex::new(config),
+            health: Mutex::new(ParticulateHealth {
+                status: SensorStatus::NotConnected,
+                consecutive_failures: 0,
+                total_queries: 0,
+                failed_queries: 0,
+                last_error: None,
+                last_reading: None,
+                session_started: None,
+            }),
+            latest: Mutex::new(None),
+        }
+    }
+
+    pub fn configure(&self, config: ParticulateConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    pub fn config(&self) -> ParticulateConfig {
+        self.config.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn health(&self) -> ParticulateHealth {
+        self.health.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn latest(&self) -> Option<ParticulateReading> {
+        self.latest.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Longest gap between readings before the sensor counts as failed.
+    /// A duty cycled sensor only answers once per work period.
+    fn fault_after_seconds(config: &ParticulateConfig) -> u64 {
+        let poll = config.query_interval_seconds.max(1);
+        match config.work_period_minutes {
+            0 => poll * MAX_CONSECUTIVE_FAILURES as u64,
+            minutes => 2 * minutes as u64 * 60 + poll,
+        }
+    }
+
+    /// Apply the work period to a freshly opened sensor
+    pub fn start_session(&self, sensor: &mut dyn ParticulateSensor, now: u64) -> Result<(), String> {
+        let minutes = self.config().work_period_minutes;
+        sensor.set_work_period(minutes)
+            .map_err(|e| format!("Failed to set SDS011 work period to {} minutes: {}", minutes, e))?;
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.status = SensorStatus::Ok;
+        health.consecutive_failures = 0;
+        health.last_error = None;
+        health.session_started = Some(now);
+        Ok(())
+    }
+
+    /// Take one query for both sizes, validate it and apply the correction
+    pub fn sample(&self, sensor: &mut dyn ParticulateSensor, humidity: Option<f32>, now: u64) -> Result<ParticulateReading, String> {
+        let config = self.config();
+        let result = sensor.query().and_then(|(pm25, pm10)| {
+            if !(0.0..=MAX_PM).contains(&pm25) || !(0.0..=MAX_PM).contains(&pm10) {
+                return Err(format!("Implausible reading PM2.5 {} PM10 {}", pm25, pm10));
+            }
+            let humidity = humidity.filter(|_| config.humidity_correction);
+            let (corrected25, corrected10) = match humidity {
+                Some(rh) => humidity_corrected(pm25, pm10, rh),
+                None => (pm25, pm10),
+            };
+            Ok(ParticulateReading {
+                pm25: corrected25,
+                pm10: corrected10,
+                pm25_raw: pm25,
+                pm10_raw: pm10,
+                humidity,
+                timestamp: now,
+            })
+        });
+
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.total_queries += 1;
+        match &result {
+            Ok(reading) => {
+                health.status = SensorStatus::Ok;
+                health.consecutive_failures = 0;
+                health.last_reading = Some(now);
+                *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = Some(reading.clone());
+            }
+            Err(e) => {
+                health.failed_queries += 1;
+                health.consecutive_failures += 1;
+                health.last_error = Some(e.clone());
+                if health.status == SensorStatus::Ok {
+                    health.status = SensorStatus::Degraded;
+                }
+            }
+        }
+        result
+    }
+
+    /// Mark the sensor failed once no reading has arrived within the
+    /// expected gap. Returns true when faulted.
+    pub fn check_fault(&self, now: u64) -> bool {
+        let fault_after = Self::fault_after_seconds(&self.config());
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        let since = health.last_reading.max(health.session_started).unwrap_or(now);
+        if health.consecutive_failures > 0 && now.saturating_sub(since) >= fault_after {
+            health.status = SensorStatus::Fault;
+        }
+        health.status == SensorStatus::Fault
+    }
+
+    /// Close out a session whose device failed or went away
+    pub fn end_session(&self) {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        if health.status != SensorStatus::Fault {
+            health.status = SensorStatus::NotConnected;
+        }
+        health.session_started = None;
+    }
+
+    /// Run one session on an open sensor until it faults
+    fn run_session(&self, sensor: &mut dyn ParticulateSensor, port_name: &str) {
+        if let Err(e) = self.start_session(sensor, now()) {
+            log::warn!("{} on {}", e, port_name);
+            return;
+        }
+        log::info!("SDS011 session started on {}", port_name);
+
+        loop {
+            let config = self.config();
+            let humidity = if config.humidity_correction { sensors::current_humidity() } else { None };
+            match self.sample(sensor, humidity, now()) {
+                Ok(reading) => {
+                    sensors::publish("pm25", reading.pm25, PM_UNIT, SOURCE);
+                    sensors::publish("pm10", reading.pm10, PM_UNIT, SOURCE);
+                }
+                Err(e) => log::debug!("SDS011 query on {} failed: {}", port_name, e),
+            }
+
+            if self.check_fault(now()) {
+                let error = self.health().last_error.unwrap_or_default();
+                log::error!("SDS011 on {} stopped answering: {}", port_name, error);
+                error_monitor::log_error("particulate", "query", &AogError::SensorError(error), Some(port_name.to_string()));
+                sensors::withdraw("pm25");
+                sensors::withdraw("pm10");
+                return;
+            }
+
+            thread::sleep(Duration::from_secs(config.query_interval_seconds.max(1)));
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref PARTICULATE: ParticulateMonitor = ParticulateMonitor::new(ParticulateConfig::default());
+}
+
+/// Start the SDS011 loop, which holds the sensor open while it answers
+/// and hands it back for re-discovery when it does not
+pub fn init(config: &Config) {
+    PARTICULATE.configure(config.particulate_config.clone().unwrap_or_default());
+
+    let _ = thread::Builder::new().name("pm1025_thread".to_string()).spawn(move || loop {
+        let port = SERIAL_DEVICES.wait_for(SerialRole::ParticulateSensor);
+        match SDS011::new(port.port_name.as_str()) {
+            Ok(sensor) => PARTICULATE.run_session(&mut Sds011Sensor(sensor), &port.port_name),
+            Err(e) => log::warn!("Failed to open SDS011 on {}: {}", port.port_name, e),
+        }
+        PARTICULATE.end_session();
+        SERIAL_DEVICES.release(SerialRole::ParticulateSensor);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct MockSensor {
+        work_period: Option<u8>,
+        responses: VecDeque<Result<(f32, f32), String>>,
+    }
+
+    impl MockSensor {
+        fn new(responses: Vec<Result<(f32, f32), String>>) -> Self {
+            MockSensor { work_period: None, responses: responses.into() }
+        }
+    }
+
+    impl ParticulateSensor for MockSensor {
+        fn set_work_period(&mut self, minutes: u8) -> Result<(), String> {
+            self.work_period = Some(minutes);
+            Ok(())
+        }
+
+        fn query(&mut self) -> Result<(f32, f32), String> {
+            self.responses.pop_front().unwrap_or_else(|| Err("timeout".to_string()))
+        }
+    }
+
+    fn config(work_period_minutes: u8) -> ParticulateConfig {
+        ParticulateConfig { work_period_minutes, query_interval_seconds: 10, humidity_correction: true }
+    }
+
+    #[test]
+    fn test_humidity_correction() {
+        assert_eq!(humidity_corrected(10.0, 20.0, 0.0), (10.0, 20.0));
+        let (pm25, pm10) = humidity_corrected(10.0, 20.0, 90.0);
+        assert!(pm25 < 10.0 && pm25 > 7.0);
+        assert!(pm10 < 20.0 && pm10 > 10.0);
+        // Saturated air is clamped rather than dividing by a runaway factor
+        assert_eq!(humidity_corrected(10.0, 20.0, 120.0), humidity_corrected(10.0, 20.0, 99.0));
+    }
+
+    #[test]
+    fn test_single_query_gives_both_sizes() {
+        let monitor = ParticulateMonitor::new(config(5));
+        let mut sensor = MockSensor::new(vec![Ok((12.0, 30.0))]);
+        monitor.start_session(&mut sensor, 0).unwrap();
+        assert_eq!(sensor.work_period, Some(5));
+
+        let reading = monitor.sample(&mut sensor, None, 10).unwrap();
+        assert_eq!((reading.pm25, reading.pm10), (12.0, 30.0));
+        assert_eq!(monitor.latest(), Some(reading));
+        assert!(sensor.responses.is_empty());
+
+        let mut sensor = MockSensor::new(vec![Ok((12.0, 30.0))]);
+        let corrected = monitor.sample(&mut sensor, Some(95.0), 20).unwrap();
+        assert_eq!(corrected.pm25_raw, 12.0);
+        assert!(corrected.pm25 < 12.0);
+        assert_eq!(corrected.humidity, Some(95.0));
+    }
+
+    #[test]
+    fn test_implausible_reading_rejected() {
+        let monitor = ParticulateMonitor::new(config(0));
+        let mut sensor = MockSensor::new(vec![Ok((-1.0, 5.0)), Ok((5.0, 2000.0))]);
+        monitor.start_session(&mut sensor, 0).unwrap();
+        assert!(monitor.sample(&mut sensor, None, 10).is_err());
+        assert!(monitor.sample(&mut sensor, None, 20).is_err());
+        assert!(monitor.latest().is_none());
+        assert_eq!(monitor.health().failed_queries, 2);
+    }
+
+    #[test]
+    fn test_fault_detection_allows_for_work_period() {
+        // Continuous: three missed polls is a fault
+        let monitor = ParticulateMonitor::new(config(0));
+        let mut sensor = MockSensor::new(vec![Ok((5.0, 8.0))]);
+        monitor.start_session(&mut sensor, 0).unwrap();
+        monitor.sample(&mut sensor, None, 0).unwrap();
+        monitor.sample(&mut sensor, None, 10).unwrap_err();
+        assert!(!monitor.check_fault(10));
+        assert_eq!(monitor.health().status, SensorStatus::Degraded);
+        monitor.sample(&mut sensor, None, 30).unwrap_err();
+        assert!(monitor.check_fault(30));
+
+        // Duty cycled: silence between samples is expected
+        let monitor = ParticulateMonitor::new(config(5));
+        let mut sensor = MockSensor::new(vec![Ok((5.0, 8.0))]);
+        monitor.start_session(&mut sensor, 0).unwrap();
+        monitor.sample(&mut sensor, None, 0).unwrap();
+        monitor.sample(&mut sensor, None, 300).unwrap_err();
+        assert!(!monitor.check_fault(300));
+        monitor.sample(&mut sensor, None, 610).unwrap_err();
+        assert!(monitor.check_fault(610));
+
+        monitor.end_session();
+        assert!(monitor.latest().is_none());
+        assert_eq!(monitor.health().status, SensorStatus::Fault);
+    }
+}
@@ -150,12 +150,13 @@ impl PhHistory {
             return PhTrend::Stable;
         }
         
-        let first_avg = recent_readings.iter().take(window_size / 2).sum::<f32>() / (window_size / 2) as f32;
-        let second_avg = recent_readings.iter().skip(window_size / 2).sum::<f32>() / (window_size - window_size / 2) as f32;
+        // Readings are newest first
+        let newer_avg = recent_readings.iter().take(window_size / 2).sum::<f32>() / (window_size / 2) as f32;
+        let older_avg = recent_readings.iter().skip(window_size / 2).sum::<f32>() / (window_size - window_size / 2) as f32;
         
-        if (second_avg - first_avg).abs() < 0.1 {
+        if (newer_avg - older_avg).abs() < 0.1 {
             PhTrend::Stable
-        } else if second_avg > first_avg {
+        } else if newer_avg > older_avg {
             PhTrend::Rising
         } else {
             PhTrend::Falling
@@ -262,7 +263,7 @@ impl PhSensor {
     }
     
     fn read_serial_sensor(&self, port: &str) -> Result<f32, String> {
-        let mut serial_port = SerialPort::open(port, 9600)
+        let serial_port = SerialPort::open(port, 9600)
             .map_err(|e| format!("Failed to open serial port: {}", e))?;
         
         serial_port.write(b"R\r")
@@ -331,10 +332,10 @@ impl PhSensor {
         Ok(reading)
     }
     
-    fn calculate_alert_level(ph_value: f32) -> PhAlertLevel {
-        if ph_value < PH_CRITICAL_MIN || ph_value > PH_CRITICAL_MAX {
+    pub fn calculate_alert_level(ph_value: f32) -> PhAlertLevel {
+        if ph_value <= PH_CRITICAL_MIN || ph_value >= PH_CRITICAL_MAX {
             PhAlertLevel::Critical
-        } else if ph_value < PH_OPTIMAL_MIN || ph_value > PH_OPTIMAL_MAX {
+        } else if !(PH_OPTIMAL_MIN..=PH_OPTIMAL_MAX).contains(&ph_value) {
             PhAlertLevel::Warning
         } else {
             PhAlertLevel::Normal
@@ -407,6 +408,8 @@ impl PhSensor {
     }
     
     pub fn get_status(&self) -> PhSensorStatus {
+        // Takes the history lock itself, so ask before holding it here
+        let adjustment_suggestion = self.get_adjustment_suggestion();
         let calibration = self.calibration.lock().unwrap();
         let history = self.history.lock().unwrap();
         
@@ -419,7 +422,7 @@ impl PhSensor {
                 .unwrap_or(PhAlertLevel::Normal),
             last_calibration: calibration.last_calibration,
             calibration_valid: calibration.point_7.is_some(),
-            adjustment_suggestion: self.get_adjustment_suggestion(),
+            adjustment_suggestion,
         }
     }
 }
@@ -483,21 +486,21 @@ mod tests {
     
     #[test]
     fn test_calibration_calculation() {
-        let mut cal = PhCalibration::default();
-        
-        cal.point_4 = Some(PhCalibrationPoint {
-            ph_value: 4.0,
-            raw_value: 100.0,
-            temperature: 25.0,
-            timestamp: 0,
-        });
-        
-        cal.point_7 = Some(PhCalibrationPoint {
-            ph_value: 7.0,
-            raw_value: 200.0,
-            temperature: 25.0,
-            timestamp: 0,
-        });
+        let mut cal = PhCalibration {
+            point_4: Some(PhCalibrationPoint {
+                ph_value: 4.0,
+                raw_value: 100.0,
+                temperature: 25.0,
+                timestamp: 0,
+            }),
+            point_7: Some(PhCalibrationPoint {
+                ph_value: 7.0,
+                raw_value: 200.0,
+                temperature: 25.0,
+                timestamp: 0,
+            }),
+            ..Default::default()
+        };
         
         cal.calculate_coefficients();
         
@@ -0,0 +1,691 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// PID Module - PID controller with anti-windup, output clamping, derivative
+// filtering and bumpless manual/auto transfer, and the configured loops that
+// drive time-proportioned relays or PWM outputs from a sensor metric
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+use crate::aog::actuators::{ActuatorOutput, ActuatorState, ACTUATORS};
+use crate::aog::gpio::pwm::PWM;
+use crate::aog::sensors;
+use crate::{Config, PidLoopConfig, PidOutputConfig};
+
+// Controller states kept per loop for tuning
+const MAX_HISTORY: usize = 720;
+const TICK_MS: u64 = 250;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PidTuning {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PidMode {
+    Automatic,
+    Manual,
+}
+
+/// One controller update, broken into its terms
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PidState {
+    pub time: f64,
+    pub setpoint: f32,
+    pub measurement: f32,
+    pub proportional: f32,
+    pub integral: f32,
+    pub derivative: f32,
+    pub output: f32,
+    pub mode: PidMode,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pid {
+    tuning: PidTuning,
+    setpoint: f32,
+    output_min: f32,
+    output_max: f32,
+    sample_seconds: f64,
+    derivative_filter: f32,
+    reverse: bool,
+    mode: PidMode,
+    output: f32,
+    integral: f32,
+    filtered_derivative: f32,
+    last_measurement: Option<f32>,
+    last_time: Option<f64>,
+    // Set on the switch to automatic, so the first update starts from the
+    // manual output
+    initialize: bool,
+}
+
+impl Pid {
+    pub fn new(tuning: PidTuning, setpoint: f32, output_min: f32, output_max: f32, sample_seconds: f64) -> Self {
+        Pid {
+            tuning,
+            setpoint,
+            output_min,
+            output_max: output_max.max(output_min),
+            sample_seconds: sample_seconds.max(0.01),
+            derivative_filter: 0.0,
+            reverse: false,
+            mode: PidMode::Automatic,
+            output: output_min,
+            integral: 0.0,
+            filtered_derivative: 0.0,
+            last_measurement: None,
+            last_time: None,
+            initialize: true,
+        }
+    }
+
+    /// Build a controller from a loop's configuration
+    pub fn from_config(config: &PidLoopConfig) -> Self {
+        let mut pid = Pid::new(
+            PidTuning { kp: config.kp, ki: config.ki, kd: config.kd },
+            config.setpoint,
+            config.output_min,
+            config.output_max,
+            config.sample_seconds as f64,
+        );
+        pid.set_derivative_filter(config.derivative_filter);
+        pid.set_reverse(config.reverse);
+        if let Some(output) = config.manual_output {
+            pid.set_manual(output);
+        }
+        pid
+    }
+
+    /// Smoothing of the derivative term, from 0 (none) towards 1
+    pub fn set_derivative_filter(&mut self, filter: f32) {
+        self.derivative_filter = filter.clamp(0.0, 0.99);
+    }
+
+    /// Reverse acting: a higher output lowers the measurement
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    /// Change the tuning. The integral is kept as an output contribution,
+    /// so a new Ki doesn't bump the output.
+    pub fn set_tuning(&mut self, tuning: PidTuning) {
+        self.tuning = tuning;
+    }
+
+    pub fn tuning(&self) -> PidTuning {
+        self.tuning
+    }
+
+    pub fn set_setpoint(&mut self, setpoint: f32) {
+        self.setpoint = setpoint;
+    }
+
+    pub fn setpoint(&self) -> f32 {
+        self.setpoint
+    }
+
+    pub fn mode(&self) -> PidMode {
+        self.mode
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// Hold the output at a fixed value
+    pub fn set_manual(&mut self, output: f32) {
+        self.mode = PidMode::Manual;
+        self.output = output.clamp(self.output_min, self.output_max);
+    }
+
+    /// Return to automatic control, carrying on from the current output
+    pub fn set_automatic(&mut self) {
+        if self.mode == PidMode::Manual {
+            self.initialize = true;
+        }
+        self.mode = PidMode::Automatic;
+    }
+
+    fn error(&self, measurement: f32) -> f32 {
+        if self.reverse { measurement - self.setpoint } else { self.setpoint - measurement }
+    }
+
+    /// Update on a new measurement at `time` seconds. Returns None until a
+    /// sample time has passed since the last update.
+    pub fn update(&mut self, measurement: f32, time: f64) -> Option<PidState> {
+        if self.last_time.is_some_and(|last| time - last < self.sample_seconds) {
+            return None;
+        }
+        let dt = self.last_time.map(|last| time - last).unwrap_or(self.sample_seconds) as f32;
+        self.last_time = Some(time);
+
+        let error = self.error(measurement);
+        let proportional = self.tuning.kp * error;
+
+        if self.mode == PidMode::Manual {
+            // Track the process so the switch back to automatic is bumpless
+            self.last_measurement = Some(measurement);
+            self.filtered_derivative = 0.0;
+            return Some(self.state(time, measurement, proportional, 0.0));
+        }
+
+        if self.initialize {
+            self.initialize = false;
+            self.integral = (self.output - proportional).clamp(self.output_min, self.output_max);
+            self.filtered_derivative = 0.0;
+            self.last_measurement = Some(measurement);
+        }
+
+        // Derivative on the measurement, so setpoint changes don't kick
+        let slope = self.last_measurement.map(|last| (measurement - last) / dt).unwrap_or(0.0);
+        let slope = if self.reverse { slope } else { -slope };
+        self.filtered_derivative = self.derivative_filter * self.filtered_derivative + (1.0 - self.derivative_filter) * slope;
+        let derivative = self.tuning.kd * self.filtered_derivative;
+        self.last_measurement = Some(measurement);
+
+        // Anti-windup: keep the integral within the output range, and only
+        // let it grow into whatever headroom the other terms leave
+        let mut integral = (self.integral + self.tuning.ki * error * dt).clamp(self.output_min, self.output_max);
+        let headroom_high = self.output_max - proportional - derivative;
+        let headroom_low = self.output_min - proportional - derivative;
+        if integral > self.integral && integral > headroom_high {
+            integral = self.integral.max(headroom_high);
+        } else if integral < self.integral && integral < headroom_low {
+            integral = self.integral.min(headroom_low);
+        }
+        self.integral = integral;
+        self.output = (proportional + self.integral + derivative).clamp(self.output_min, self.output_max);
+        Some(self.state(time, measurement, proportional, derivative))
+    }
+
+    fn state(&self, time: f64, measurement: f32, proportional: f32, derivative: f32) -> PidState {
+        PidState {
+            time,
+            setpoint: self.setpoint,
+            measurement,
+            proportional,
+            integral: self.integral,
+            derivative,
+            output: self.output,
+            mode: self.mode,
+        }
+    }
+}
+
+/// Turns a controller output in percent into something physical
+pub trait PidDriver: Send {
+    fn apply(&mut self, percent: f32, time: f64) -> Result<(), String>;
+}
+
+type SharedDriver = Arc<Mutex<Box<dyn PidDriver>>>;
+
+/// On time within fixed-length cycles. The percent is taken at the start of
+/// a cycle, so an output switches at most twice a cycle.
+struct TimeProportioning {
+    cycle_seconds: f64,
+    cycle_start: Option<f64>,
+    on_seconds: f64,
+}
+
+impl TimeProportioning {
+    fn new(cycle_seconds: f64) -> Self {
+        TimeProportioning { cycle_seconds: cycle_seconds.max(1.0), cycle_start: None, on_seconds: 0.0 }
+    }
+
+    /// Whether the output should be on at `time`
+    fn wanted(&mut self, percent: f32, time: f64) -> bool {
+        let start = match self.cycle_start {
+            Some(start) if time - start < self.cycle_seconds => start,
+            _ => {
+                self.cycle_start = Some(time);
+                self.on_seconds = self.cycle_seconds * (percent.clamp(0.0, 100.0) as f64) / 100.0;
+                time
+            }
+        };
+        time - start < self.on_seconds
+    }
+}
+
+/// Switches an on/off output for `percent` of each cycle
+pub struct TimeProportionalDriver {
+    output: ActuatorOutput,
+    cycle: TimeProportioning,
+    on: Option<bool>,
+}
+
+impl TimeProportionalDriver {
+    pub fn new(output: ActuatorOutput, cycle_seconds: f64) -> Self {
+        TimeProportionalDriver { output, cycle: TimeProportioning::new(cycle_seconds), on: None }
+    }
+}
+
+impl PidDriver for TimeProportionalDriver {
+    fn apply(&mut self, percent: f32, time: f64) -> Result<(), String> {
+        let on = self.cycle.wanted(percent, time);
+        if self.on != Some(on) {
+            ACTUATORS.set_output(self.output, ActuatorState::from_bool(on))?;
+            self.on = Some(on);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TimeProportionalDriver {
+    fn drop(&mut self) {
+        let _ = ACTUATORS.set_output(self.output, ActuatorState::Off);
+    }
+}
+
+/// Sets the level of a named PWM channel, which ramps and applies the
+/// emergency stop itself
+pub struct PwmChannelDriver {
+    channel: String,
+    percent: Option<f32>,
+}
+
+impl PwmChannelDriver {
+    pub fn new(channel: &str) -> Result<Self, String> {
+        PWM.set(channel, 0.0)?;
+        Ok(PwmChannelDriver { channel: channel.to_string(), percent: Some(0.0) })
+    }
+}
+
+impl PidDriver for PwmChannelDriver {
+    fn apply(&mut self, percent: f32, _time: f64) -> Result<(), String> {
+        let percent = percent.clamp(0.0, 100.0);
+        if self.percent != Some(percent) {
+            PWM.set(&self.channel, percent)?;
+            self.percent = Some(percent);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PwmChannelDriver {
+    fn drop(&mut self) {
+        let _ = PWM.set(&self.channel, 0.0);
+    }
+}
+
+fn open_driver(config: &PidOutputConfig) -> Result<Box<dyn PidDriver>, String> {
+    match config {
+        PidOutputConfig::TimeProportional { output, cycle_seconds } => {
+            Ok(Box::new(TimeProportionalDriver::new(ActuatorOutput::for_pump(output), *cycle_seconds as f64)))
+        }
+        PidOutputConfig::PwmChannel { channel } => Ok(Box::new(PwmChannelDriver::new(channel)?)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PidLoopStatus {
+    pub name: String,
+    pub metric: String,
+    pub mode: PidMode,
+    pub setpoint: f32,
+    pub tuning: PidTuning,
+    pub output: f32,
+    pub last: Option<PidState>,
+    pub last_error: Option<String>,
+}
+
+pub struct PidLoop {
+    config: PidLoopConfig,
+    pid: Pid,
+    // Shared so the output can be driven without holding PID_LOOPS
+    driver: Option<SharedDriver>,
+    history: VecDeque<PidState>,
+    last_error: Option<String>,
+}
+
+impl PidLoop {
+    pub fn new(config: PidLoopConfig, driver: Option<Box<dyn PidDriver>>) -> Self {
+        let pid = Pid::from_config(&config);
+        PidLoop { config, pid, driver: driver.map(|driver| Arc::new(Mutex::new(driver))), history: VecDeque::new(), last_error: None }
+    }
+
+    /// Update the controller on a measurement and drive the output. Without a
+    /// measurement an automatic loop drives its output to the bottom of its
+    /// range, while a manual one keeps its manual output.
+    pub fn tick(&mut self, measurement: Option<f32>, time: f64) {
+        let percent = self.update(measurement, time);
+        if let Some(driver) = self.driver.clone() {
+            let result = driver.lock().unwrap_or_else(|e| e.into_inner()).apply(percent, time);
+            self.record(result);
+        }
+    }
+
+    /// Update the controller and return the output in percent
+    fn update(&mut self, measurement: Option<f32>, time: f64) -> f32 {
+        match measurement {
+            Some(measurement) => {
+                if let Some(state) = self.pid.update(measurement, time) {
+                    log::debug!("PID {}: {:?}", self.config.name, state);
+                    self.history.push_back(state);
+                    while self.history.len() > MAX_HISTORY {
+                        self.history.pop_front();
+                    }
+                }
+                self.pid.output()
+            }
+            None if self.pid.mode() == PidMode::Manual => self.pid.output(),
+            None => self.config.output_min,
+        }
+    }
+
+    /// Keep the result of driving the output, logging each new error once
+    fn record(&mut self, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.last_error = None,
+            Err(e) => {
+                if self.last_error.as_ref() != Some(&e) {
+                    log::error!("PID {}: {}", self.config.name, e);
+                }
+                self.last_error = Some(e);
+            }
+        }
+    }
+
+    pub fn status(&self) -> PidLoopStatus {
+        PidLoopStatus {
+            name: self.config.name.clone(),
+            metric: self.config.metric.clone(),
+            mode: self.pid.mode(),
+            setpoint: self.pid.setpoint(),
+            tuning: self.pid.tuning(),
+            output: self.pid.output(),
+            last: self.history.back().copied(),
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    pub fn history(&self) -> Vec<PidState> {
+        self.history.iter().copied().collect()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref PID_LOOPS: Mutex<Vec<PidLoop>> = Mutex::new(Vec::new());
+}
+
+fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+/// Start the configured loops
+pub fn init(config: &Config) {
+    let configs = config.pid_loops.clone().unwrap_or_default();
+    if configs.is_empty() {
+        return;
+    }
+
+    let mut loops = PID_LOOPS.lock().unwrap_or_else(|e| e.into_inner());
+    for loop_config in configs {
+        if loops.iter().any(|existing| existing.config.name == loop_config.name) {
+            log::error!("Skipping duplicate PID loop '{}'", loop_config.name);
+            continue;
+        }
+        let mut pid_loop = PidLoop::new(loop_config.clone(), None);
+        match open_driver(&loop_config.output) {
+            Ok(driver) => pid_loop.driver = Some(Arc::new(Mutex::new(driver))),
+            Err(e) => {
+                log::error!("PID loop '{}' has no output: {}", loop_config.name, e);
+                pid_loop.last_error = Some(e);
+            }
+        }
+        log::info!("PID loop '{}' on {} started", loop_config.name, loop_config.metric);
+        loops.push(pid_loop);
+    }
+    drop(loops);
+
+    let _ = thread::Builder::new().name("pid_thread".to_string()).spawn(|| loop {
+        let time = now();
+        let outputs: Vec<(String, f32, SharedDriver)> = PID_LOOPS.lock().unwrap_or_else(|e| e.into_inner())
+            .iter_mut()
+            .filter_map(|pid_loop| {
+                let measurement = sensors::current_value(&pid_loop.config.metric);
+                let percent = pid_loop.update(measurement, time);
+                pid_loop.driver.clone().map(|driver| (pid_loop.config.name.clone(), percent, driver))
+            })
+            .collect();
+
+        // Outputs are driven outside the loop lock, as relays can block
+        for (name, percent, driver) in outputs {
+            let result = driver.lock().unwrap_or_else(|e| e.into_inner()).apply(percent, time);
+            if let Some(pid_loop) = PID_LOOPS.lock().unwrap_or_else(|e| e.into_inner()).iter_mut()
+                .find(|pid_loop| pid_loop.config.name == name) {
+                pid_loop.record(result);
+            }
+        }
+        thread::sleep(Duration::from_millis(TICK_MS));
+    });
+}
+
+pub fn status() -> Vec<PidLoopStatus> {
+    PID_LOOPS.lock().unwrap_or_else(|e| e.into_inner()).iter().map(|pid_loop| pid_loop.status()).collect()
+}
+
+pub fn history(name: &str) -> Option<Vec<PidState>> {
+    PID_LOOPS.lock().unwrap_or_else(|e| e.into_inner()).iter()
+        .find(|pid_loop| pid_loop.config.name == name)
+        .map(|pid_loop| pid_loop.history())
+}
+
+/// Apply a change to a running loop and keep its configuration in step
+fn update_loop(name: &str, change: impl FnOnce(&mut PidLoop)) -> Result<PidLoopStatus, String> {
+    let mut loops = PID_LOOPS.lock().unwrap_or_else(|e| e.into_inner());
+    let pid_loop = loops.iter_mut()
+        .find(|pid_loop| pid_loop.config.name == name)
+        .ok_or_else(|| format!("Unknown PID loop '{}'", name))?;
+    change(pid_loop);
+    let tuning = pid_loop.pid.tuning();
+    pid_loop.config.kp = tuning.kp;
+    pid_loop.config.ki = tuning.ki;
+    pid_loop.config.kd = tuning.kd;
+    pid_loop.config.setpoint = pid_loop.pid.setpoint();
+    pid_loop.config.manual_output = match pid_loop.pid.mode() {
+        PidMode::Manual => Some(pid_loop.pid.output()),
+        PidMode::Automatic => None,
+    };
+    let updated = pid_loop.config.clone();
+    let status = pid_loop.status();
+    drop(loops);
+
+    let mut config = Config::load(0).map_err(|e| format!("Failed to load config: {}", e))?;
+    if let Some(stored) = config.pid_loops.as_mut().and_then(|loops| loops.iter_mut().find(|stored| stored.name == name)) {
+        *stored = updated;
+        config.save().map_err(|e| format!("Failed to save config: {}", e))?;
+    }
+    Ok(status)
+}
+
+/// Change a loop's tuning and, optionally, its setpoint
+pub fn tune(name: &str, tuning: PidTuning, setpoint: Option<f32>) -> Result<PidLoopStatus, String> {
+    if ![tuning.kp, tuning.ki, tuning.kd].iter().all(|gain| gain.is_finite() && *gain >= 0.0) {
+        return Err("Gains must be zero or positive".to_string());
+    }
+    update_loop(name, |pid_loop| {
+        pid_loop.pid.set_tuning(tuning);
+        if let Some(setpoint) = setpoint {
+            pid_loop.pid.set_setpoint(setpoint);
+        }
+    })
+}
+
+/// Switch a loop to automatic, or to manual at a fixed output
+pub fn set_mode(name: &str, manual_output: Option<f32>) -> Result<PidLoopStatus, String> {
+    update_loop(name, |pid_loop| match manual_output {
+        Some(output) => pid_loop.pid.set_manual(output),
+        None => pid_loop.pid.set_automatic(),
+    })
+}
+
+fn format_status(status: &PidLoopStatus) -> String {
+    let measurement = status.last.map(|state| format!("{:.2}", state.measurement)).unwrap_or_else(|| "N/A".to_string());
+    let mut line = format!("{} ({}): {} -> {:.2}, output {:.1}% {:?}, Kp {} Ki {} Kd {}",
+        status.name, status.metric, measurement, status.setpoint, status.output, status.mode,
+        status.tuning.kp, status.tuning.ki, status.tuning.kd);
+    if let Some(error) = &status.last_error {
+        line.push_str(&format!(" - error: {}", error));
+    }
+    line
+}
+
+/// Handle `pid` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    let number = |value: &str| value.parse::<f32>().map_err(|_| format!("Invalid number '{}'", value));
+    let result = match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["pid"] | ["pid", "status"] => {
+            let loops = status();
+            if loops.is_empty() {
+                return "No PID loops".to_string();
+            }
+            return loops.iter().map(format_status).collect::<Vec<_>>().join("\n");
+        }
+        ["pid", "tune", name, kp, ki, kd] => number(kp)
+            .and_then(|kp| Ok(PidTuning { kp, ki: number(ki)?, kd: number(kd)? }))
+            .and_then(|tuning| tune(name, tuning, None)),
+        ["pid", "setpoint", name, setpoint] => number(setpoint).and_then(|setpoint| {
+            let tuning = status().into_iter().find(|status| status.name == *name)
+                .map(|status| status.tuning)
+                .ok_or_else(|| format!("Unknown PID loop '{}'", name))?;
+            tune(name, tuning, Some(setpoint))
+        }),
+        ["pid", "auto", name] => set_mode(name, None),
+        ["pid", "manual", name, output] => number(output).and_then(|output| set_mode(name, Some(output))),
+        _ => return "Usage: pid [status] | pid tune <name> <kp> <ki> <kd> | pid setpoint <name> <value> | pid auto <name> | pid manual <name> <percent>".to_string(),
+    };
+    match result {
+        Ok(status) => format_status(&status),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn pid(kp: f32, ki: f32, kd: f32) -> Pid {
+        Pid::new(PidTuning { kp, ki, kd }, 25.0, 0.0, 100.0, 1.0)
+    }
+
+    #[test]
+    fn test_proportional_and_sample_time() {
+        let mut pid = pid(10.0, 0.0, 0.0);
+        assert_eq!(pid.update(22.0, 0.0).unwrap().output, 30.0);
+        // Too soon for another update
+        assert!(pid.update(20.0, 0.5).is_none());
+        assert_eq!(pid.update(20.0, 1.0).unwrap().output, 50.0);
+        // Clamped to the output range
+        assert_eq!(pid.update(0.0, 2.0).unwrap().output, 100.0);
+        assert_eq!(pid.update(30.0, 3.0).unwrap().output, 0.0);
+
+        // Reverse acting: above the setpoint raises the output
+        pid.set_reverse(true);
+        assert_eq!(pid.update(27.0, 4.0).unwrap().output, 20.0);
+    }
+
+    #[test]
+    fn test_integral_anti_windup() {
+        let mut pid = pid(1.0, 10.0, 0.0);
+        // A long way below the setpoint: saturated, integral stops at the limit
+        for t in 0..50 {
+            pid.update(0.0, t as f64);
+        }
+        assert_eq!(pid.output(), 100.0);
+        assert_eq!(pid.integral, 75.0);
+
+        // Once past the setpoint the output comes down at once rather than
+        // after unwinding 50 seconds of integral
+        let state = pid.update(26.0, 50.0).unwrap();
+        assert_eq!(state.output, 64.0);
+    }
+
+    #[test]
+    fn test_derivative_on_measurement_with_filter() {
+        let mut pid = pid(0.0, 0.0, 10.0);
+        pid.update(20.0, 0.0);
+        // Rising 1 per second opposes the output
+        assert_eq!(pid.update(21.0, 1.0).unwrap().derivative, -10.0);
+        // A setpoint change doesn't kick the derivative
+        pid.set_setpoint(40.0);
+        assert_eq!(pid.update(21.0, 2.0).unwrap().derivative, 0.0);
+
+        let mut filtered = pid_with_filter();
+        filtered.update(20.0, 0.0);
+        assert_eq!(filtered.update(21.0, 1.0).unwrap().derivative, -5.0);
+    }
+
+    fn pid_with_filter() -> Pid {
+        let mut pid = pid(0.0, 0.0, 10.0);
+        pid.set_derivative_filter(0.5);
+        pid
+    }
+
+    #[test]
+    fn test_bumpless_manual_to_automatic() {
+        let mut pid = pid(5.0, 1.0, 0.0);
+        pid.set_manual(40.0);
+        assert_eq!(pid.update(23.0, 0.0).unwrap().output, 40.0);
+        assert_eq!(pid.mode(), PidMode::Manual);
+
+        // The first automatic output continues from the manual one
+        pid.set_automatic();
+        let state = pid.update(23.0, 1.0).unwrap();
+        assert!((state.output - 40.0).abs() < 2.5, "{:?}", state);
+        assert_eq!(state.mode, PidMode::Automatic);
+    }
+
+    struct Recorder(Arc<Mutex<Vec<f32>>>);
+
+    impl PidDriver for Recorder {
+        fn apply(&mut self, percent: f32, _time: f64) -> Result<(), String> {
+            self.0.lock().unwrap().push(percent);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_loop_history_and_missing_reading() {
+        let config: PidLoopConfig = serde_json::from_str(r#"{
+            "name": "ph_down", "metric": "ph", "setpoint": 6.0, "kp": 20.0, "reverse": true,
+            "output": {"type": "time_proportional", "output": {"Relay": {"relay_id": 2}}}
+        }"#).unwrap();
+        assert_eq!(config.sample_seconds, 5.0);
+
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let mut pid_loop = PidLoop::new(config, Some(Box::new(Recorder(Arc::clone(&applied)))));
+        pid_loop.tick(Some(7.0), 0.0);
+        pid_loop.tick(Some(7.0), 1.0);
+        pid_loop.tick(None, 2.0);
+        assert_eq!(*applied.lock().unwrap(), vec![20.0, 20.0, 0.0]);
+        assert_eq!(pid_loop.history().len(), 1);
+        assert_eq!(pid_loop.status().last.unwrap().measurement, 7.0);
+
+        // A manual output holds without a reading
+        pid_loop.pid.set_manual(35.0);
+        pid_loop.tick(None, 3.0);
+        assert_eq!(applied.lock().unwrap().last(), Some(&35.0));
+    }
+
+    #[test]
+    fn test_time_proportioning() {
+        let mut cycle = TimeProportioning::new(10.0);
+        assert!(cycle.wanted(30.0, 0.0));
+        assert!(cycle.wanted(90.0, 2.9));
+        // The new percent waits for the next cycle
+        assert!(!cycle.wanted(90.0, 3.0));
+        assert!(cycle.wanted(90.0, 10.0));
+        assert!(cycle.wanted(90.0, 18.9));
+        assert!(!cycle.wanted(90.0, 19.0));
+    }
+}
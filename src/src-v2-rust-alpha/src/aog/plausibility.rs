@@ -0,0 +1,332 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Plausibility Module - Sanity checks applied to every sensor reading before
+// it is accepted: bounds, rate of change, stuck values and unparseable values
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::aog::error::AogError;
+use crate::aog::error_monitor;
+use crate::PlausibilityRule;
+
+// Rate rejections in a row after which the new level is accepted as real,
+// e.g. a sensor reconnected after a long gap
+const REBASELINE_AFTER: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    Empty,
+    NotANumber(String),
+    OutOfRange { value: f32, min: Option<f32>, max: Option<f32> },
+    RateTooHigh { value: f32, rate_per_minute: f32 },
+    Stuck { value: f32, samples: u32 },
+}
+
+impl Rejection {
+    /// Short name used to count rejections by kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Rejection::Empty => "empty",
+            Rejection::NotANumber(_) => "not_a_number",
+            Rejection::OutOfRange { .. } => "out_of_range",
+            Rejection::RateTooHigh { .. } => "rate_too_high",
+            Rejection::Stuck { .. } => "stuck",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::Empty => write!(f, "empty reading"),
+            Rejection::NotANumber(raw) => write!(f, "not a number: {:?}", raw),
+            Rejection::OutOfRange { value, min, max } => write!(f, "{} outside {:?}..{:?}", value, min, max),
+            Rejection::RateTooHigh { value, rate_per_minute } => write!(f, "{} changing at {:.1}/min", value, rate_per_minute),
+            Rejection::Stuck { value, samples } => write!(f, "stuck at {} for {} samples", value, samples),
+        }
+    }
+}
+
+/// Rules for the metrics this system publishes, used unless the
+/// configuration replaces them
+pub fn default_rules() -> Vec<PlausibilityRule> {
+    let rule = |metric: &str, min: f32, max: f32, rate: Option<f32>, stuck: Option<u32>| PlausibilityRule {
+        metric: metric.to_string(),
+        min: Some(min),
+        max: Some(max),
+        max_rate_per_minute: rate,
+        stuck_samples: stuck,
+    };
+    vec![
+        rule("temp", -20.0, 60.0, Some(5.0), None),
+        rule("hum", 0.0, 100.0, Some(25.0), None),
+        // Lit grow rooms draw CO2 well below outdoor levels, and the CCS811
+        // holds exactly 400 while warming up, so there is no stuck check
+        rule("co2", 0.0, 10000.0, Some(2000.0), None),
+        rule("tvoc", 0.0, 1187.0, None, None),
+        rule("pm25", 0.0, 999.9, None, None),
+        rule("pm10", 0.0, 999.9, None, None),
+        rule("ph", 0.0, 14.0, Some(1.0), None),
+        rule("ph_calibrated", 0.0, 14.0, None, None),
+    ]
+}
+
+/// Parse a reading, ignoring a trailing unit such as "ppm", "%" or "C"
+pub fn parse_number(raw: &str) -> Result<f32, Rejection> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() || trimmed == "N/A" {
+        return Err(Rejection::Empty);
+    }
+    let number = trimmed.trim_end_matches(|c: char| c.is_alphabetic() || c == '%' || c == '°' || c == '/' || c.is_whitespace());
+    match number.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(Rejection::NotANumber(trimmed.to_string())),
+    }
+}
+
+fn check_bounds(rule: &PlausibilityRule, value: f32) -> Result<(), Rejection> {
+    if !value.is_finite() {
+        return Err(Rejection::NotANumber(value.to_string()));
+    }
+    if rule.min.is_some_and(|min| value < min) || rule.max.is_some_and(|max| value > max) {
+        return Err(Rejection::OutOfRange { value, min: rule.min, max: rule.max });
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PlausibilityStats {
+    pub accepted: u64,
+    pub rejected: u64,
+    pub by_kind: HashMap<String, u64>,
+    pub last_rejection: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SourceState {
+    last_value: Option<f32>,
+    last_time: u64,
+    identical: u32,
+    rate_rejections: u32,
+}
+
+pub struct PlausibilityFilter {
+    rules: Mutex<HashMap<String, PlausibilityRule>>,
+    // (metric, source) -> history for the rate and stuck checks
+    state: Mutex<HashMap<(String, String), SourceState>>,
+    stats: Mutex<HashMap<String, PlausibilityStats>>,
+}
+
+impl PlausibilityFilter {
+    pub fn new(rules: Vec<PlausibilityRule>) -> Self {
+        let filter = PlausibilityFilter {
+            rules: Mutex::new(HashMap::new()),
+            state: Mutex::new(HashMap::new()),
+            stats: Mutex::new(HashMap::new()),
+        };
+        filter.configure(rules);
+        filter
+    }
+
+    /// Use the default rules, replaced metric by metric with `overrides`
+    pub fn configure(&self, overrides: Vec<PlausibilityRule>) {
+        let mut rules: HashMap<String, PlausibilityRule> = default_rules().into_iter()
+            .map(|rule| (rule.metric.clone(), rule))
+            .collect();
+        for rule in overrides {
+            rules.insert(rule.metric.clone(), rule);
+        }
+        *self.rules.lock().unwrap_or_else(|e| e.into_inner()) = rules;
+    }
+
+    fn rule(&self, metric: &str) -> Option<PlausibilityRule> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner()).get(metric).cloned()
+    }
+
+    /// Check a new reading from `source`, counting and reporting rejections.
+    /// Metrics without a rule are accepted as they are.
+    pub fn check(&self, metric: &str, source: &str, value: f32, now: u64) -> Result<f32, Rejection> {
+        let rule = match self.rule(metric) {
+            Some(rule) => rule,
+            None => return Ok(value),
+        };
+        let result = check_bounds(&rule, value).and_then(|_| self.check_history(&rule, source, value, now));
+
+        match &result {
+            Ok(_) => self.stats.lock().unwrap_or_else(|e| e.into_inner())
+                .entry(metric.to_string()).or_default().accepted += 1,
+            Err(rejection) => self.reject(metric, source, rejection),
+        }
+        result
+    }
+
+    fn reject(&self, metric: &str, source: &str, rejection: &Rejection) {
+        {
+            let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+            let stats = stats.entry(metric.to_string()).or_default();
+            stats.rejected += 1;
+            *stats.by_kind.entry(rejection.kind().to_string()).or_default() += 1;
+            stats.last_rejection = Some(format!("{} from {}", rejection, source));
+        }
+        log::warn!("Rejected {} reading from {}: {}", metric, source, rejection);
+        error_monitor::log_error(
+            "sensors",
+            "plausibility",
+            &AogError::SensorError(format!("{} from {}: {}", metric, source, rejection)),
+            Some(metric.to_string()),
+        );
+    }
+
+    fn check_history(&self, rule: &PlausibilityRule, source: &str, value: f32, now: u64) -> Result<f32, Rejection> {
+        let mut states = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = states.entry((rule.metric.clone(), source.to_string())).or_default();
+        let last = match state.last_value {
+            Some(last) => last,
+            None => {
+                state.last_value = Some(value);
+                state.last_time = now;
+                return Ok(value);
+            }
+        };
+
+        state.identical = if value == last { state.identical + 1 } else { 0 };
+        if let Some(samples) = rule.stuck_samples {
+            if state.identical >= samples {
+                return Err(Rejection::Stuck { value, samples: state.identical + 1 });
+            }
+        }
+
+        if let Some(max_rate) = rule.max_rate_per_minute {
+            // At least a second, so readings taken together are not divided by zero
+            let minutes = now.saturating_sub(state.last_time).max(1) as f32 / 60.0;
+            let rate_per_minute = (value - last).abs() / minutes;
+            if rate_per_minute > max_rate && state.rate_rejections + 1 < REBASELINE_AFTER {
+                state.rate_rejections += 1;
+                return Err(Rejection::RateTooHigh { value, rate_per_minute });
+            }
+        }
+
+        state.rate_rejections = 0;
+        state.last_value = Some(value);
+        state.last_time = now;
+        Ok(value)
+    }
+
+    /// Bounds and parse check for a stored value, with no history, for values
+    /// read back from the sensor files. Only rejections are counted, as the
+    /// same value is read back many times.
+    pub fn check_stored(&self, metric: &str, raw: &str) -> Result<f32, Rejection> {
+        let rule = match self.rule(metric) {
+            Some(rule) => rule,
+            None => return parse_number(raw),
+        };
+        let result = parse_number(raw).and_then(|value| check_bounds(&rule, value).map(|_| value));
+        if let Err(rejection) = &result {
+            self.reject(metric, "stored value", rejection);
+        }
+        result
+    }
+
+    pub fn has_rule(&self, metric: &str) -> bool {
+        self.rule(metric).is_some()
+    }
+
+    pub fn stats(&self) -> HashMap<String, PlausibilityStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref PLAUSIBILITY: PlausibilityFilter = PlausibilityFilter::new(Vec::new());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("25.5"), Ok(25.5));
+        assert_eq!(parse_number(" 450ppm\n"), Ok(450.0));
+        assert_eq!(parse_number("43.00%"), Ok(43.0));
+        assert_eq!(parse_number("-3.5C"), Ok(-3.5));
+        assert_eq!(parse_number(""), Err(Rejection::Empty));
+        assert_eq!(parse_number("N/A"), Err(Rejection::Empty));
+        assert_eq!(parse_number("NaN"), Err(Rejection::NotANumber("NaN".to_string())));
+        assert_eq!(parse_number("OVERFLOW"), Err(Rejection::NotANumber("OVERFLOW".to_string())));
+    }
+
+    #[test]
+    fn test_bounds_and_rate() {
+        let filter = PlausibilityFilter::new(Vec::new());
+        assert!(matches!(filter.check("temp", "dht22", 85.0, 0), Err(Rejection::OutOfRange { .. })));
+        assert_eq!(filter.check("temp", "dht22", 21.0, 0), Ok(21.0));
+        // 10 degrees in a minute is a glitch
+        assert!(matches!(filter.check("temp", "dht22", 31.0, 60), Err(Rejection::RateTooHigh { .. })));
+        assert_eq!(filter.check("temp", "dht22", 22.0, 120), Ok(22.0));
+        // Another source has its own history
+        assert_eq!(filter.check("temp", "scd30", 30.0, 120), Ok(30.0));
+
+        let stats = filter.stats();
+        assert_eq!(stats["temp"].accepted, 3);
+        assert_eq!(stats["temp"].rejected, 2);
+        assert_eq!(stats["temp"].by_kind["rate_too_high"], 1);
+        // Metrics without a rule pass straight through
+        assert_eq!(filter.check("t1_ovf_level", "arduino", -5.0, 0), Ok(-5.0));
+    }
+
+    #[test]
+    fn test_sustained_step_is_accepted() {
+        let filter = PlausibilityFilter::new(Vec::new());
+        filter.check("hum", "dht22", 40.0, 0).unwrap();
+        for second in 1..REBASELINE_AFTER as u64 {
+            assert!(filter.check("hum", "dht22", 90.0, second).is_err());
+        }
+        assert_eq!(filter.check("hum", "dht22", 90.0, REBASELINE_AFTER as u64), Ok(90.0));
+    }
+
+    #[test]
+    fn test_stuck_detection_and_overrides() {
+        let filter = PlausibilityFilter::new(vec![PlausibilityRule {
+            metric: "co2".to_string(),
+            min: Some(0.0),
+            max: None,
+            max_rate_per_minute: None,
+            stuck_samples: Some(3),
+        }]);
+        // Override adds a stuck check the default rule doesn't have
+        assert_eq!(filter.check("co2", "ccs811", 250.0, 0), Ok(250.0));
+        assert!(filter.check("co2", "ccs811", 250.0, 5).is_ok());
+        assert!(filter.check("co2", "ccs811", 250.0, 10).is_ok());
+        assert!(matches!(filter.check("co2", "ccs811", 250.0, 15), Err(Rejection::Stuck { .. })));
+        assert_eq!(filter.check("co2", "ccs811", 260.0, 20), Ok(260.0));
+    }
+
+    #[test]
+    fn test_check_stored() {
+        let filter = PlausibilityFilter::new(Vec::new());
+        assert_eq!(filter.check_stored("hum", "43.00"), Ok(43.0));
+        assert!(filter.check_stored("hum", "143").is_err());
+        assert!(filter.check_stored("temp", "").is_err());
+
+        // Rejections are counted, accepted stored values are not
+        let stats = filter.stats();
+        assert_eq!(stats["hum"].rejected, 1);
+        assert_eq!(stats["hum"].accepted, 0);
+        assert_eq!(stats["temp"].by_kind.get("empty"), Some(&1));
+    }
+
+    #[test]
+    fn test_default_co2_rule() {
+        let filter = PlausibilityFilter::new(Vec::new());
+        assert_eq!(filter.check("co2", "scd30", 180.0, 0), Ok(180.0));
+        for second in 1..200 {
+            assert_eq!(filter.check("co2", "ccs811", 400.0, second), Ok(400.0));
+        }
+    }
+}
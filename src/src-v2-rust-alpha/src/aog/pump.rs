@@ -24,8 +24,6 @@ use std::sync::mpsc::{self, TryRecvError};
 use std::time::{Duration, Instant};
 use std::thread::sleep;
 
-use rppal::gpio::Gpio;
-
 use std::sync::Mutex;
 
 
@@ -38,12 +36,14 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
 use chrono::{Local, Timelike};
-use crate::aog::error::{AogError, Result, ErrorContext, log_error_with_context};
-use crate::retry_with_backoff;
+use crate::aog::error::{AogError, ErrorContext, log_error_with_context};
 
 // Import pump safety module
-use crate::aog::pump_safety::{PumpSafetyMonitor, PumpType, SAFETY_MONITOR};
+use crate::aog::pump_safety::{PumpType, SAFETY_MONITOR};
+use crate::aog::gpio::{input, output};
 
+// BCM pin of the tank float switch
+pub const FLOAT_SWITCH_PIN: u8 = 16;
 
 #[derive(Debug, Clone)]
 pub struct PumpThread {
@@ -85,8 +85,10 @@ impl Default for PumpThread {
 
 // Helper function to check if pump should run based on photo cycle
 fn is_within_photo_cycle(start: u8, end: u8) -> bool {
-    let current_hour = Local::now().hour() as u8;
-    
+    hour_within_photo_cycle(Local::now().hour() as u8, start, end)
+}
+
+fn hour_within_photo_cycle(current_hour: u8, start: u8, end: u8) -> bool {
     if start < end {
         // Normal case: start=6, end=22 means run from 6am to 10pm
         current_hour >= start && current_hour < end
@@ -98,30 +100,32 @@ fn is_within_photo_cycle(start: u8, end: u8) -> bool {
 
 // Helper function to check safety GPIO pin
 fn check_safety_pin(pin_number: u8) -> bool {
-    let gpio = match Gpio::new() {
-        Ok(g) => g,
-        Err(e) => {
-            log::error!("Failed to initialize GPIO for safety check: {:?}", e);
-            return false; // Fail safe: don't run if we can't check safety
-        }
-    };
-    
-    match gpio.get(pin_number) {
-        Ok(pin) => {
-            let input_pin = pin.into_input();
-            let is_safe = input_pin.is_high();
-            if !is_safe {
-                log::warn!("Safety pin {} is LOW - pump operation blocked", pin_number);
-            }
-            is_safe
+    match input::is_high(pin_number) {
+        Ok(true) => true,
+        Ok(false) => {
+            log::warn!("Safety pin {} is LOW - pump operation blocked", pin_number);
+            false
         },
         Err(e) => {
-            log::error!("Failed to read safety pin {}: {:?}", pin_number, e);
-            false // Fail safe
+            log::error!("Failed to read safety pin {}: {}", pin_number, e);
+            false // Fail safe: don't run if we can't check safety
         }
     }
 }
 
+// Switch the pump pin through the shared GPIO outputs (active-low relay)
+fn switch_pump(pin: u8, on: bool) -> std::result::Result<(), String> {
+    output::set(&pin.to_string(), on).map(|_| ())
+}
+
+// Float switch in the tank, high while it needs more water
+fn float_switch_high() -> bool {
+    input::is_high(FLOAT_SWITCH_PIN).unwrap_or_else(|e| {
+        log::error!("Failed to read float switch: {}", e);
+        false
+    })
+}
+
 pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx: std::sync::mpsc::Receiver<String>){
 
     let pump_thread_lock = match pump_thread.lock() {
@@ -136,14 +140,12 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
     };
 
 
-    // Abort start if device doesn't have a GPIO bus (non-pi devices)
-    let gpio = Gpio::new();
-    if gpio.is_err() {
-        log::warn!("No GIOS bus found. Halting pump thread: {}", pump_thread_lock.id);
+    // Abort start if the pump pin can't be driven (non-pi devices)
+    if let Err(e) = switch_pump(pump_thread_lock.gpio_pin, false) {
+        log::warn!("Pump pin unavailable ({}). Halting pump thread: {}", e, pump_thread_lock.id);
         std::mem::drop(pump_thread_lock);
         return;
     }
-    std::mem::drop(gpio);
 
     log::info!("Starting pump thread: {}", pump_thread_lock.id);
 
@@ -167,8 +169,8 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
         };
 
         // Check if photo cycle is enabled and if we're within the allowed time
-        if pump_thread_lock.photo_cycle_enabled {
-            if !is_within_photo_cycle(pump_thread_lock.photo_cycle_start, pump_thread_lock.photo_cycle_end) {
+        if pump_thread_lock.photo_cycle_enabled
+            && !is_within_photo_cycle(pump_thread_lock.photo_cycle_start, pump_thread_lock.photo_cycle_end) {
                 log::debug!("Pump {} outside photo cycle hours ({}-{})", 
                     pump_thread_lock.id, pump_thread_lock.photo_cycle_start, pump_thread_lock.photo_cycle_end);
                 std::mem::drop(pump_thread_lock);
@@ -184,7 +186,6 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                 }
                 continue;
             }
-        }
 
         // Check safety GPIO pin if configured
         if let Some(safety_pin) = pump_thread_lock.safety_gpio_pin {
@@ -206,57 +207,39 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
             }
         }
 
-        let gpio = Gpio::new();
-
-        match gpio {
-            Ok(u_gpio) => {
-            
-            let pump_pin = match u_gpio.get(pump_thread_lock.gpio_pin) {
-                Ok(pin) => pin,
-                Err(e) => {
-                    let ctx = ErrorContext::new("pump", "pump_pin_get")
-                        .with_details(format!("Failed to get pump pin {}: {}", pump_thread_lock.gpio_pin, e));
-                    let error = AogError::GpioError(e.to_string());
-                    log_error_with_context(&error, &ctx);
-                    std::mem::drop(pump_thread_lock);
-                    sleep(Duration::from_secs(5));
-                    continue;
-                }
-            };
-            
-            let sensor_pin = match u_gpio.get(16) {
-                Ok(pin) => pin,
-                Err(e) => {
-                    let ctx = ErrorContext::new("pump", "sensor_pin_get")
-                        .with_details(format!("Failed to get sensor pin 16: {}", e));
-                    let error = AogError::GpioError(e.to_string());
-                    log_error_with_context(&error, &ctx);
-                    std::mem::drop(pump_thread_lock);
-                    sleep(Duration::from_secs(5));
-                    continue;
-                }
-            };
-            
-            let mut pump_pin_out = pump_pin.into_output();
-            let ovf_sensor_pin = sensor_pin.into_input();
+        let pump_pin = pump_thread_lock.gpio_pin;
+
+        match switch_pump(pump_pin, false) {
+            Ok(()) => {
+
+            if let Err(e) = input::is_high(FLOAT_SWITCH_PIN) {
+                let ctx = ErrorContext::new("pump", "sensor_pin_get")
+                    .with_details(format!("Failed to watch sensor pin {}: {}", FLOAT_SWITCH_PIN, e));
+                let error = AogError::GpioError(e);
+                log_error_with_context(&error, &ctx);
+                std::mem::drop(pump_thread_lock);
+                sleep(Duration::from_secs(5));
+                continue;
+            }
 
                
 
-                // pump off initially
-                pump_pin_out.set_high();
-                
                 // CRITICAL SAFETY CHECK: Check for overflow conditions before operating pump
                 let t1_ovf = crate::aog::sensors::get_value("t1_ovf");
                 let t2_ovf = crate::aog::sensors::get_value("t2_ovf");
                 let sensor_error = std::path::Path::new("/opt/aog/sensors/overflow_error").exists();
                 
-                // If any overflow condition exists, DO NOT operate pump
-                if t1_ovf.contains("OVERFLOW") || t2_ovf.contains("OVERFLOW") || sensor_error {
+                // Latched emergency stop holds every pump off until it is reset
+                if SAFETY_MONITOR.is_emergency_stop_active() {
+                    log::warn!("Emergency stop active - pump {} held off", pump_thread_lock.id);
+                    let _ = switch_pump(pump_pin, false);
+                    sleep(Duration::from_secs(5));
+                } else if t1_ovf.contains("OVERFLOW") || t2_ovf.contains("OVERFLOW") || sensor_error {
                     log::error!("CRITICAL SAFETY: Overflow condition detected - pump operation blocked!");
                     log::error!("Tank 1: {}, Tank 2: {}, Sensor Error: {}", t1_ovf, t2_ovf, sensor_error);
                     
                     // Ensure pump is definitely off
-                    pump_pin_out.set_high();
+                    let _ = switch_pump(pump_pin, false);
                     
                     // Wait before checking again
                     sleep(Duration::from_secs(30));
@@ -265,7 +248,7 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                     log::info!("Pump {} in continuous mode", pump_thread_lock.id);
                     
                     // Run continuously with periodic safety checks
-                    pump_pin_out.set_low(); // Turn pump on
+                    let _ = switch_pump(pump_pin, true); // Turn pump on
                     
                     // Sleep for a short interval to allow safety checks
                     for _ in 0..10 { // Check every second for 10 seconds
@@ -277,15 +260,21 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         
                         if t1_check.contains("OVERFLOW") || t2_check.contains("OVERFLOW") {
                             log::error!("CRITICAL: Overflow detected during continuous pump operation - emergency shutdown!");
-                            pump_pin_out.set_high();
+                            let _ = switch_pump(pump_pin, false);
                             break;
                         }
                         
+                        if SAFETY_MONITOR.is_emergency_stop_active() {
+                            log::error!("Emergency stop during continuous pump operation - stopping pump");
+                            let _ = switch_pump(pump_pin, false);
+                            break;
+                        }
+
                         // Check safety pin during continuous operation
                         if let Some(safety_pin) = pump_thread_lock.safety_gpio_pin {
                             if !check_safety_pin(safety_pin) {
                                 log::error!("Safety pin triggered during continuous operation - stopping pump");
-                                pump_pin_out.set_high();
+                                let _ = switch_pump(pump_pin, false);
                                 break;
                             }
                         }
@@ -293,7 +282,7 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         // Check for stop signal
                         match rx.try_recv() {
                             Ok(_) | Err(TryRecvError::Disconnected) => {
-                                pump_pin_out.set_high();
+                                let _ = switch_pump(pump_pin, false);
                                 stop_pump_thread(Arc::clone(&pump_thread));
                                 std::mem::drop(pump_thread_lock);
                                 return;
@@ -306,7 +295,7 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                     // need more water?
                     // oscillating_state_safety protects against faulty connections to float sensor
                     let mut oscillating_state_safety:u64 = 0;
-                    let mut oscillation_start_time = Instant::now();
+                    let oscillation_start_time = Instant::now();
                     let max_oscillation_time = Duration::from_secs(300); // 5 minutes max
                     
                     // Register pump start with safety monitor
@@ -315,14 +304,20 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         PumpType::Fill  // Determine actual type based on GPIO pin
                     );
                     
-                    while ovf_sensor_pin.is_high(){
+                    while float_switch_high(){
+                        if SAFETY_MONITOR.is_emergency_stop_active() {
+                            log::error!("Emergency stop during pump operation - stopping pump");
+                            let _ = switch_pump(pump_pin, false);
+                            break;
+                        }
+
                         // Double-check overflow status before each pump activation
                         let t1_check = crate::aog::sensors::get_value("t1_ovf");
                         let t2_check = crate::aog::sensors::get_value("t2_ovf");
                         
                         if t1_check.contains("OVERFLOW") || t2_check.contains("OVERFLOW") {
                             log::error!("CRITICAL: Overflow detected during pump operation - emergency shutdown!");
-                            pump_pin_out.set_high();
+                            let _ = switch_pump(pump_pin, false);
                             break;
                         }
                         
@@ -330,7 +325,7 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         if let Some(safety_pin) = pump_thread_lock.safety_gpio_pin {
                             if !check_safety_pin(safety_pin) {
                                 log::error!("Safety pin triggered - stopping pump");
-                                pump_pin_out.set_high();
+                                let _ = switch_pump(pump_pin, false);
                                 break;
                             }
                         }
@@ -338,7 +333,7 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         // Check oscillation time limit
                         if oscillation_start_time.elapsed() > max_oscillation_time {
                             log::warn!("Oscillation time limit exceeded - stopping pump");
-                            pump_pin_out.set_high();
+                            let _ = switch_pump(pump_pin, false);
                             break;
                         }
                         
@@ -348,27 +343,27 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         // Validate oscillation safety with pump safety monitor
                         match SAFETY_MONITOR.check_oscillation_safety(&pump_thread_lock.id, oscillation_period) {
                             Ok(true) => {
-                                if oscillating_state_safety > 10 && ovf_sensor_pin.is_high(){
+                                if oscillating_state_safety > 10 && float_switch_high(){
                                     // pump on
                                     log::debug!("Pump On - Cycle {}", oscillating_state_safety);
-                                    pump_pin_out.set_low();
+                                    let _ = switch_pump(pump_pin, true);
                                     sleep(Duration::from_millis(oscillation_period));
                                 } else {
                                     // pump off
                                     log::debug!("Pump Off - Safety counter: {}", oscillating_state_safety);
-                                    pump_pin_out.set_high();
+                                    let _ = switch_pump(pump_pin, false);
                                     oscillating_state_safety += 1;
                                     sleep(Duration::from_millis(oscillation_period));
                                 }
                             },
                             Ok(false) => {
                                 log::error!("Oscillation safety check failed");
-                                pump_pin_out.set_high();
+                                let _ = switch_pump(pump_pin, false);
                                 break;
                             },
                             Err(e) => {
                                 log::error!("Oscillation safety check failed: {}", e);
-                                pump_pin_out.set_high();
+                                let _ = switch_pump(pump_pin, false);
                                 break;
                             }
                         }
@@ -376,17 +371,14 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
                         // Check runtime limits
                         if !SAFETY_MONITOR.check_runtime_limit(&pump_thread_lock.id, PumpType::Fill) {
                             log::warn!("Runtime limit exceeded for pump {}", pump_thread_lock.id);
-                            pump_pin_out.set_high();
+                            let _ = switch_pump(pump_pin, false);
                             break;
                         }
                     }
                 } 
 
                 // pump off
-                pump_pin_out.set_high();
-
-                // this should make the pump pin available
-                drop(pump_pin_out);
+                let _ = switch_pump(pump_pin, false);
 
                 // Register pump stop with safety monitor
                 SAFETY_MONITOR.register_pump_stop(
@@ -409,9 +401,9 @@ pub fn start(pump_thread: Arc<Mutex<PumpThread>>, _term_now: Arc<AtomicBool>, rx
 
             }
             Err(e) => {
-                let ctx = ErrorContext::new("pump", "gpio_init")
-                    .with_details(format!("GPIO initialization failed: {}", e));
-                let error = AogError::GpioError(e.to_string());
+                let ctx = ErrorContext::new("pump", "pump_pin_set")
+                    .with_details(format!("Failed to switch pump pin {}: {}", pump_pin, e));
+                let error = AogError::GpioError(e);
                 log_error_with_context(&error, &ctx);
                 // If we can't communicate with the GPIO bus...stop the pump...try again
                 stop_physical_pump(Arc::clone(&pump_thread));
@@ -460,30 +452,12 @@ pub fn stop_physical_pump(pump_thread: Arc<Mutex<PumpThread>>){
         }
     };
     
-    match Gpio::new() {
-        Ok(gpio) => {
-            match gpio.get(pump_thread_lock.gpio_pin) {
-                Ok(pin) => {
-                    let mut pin_out = pin.into_output();
-                    pin_out.set_high();
-                },
-                Err(e) => {
-                    let ctx = ErrorContext::new("pump", "stop_physical_pump")
-                        .with_details(format!("Failed to get GPIO pin {}: {}", pump_thread_lock.gpio_pin, e));
-                    let error = AogError::GpioError(e.to_string());
-                    log_error_with_context(&error, &ctx);
-                }
-            }
-        },
-        Err(e) => {
-            let ctx = ErrorContext::new("pump", "stop_physical_pump")
-                .with_details(format!("GPIO init failed: {}", e));
-            let error = AogError::GpioError(e.to_string());
-            log_error_with_context(&error, &ctx);
-        }
+    if let Err(e) = switch_pump(pump_thread_lock.gpio_pin, false) {
+        let ctx = ErrorContext::new("pump", "stop_physical_pump")
+            .with_details(format!("Failed to switch off pump pin {}: {}", pump_thread_lock.gpio_pin, e));
+        let error = AogError::GpioError(e);
+        log_error_with_context(&error, &ctx);
     }
-    
-    let _ = crate::aog::command::run(format!("gpio off {}", pump_thread_lock.gpio_pin));
     std::mem::drop(pump_thread_lock);
 }
 
@@ -512,7 +486,6 @@ pub fn stop(pump_thread: Arc<Mutex<PumpThread>>){
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
     use std::sync::atomic::Ordering;
 
     #[test]
@@ -521,9 +494,9 @@ mod tests {
         assert_eq!(pump.id.len(), 100);
         assert_eq!(pump.gpio_pin, 17);
         assert_eq!(pump.sensor_flag, "T1_OVF: NONE");
-        assert_eq!(pump.running, false);
-        assert_eq!(pump.continuous, false);
-        assert_eq!(pump.photo_cycle_enabled, false);
+        assert!(!pump.running);
+        assert!(!pump.continuous);
+        assert!(!pump.photo_cycle_enabled);
         assert_eq!(pump.photo_cycle_start, 6);
         assert_eq!(pump.photo_cycle_end, 24);
         assert_eq!(pump.safety_gpio_pin, None);
@@ -547,9 +520,9 @@ mod tests {
         assert_eq!(pump.id, "test_pump");
         assert_eq!(pump.gpio_pin, 22);
         assert_eq!(pump.sensor_flag, "CUSTOM_FLAG");
-        assert_eq!(pump.running, true);
-        assert_eq!(pump.continuous, true);
-        assert_eq!(pump.photo_cycle_enabled, true);
+        assert!(pump.running);
+        assert!(pump.continuous);
+        assert!(pump.photo_cycle_enabled);
         assert_eq!(pump.photo_cycle_start, 8);
         assert_eq!(pump.photo_cycle_end, 20);
         assert_eq!(pump.safety_gpio_pin, Some(23));
@@ -557,11 +530,13 @@ mod tests {
 
     #[test]
     fn test_stop_function() {
-        let pump = Arc::new(Mutex::new(PumpThread::default()));
+        let (tx, rx) = mpsc::channel();
+        let pump = Arc::new(Mutex::new(PumpThread { tx, ..PumpThread::default() }));
         let pump_clone = Arc::clone(&pump);
         
         // This should send stop signal through channel
         stop(pump_clone);
+        assert_eq!(rx.try_recv().unwrap(), "stop");
         
         // Verify the channel can still be accessed
         let pump_lock = pump.lock().unwrap();
@@ -598,7 +573,7 @@ mod tests {
         {
             let pump_lock = pump_clone2.lock().unwrap();
             assert_eq!(pump_lock.gpio_pin, 25);
-            assert_eq!(pump_lock.running, true);
+            assert!(pump_lock.running);
         }
     }
 
@@ -634,13 +609,13 @@ mod tests {
         let term_clone = Arc::clone(&term_now);
         
         // Initially false
-        assert_eq!(term_now.load(Ordering::Relaxed), false);
+        assert!(!term_now.load(Ordering::Relaxed));
         
         // Set to true through clone
         term_clone.store(true, Ordering::Relaxed);
         
         // Verify change
-        assert_eq!(term_now.load(Ordering::Relaxed), true);
+        assert!(term_now.load(Ordering::Relaxed));
     }
 
     #[test]
@@ -705,11 +680,15 @@ mod tests {
     #[test]
     fn test_photo_cycle_logic() {
         // Test normal day cycle (6am to 10pm)
-        assert!(is_within_photo_cycle(6, 22));
+        assert!(hour_within_photo_cycle(6, 6, 22));
+        assert!(hour_within_photo_cycle(21, 6, 22));
+        assert!(!hour_within_photo_cycle(22, 6, 22));
+        assert!(!hour_within_photo_cycle(3, 6, 22));
         
         // Test overnight cycle (10pm to 6am)  
-        // Note: This will depend on actual time, so we just test the logic exists
-        let _ = is_within_photo_cycle(22, 6);
+        assert!(hour_within_photo_cycle(23, 22, 6));
+        assert!(hour_within_photo_cycle(2, 22, 6));
+        assert!(!hour_within_photo_cycle(12, 22, 6));
     }
 
     #[test]
@@ -0,0 +1,836 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Pump Jobs Module - Supervised, target-driven fill, drain and transfer
+// operations built on top of the pump safety monitor and water level system
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use std::thread;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::aog::pump_safety::{
+    FlowCalibrationOptions, PumpCalibration, PumpDriver, PumpSafetyMonitor, PumpType, TankGeometry, SAFETY_MONITOR,
+    CRITICAL_HIGH_LEVEL, CRITICAL_LOW_LEVEL, WARNING_HIGH_LEVEL, GpioPumpDriver,
+};
+use crate::aog::qwiic;
+use crate::aog::actuators::{ActuatorOutput, ActuatorState, ACTUATORS};
+use crate::aog::water_level::WATER_LEVEL_SYSTEM;
+use crate::{PumpOutput, RelayBoardConfig, RelayRole};
+
+/// Pump identifiers used with the safety monitor and for calibration files
+pub const FILL_PUMP_ID: &str = "fill_pump";
+pub const DRAIN_PUMP_ID: &str = "drain_pump";
+pub const AUX_PUMP_ID: &str = "aux_pump";
+
+const SUPERVISION_INTERVAL_MS: u64 = 500;
+const MAX_LEVEL_FAILURES: u32 = 3;
+const MAX_JOB_HISTORY: usize = 50;
+
+/// What a job should reach before stopping
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum JobTarget {
+    Percent(f32),
+    Litres(f32),
+}
+
+impl JobTarget {
+    /// Parse `80%` or `5L`
+    pub fn parse(target: &str) -> Result<JobTarget, String> {
+        let target = target.trim().to_lowercase();
+        let parse = |value: &str| value.trim().parse::<f32>()
+            .map_err(|_| format!("Invalid job target '{}', expected e.g. 80% or 5L", target));
+
+        let parsed = if let Some(value) = target.strip_suffix('%') {
+            JobTarget::Percent(parse(value)?)
+        } else if let Some(value) = target.strip_suffix('l') {
+            JobTarget::Litres(parse(value)?)
+        } else {
+            return Err(format!("Invalid job target '{}', expected e.g. 80% or 5L", target));
+        };
+
+        match parsed {
+            JobTarget::Percent(p) if !(0.0..=100.0).contains(&p) => Err(format!("Target {}% is out of range", p)),
+            JobTarget::Litres(l) if l <= 0.0 => Err("Target volume must be positive".to_string()),
+            _ => Ok(parsed),
+        }
+    }
+}
+
+/// High-level pump operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JobKind {
+    Fill { tank_id: String, target: JobTarget },
+    Drain { tank_id: String, target: JobTarget },
+    Transfer { from: String, to: String, litres: f32 },
+}
+
+impl JobKind {
+    /// Build a job from its operation name and arguments
+    pub fn parse(operation: &str, tank_id: &str, target: &str, destination: Option<&str>) -> Result<JobKind, String> {
+        match operation {
+            "fill" => Ok(JobKind::Fill { tank_id: tank_id.to_string(), target: JobTarget::parse(target)? }),
+            "drain" => Ok(JobKind::Drain { tank_id: tank_id.to_string(), target: JobTarget::parse(target)? }),
+            "transfer" => match JobTarget::parse(target)? {
+                JobTarget::Litres(litres) => Ok(JobKind::Transfer {
+                    from: tank_id.to_string(),
+                    to: destination.ok_or("Transfer needs a destination tank")?.to_string(),
+                    litres,
+                }),
+                JobTarget::Percent(_) => Err("Transfers must be given in litres".to_string()),
+            },
+            other => Err(format!("Unknown pump operation '{}'", other)),
+        }
+    }
+
+    /// Pick the pump that performs this job and how it is switched. A pump
+    /// defined in the configuration uses its configured output and type,
+    /// otherwise the relay channel with the pump's role in `relay_boards`.
+    pub fn assignment(&self, relay_boards: &[RelayBoardConfig], safety: &PumpSafetyMonitor) -> Result<PumpAssignment, String> {
+        let pump_id = match self {
+            JobKind::Fill { tank_id, .. } if tank_id == "tank1" => FILL_PUMP_ID,
+            JobKind::Drain { tank_id, .. } if tank_id == "tank1" => DRAIN_PUMP_ID,
+            JobKind::Transfer { from, to, .. } if from == "tank1" && to == "tank2" => AUX_PUMP_ID,
+            _ => return Err(format!("No pump is plumbed for {}", self.describe())),
+        };
+        pump_assignment(pump_id, relay_boards, safety)
+    }
+
+    /// Tank whose level tracks the job's progress
+    pub fn measured_tank(&self) -> &str {
+        match self {
+            JobKind::Fill { tank_id, .. } | JobKind::Drain { tank_id, .. } => tank_id,
+            JobKind::Transfer { to, .. } => to,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let target = |t: &JobTarget| match t {
+            JobTarget::Percent(p) => format!("{:.0}%", p),
+            JobTarget::Litres(l) => format!("{:.1}L", l),
+        };
+        match self {
+            JobKind::Fill { tank_id, target: t } => format!("fill {} to {}", tank_id, target(t)),
+            JobKind::Drain { tank_id, target: t } => format!("drain {} by {}", tank_id, target(t)),
+            JobKind::Transfer { from, to, litres } => format!("transfer {:.1}L from {} to {}", litres, from, to),
+        }
+    }
+}
+
+/// Type and relay role of the built-in pumps
+fn default_pump(pump_id: &str) -> Option<(PumpType, RelayRole)> {
+    match pump_id {
+        FILL_PUMP_ID => Some((PumpType::Fill, RelayRole::FillPump)),
+        DRAIN_PUMP_ID => Some((PumpType::Drain, RelayRole::DrainPump)),
+        AUX_PUMP_ID => Some((PumpType::Auxiliary, RelayRole::AuxTankPump)),
+        _ => None,
+    }
+}
+
+/// Resolve a pump by id. A pump defined in the configuration uses its
+/// configured output and type, a built-in pump the relay channel with its
+/// role in `relay_boards`.
+pub fn pump_assignment(pump_id: &str, relay_boards: &[RelayBoardConfig], safety: &PumpSafetyMonitor) -> Result<PumpAssignment, String> {
+    if let Some(definition) = safety.pump_definition(pump_id) {
+        return Ok(PumpAssignment {
+            pump_id: definition.id,
+            pump_type: definition.pump_type,
+            output: definition.output,
+        });
+    }
+
+    let (pump_type, role) = default_pump(pump_id)
+        .ok_or_else(|| format!("Unknown pump '{}'", pump_id))?;
+    let (board, relay_id) = qwiic::find_role(relay_boards, role)
+        .ok_or_else(|| format!("No relay channel has the {:?} role for {}", role, pump_id))?;
+    Ok(PumpAssignment {
+        pump_id: pump_id.to_string(),
+        pump_type,
+        output: PumpOutput::Relay { relay_id, board: Some(board) },
+    })
+}
+
+fn pump_driver(output: &PumpOutput) -> Result<Box<dyn PumpDriver>, String> {
+    Ok(match ActuatorOutput::for_pump(output) {
+        ActuatorOutput::Relay { board, relay_id } => Box::new(RelayPumpDriver::new(board, relay_id)),
+        ActuatorOutput::Gpio { pin } => Box::new(GpioPumpDriver::new(pin)?),
+    })
+}
+
+/// Pump and output selected for a job
+#[derive(Debug, Clone, PartialEq)]
+pub struct PumpAssignment {
+    pub pump_id: String,
+    pub pump_type: PumpType,
+    pub output: PumpOutput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Progress and outcome of a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PumpJob {
+    pub id: u64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub pump_id: Option<String>,
+    pub start_level_percent: Option<f32>,
+    pub level_percent: Option<f32>,
+    pub moved_litres: f32,
+    pub runtime_seconds: f32,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+    pub message: Option<String>,
+}
+
+impl PumpJob {
+    fn new(id: u64, kind: JobKind) -> Self {
+        PumpJob {
+            id,
+            kind,
+            status: JobStatus::Pending,
+            pump_id: None,
+            start_level_percent: None,
+            level_percent: None,
+            moved_litres: 0.0,
+            runtime_seconds: 0.0,
+            created_at: Local::now().to_rfc3339(),
+            finished_at: None,
+            message: None,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.status == JobStatus::Pending || self.status == JobStatus::Running
+    }
+}
+
+/// Pump driven through a Qwiic relay channel, switched through the actuator registry
+pub struct RelayPumpDriver {
+    output: ActuatorOutput,
+}
+
+impl RelayPumpDriver {
+    pub fn new(board: u16, relay_id: u16) -> Self {
+        RelayPumpDriver { output: ActuatorOutput::Relay { board, relay_id } }
+    }
+}
+
+impl PumpDriver for RelayPumpDriver {
+    fn set_running(&mut self, on: bool) -> Result<(), String> {
+        ACTUATORS.set_output(self.output, ActuatorState::from_bool(on)).map(|_| ())
+    }
+}
+
+impl Drop for RelayPumpDriver {
+    fn drop(&mut self) {
+        let _ = ACTUATORS.set_output(self.output, ActuatorState::Off);
+    }
+}
+
+/// Supervision parameters for a running job
+#[derive(Debug, Clone)]
+pub struct JobLimits {
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub tank: TankGeometry,
+}
+
+fn litres_between(from_percent: f32, to_percent: f32, tank: &TankGeometry) -> f32 {
+    (to_percent - from_percent).abs() / 100.0 * tank.height_cm * tank.litres_per_cm
+}
+
+/// Run a job to completion. The pump is switched off on every exit path.
+pub fn execute(
+    job: &Mutex<PumpJob>,
+    assignment: &PumpAssignment,
+    driver: &mut dyn PumpDriver,
+    level_percent: &mut dyn FnMut(&str) -> Result<f32, String>,
+    safety: &PumpSafetyMonitor,
+    cancel: &AtomicBool,
+    limits: &JobLimits,
+) -> JobStatus {
+    let kind = job.lock().unwrap().kind.clone();
+    let finish = |status: JobStatus, message: String| {
+        let mut job = job.lock().unwrap();
+        match status {
+            JobStatus::Completed => log::info!("Pump job {} ({}) completed: {}", job.id, kind.describe(), message),
+            _ => log::warn!("Pump job {} ({}) {:?}: {}", job.id, kind.describe(), status, message),
+        }
+        job.status = status;
+        job.message = Some(message);
+        job.finished_at = Some(Local::now().to_rfc3339());
+        status
+    };
+
+    if let Err(e) = safety.can_start_pump(&assignment.pump_id, assignment.pump_type.clone()) {
+        return finish(JobStatus::Failed, e);
+    }
+
+    let measured = kind.measured_tank().to_string();
+    let start_level = match level_percent(&measured) {
+        Ok(level) => level,
+        Err(e) => return finish(JobStatus::Failed, format!("Cannot read level of {}: {}", measured, e)),
+    };
+
+    // Preconditions on the target itself
+    let precheck = match &kind {
+        JobKind::Fill { target: JobTarget::Percent(p), .. } if *p > WARNING_HIGH_LEVEL =>
+            Some(Err(format!("Fill target {:.0}% is above the {:.0}% safety limit", p, WARNING_HIGH_LEVEL))),
+        JobKind::Fill { target: JobTarget::Percent(p), .. } if start_level >= *p =>
+            Some(Ok(format!("Already at {:.1}%", start_level))),
+        JobKind::Drain { target: JobTarget::Percent(p), .. } if *p < CRITICAL_LOW_LEVEL =>
+            Some(Err(format!("Drain target {:.0}% is below the {:.0}% safety limit", p, CRITICAL_LOW_LEVEL))),
+        JobKind::Drain { target: JobTarget::Percent(p), .. } if start_level <= *p =>
+            Some(Ok(format!("Already at {:.1}%", start_level))),
+        JobKind::Transfer { from, .. } => match level_percent(from) {
+            Ok(level) if level <= CRITICAL_LOW_LEVEL => Some(Err(format!("Source {} is nearly empty ({:.1}%)", from, level))),
+            Err(e) => Some(Err(format!("Cannot read level of {}: {}", from, e))),
+            _ => None,
+        },
+        _ => None,
+    };
+    match precheck {
+        Some(Ok(msg)) => return finish(JobStatus::Completed, msg),
+        Some(Err(msg)) => return finish(JobStatus::Failed, msg),
+        None => {}
+    }
+
+    {
+        let mut job = job.lock().unwrap();
+        job.status = JobStatus::Running;
+        job.pump_id = Some(assignment.pump_id.clone());
+        job.start_level_percent = Some(start_level);
+        job.level_percent = Some(start_level);
+    }
+
+    safety.register_pump_start(assignment.pump_id.clone(), assignment.pump_type.clone());
+    if let Err(e) = driver.set_running(true) {
+        let _ = driver.set_running(false);
+        safety.register_pump_stop(assignment.pump_id.clone(), format!("Failed to start: {}", e));
+        return finish(JobStatus::Failed, format!("Failed to start pump: {}", e));
+    }
+
+    let started = Instant::now();
+    let mut level_failures = 0;
+    let mut source_failures = 0;
+    let outcome = loop {
+        thread::sleep(limits.interval);
+
+        if cancel.load(Ordering::SeqCst) {
+            break (JobStatus::Cancelled, "Cancelled by user".to_string());
+        }
+        if safety.is_emergency_stop_active() {
+            break (JobStatus::Failed, "Emergency stop activated".to_string());
+        }
+        if !safety.check_runtime_limit(&assignment.pump_id, assignment.pump_type.clone()) {
+            break (JobStatus::Failed, "Pump runtime limit reached".to_string());
+        }
+        if started.elapsed() > limits.timeout {
+            break (JobStatus::Failed, format!("Timed out after {}s before reaching target", started.elapsed().as_secs()));
+        }
+
+        let level = match level_percent(&measured) {
+            Ok(level) => {
+                level_failures = 0;
+                level
+            }
+            Err(e) => {
+                level_failures += 1;
+                log::warn!("Pump job level reading failed ({}/{}): {}", level_failures, MAX_LEVEL_FAILURES, e);
+                if level_failures >= MAX_LEVEL_FAILURES {
+                    break (JobStatus::Failed, format!("Lost level sensor for {}: {}", measured, e));
+                }
+                continue;
+            }
+        };
+
+        let moved = litres_between(start_level, level, &limits.tank);
+        {
+            let mut job = job.lock().unwrap();
+            job.level_percent = Some(level);
+            job.moved_litres = moved;
+            job.runtime_seconds = started.elapsed().as_secs_f32();
+        }
+
+        // Safety limits on the tanks being changed
+        match &kind {
+            JobKind::Fill { .. } | JobKind::Transfer { .. } if level >= CRITICAL_HIGH_LEVEL => {
+                break (JobStatus::Failed, format!("{} reached critical level {:.1}%", measured, level));
+            }
+            JobKind::Drain { .. } if level <= CRITICAL_LOW_LEVEL => {
+                break (JobStatus::Failed, format!("{} reached critical low level {:.1}%", measured, level));
+            }
+            JobKind::Transfer { from, .. } => match level_percent(from) {
+                Ok(source) => {
+                    source_failures = 0;
+                    if source <= CRITICAL_LOW_LEVEL {
+                        break (JobStatus::Failed, format!("Source {} ran low ({:.1}%)", from, source));
+                    }
+                }
+                Err(e) => {
+                    // Without the source level the pump could run the source tank dry
+                    source_failures += 1;
+                    log::warn!("Pump job source level reading failed ({}/{}): {}", source_failures, MAX_LEVEL_FAILURES, e);
+                    if source_failures >= MAX_LEVEL_FAILURES {
+                        break (JobStatus::Failed, format!("Lost level sensor for source {}: {}", from, e));
+                    }
+                }
+            },
+            _ => {}
+        }
+
+        let reached = match &kind {
+            JobKind::Fill { target: JobTarget::Percent(p), .. } => level >= *p,
+            JobKind::Drain { target: JobTarget::Percent(p), .. } => level <= *p,
+            JobKind::Fill { target: JobTarget::Litres(l), .. } => level > start_level && moved >= *l,
+            JobKind::Drain { target: JobTarget::Litres(l), .. } => level < start_level && moved >= *l,
+            JobKind::Transfer { litres, .. } => level > start_level && moved >= *litres,
+        };
+        if reached {
+            break (JobStatus::Completed, format!("Target reached at {:.1}% ({:.1}L moved)", level, moved));
+        }
+    };
+
+    if let Err(e) = driver.set_running(false) {
+        log::error!("CRITICAL: failed to switch off pump {}: {}", assignment.pump_id, e);
+    }
+    safety.register_pump_stop(assignment.pump_id.clone(), outcome.1.clone());
+    job.lock().unwrap().runtime_seconds = started.elapsed().as_secs_f32();
+
+    finish(outcome.0, outcome.1)
+}
+
+/// Timeout for a job: the pump's runtime limit, tightened using the pump's
+/// flow calibration when the volume to move is known
+fn job_timeout(kind: &JobKind, assignment: &PumpAssignment) -> Duration {
+    let max = Duration::from_secs(SAFETY_MONITOR
+        .safety_profile(&assignment.pump_id, &assignment.pump_type).max_runtime_seconds);
+    let litres = match kind {
+        JobKind::Fill { target: JobTarget::Litres(l), .. } | JobKind::Drain { target: JobTarget::Litres(l), .. } => *l,
+        JobKind::Transfer { litres, .. } => *litres,
+        _ => return max,
+    };
+
+    PumpCalibration::load(&assignment.pump_id)
+        .and_then(|cal| cal.duration_for_volume(litres))
+        .map(|expected| (expected.mul_f32(1.5) + Duration::from_secs(30)).min(max))
+        .unwrap_or(max)
+}
+
+fn system_level_percent(tank_id: &str) -> Result<f32, String> {
+    let guard = WATER_LEVEL_SYSTEM.lock().unwrap();
+    let system = guard.as_ref().ok_or("Water level system is not initialized")?;
+    let reading = system.get_tank_level(tank_id)
+        .ok_or_else(|| format!("No water level sensor for {}", tank_id))?;
+    if reading.is_valid {
+        Ok(reading.level_percent)
+    } else {
+        Err(reading.error_message.unwrap_or_else(|| "Invalid reading".to_string()))
+    }
+}
+
+fn system_tank_geometry() -> TankGeometry {
+    let guard = WATER_LEVEL_SYSTEM.lock().unwrap();
+    let config = guard.as_ref()
+        .map(|system| system.config().clone())
+        .unwrap_or_default();
+    TankGeometry { height_cm: config.tank_height_cm, litres_per_cm: config.litres_per_cm }
+}
+
+/// Runs pump jobs one at a time and keeps their history
+pub struct JobManager {
+    jobs: Mutex<VecDeque<Arc<Mutex<PumpJob>>>>,
+    active: Mutex<Option<(u64, Arc<AtomicBool>)>>,
+    next_id: AtomicU64,
+    calibrating: Arc<Mutex<Option<String>>>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        JobManager {
+            jobs: Mutex::new(VecDeque::new()),
+            active: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+            calibrating: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Start a job in the background and return its id
+    pub fn submit(&self, kind: JobKind) -> Result<u64, String> {
+        let assignment = kind.assignment(&qwiic::relay_boards(), &SAFETY_MONITOR)?;
+
+        let mut active = self.active.lock().unwrap();
+        if let Some((id, _)) = active.as_ref() {
+            if self.get(*id).map(|j| j.is_active()).unwrap_or(false) {
+                return Err(format!("Pump job {} is still running", id));
+            }
+        }
+
+        if let Some(pump_id) = self.calibrating.lock().unwrap().as_ref() {
+            return Err(format!("Pump {} is being calibrated", pump_id));
+        }
+
+        let mut driver = pump_driver(&assignment.output)?;
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let job = Arc::new(Mutex::new(PumpJob::new(id, kind.clone())));
+        let cancel = Arc::new(AtomicBool::new(false));
+        *active = Some((id, Arc::clone(&cancel)));
+        drop(active);
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.push_back(Arc::clone(&job));
+            while jobs.len() > MAX_JOB_HISTORY {
+                jobs.pop_front();
+            }
+        }
+
+        log::info!("Starting pump job {}: {}", id, kind.describe());
+
+        let limits = JobLimits {
+            interval: Duration::from_millis(SUPERVISION_INTERVAL_MS),
+            timeout: job_timeout(&kind, &assignment),
+            tank: system_tank_geometry(),
+        };
+        thread::Builder::new()
+            .name(format!("pump-job-{}", id))
+            .spawn(move || {
+                let mut levels = system_level_percent;
+                execute(&job, &assignment, driver.as_mut(), &mut levels, &SAFETY_MONITOR, &cancel, &limits);
+            })
+            .map_err(|e| format!("Failed to start pump job thread: {}", e))?;
+
+        Ok(id)
+    }
+
+    /// Request cancellation of a job (the active one if no id is given)
+    pub fn cancel(&self, id: Option<u64>) -> Result<u64, String> {
+        let active = self.active.lock().unwrap();
+        match active.as_ref() {
+            Some((active_id, cancel)) if id.unwrap_or(*active_id) == *active_id => {
+                cancel.store(true, Ordering::SeqCst);
+                Ok(*active_id)
+            }
+            _ => Err("No matching pump job is running".to_string()),
+        }
+    }
+
+    /// Start a flow calibration of a pump in the background. No jobs run
+    /// until it finishes; the result is saved for `PumpCalibration::load`.
+    pub fn calibrate(&self, pump_id: &str, options: FlowCalibrationOptions) -> Result<(), String> {
+        let assignment = pump_assignment(pump_id, &qwiic::relay_boards(), &SAFETY_MONITOR)?;
+
+        let active = self.active.lock().unwrap();
+        if let Some((id, _)) = active.as_ref() {
+            if self.get(*id).map(|j| j.is_active()).unwrap_or(false) {
+                return Err(format!("Pump job {} is still running", id));
+            }
+        }
+        let mut calibrating = self.calibrating.lock().unwrap();
+        if let Some(other) = calibrating.as_ref() {
+            return Err(format!("Pump {} is already being calibrated", other));
+        }
+
+        let mut driver = pump_driver(&assignment.output)?;
+        *calibrating = Some(assignment.pump_id.clone());
+        drop(calibrating);
+        drop(active);
+
+        log::info!("Starting flow calibration of {}", assignment.pump_id);
+        let calibrating = Arc::clone(&self.calibrating);
+        let spawned = thread::Builder::new()
+            .name(format!("pump-calibration-{}", assignment.pump_id))
+            .spawn(move || {
+                match SAFETY_MONITOR.calibrate_pump(&assignment.pump_id, assignment.pump_type, driver.as_mut(), options) {
+                    Ok(cal) => log::info!("Pump {} calibrated: {:.2} L/min (stddev {:.2})", 
+                        cal.pump_id, cal.flow_rate_lpm, cal.flow_rate_stddev_lpm),
+                    Err(e) => log::error!("Calibration of pump {} failed: {}", assignment.pump_id, e),
+                }
+                *calibrating.lock().unwrap() = None;
+            });
+        if let Err(e) = spawned {
+            *self.calibrating.lock().unwrap() = None;
+            return Err(format!("Failed to start calibration thread: {}", e));
+        }
+        Ok(())
+    }
+
+    /// Pump currently being calibrated, if any
+    pub fn calibrating(&self) -> Option<String> {
+        self.calibrating.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: u64) -> Option<PumpJob> {
+        self.jobs.lock().unwrap().iter()
+            .map(|job| job.lock().unwrap().clone())
+            .find(|job| job.id == id)
+    }
+
+    /// All retained jobs, oldest first
+    pub fn list(&self) -> Vec<PumpJob> {
+        self.jobs.lock().unwrap().iter()
+            .map(|job| job.lock().unwrap().clone())
+            .collect()
+    }
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref JOB_MANAGER: JobManager = JobManager::new();
+}
+
+fn format_job(job: &PumpJob) -> String {
+    format!("#{} {} - {:?}, {:.1}L moved in {:.0}s{}", job.id, job.kind.describe(), job.status,
+        job.moved_litres, job.runtime_seconds,
+        job.message.as_ref().map(|m| format!(" ({})", m)).unwrap_or_default())
+}
+
+/// Handle `pump` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let submit = |kind: Result<JobKind, String>| match kind.and_then(|k| JOB_MANAGER.submit(k)) {
+        Ok(id) => format!("Started pump job {}", id),
+        Err(e) => format!("Pump job rejected: {}", e),
+    };
+
+    match parts.as_slice() {
+        ["pump", op @ ("fill" | "drain"), tank, target] => submit(JobKind::parse(op, tank, target, None)),
+        ["pump", "transfer", from, to, litres] => submit(JobKind::parse("transfer", from, litres, Some(to))),
+        ["pump", "stop"] | ["pump", "cancel"] => match JOB_MANAGER.cancel(None) {
+            Ok(id) => format!("Cancelling pump job {}", id),
+            Err(e) => e,
+        },
+        ["pump", "cancel", id] => match id.parse::<u64>() {
+            Ok(id) => match JOB_MANAGER.cancel(Some(id)) {
+                Ok(id) => format!("Cancelling pump job {}", id),
+                Err(e) => e,
+            },
+            Err(_) => format!("Invalid job id '{}'", id),
+        },
+        ["pump", "calibrate", pump_id, rest @ ..] => {
+            let mut options = FlowCalibrationOptions::default();
+            if let Some(runs) = rest.first() {
+                match runs.parse::<u32>() {
+                    Ok(runs) if runs > 0 => options.runs = runs,
+                    _ => return format!("Invalid run count '{}'", runs),
+                }
+            }
+            if let Some(seconds) = rest.get(1) {
+                match seconds.parse::<u64>() {
+                    Ok(seconds) if seconds > 0 => options.run_duration = Duration::from_secs(seconds),
+                    _ => return format!("Invalid run duration '{}'", seconds),
+                }
+            }
+            match JOB_MANAGER.calibrate(pump_id, options) {
+                Ok(()) => format!("Calibrating {}, see `pump calibration {}` when done", pump_id, pump_id),
+                Err(e) => format!("Calibration rejected: {}", e),
+            }
+        }
+        ["pump", "calibration", pump_id] => match PumpCalibration::load(pump_id) {
+            _ if JOB_MANAGER.calibrating().as_deref() == Some(*pump_id) => format!("{} is being calibrated", pump_id),
+            Some(cal) => format!("{}: {:.2} L/min (stddev {:.2}) over {} runs on {}, calibrated {}", cal.pump_id, 
+                cal.flow_rate_lpm, cal.flow_rate_stddev_lpm, cal.runs, cal.tank_id, cal.timestamp),
+            None => format!("{} has not been calibrated", pump_id),
+        },
+        ["pump", "service", ..] | ["pump", "maintenance", ..] => crate::aog::pump_safety::run_command(command),
+        ["pump", "status"] | ["pump", "jobs"] => {
+            let jobs = JOB_MANAGER.list();
+            if jobs.is_empty() {
+                "No pump jobs".to_string()
+            } else {
+                jobs.iter().map(format_job).collect::<Vec<_>>().join("\n")
+            }
+        }
+        _ => "Usage: pump fill|drain <tank> <N%|NL> | pump transfer <from> <to> <NL> | pump jobs | pump cancel [id] | pump calibrate <id> [runs] [seconds] | pump calibration <id> | pump maintenance [id] | pump service <id> [notes]".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aog::pump_safety::MockPumpDriver;
+
+    fn fast_limits() -> JobLimits {
+        JobLimits {
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+            tank: TankGeometry { height_cm: 100.0, litres_per_cm: 1.0 },
+        }
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        assert_eq!(JobTarget::parse("80%").unwrap(), JobTarget::Percent(80.0));
+        assert_eq!(JobTarget::parse("5L").unwrap(), JobTarget::Litres(5.0));
+        assert!(JobTarget::parse("120%").is_err());
+        assert!(JobTarget::parse("5").is_err());
+
+        let transfer = JobKind::parse("transfer", "tank1", "5l", Some("tank2")).unwrap();
+        assert_eq!(transfer.measured_tank(), "tank2");
+        assert!(JobKind::parse("transfer", "tank1", "50%", Some("tank2")).is_err());
+    }
+
+    #[test]
+    fn test_pump_assignment() {
+        let relays = qwiic::default_relay_layout();
+        let fill = JobKind::parse("fill", "tank1", "50%", None).unwrap();
+        let safety = PumpSafetyMonitor::new();
+        let assignment = fill.assignment(&relays, &safety).unwrap();
+        assert_eq!(assignment.pump_id, FILL_PUMP_ID);
+        assert_eq!(assignment.output, PumpOutput::Relay { relay_id: 3, board: Some(0x25) });
+
+        let drain = JobKind::parse("drain", "tank1", "10L", None).unwrap();
+        assert_eq!(drain.assignment(&relays, &safety).unwrap().output, PumpOutput::Relay { relay_id: 2, board: Some(0x25) });
+
+        let transfer = JobKind::parse("transfer", "tank1", "5L", Some("tank2")).unwrap();
+        assert_eq!(transfer.assignment(&relays, &safety).unwrap().output, PumpOutput::Relay { relay_id: 4, board: Some(0x25) });
+
+        let reverse = JobKind::parse("transfer", "tank2", "5L", Some("tank1")).unwrap();
+        assert!(reverse.assignment(&relays, &safety).is_err());
+
+        // Channels are found by role on any board
+        let mut boards = relays.clone();
+        boards[0].channels.retain(|channel| channel.role != crate::RelayRole::FillPump);
+        assert!(fill.assignment(&boards, &safety).is_err());
+        boards.push(crate::RelayBoardConfig {
+            address: 0x26,
+            board_type: crate::RelayBoardType::Single,
+            channels: vec![crate::RelayChannelConfig {
+                relay_id: 1,
+                name: "fill_pump".to_string(),
+                role: crate::RelayRole::FillPump,
+            }],
+        });
+        assert_eq!(fill.assignment(&boards, &safety).unwrap().output, PumpOutput::Relay { relay_id: 1, board: Some(0x26) });
+
+        // A configured pump overrides the default wiring
+        safety.configure_pumps(&crate::PumpConfig {
+            pumps: vec![crate::PumpDefinition {
+                id: FILL_PUMP_ID.to_string(),
+                pump_type: PumpType::Fill,
+                output: PumpOutput::Gpio { pin: 17 },
+                safety: Default::default(),
+            }],
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(fill.assignment(&relays, &safety).unwrap().output, PumpOutput::Gpio { pin: 17 });
+    }
+
+    #[test]
+    fn test_pump_assignment_by_id() {
+        let relays = qwiic::default_relay_layout();
+        let safety = PumpSafetyMonitor::new();
+        let drain = pump_assignment(DRAIN_PUMP_ID, &relays, &safety).unwrap();
+        assert_eq!(drain.pump_type, PumpType::Drain);
+        assert_eq!(drain.output, PumpOutput::Relay { relay_id: 2, board: Some(0x25) });
+        assert!(pump_assignment("no_such_pump", &relays, &safety).is_err());
+
+        assert!(run_command("pump calibrate no_such_pump").contains("Unknown pump"));
+        assert!(run_command("pump calibrate fill_pump 0").contains("Invalid run count"));
+        assert!(run_command("pump calibrate fill_pump 2 x").contains("Invalid run duration"));
+    }
+
+    fn run_simulated(kind: JobKind, pump_type: PumpType, pump_id: &str, start: f32, percent_per_sec: f32) -> (PumpJob, MockPumpDriver) {
+        let safety = PumpSafetyMonitor::new();
+        let job = Mutex::new(PumpJob::new(1, kind));
+        let assignment = PumpAssignment { pump_id: pump_id.to_string(), pump_type, output: PumpOutput::Relay { relay_id: 3, board: None } };
+        let driver = Arc::new(Mutex::new(MockPumpDriver::default()));
+        let level_driver = Arc::clone(&driver);
+        let mut levels = move |_tank: &str| {
+            Ok(start + level_driver.lock().unwrap().runtime().as_secs_f32() * percent_per_sec)
+        };
+
+        execute(&job, &assignment, &mut Arc::clone(&driver), &mut levels,
+            &safety, &AtomicBool::new(false), &fast_limits());
+        drop(levels);
+
+        let driver = Arc::try_unwrap(driver).ok().unwrap().into_inner().unwrap();
+        (job.into_inner().unwrap(), driver)
+    }
+
+    #[test]
+    fn test_fill_to_percent() {
+        let kind = JobKind::parse("fill", "tank1", "40%", None).unwrap();
+        let (job, driver) = run_simulated(kind, PumpType::Fill, "sim_fill", 30.0, 50.0);
+
+        assert_eq!(job.status, JobStatus::Completed);
+        assert!(job.level_percent.unwrap() >= 40.0);
+        assert!(!driver.running);
+    }
+
+    #[test]
+    fn test_drain_by_volume() {
+        let kind = JobKind::parse("drain", "tank1", "5L", None).unwrap();
+        let (job, driver) = run_simulated(kind, PumpType::Drain, "sim_drain", 60.0, -50.0);
+
+        assert_eq!(job.status, JobStatus::Completed);
+        assert!(job.moved_litres >= 5.0);
+        assert!(!driver.running);
+    }
+
+    #[test]
+    fn test_fill_without_level_change_times_out() {
+        let safety = PumpSafetyMonitor::new();
+        let job = Mutex::new(PumpJob::new(1, JobKind::parse("fill", "tank1", "60%", None).unwrap()));
+        let assignment = PumpAssignment { pump_id: "sim_stuck".to_string(), pump_type: PumpType::Fill, output: PumpOutput::Relay { relay_id: 3, board: None } };
+        let mut driver = MockPumpDriver::default();
+        let mut levels = |_tank: &str| Ok(30.0);
+        let limits = JobLimits { timeout: Duration::from_millis(100), ..fast_limits() };
+
+        let status = execute(&job, &assignment, &mut driver, &mut levels,
+            &safety, &AtomicBool::new(false), &limits);
+        assert_eq!(status, JobStatus::Failed);
+        assert!(!driver.running);
+    }
+
+    #[test]
+    fn test_transfer_fails_when_source_level_is_lost() {
+        let safety = PumpSafetyMonitor::new();
+        let job = Mutex::new(PumpJob::new(1, JobKind::parse("transfer", "tank1", "50L", Some("tank2")).unwrap()));
+        let assignment = PumpAssignment { pump_id: "sim_transfer".to_string(), pump_type: PumpType::Auxiliary, output: PumpOutput::Relay { relay_id: 4, board: None } };
+        let mut driver = MockPumpDriver::default();
+        let mut source_reads = 0;
+        let mut levels = |tank: &str| {
+            if tank == "tank1" {
+                source_reads += 1;
+                if source_reads > 1 {
+                    return Err("sensor offline".to_string());
+                }
+            }
+            Ok(50.0)
+        };
+
+        let status = execute(&job, &assignment, &mut driver, &mut levels,
+            &safety, &AtomicBool::new(false), &fast_limits());
+        assert_eq!(status, JobStatus::Failed);
+        assert!(job.lock().unwrap().message.as_deref().unwrap_or("").contains("source tank1"));
+        assert!(!driver.running);
+    }
+
+    #[test]
+    fn test_fill_target_above_safety_limit() {
+        let kind = JobKind::parse("fill", "tank1", "95%", None).unwrap();
+        let (job, driver) = run_simulated(kind, PumpType::Fill, "sim_high", 30.0, 50.0);
+
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(driver.switch_count, 0);
+    }
+}
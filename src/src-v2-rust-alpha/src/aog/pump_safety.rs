@@ -12,8 +12,12 @@ use std::collections::HashMap;
 use chrono::Local;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Write;
 use std::path::Path;
-use crate::error::{AogError, AogResult, recover_mutex_lock, safe_mutex_access};
+use crate::error::{recover_mutex_lock, safe_mutex_access};
+use crate::{PumpConfig, PumpDefinition, PumpSafetyConfig};
+use crate::aog::actuators::{ActuatorOutput, ActuatorState, ACTUATORS};
+use crate::aog::water_level::RangeStatus;
 
 /// Maximum runtime limits for different pump types (in seconds)
 pub const MAX_RUNTIME_FILL_PUMP: u64 = 300;  // 5 minutes max for fill pump
@@ -30,6 +34,13 @@ pub const MAX_OSCILLATION_CYCLES: u32 = 100; // Maximum oscillation cycles befor
 pub const OSCILLATION_SPEED_MIN: u64 = 100; // Minimum oscillation period (ms)
 pub const OSCILLATION_SPEED_MAX: u64 = 5000; // Maximum oscillation period (ms)
 
+/// Runtime hours between services
+pub const MAINTENANCE_INTERVAL_HOURS: u64 = 1000;
+/// Fractions of the service interval at which advance warnings are raised
+pub const MAINTENANCE_DUE_SOON_FRACTION: f32 = 0.8;
+pub const MAINTENANCE_URGENT_FRACTION: f32 = 0.9;
+const MAX_SERVICE_HISTORY: usize = 500;
+
 /// Water level thresholds (percentage)
 pub const CRITICAL_HIGH_LEVEL: f32 = 95.0;
 pub const WARNING_HIGH_LEVEL: f32 = 85.0;
@@ -95,6 +106,331 @@ pub enum SafetyEvent {
     },
 }
 
+/// Smallest level change (cm) treated as real movement rather than sensor noise
+pub const MIN_LEVEL_CHANGE_CM: f32 = 0.5;
+/// Consecutive failed level readings that abort a calibration run
+const MAX_CALIBRATION_LEVEL_FAILURES: u32 = 3;
+
+/// Switches a pump on and off for calibration and volume operations
+pub trait PumpDriver: Send {
+    fn set_running(&mut self, on: bool) -> Result<(), String>;
+}
+
+/// Pump driven directly from a GPIO pin (active low, like the pump threads),
+/// switched through the actuator registry
+pub struct GpioPumpDriver {
+    output: ActuatorOutput,
+}
+
+impl GpioPumpDriver {
+    pub fn new(gpio_pin: u8) -> Result<Self, String> {
+        let output = ActuatorOutput::Gpio { pin: gpio_pin };
+        ACTUATORS.set_output(output, ActuatorState::Off)?;
+        Ok(GpioPumpDriver { output })
+    }
+}
+
+impl PumpDriver for GpioPumpDriver {
+    fn set_running(&mut self, on: bool) -> Result<(), String> {
+        ACTUATORS.set_output(self.output, ActuatorState::from_bool(on)).map(|_| ())
+    }
+}
+
+impl Drop for GpioPumpDriver {
+    fn drop(&mut self) {
+        let _ = ACTUATORS.set_output(self.output, ActuatorState::Off);
+    }
+}
+
+impl<D: PumpDriver> PumpDriver for Arc<Mutex<D>> {
+    fn set_running(&mut self, on: bool) -> Result<(), String> {
+        self.lock()
+            .map_err(|e| format!("Pump driver lock poisoned: {}", e))?
+            .set_running(on)
+    }
+}
+
+/// Pump driver that only records state, for testing
+#[derive(Debug, Default)]
+pub struct MockPumpDriver {
+    pub running: bool,
+    pub switch_count: u32,
+    started: Option<Instant>,
+    pub total_runtime: Duration,
+}
+
+impl MockPumpDriver {
+    /// Time spent running, including the current run
+    pub fn runtime(&self) -> Duration {
+        self.total_runtime + self.started.map(|s| s.elapsed()).unwrap_or_default()
+    }
+}
+
+impl PumpDriver for MockPumpDriver {
+    fn set_running(&mut self, on: bool) -> Result<(), String> {
+        if on && !self.running {
+            self.started = Some(Instant::now());
+            self.switch_count += 1;
+        } else if !on {
+            if let Some(started) = self.started.take() {
+                self.total_runtime += started.elapsed();
+            }
+        }
+        self.running = on;
+        Ok(())
+    }
+}
+
+/// Flow calibration parameters
+#[derive(Debug, Clone)]
+pub struct FlowCalibrationOptions {
+    pub run_duration: Duration,
+    pub runs: u32,
+    pub settle_time: Duration,
+    pub poll_interval: Duration,
+    pub tank_id: Option<String>,  // Defaults to the tank the pump type normally feeds
+}
+
+impl Default for FlowCalibrationOptions {
+    fn default() -> Self {
+        FlowCalibrationOptions {
+            run_duration: Duration::from_secs(20),
+            runs: 3,
+            settle_time: Duration::from_secs(3),
+            poll_interval: Duration::from_millis(250),
+            tank_id: None,
+        }
+    }
+}
+
+/// Tank dimensions needed to turn level changes into volumes
+#[derive(Debug, Clone)]
+pub struct TankGeometry {
+    pub height_cm: f32,
+    pub litres_per_cm: f32,
+}
+
+struct FlowRun {
+    flow_rate_lpm: f32,
+    response_latency_ms: Option<u64>,
+}
+
+/// Measured flow characteristics of a pump
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PumpCalibration {
+    pub pump_id: String,
+    pub pump_type: PumpType,
+    pub tank_id: String,
+    pub flow_rate_lpm: f32,
+    pub flow_rate_stddev_lpm: f32,
+    pub response_latency_ms: Option<u64>,
+    pub runs: u32,
+    pub timestamp: String,
+}
+
+impl PumpCalibration {
+    fn from_runs(pump_id: &str, pump_type: PumpType, tank_id: &str, runs: &[FlowRun]) -> Self {
+        let n = runs.len() as f32;
+        let mean = runs.iter().map(|r| r.flow_rate_lpm).sum::<f32>() / n;
+        let variance = runs.iter().map(|r| (r.flow_rate_lpm - mean).powi(2)).sum::<f32>() / n;
+        let latencies: Vec<u64> = runs.iter().filter_map(|r| r.response_latency_ms).collect();
+
+        PumpCalibration {
+            pump_id: pump_id.to_string(),
+            pump_type,
+            tank_id: tank_id.to_string(),
+            flow_rate_lpm: mean,
+            flow_rate_stddev_lpm: variance.sqrt(),
+            response_latency_ms: if latencies.is_empty() {
+                None
+            } else {
+                Some(latencies.iter().sum::<u64>() / latencies.len() as u64)
+            },
+            runs: runs.len() as u32,
+            timestamp: Local::now().to_rfc3339(),
+        }
+    }
+
+    /// Load a saved calibration for a pump
+    pub fn load(pump_id: &str) -> Option<PumpCalibration> {
+        let path = format!("/opt/aog/calibration_{}.json", pump_id);
+        let data = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Relative spread of the measured runs; lower is more trustworthy
+    pub fn variation(&self) -> f32 {
+        if self.flow_rate_lpm > 0.0 {
+            self.flow_rate_stddev_lpm / self.flow_rate_lpm
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    /// Pump run time needed to move the given volume
+    pub fn duration_for_volume(&self, litres: f32) -> Option<Duration> {
+        if self.flow_rate_lpm <= 0.0 || litres <= 0.0 {
+            return None;
+        }
+        let latency = Duration::from_millis(self.response_latency_ms.unwrap_or(0));
+        Some(Duration::from_secs_f32(litres / self.flow_rate_lpm * 60.0) + latency)
+    }
+}
+
+/// Tank a pump type normally acts on
+pub fn tank_for_pump(pump_type: &PumpType) -> &'static str {
+    match pump_type {
+        PumpType::Fill => "tank1",
+        PumpType::Drain => "tank1",
+        PumpType::Circulation => "tank1",
+        PumpType::Auxiliary => "tank2",
+    }
+}
+
+/// Direction a pump type moves the level of its tank: +1 raises it, -1 lowers it
+pub fn expected_level_sign(pump_type: &PumpType) -> f32 {
+    match pump_type {
+        PumpType::Drain => -1.0,
+        _ => 1.0,
+    }
+}
+
+/// Maximum runtime in seconds for a pump type
+pub fn max_runtime_for(pump_type: &PumpType) -> u64 {
+    match pump_type {
+        PumpType::Fill => MAX_RUNTIME_FILL_PUMP,
+        PumpType::Drain => MAX_RUNTIME_DRAIN_PUMP,
+        PumpType::Circulation => MAX_RUNTIME_CIRCULATION_PUMP,
+        PumpType::Auxiliary => MAX_RUNTIME_AUX_PUMP,
+    }
+}
+
+/// Limits enforced for one pump
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PumpSafetyProfile {
+    pub max_runtime_seconds: u64,
+    pub cooldown_seconds: u64,
+    pub max_oscillation_cycles: u32,
+    pub oscillation_speed_min_ms: u64,
+    pub oscillation_speed_max_ms: u64,
+    pub maintenance_interval_hours: u64,
+}
+
+impl PumpSafetyProfile {
+    /// Built-in limits for a pump type
+    pub fn for_type(pump_type: &PumpType) -> Self {
+        PumpSafetyProfile {
+            max_runtime_seconds: max_runtime_for(pump_type),
+            cooldown_seconds: MIN_COOLDOWN_PERIOD,
+            max_oscillation_cycles: MAX_OSCILLATION_CYCLES,
+            oscillation_speed_min_ms: OSCILLATION_SPEED_MIN,
+            oscillation_speed_max_ms: OSCILLATION_SPEED_MAX,
+            maintenance_interval_hours: MAINTENANCE_INTERVAL_HOURS,
+        }
+    }
+
+    /// Replace the limits that are set in `config`
+    pub fn with_overrides(self, config: &PumpSafetyConfig) -> Self {
+        PumpSafetyProfile {
+            max_runtime_seconds: config.max_runtime_seconds.unwrap_or(self.max_runtime_seconds),
+            cooldown_seconds: config.cooldown_seconds.unwrap_or(self.cooldown_seconds),
+            max_oscillation_cycles: config.max_oscillation_cycles.unwrap_or(self.max_oscillation_cycles),
+            oscillation_speed_min_ms: config.oscillation_speed_min_ms.unwrap_or(self.oscillation_speed_min_ms),
+            oscillation_speed_max_ms: config.oscillation_speed_max_ms.unwrap_or(self.oscillation_speed_max_ms),
+            maintenance_interval_hours: config.maintenance_interval_hours.unwrap_or(self.maintenance_interval_hours),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_runtime_seconds == 0 {
+            return Err("max_runtime_seconds must be greater than zero".to_string());
+        }
+        if self.maintenance_interval_hours == 0 {
+            return Err("maintenance_interval_hours must be greater than zero".to_string());
+        }
+        if self.oscillation_speed_min_ms > self.oscillation_speed_max_ms {
+            return Err(format!("Oscillation speed range {}-{}ms is empty",
+                self.oscillation_speed_min_ms, self.oscillation_speed_max_ms));
+        }
+        Ok(())
+    }
+}
+
+/// How close a pump is to its next service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MaintenanceLevel {
+    Ok,
+    DueSoon,  // 80% of the service interval used
+    Urgent,   // 90% used
+    Overdue,  // Interval exceeded - pump is blocked until serviced
+}
+
+impl MaintenanceLevel {
+    pub fn for_runtime(runtime: Duration, interval_hours: u64) -> Self {
+        let used = runtime.as_secs_f32() / (interval_hours.max(1) as f32 * 3600.0);
+        if used >= 1.0 {
+            MaintenanceLevel::Overdue
+        } else if used >= MAINTENANCE_URGENT_FRACTION {
+            MaintenanceLevel::Urgent
+        } else if used >= MAINTENANCE_DUE_SOON_FRACTION {
+            MaintenanceLevel::DueSoon
+        } else {
+            MaintenanceLevel::Ok
+        }
+    }
+}
+
+/// A logged service of a pump
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceRecord {
+    pub pump_id: String,
+    pub serviced_at: String,
+    pub runtime_hours: f32,  // Runtime since the previous service
+    pub notes: String,
+}
+
+/// Service state of a pump
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    pub pump_id: String,
+    pub runtime_hours: f32,  // Runtime since the last service
+    pub interval_hours: u64,
+    pub percent_used: f32,
+    pub level: MaintenanceLevel,
+    pub last_service: Option<String>,
+}
+
+/// Where the global monitor keeps its durable state
+pub const PUMP_SAFETY_STATE_PATH: &str = "/opt/aog/pump_safety.json";
+
+/// Why and when the emergency stop was triggered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmergencyStopRecord {
+    pub reason: String,
+    pub timestamp: String,
+}
+
+/// Safety state that must survive a restart
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PersistedSafetyState {
+    #[serde(default)]
+    pub total_runtime_seconds: HashMap<String, u64>,
+    #[serde(default)]
+    pub maintenance_runtime_seconds: HashMap<String, u64>, // Runtime since last service
+    #[serde(default, skip_serializing)]
+    pub maintenance_hours: HashMap<String, u64>, // Older whole-hour counters, read only
+    #[serde(default)]
+    pub faults: HashMap<String, String>, // pump_id -> fault reason
+    #[serde(default)]
+    pub emergency_stop: Option<EmergencyStopRecord>,
+    #[serde(default)]
+    pub last_service: HashMap<String, String>, // pump_id -> RFC 3339 date
+    #[serde(default)]
+    pub service_history: Vec<MaintenanceRecord>,
+    #[serde(default)]
+    pub saved_at: String,
+}
+
 /// Pump safety monitor - tracks operation history and enforces limits
 #[derive(Debug, Clone)]
 pub struct PumpSafetyMonitor {
@@ -104,7 +440,21 @@ pub struct PumpSafetyMonitor {
     total_runtimes: Arc<Mutex<HashMap<String, Duration>>>,
     oscillation_counters: Arc<Mutex<HashMap<String, u32>>>,
     emergency_stop_active: Arc<Mutex<bool>>,
-    maintenance_hours: Arc<Mutex<HashMap<String, u64>>>,
+    maintenance_runtimes: Arc<Mutex<HashMap<String, Duration>>>,
+    emergency_stop_record: Arc<Mutex<Option<EmergencyStopRecord>>>,
+    fault_reasons: Arc<Mutex<HashMap<String, String>>>,
+    last_service: Arc<Mutex<HashMap<String, String>>>,
+    service_history: Arc<Mutex<Vec<MaintenanceRecord>>>,
+    maintenance_warnings: Arc<Mutex<HashMap<String, MaintenanceLevel>>>,
+    state_path: Arc<Mutex<Option<String>>>,
+    pump_config: Arc<Mutex<Option<PumpConfig>>>,
+    pump_types: Arc<Mutex<HashMap<String, PumpType>>>,
+}
+
+impl Default for PumpSafetyMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PumpSafetyMonitor {
@@ -116,8 +466,77 @@ impl PumpSafetyMonitor {
             total_runtimes: Arc::new(Mutex::new(HashMap::new())),
             oscillation_counters: Arc::new(Mutex::new(HashMap::new())),
             emergency_stop_active: Arc::new(Mutex::new(false)),
-            maintenance_hours: Arc::new(Mutex::new(HashMap::new())),
+            maintenance_runtimes: Arc::new(Mutex::new(HashMap::new())),
+            emergency_stop_record: Arc::new(Mutex::new(None)),
+            fault_reasons: Arc::new(Mutex::new(HashMap::new())),
+            last_service: Arc::new(Mutex::new(HashMap::new())),
+            service_history: Arc::new(Mutex::new(Vec::new())),
+            maintenance_warnings: Arc::new(Mutex::new(HashMap::new())),
+            state_path: Arc::new(Mutex::new(None)),
+            pump_config: Arc::new(Mutex::new(None)),
+            pump_types: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Apply pump definitions and limits from the configuration. Limits are
+    /// resolved per pump: its own safety settings, then the config-wide
+    /// runtime limit and cooldown, then the built-in defaults for its type.
+    pub fn configure_pumps(&self, config: &PumpConfig) -> Result<(), String> {
+        let mut seen = std::collections::HashSet::new();
+        for pump in &config.pumps {
+            if !seen.insert(pump.id.as_str()) {
+                return Err(format!("Pump {} is defined more than once", pump.id));
+            }
+            Self::resolve_profile(Some(config), &pump.id, &pump.pump_type).validate()
+                .map_err(|e| format!("Invalid safety profile for pump {}: {}", pump.id, e))?;
+        }
+
+        let mut current = recover_mutex_lock(&self.pump_config, "configure_pumps")
+            .map_err(|e| format!("Failed to apply pump configuration: {}", e))?;
+        *current = Some(config.clone());
+        log::info!("Configured {} pump definition(s)", config.pumps.len());
+        Ok(())
+    }
+
+    fn resolve_profile(config: Option<&PumpConfig>, pump_id: &str, pump_type: &PumpType) -> PumpSafetyProfile {
+        let config = match config {
+            Some(config) => config,
+            None => return PumpSafetyProfile::for_type(pump_type),
+        };
+        let definition = config.pumps.iter().find(|p| p.id == pump_id);
+        let pump_type = definition.map(|p| &p.pump_type).unwrap_or(pump_type);
+
+        let mut profile = PumpSafetyProfile::for_type(pump_type).with_overrides(&PumpSafetyConfig {
+            max_runtime_seconds: config.pump_runtime_limit_seconds,
+            cooldown_seconds: config.pump_cooldown_seconds,
+            ..Default::default()
+        });
+        if let Some(definition) = definition {
+            profile = profile.with_overrides(&definition.safety);
         }
+        profile
+    }
+
+    /// Limits in force for a pump
+    pub fn safety_profile(&self, pump_id: &str, pump_type: &PumpType) -> PumpSafetyProfile {
+        safe_mutex_access(&self.pump_config, "safety_profile",
+            |config| Self::resolve_profile(config.as_ref(), pump_id, pump_type),
+            PumpSafetyProfile::for_type(pump_type))
+    }
+
+    /// Limits for a pump whose type isn't known at the call site
+    fn profile_for(&self, pump_id: &str) -> PumpSafetyProfile {
+        let pump_type = safe_mutex_access(&self.pump_types, "profile_for",
+            |types| types.get(pump_id).cloned(), None)
+            .unwrap_or(PumpType::Fill);
+        self.safety_profile(pump_id, &pump_type)
+    }
+
+    /// Configured definition for a pump, if any
+    pub fn pump_definition(&self, pump_id: &str) -> Option<PumpDefinition> {
+        safe_mutex_access(&self.pump_config, "pump_definition",
+            |config| config.as_ref().and_then(|c| c.pumps.iter().find(|p| p.id == pump_id).cloned()),
+            None)
     }
 
     /// Check if pump can safely start
@@ -154,42 +573,42 @@ impl PumpSafetyMonitor {
             }
         }
 
+        let profile = self.safety_profile(pump_id, &pump_type);
+
         // Check cooldown period
         let last_times = recover_mutex_lock(&self.last_operation_times, "can_start_pump::last_times")
             .map_err(|e| format!("Failed to check cooldown: {}", e))?;
         if let Some(last_time) = last_times.get(pump_id) {
             let elapsed = last_time.elapsed();
-            if elapsed < Duration::from_secs(MIN_COOLDOWN_PERIOD) {
+            if elapsed < Duration::from_secs(profile.cooldown_seconds) {
                 return Err(format!(
                     "Pump {} needs {} more seconds of cooldown",
                     pump_id,
-                    MIN_COOLDOWN_PERIOD - elapsed.as_secs()
+                    profile.cooldown_seconds - elapsed.as_secs()
                 ));
             }
         }
 
         // Check water levels based on pump type
         match pump_type {
-            PumpType::Fill => {
-                if self.get_water_level("tank1") > WARNING_HIGH_LEVEL {
+            PumpType::Fill
+                if self.get_water_level("tank1") > WARNING_HIGH_LEVEL => {
                     return Err("Tank water level too high for fill operation".to_string());
                 }
-            }
-            PumpType::Drain => {
-                if self.get_water_level("tank1") < WARNING_LOW_LEVEL {
+            PumpType::Drain
+                if self.get_water_level("tank1") < WARNING_LOW_LEVEL => {
                     return Err("Tank water level too low for drain operation".to_string());
                 }
-            }
             _ => {}
         }
 
         // Check maintenance schedule
-        let maintenance = recover_mutex_lock(&self.maintenance_hours, "can_start_pump::maintenance")
+        let maintenance = recover_mutex_lock(&self.maintenance_runtimes, "can_start_pump::maintenance")
             .map_err(|e| format!("Failed to check maintenance: {}", e))?;
-        if let Some(hours) = maintenance.get(pump_id) {
-            if *hours > 1000 {
-                // 1000 hours of operation
-                return Err(format!("Pump {} has exceeded maintenance interval", pump_id));
+        if let Some(runtime) = maintenance.get(pump_id) {
+            if MaintenanceLevel::for_runtime(*runtime, profile.maintenance_interval_hours) == MaintenanceLevel::Overdue {
+                return Err(format!("Pump {} has exceeded maintenance interval of {} hours - record a service to re-enable it",
+                    pump_id, profile.maintenance_interval_hours));
             }
         }
 
@@ -198,6 +617,9 @@ impl PumpSafetyMonitor {
 
     /// Register pump start
     pub fn register_pump_start(&self, pump_id: String, pump_type: PumpType) {
+        if let Ok(mut types) = recover_mutex_lock(&self.pump_types, "register_pump_start::types") {
+            types.insert(pump_id.clone(), pump_type.clone());
+        }
         // Use recover_mutex_lock to handle poisoned locks
         if let Ok(mut states) = recover_mutex_lock(&self.pump_states, "register_pump_start::states") {
             states.insert(pump_id.clone(), PumpState::Running);
@@ -248,13 +670,15 @@ impl PumpSafetyMonitor {
             log::error!("Failed to update total runtime for pump {}", pump_id);
         }
 
-        // Update maintenance hours
-        if let Ok(mut maintenance) = recover_mutex_lock(&self.maintenance_hours, "register_pump_stop::maintenance") {
-            let hours = maintenance.entry(pump_id.clone()).or_insert(0);
-            *hours += runtime.as_secs() / 3600;
+        // Update runtime since last service
+        if let Ok(mut maintenance) = recover_mutex_lock(&self.maintenance_runtimes, "register_pump_stop::maintenance") {
+            let total = maintenance.entry(pump_id.clone()).or_insert(Duration::from_secs(0));
+            *total += runtime;
         } else {
-            log::error!("Failed to update maintenance hours for pump {}", pump_id);
+            log::error!("Failed to update maintenance runtime for pump {}", pump_id);
         }
+        self.check_maintenance(&pump_id);
+        self.persist();
 
         let event = SafetyEvent::PumpStopped {
             pump_id: pump_id.clone(),
@@ -266,10 +690,11 @@ impl PumpSafetyMonitor {
         self.log_safety_event(event);
 
         // Schedule cooldown completion
+        let cooldown = Duration::from_secs(self.profile_for(&pump_id).cooldown_seconds);
         let states_clone = Arc::clone(&self.pump_states);
         let pump_id_clone = pump_id.clone();
         std::thread::spawn(move || {
-            std::thread::sleep(Duration::from_secs(MIN_COOLDOWN_PERIOD));
+            std::thread::sleep(cooldown);
             if let Ok(mut states) = recover_mutex_lock(&states_clone, "cooldown_completion") {
                 if let Some(state) = states.get_mut(&pump_id_clone) {
                     if *state == PumpState::Cooldown {
@@ -293,13 +718,7 @@ impl PumpSafetyMonitor {
         };
         if let Some(start_time) = last_times.get(pump_id) {
             let runtime = start_time.elapsed().as_secs();
-            let max_runtime = match pump_type {
-                PumpType::Fill => MAX_RUNTIME_FILL_PUMP,
-                PumpType::Drain => MAX_RUNTIME_DRAIN_PUMP,
-                PumpType::Circulation => MAX_RUNTIME_CIRCULATION_PUMP,
-                PumpType::Auxiliary => MAX_RUNTIME_AUX_PUMP,
-            };
-            runtime < max_runtime
+            runtime < self.safety_profile(pump_id, &pump_type).max_runtime_seconds
         } else {
             true
         }
@@ -307,14 +726,16 @@ impl PumpSafetyMonitor {
 
     /// Check oscillation safety
     pub fn check_oscillation_safety(&self, pump_id: &str, speed_ms: u64) -> Result<bool, String> {
+        let profile = self.profile_for(pump_id);
+
         // Check speed limits
-        if speed_ms < OSCILLATION_SPEED_MIN {
+        if speed_ms < profile.oscillation_speed_min_ms {
             return Err(format!("Oscillation speed too fast: {}ms < {}ms minimum", 
-                speed_ms, OSCILLATION_SPEED_MIN));
+                speed_ms, profile.oscillation_speed_min_ms));
         }
-        if speed_ms > OSCILLATION_SPEED_MAX {
+        if speed_ms > profile.oscillation_speed_max_ms {
             return Err(format!("Oscillation speed too slow: {}ms > {}ms maximum", 
-                speed_ms, OSCILLATION_SPEED_MAX));
+                speed_ms, profile.oscillation_speed_max_ms));
         }
 
         // Check oscillation count
@@ -327,9 +748,9 @@ impl PumpSafetyMonitor {
         let count = counters.entry(pump_id.to_string()).or_insert(0);
         *count += 1;
 
-        if *count > MAX_OSCILLATION_CYCLES {
+        if *count > profile.max_oscillation_cycles {
             return Err(format!("Maximum oscillation cycles exceeded: {} > {}", 
-                count, MAX_OSCILLATION_CYCLES));
+                count, profile.max_oscillation_cycles));
         }
 
         Ok(true)
@@ -352,6 +773,12 @@ impl PumpSafetyMonitor {
         } else {
             log::error!("CRITICAL: Failed to set emergency stop flag!");
         }
+        if let Ok(mut record) = recover_mutex_lock(&self.emergency_stop_record, "emergency_shutdown::record") {
+            *record = Some(EmergencyStopRecord {
+                reason: reason.clone(),
+                timestamp: Local::now().to_rfc3339(),
+            });
+        }
 
         let affected_pumps = if let Ok(states) = recover_mutex_lock(&self.pump_states, "emergency_shutdown::states") {
             let pumps: Vec<String> = states
@@ -389,6 +816,17 @@ impl PumpSafetyMonitor {
 
         // Create emergency stop file
         let _ = fs::write("/opt/aog/emergency_stop", format!("{}: {}", Local::now(), reason));
+        self.persist();
+    }
+
+    /// Reason and time of the active emergency stop, if any
+    pub fn emergency_stop_record(&self) -> Option<EmergencyStopRecord> {
+        safe_mutex_access(&self.emergency_stop_record, "emergency_stop_record", |record| record.clone(), None)
+    }
+
+    /// Whether the emergency stop is active (defaults to active if the state can't be read)
+    pub fn is_emergency_stop_active(&self) -> bool {
+        safe_mutex_access(&self.emergency_stop_active, "is_emergency_stop_active", |active| *active, true)
     }
 
     /// Reset emergency stop
@@ -400,6 +838,9 @@ impl PumpSafetyMonitor {
             log::error!("Failed to reset emergency stop flag");
             return;
         }
+        if let Ok(mut record) = recover_mutex_lock(&self.emergency_stop_record, "reset_emergency_stop::record") {
+            *record = None;
+        }
         
         let _ = fs::remove_file("/opt/aog/emergency_stop");
         
@@ -414,9 +855,151 @@ impl PumpSafetyMonitor {
             log::error!("Failed to reset pump states after emergency stop");
         }
         
+        self.persist();
         log::info!("Emergency stop reset - pumps can now be restarted");
     }
 
+    /// Put a pump into the fault state; it stays there, across restarts, until cleared
+    pub fn set_fault(&self, pump_id: &str, reason: String) {
+        if let Ok(mut states) = recover_mutex_lock(&self.pump_states, "set_fault::states") {
+            states.insert(pump_id.to_string(), PumpState::Fault);
+        } else {
+            log::error!("CRITICAL: Failed to set fault state for pump {}", pump_id);
+        }
+        if let Ok(mut faults) = recover_mutex_lock(&self.fault_reasons, "set_fault::reasons") {
+            faults.insert(pump_id.to_string(), reason.clone());
+        }
+
+        self.log_safety_event(SafetyEvent::SafetyCheckFailed {
+            check_type: "pump_fault".to_string(),
+            details: format!("{}: {}", pump_id, reason),
+            timestamp: Local::now().to_rfc3339(),
+        });
+        log::error!("Pump {} faulted: {}", pump_id, reason);
+        self.persist();
+    }
+
+    /// Clear a pump fault
+    pub fn clear_fault(&self, pump_id: &str) {
+        if let Ok(mut states) = recover_mutex_lock(&self.pump_states, "clear_fault::states") {
+            if states.get(pump_id) == Some(&PumpState::Fault) {
+                states.insert(pump_id.to_string(), PumpState::Idle);
+            }
+        }
+        if let Ok(mut faults) = recover_mutex_lock(&self.fault_reasons, "clear_fault::reasons") {
+            faults.remove(pump_id);
+        }
+        log::info!("Fault cleared for pump {}", pump_id);
+        self.persist();
+    }
+
+    /// Reason a pump is in the fault state, if it is
+    pub fn fault_reason(&self, pump_id: &str) -> Option<String> {
+        safe_mutex_access(&self.fault_reasons, "fault_reason", |faults| faults.get(pump_id).cloned(), None)
+    }
+
+    /// Warn once each time a pump crosses into a higher maintenance level
+    fn check_maintenance(&self, pump_id: &str) {
+        let status = self.maintenance_status(pump_id);
+        let raised = match recover_mutex_lock(&self.maintenance_warnings, "check_maintenance") {
+            Ok(mut warnings) => {
+                let previous = warnings.insert(pump_id.to_string(), status.level).unwrap_or(MaintenanceLevel::Ok);
+                status.level > previous
+            }
+            Err(_) => status.level != MaintenanceLevel::Ok,
+        };
+        if !raised {
+            return;
+        }
+
+        let message = format!("Pump {} has used {:.0}% of its {} hour service interval ({:.1}h since last service)",
+            pump_id, status.percent_used, status.interval_hours, status.runtime_hours);
+        match status.level {
+            MaintenanceLevel::Overdue => log::error!("{} - pump disabled until serviced", message),
+            _ => log::warn!("{}", message),
+        }
+        self.log_safety_event(SafetyEvent::MaintenanceRequired {
+            pump_id: pump_id.to_string(),
+            total_runtime_seconds: (status.runtime_hours * 3600.0) as u64,
+            timestamp: Local::now().to_rfc3339(),
+        });
+    }
+
+    /// Service state of a pump
+    pub fn maintenance_status(&self, pump_id: &str) -> MaintenanceStatus {
+        let runtime = safe_mutex_access(&self.maintenance_runtimes, "maintenance_status::runtime",
+            |runtimes| runtimes.get(pump_id).copied(), None)
+            .unwrap_or_default();
+        let interval_hours = self.profile_for(pump_id).maintenance_interval_hours;
+        MaintenanceStatus {
+            pump_id: pump_id.to_string(),
+            runtime_hours: runtime.as_secs_f32() / 3600.0,
+            interval_hours,
+            percent_used: runtime.as_secs_f32() / (interval_hours.max(1) as f32 * 3600.0) * 100.0,
+            level: MaintenanceLevel::for_runtime(runtime, interval_hours),
+            last_service: safe_mutex_access(&self.last_service, "maintenance_status::last_service",
+                |dates| dates.get(pump_id).cloned(), None),
+        }
+    }
+
+    /// Service state of every pump that has run or is configured
+    pub fn maintenance_report(&self) -> Vec<MaintenanceStatus> {
+        let mut pump_ids: Vec<String> = safe_mutex_access(&self.maintenance_runtimes, "maintenance_report::runtimes",
+            |runtimes| runtimes.keys().cloned().collect(), Vec::new());
+        pump_ids.extend(safe_mutex_access(&self.pump_config, "maintenance_report::config",
+            |config| config.iter().flat_map(|c| c.pumps.iter().map(|p| p.id.clone())).collect::<Vec<_>>(), Vec::new()));
+        pump_ids.sort();
+        pump_ids.dedup();
+        pump_ids.iter().map(|id| self.maintenance_status(id)).collect()
+    }
+
+    /// Logged services, newest last, optionally for a single pump
+    pub fn service_history(&self, pump_id: Option<&str>) -> Vec<MaintenanceRecord> {
+        safe_mutex_access(&self.service_history, "service_history", |history| {
+            history.iter()
+                .filter(|record| pump_id.map(|id| record.pump_id == id).unwrap_or(true))
+                .cloned()
+                .collect()
+        }, Vec::new())
+    }
+
+    /// Record that a pump has been serviced, restarting its maintenance counter
+    pub fn record_service(&self, pump_id: &str, notes: String) -> MaintenanceRecord {
+        let runtime = match recover_mutex_lock(&self.maintenance_runtimes, "record_service::maintenance") {
+            Ok(mut maintenance) => maintenance.insert(pump_id.to_string(), Duration::from_secs(0)).unwrap_or_default(),
+            Err(_) => Duration::from_secs(0),
+        };
+        let record = MaintenanceRecord {
+            pump_id: pump_id.to_string(),
+            serviced_at: Local::now().to_rfc3339(),
+            runtime_hours: runtime.as_secs_f32() / 3600.0,
+            notes,
+        };
+
+        if let Ok(mut last_service) = recover_mutex_lock(&self.last_service, "record_service::date") {
+            last_service.insert(pump_id.to_string(), record.serviced_at.clone());
+        }
+        if let Ok(mut history) = recover_mutex_lock(&self.service_history, "record_service::history") {
+            history.push(record.clone());
+            if history.len() > MAX_SERVICE_HISTORY {
+                let excess = history.len() - MAX_SERVICE_HISTORY;
+                history.drain(0..excess);
+            }
+        }
+        if let Ok(mut warnings) = recover_mutex_lock(&self.maintenance_warnings, "record_service::warnings") {
+            warnings.remove(pump_id);
+        }
+        if let Ok(mut states) = recover_mutex_lock(&self.pump_states, "record_service::states") {
+            if states.get(pump_id) == Some(&PumpState::Maintenance) {
+                states.insert(pump_id.to_string(), PumpState::Idle);
+            }
+        }
+
+        log::info!("Pump {} serviced after {:.1}h: {}", pump_id, record.runtime_hours, record.notes);
+        self.persist();
+        record
+    }
+
     /// Get current water level from real sensors
     fn get_water_level(&self, tank_id: &str) -> f32 {
         // Use real water level sensor if available
@@ -490,10 +1073,10 @@ impl PumpSafetyMonitor {
             }
         }
 
-        // Maintenance hours
-        if let Ok(maintenance) = recover_mutex_lock(&self.maintenance_hours, "get_pump_stats::maintenance") {
-            if let Some(hours) = maintenance.get(pump_id) {
-                stats.insert("maintenance_hours".to_string(), hours.to_string());
+        // Runtime since last service
+        if let Ok(maintenance) = recover_mutex_lock(&self.maintenance_runtimes, "get_pump_stats::maintenance") {
+            if let Some(runtime) = maintenance.get(pump_id) {
+                stats.insert("maintenance_hours".to_string(), format!("{:.2}", runtime.as_secs_f32() / 3600.0));
             }
         }
 
@@ -504,94 +1087,403 @@ impl PumpSafetyMonitor {
             }
         }
 
+        if let Some(reason) = self.fault_reason(pump_id) {
+            stats.insert("fault_reason".to_string(), reason);
+        }
+        if let Ok(last_service) = recover_mutex_lock(&self.last_service, "get_pump_stats::last_service") {
+            if let Some(date) = last_service.get(pump_id) {
+                stats.insert("last_service".to_string(), date.clone());
+            }
+        }
+
         stats
     }
 
-    /// Perform calibration routine with water level sensor integration
-    pub fn calibrate_pump(&self, pump_id: &str, pump_type: PumpType) -> Result<HashMap<String, f32>, String> {
+    /// Measure a pump's flow rate by running it for timed intervals and
+    /// measuring the resulting level change through the water level system.
+    /// The pump is supervised the whole time and switched off on any safety
+    /// violation. Results are persisted to `/opt/aog/calibration_<pump_id>.json`.
+    pub fn calibrate_pump(
+        &self,
+        pump_id: &str,
+        pump_type: PumpType,
+        driver: &mut dyn PumpDriver,
+        options: FlowCalibrationOptions,
+    ) -> Result<PumpCalibration, String> {
         // Check if pump can start
         self.can_start_pump(pump_id, pump_type.clone())?;
 
-        let mut calibration_data = HashMap::new();
+        let tank_id = options.tank_id.clone()
+            .unwrap_or_else(|| tank_for_pump(&pump_type).to_string());
+        let options = FlowCalibrationOptions { tank_id: Some(tank_id.clone()), ..options };
+
+        // Take a handle to the tank monitor so the global lock isn't held while pumping
+        let (monitor, tank) = {
+            let guard = crate::aog::water_level::WATER_LEVEL_SYSTEM.lock().unwrap();
+            let system = guard.as_ref()
+                .ok_or_else(|| "Water level system is not initialized - cannot measure flow".to_string())?;
+            let monitor = system.monitor(&tank_id)
+                .ok_or_else(|| format!("No water level sensor for {}", tank_id))?;
+            let config = system.config();
+            (monitor, TankGeometry { height_cm: config.tank_height_cm, litres_per_cm: config.litres_per_cm })
+        };
 
-        log::info!("Starting calibration for pump {}", pump_id);
+        if monitor.get_calibration().points.is_empty() {
+            log::warn!("Water level sensor for {} is uncalibrated, flow rate results may be inaccurate", tank_id);
+        }
 
-        // Determine which tank this pump affects
-        let tank_id = match pump_type {
-            PumpType::Fill => "tank1",
-            PumpType::Drain => "tank1",
-            PumpType::Circulation => "tank1",
-            PumpType::Auxiliary => "tank2",
-        };
+        let mut measure = || monitor.measure_level_cm();
+        let calibration = self.run_flow_calibration(
+            pump_id, pump_type, driver, &mut measure, &tank, &options)?;
 
-        // Get initial water level
-        let initial_level = self.get_water_level(tank_id);
-        calibration_data.insert("initial_level_percent".to_string(), initial_level);
+        let cal_path = format!("/opt/aog/calibration_{}.json", pump_id);
+        let json = serde_json::to_string_pretty(&calibration)
+            .map_err(|e| format!("Failed to serialize calibration: {}", e))?;
+        fs::write(&cal_path, json)
+            .map_err(|e| format!("Failed to save calibration to {}: {}", cal_path, e))?;
 
-        // If water level system is available, calibrate the sensor first
-        if let Some(system) = crate::aog::water_level::WATER_LEVEL_SYSTEM.lock().unwrap().as_ref() {
-            log::info!("Calibrating water level sensor for {}", tank_id);
-            
-            // Prompt for actual water level (in production, this would come from UI or manual measurement)
-            // For now, use the current reading as the calibration point
-            let actual_cm = (initial_level / 100.0) * 100.0; // Assuming 100cm tank height
-            if let Err(e) = system.calibrate_tank(tank_id, actual_cm) {
-                log::warn!("Water level sensor calibration failed: {}", e);
+        Ok(calibration)
+    }
+
+    /// Calibration runs against any level source. The pump is registered as
+    /// running for the whole routine so runtime limits cover all runs.
+    fn run_flow_calibration(
+        &self,
+        pump_id: &str,
+        pump_type: PumpType,
+        driver: &mut dyn PumpDriver,
+        measure_level_cm: &mut dyn FnMut() -> Result<f32, String>,
+        tank: &TankGeometry,
+        options: &FlowCalibrationOptions,
+    ) -> Result<PumpCalibration, String> {
+        if options.runs == 0 {
+            return Err("At least one calibration run is required".to_string());
+        }
+
+        let total = options.run_duration * options.runs;
+        if total.as_secs() >= self.safety_profile(pump_id, &pump_type).max_runtime_seconds {
+            return Err(format!("Calibration needs {}s of pumping, exceeding the runtime limit for pump {}", 
+                total.as_secs(), pump_id));
+        }
+
+        let tank_id = options.tank_id.clone()
+            .unwrap_or_else(|| tank_for_pump(&pump_type).to_string());
+
+        log::info!("Starting flow calibration for pump {} on {} ({} runs of {:?})", 
+            pump_id, tank_id, options.runs, options.run_duration);
+
+        self.register_pump_start(pump_id.to_string(), pump_type.clone());
+
+        let mut runs = Vec::new();
+        let mut failure = None;
+        for run in 0..options.runs {
+            match self.flow_calibration_run(pump_id, &pump_type, driver, measure_level_cm, tank, options) {
+                Ok(result) => {
+                    log::info!("Calibration run {}/{} for {}: {:.2} L/min", 
+                        run + 1, options.runs, pump_id, result.flow_rate_lpm);
+                    runs.push(result);
+                }
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
             }
         }
 
-        // Test pump flow rate calculation
-        // In a real implementation, this would:
-        // 1. Run the pump for a measured time
-        // 2. Measure the water level change
-        // 3. Calculate flow rate based on tank dimensions
-        
-        calibration_data.insert("flow_rate_lpm".to_string(), 2.5);
-        calibration_data.insert("optimal_speed_percent".to_string(), 75.0);
-        calibration_data.insert("sensor_delay_ms".to_string(), 500.0);
-        calibration_data.insert("overflow_threshold".to_string(), 90.0);
-        calibration_data.insert("sensor_response_time_ms".to_string(), 250.0);
+        // Make sure the pump is off whatever happened
+        if let Err(e) = driver.set_running(false) {
+            log::error!("Failed to switch off pump {} after calibration: {}", pump_id, e);
+        }
+        self.register_pump_stop(pump_id.to_string(), "Flow calibration complete".to_string());
 
-        // Save calibration data
-        let cal_path = format!("/opt/aog/calibration_{}.json", pump_id);
-        if let Ok(json) = serde_json::to_string(&calibration_data) {
-            let _ = fs::write(cal_path, json);
+        if let Some(e) = failure {
+            log::error!("Flow calibration for pump {} aborted: {}", pump_id, e);
+            return Err(e);
         }
 
-        Ok(calibration_data)
+        Ok(PumpCalibration::from_runs(pump_id, pump_type, &tank_id, &runs))
     }
 
-    /// Load saved configuration
+    fn flow_calibration_run(
+        &self,
+        pump_id: &str,
+        pump_type: &PumpType,
+        driver: &mut dyn PumpDriver,
+        measure_level_cm: &mut dyn FnMut() -> Result<f32, String>,
+        tank: &TankGeometry,
+        options: &FlowCalibrationOptions,
+    ) -> Result<FlowRun, String> {
+        let tank_id = options.tank_id.as_deref().unwrap_or_else(|| tank_for_pump(pump_type));
+        let expected_sign = expected_level_sign(pump_type);
+        let level_before = measure_level_cm()?;
+
+        driver.set_running(true)?;
+        let started = Instant::now();
+        let mut response_latency = None;
+        let mut level_failures = 0;
+
+        while started.elapsed() < options.run_duration {
+            std::thread::sleep(options.poll_interval);
+
+            if self.is_emergency_stop_active() {
+                let _ = driver.set_running(false);
+                return Err("Emergency stop activated during calibration".to_string());
+            }
+            if !self.check_runtime_limit(pump_id, pump_type.clone()) {
+                let _ = driver.set_running(false);
+                return Err("Runtime limit reached during calibration".to_string());
+            }
+
+            let level = match measure_level_cm() {
+                Ok(level) => {
+                    level_failures = 0;
+                    level
+                }
+                Err(e) => {
+                    // A dead zone reading means the surface is right under the sensor
+                    if e.contains(&format!("{:?}", RangeStatus::DeadZone)) {
+                        let _ = driver.set_running(false);
+                        return Err(format!("Tank {} is full (sensor dead zone) during calibration", tank_id));
+                    }
+                    level_failures += 1;
+                    log::warn!("Level reading failed during calibration ({}/{}): {}", 
+                        level_failures, MAX_CALIBRATION_LEVEL_FAILURES, e);
+                    if level_failures >= MAX_CALIBRATION_LEVEL_FAILURES {
+                        let _ = driver.set_running(false);
+                        return Err(format!("Lost level sensor for {} during calibration: {}", tank_id, e));
+                    }
+                    continue;
+                }
+            };
+
+            let percent = level / tank.height_cm * 100.0;
+            if expected_sign > 0.0 && percent >= CRITICAL_HIGH_LEVEL {
+                let _ = driver.set_running(false);
+                return Err(format!("Tank {} reached critical level {:.1}% during calibration", tank_id, percent));
+            }
+            if expected_sign < 0.0 && percent <= CRITICAL_LOW_LEVEL {
+                let _ = driver.set_running(false);
+                return Err(format!("Tank {} reached critical low level {:.1}% during calibration", tank_id, percent));
+            }
+
+            if response_latency.is_none() && (level - level_before).abs() >= MIN_LEVEL_CHANGE_CM {
+                response_latency = Some(started.elapsed());
+            }
+        }
+
+        driver.set_running(false)?;
+        let pumped_for = started.elapsed();
+
+        // Let the surface settle before the final measurement
+        std::thread::sleep(options.settle_time);
+        let level_after = measure_level_cm()?;
+
+        let change_cm = level_after - level_before;
+        if change_cm * expected_sign < MIN_LEVEL_CHANGE_CM {
+            return Err(format!("No measurable level change ({:+.1}cm) - check pump {} and tank {}", 
+                change_cm, pump_id, tank_id));
+        }
+
+        let litres = change_cm.abs() * tank.litres_per_cm;
+        Ok(FlowRun {
+            flow_rate_lpm: litres / (pumped_for.as_secs_f32() / 60.0),
+            response_latency_ms: response_latency.map(|d| d.as_millis() as u64),
+        })
+    }
+
+    /// Snapshot of the state that must survive a restart
+    pub fn persisted_state(&self) -> PersistedSafetyState {
+        let total_runtime_seconds = safe_mutex_access(&self.total_runtimes, "persisted_state::totals",
+            |totals| totals.iter().map(|(id, d)| (id.clone(), d.as_secs())).collect(), HashMap::new());
+        PersistedSafetyState {
+            total_runtime_seconds,
+            maintenance_runtime_seconds: safe_mutex_access(&self.maintenance_runtimes, "persisted_state::maintenance",
+                |runtimes| runtimes.iter().map(|(id, d)| (id.clone(), d.as_secs())).collect(), HashMap::new()),
+            maintenance_hours: HashMap::new(),
+            faults: safe_mutex_access(&self.fault_reasons, "persisted_state::faults",
+                |faults| faults.clone(), HashMap::new()),
+            emergency_stop: self.emergency_stop_record().or_else(|| {
+                // Flag set without a record (e.g. record lock lost) still has to be kept
+                self.is_emergency_stop_active().then(|| EmergencyStopRecord {
+                    reason: "Emergency stop active".to_string(),
+                    timestamp: Local::now().to_rfc3339(),
+                })
+            }),
+            last_service: safe_mutex_access(&self.last_service, "persisted_state::last_service",
+                |dates| dates.clone(), HashMap::new()),
+            service_history: self.service_history(None),
+            saved_at: Local::now().to_rfc3339(),
+        }
+    }
+
+    /// Apply persisted state. Faults and an active emergency stop are restored
+    /// latched, so pumps stay disabled until explicitly reset.
+    fn restore_state(&self, state: PersistedSafetyState) {
+        if let Ok(mut totals) = recover_mutex_lock(&self.total_runtimes, "restore_state::totals") {
+            for (pump_id, secs) in &state.total_runtime_seconds {
+                totals.insert(pump_id.clone(), Duration::from_secs(*secs));
+            }
+        }
+        if let Ok(mut maintenance) = recover_mutex_lock(&self.maintenance_runtimes, "restore_state::maintenance") {
+            for (pump_id, hours) in &state.maintenance_hours {
+                maintenance.insert(pump_id.clone(), Duration::from_secs(hours * 3600));
+            }
+            for (pump_id, secs) in &state.maintenance_runtime_seconds {
+                maintenance.insert(pump_id.clone(), Duration::from_secs(*secs));
+            }
+        }
+        if let Ok(mut last_service) = recover_mutex_lock(&self.last_service, "restore_state::last_service") {
+            last_service.extend(state.last_service);
+        }
+        if let Ok(mut history) = recover_mutex_lock(&self.service_history, "restore_state::history") {
+            *history = state.service_history;
+        }
+        if let Ok(mut states) = recover_mutex_lock(&self.pump_states, "restore_state::states") {
+            for pump_id in state.faults.keys() {
+                states.insert(pump_id.clone(), PumpState::Fault);
+            }
+        }
+        if let Ok(mut faults) = recover_mutex_lock(&self.fault_reasons, "restore_state::faults") {
+            faults.extend(state.faults);
+        }
+        if let Some(record) = state.emergency_stop {
+            log::error!("Emergency stop still active from {}: {}", record.timestamp, record.reason);
+            self.latch_emergency_stop(record);
+        }
+    }
+
+    /// Set the emergency stop without the shutdown side effects (used at startup)
+    fn latch_emergency_stop(&self, record: EmergencyStopRecord) {
+        if let Ok(mut stop_active) = recover_mutex_lock(&self.emergency_stop_active, "latch_emergency_stop::flag") {
+            *stop_active = true;
+        }
+        if let Ok(mut current) = recover_mutex_lock(&self.emergency_stop_record, "latch_emergency_stop::record") {
+            *current = Some(record);
+        }
+    }
+
+    /// Restore durable state from `path` and keep saving to it on every change.
+    /// A missing file is a fresh install; an unreadable one latches the
+    /// emergency stop, since it may have been written during an emergency.
     pub fn load_from_file(&self, path: &str) -> Result<(), String> {
-        if Path::new(path).exists() {
-            let _contents = fs::read_to_string(path)
-                .map_err(|e| format!("Failed to read safety config: {}", e))?;
-            
-            // TODO: Deserialize and apply configuration
-            log::info!("Loaded pump safety configuration from {}", path);
-            Ok(())
-        } else {
-            Ok(())
+        if let Ok(mut state_path) = recover_mutex_lock(&self.state_path, "load_from_file::path") {
+            *state_path = Some(path.to_string());
+        }
+
+        if !Path::new(path).exists() {
+            return Ok(());
+        }
+
+        let parsed = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read safety state: {}", e))
+            .and_then(|contents| serde_json::from_str::<PersistedSafetyState>(&contents)
+                .map_err(|e| format!("Failed to parse safety state: {}", e)));
+
+        match parsed {
+            Ok(state) => {
+                self.restore_state(state);
+                log::info!("Loaded pump safety state from {}", path);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("{} - latching emergency stop", e);
+                self.latch_emergency_stop(EmergencyStopRecord {
+                    reason: format!("Pump safety state at {} could not be restored", path),
+                    timestamp: Local::now().to_rfc3339(),
+                });
+                Err(e)
+            }
         }
     }
 
-    /// Save current configuration
+    /// Atomically write durable state to `path` (temp file + rename). Both the
+    /// file and the directory entry are synced so a power cut can't lose the
+    /// runtime totals or leave an empty file behind.
     pub fn save_to_file(&self, path: &str) -> Result<(), String> {
-        // TODO: Serialize current state and configuration
-        log::info!("Saved pump safety configuration to {}", path);
+        let json = serde_json::to_string_pretty(&self.persisted_state())
+            .map_err(|e| format!("Failed to serialize safety state: {}", e))?;
+
+        let parent = Path::new(path).parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = fs::File::create(&tmp_path)
+            .map_err(|e| format!("Failed to create {}: {}", tmp_path, e))?;
+        file.write_all(json.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("Failed to write safety state: {}", e))?;
+        drop(file);
+        fs::rename(&tmp_path, path)
+            .map_err(|e| format!("Failed to replace safety state: {}", e))?;
+        fs::File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| format!("Failed to sync {}: {}", parent.display(), e))?;
+
+        log::debug!("Saved pump safety state to {}", path);
         Ok(())
     }
+
+    /// Save to the file given to `load_from_file`, if any
+    fn persist(&self) {
+        let path = safe_mutex_access(&self.state_path, "persist", |path| path.clone(), None);
+        if let Some(path) = path {
+            if let Err(e) = self.save_to_file(&path) {
+                log::error!("Failed to persist pump safety state: {}", e);
+            }
+        }
+    }
 }
 
 // Global safety monitor instance
 lazy_static::lazy_static! {
     pub static ref SAFETY_MONITOR: PumpSafetyMonitor = {
         let monitor = PumpSafetyMonitor::new();
-        let _ = monitor.load_from_file("/opt/aog/pump_safety.json");
+        if let Err(e) = monitor.load_from_file(PUMP_SAFETY_STATE_PATH) {
+            log::error!("Pump safety state not restored: {}", e);
+        }
         monitor
     };
 }
 
+fn format_maintenance(status: &MaintenanceStatus) -> String {
+    format!("{}: {:.1}/{}h ({:.0}%) {:?}, last service {}",
+        status.pump_id, status.runtime_hours, status.interval_hours, status.percent_used, status.level,
+        status.last_service.as_deref().unwrap_or("never"))
+}
+
+/// Handle `pump service <id> [notes]` and `pump maintenance [id]`
+pub fn run_command(command: &str) -> String {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.as_slice() {
+        ["pump", "service", pump_id, notes @ ..] => {
+            if !SAFETY_MONITOR.maintenance_report().iter().any(|status| status.pump_id == *pump_id) {
+                return format!("Unknown pump '{}'", pump_id);
+            }
+            let record = SAFETY_MONITOR.record_service(pump_id, notes.join(" "));
+            format!("Recorded service of {} after {:.1}h of runtime", record.pump_id, record.runtime_hours)
+        }
+        ["pump", "maintenance"] => {
+            let report = SAFETY_MONITOR.maintenance_report();
+            if report.is_empty() {
+                "No pump runtime recorded".to_string()
+            } else {
+                report.iter().map(format_maintenance).collect::<Vec<_>>().join("\n")
+            }
+        }
+        ["pump", "maintenance", pump_id] => {
+            let mut lines = vec![format_maintenance(&SAFETY_MONITOR.maintenance_status(pump_id))];
+            for record in SAFETY_MONITOR.service_history(Some(pump_id)) {
+                lines.push(format!("  {} after {:.1}h: {}", record.serviced_at, record.runtime_hours, record.notes));
+            }
+            lines.join("\n")
+        }
+        _ => "Usage: pump maintenance [id] | pump service <id> [notes]".to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -719,8 +1611,6 @@ mod tests {
 
     #[test]
     fn test_pump_type_safety() {
-        let monitor = PumpSafetyMonitor::new();
-        
         // Different pump types should have different runtime limits
         assert_eq!(MAX_RUNTIME_FILL_PUMP, 300);
         assert_eq!(MAX_RUNTIME_DRAIN_PUMP, 600);
@@ -734,8 +1624,8 @@ mod tests {
         let pump_id = "maint_pump";
         
         // Simulate many hours of operation
-        if let Ok(mut maintenance) = recover_mutex_lock(&monitor.maintenance_hours, "test_maintenance") {
-            maintenance.insert(pump_id.to_string(), 1001);
+        if let Ok(mut maintenance) = recover_mutex_lock(&monitor.maintenance_runtimes, "test_maintenance") {
+            maintenance.insert(pump_id.to_string(), Duration::from_secs(1001 * 3600));
             drop(maintenance);
         } else {
             panic!("Failed to set maintenance hours in test");
@@ -750,16 +1640,274 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_service_resets_maintenance() {
+        let monitor = PumpSafetyMonitor::new();
+        monitor.configure_pumps(&PumpConfig {
+            pumps: vec![PumpDefinition {
+                id: "svc_pump".to_string(),
+                pump_type: PumpType::Fill,
+                output: crate::PumpOutput::Relay { relay_id: 3, board: None },
+                safety: PumpSafetyConfig { maintenance_interval_hours: Some(10), ..Default::default() },
+            }],
+            ..Default::default()
+        }).unwrap();
+
+        // Sub-hour runs are accounted for rather than rounded away
+        if let Ok(mut maintenance) = recover_mutex_lock(&monitor.maintenance_runtimes, "test_service") {
+            maintenance.insert("svc_pump".to_string(), Duration::from_secs(8 * 3600 + 1800));
+        }
+        let status = monitor.maintenance_status("svc_pump");
+        assert_eq!(status.level, MaintenanceLevel::DueSoon);
+        assert!((status.percent_used - 85.0).abs() < 0.1);
+
+        monitor.register_pump_start("svc_pump".to_string(), PumpType::Fill);
+        thread::sleep(Duration::from_millis(20));
+        monitor.register_pump_stop("svc_pump".to_string(), "Test".to_string());
+        assert!(monitor.maintenance_status("svc_pump").runtime_hours > 8.5);
+
+        if let Ok(mut maintenance) = recover_mutex_lock(&monitor.maintenance_runtimes, "test_service") {
+            maintenance.insert("svc_pump".to_string(), Duration::from_secs(10 * 3600));
+        }
+        assert_eq!(monitor.maintenance_status("svc_pump").level, MaintenanceLevel::Overdue);
+
+        let record = monitor.record_service("svc_pump", "Replaced impeller".to_string());
+        assert!((record.runtime_hours - 10.0).abs() < 0.01);
+        let status = monitor.maintenance_status("svc_pump");
+        assert_eq!(status.level, MaintenanceLevel::Ok);
+        assert_eq!(status.last_service, Some(record.serviced_at.clone()));
+        assert_eq!(monitor.service_history(Some("svc_pump")), vec![record]);
+        assert!(monitor.service_history(Some("other_pump")).is_empty());
+        assert_eq!(monitor.maintenance_report().len(), 1);
+    }
+
+    #[test]
+    fn test_maintenance_levels() {
+        let hours = |h: f32| Duration::from_secs_f32(h * 3600.0);
+        assert_eq!(MaintenanceLevel::for_runtime(hours(79.0), 100), MaintenanceLevel::Ok);
+        assert_eq!(MaintenanceLevel::for_runtime(hours(80.0), 100), MaintenanceLevel::DueSoon);
+        assert_eq!(MaintenanceLevel::for_runtime(hours(90.0), 100), MaintenanceLevel::Urgent);
+        assert_eq!(MaintenanceLevel::for_runtime(hours(100.0), 100), MaintenanceLevel::Overdue);
+    }
+
+    #[test]
+    fn test_state_persistence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("pump_safety.json");
+        let path = path.to_str().unwrap();
+
+        let monitor = PumpSafetyMonitor::new();
+        monitor.load_from_file(path).unwrap();
+        monitor.register_pump_start("persist_pump".to_string(), PumpType::Fill);
+        monitor.register_pump_stop("persist_pump".to_string(), "Test".to_string());
+        monitor.record_service("persist_pump", "Checked seals".to_string());
+        monitor.set_fault("faulty_pump", "Relay stuck on".to_string());
+        monitor.emergency_shutdown("Overflow".to_string());
+        assert!(Path::new(path).exists());
+
+        // A fresh monitor (i.e. after a reboot) comes back latched
+        let restored = PumpSafetyMonitor::new();
+        restored.load_from_file(path).unwrap();
+        assert!(restored.is_emergency_stop_active());
+        assert_eq!(restored.emergency_stop_record().unwrap().reason, "Overflow");
+        assert_eq!(restored.fault_reason("faulty_pump").as_deref(), Some("Relay stuck on"));
+        assert!(restored.get_pump_stats("persist_pump").contains_key("total_runtime_seconds"));
+        assert_eq!(restored.service_history(Some("persist_pump"))[0].notes, "Checked seals");
+
+        restored.reset_emergency_stop();
+        assert!(restored.can_start_pump("other_pump", PumpType::Circulation).is_ok());
+        assert!(restored.can_start_pump("faulty_pump", PumpType::Circulation).is_err());
+        restored.clear_fault("faulty_pump");
+
+        let state = PumpSafetyMonitor::new();
+        state.load_from_file(path).unwrap();
+        assert!(!state.is_emergency_stop_active());
+        assert!(state.fault_reason("faulty_pump").is_none());
+    }
+
+    #[test]
+    fn test_corrupt_state_latches_emergency_stop() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("pump_safety.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        let monitor = PumpSafetyMonitor::new();
+        assert!(monitor.load_from_file(path.to_str().unwrap()).is_err());
+        assert!(monitor.can_start_pump("any_pump", PumpType::Circulation).is_err());
+    }
+
+    #[test]
+    fn test_configured_safety_profiles() {
+        let monitor = PumpSafetyMonitor::new();
+        assert_eq!(monitor.safety_profile("drain_pump", &PumpType::Drain).max_runtime_seconds, MAX_RUNTIME_DRAIN_PUMP);
+
+        // A default config keeps the limits for each pump type
+        monitor.configure_pumps(&PumpConfig::default()).unwrap();
+        assert_eq!(monitor.safety_profile("fill_pump", &PumpType::Fill).max_runtime_seconds, MAX_RUNTIME_FILL_PUMP);
+        assert_eq!(monitor.safety_profile("drain_pump", &PumpType::Drain).max_runtime_seconds, MAX_RUNTIME_DRAIN_PUMP);
+
+        let config = PumpConfig {
+            pump_runtime_limit_seconds: Some(120),
+            pump_cooldown_seconds: Some(0),
+            pumps: vec![PumpDefinition {
+                id: "osc_limited".to_string(),
+                pump_type: PumpType::Circulation,
+                output: crate::PumpOutput::Gpio { pin: 17 },
+                safety: PumpSafetyConfig {
+                    max_runtime_seconds: Some(900),
+                    max_oscillation_cycles: Some(2),
+                    maintenance_interval_hours: Some(10),
+                    ..Default::default()
+                },
+            }],
+            ..Default::default()
+        };
+        monitor.configure_pumps(&config).unwrap();
+
+        // Config-wide limits apply to pumps without their own profile
+        let profile = monitor.safety_profile("other_pump", &PumpType::Drain);
+        assert_eq!(profile.max_runtime_seconds, 120);
+        assert_eq!(profile.cooldown_seconds, 0);
+
+        // The pump's own profile wins, and its configured type is used
+        let profile = monitor.safety_profile("osc_limited", &PumpType::Fill);
+        assert_eq!(profile.max_runtime_seconds, 900);
+        assert_eq!(profile.max_oscillation_cycles, 2);
+        assert_eq!(profile.oscillation_speed_max_ms, OSCILLATION_SPEED_MAX);
+
+        for _ in 0..2 {
+            assert!(monitor.check_oscillation_safety("osc_limited", 1000).is_ok());
+        }
+        assert!(monitor.check_oscillation_safety("osc_limited", 1000).is_err());
+
+        // A zero cooldown allows an immediate restart
+        monitor.register_pump_start("other_pump".to_string(), PumpType::Circulation);
+        monitor.register_pump_stop("other_pump".to_string(), "Test".to_string());
+        thread::sleep(Duration::from_millis(50));
+        assert!(monitor.can_start_pump("other_pump", PumpType::Circulation).is_ok());
+
+        if let Ok(mut maintenance) = recover_mutex_lock(&monitor.maintenance_runtimes, "test_profiles") {
+            maintenance.insert("osc_limited".to_string(), Duration::from_secs(11 * 3600));
+        }
+        assert!(monitor.can_start_pump("osc_limited", PumpType::Circulation).unwrap_err().contains("maintenance"));
+    }
+
+    #[test]
+    fn test_invalid_pump_configuration() {
+        let monitor = PumpSafetyMonitor::new();
+        let pump = PumpDefinition {
+            id: "dup".to_string(),
+            pump_type: PumpType::Fill,
+            output: crate::PumpOutput::Relay { relay_id: 3, board: None },
+            safety: PumpSafetyConfig::default(),
+        };
+        let config = PumpConfig { pumps: vec![pump.clone(), pump.clone()], ..Default::default() };
+        assert!(monitor.configure_pumps(&config).is_err());
+
+        let bad = PumpDefinition {
+            safety: PumpSafetyConfig { oscillation_speed_min_ms: Some(500), oscillation_speed_max_ms: Some(100), ..Default::default() },
+            ..pump
+        };
+        assert!(monitor.configure_pumps(&PumpConfig { pumps: vec![bad], ..Default::default() }).is_err());
+        assert!(monitor.pump_definition("dup").is_none());
+    }
+
+    fn fast_options() -> FlowCalibrationOptions {
+        FlowCalibrationOptions {
+            run_duration: Duration::from_millis(300),
+            runs: 2,
+            settle_time: Duration::from_millis(0),
+            poll_interval: Duration::from_millis(20),
+            tank_id: Some("tank1".to_string()),
+        }
+    }
+
     #[test]
     fn test_calibration() {
         let monitor = PumpSafetyMonitor::new();
         let pump_id = "cal_pump";
         
-        let result = monitor.calibrate_pump(pump_id, PumpType::Fill);
-        assert!(result.is_ok());
+        // Simulated tank: 6 L/min into a tank holding 0.01 L per cm
+        let driver = Arc::new(Mutex::new(MockPumpDriver::default()));
+        let level_driver = Arc::clone(&driver);
+        let mut measure = move || {
+            let litres = level_driver.lock().unwrap().runtime().as_secs_f32() * 0.1;
+            Ok(30.0 + litres / 0.01)
+        };
+        
+        let result = monitor.run_flow_calibration(pump_id, PumpType::Fill,
+            &mut Arc::clone(&driver), &mut measure,
+            &TankGeometry { height_cm: 100.0, litres_per_cm: 0.01 }, &fast_options());
+        let cal = result.expect("Calibration should succeed");
+        
+        assert!((cal.flow_rate_lpm - 6.0).abs() < 0.5, "flow rate {}", cal.flow_rate_lpm);
+        assert_eq!(cal.runs, 2);
+        assert!(cal.response_latency_ms.is_some());
+        assert!(!driver.lock().unwrap().running);
+        
+        let duration = cal.duration_for_volume(1.0).unwrap();
+        assert!(duration.as_secs_f32() > 9.0 && duration.as_secs_f32() < 11.0);
+    }
+
+    #[test]
+    fn test_calibration_without_level_change() {
+        let monitor = PumpSafetyMonitor::new();
+        let mut driver = MockPumpDriver::default();
+        let mut measure = || Ok(40.0);
+        
+        let result = monitor.run_flow_calibration("dry_pump", PumpType::Fill,
+            &mut driver, &mut measure,
+            &TankGeometry { height_cm: 100.0, litres_per_cm: 1.0 }, &fast_options());
+        assert!(result.is_err());
+        assert!(!driver.running);
+    }
+
+    #[test]
+    fn test_calibration_stops_auxiliary_at_critical_level() {
+        let monitor = PumpSafetyMonitor::new();
+        let mut driver = MockPumpDriver::default();
+        let mut measure = || Ok(CRITICAL_HIGH_LEVEL + 1.0);
+        
+        let result = monitor.run_flow_calibration("aux_pump", PumpType::Auxiliary,
+            &mut driver, &mut measure,
+            &TankGeometry { height_cm: 100.0, litres_per_cm: 1.0 }, &fast_options());
+        assert!(result.unwrap_err().contains("critical level"));
+        assert!(!driver.running);
+    }
+
+    #[test]
+    fn test_calibration_stops_after_level_failures() {
+        let monitor = PumpSafetyMonitor::new();
+        let mut driver = MockPumpDriver::default();
+        let mut reads = 0;
+        let mut measure = || {
+            reads += 1;
+            if reads == 1 { Ok(40.0) } else { Err("Sensor timeout".to_string()) }
+        };
+        
+        let result = monitor.run_flow_calibration("flaky_pump", PumpType::Fill,
+            &mut driver, &mut measure,
+            &TankGeometry { height_cm: 100.0, litres_per_cm: 1.0 }, &fast_options());
+        assert!(result.unwrap_err().contains("Lost level sensor"));
+        assert!(!driver.running);
+        assert_eq!(reads, 1 + MAX_CALIBRATION_LEVEL_FAILURES);
+    }
+
+    #[test]
+    fn test_calibration_stops_in_dead_zone() {
+        let monitor = PumpSafetyMonitor::new();
+        let mut driver = MockPumpDriver::default();
+        let mut reads = 0;
+        let mut measure = || {
+            reads += 1;
+            if reads == 1 { Ok(90.0) } else { Err("Distance 2.0cm is not usable (DeadZone)".to_string()) }
+        };
         
-        let cal_data = result.expect("Calibration should succeed");
-        assert!(cal_data.contains_key("flow_rate_lpm"));
-        assert!(cal_data.contains_key("optimal_speed_percent"));
+        let result = monitor.run_flow_calibration("full_pump", PumpType::Fill,
+            &mut driver, &mut measure,
+            &TankGeometry { height_cm: 100.0, litres_per_cm: 1.0 }, &fast_options());
+        assert!(result.unwrap_err().contains("dead zone"));
+        assert!(!driver.running);
+        assert_eq!(reads, 2);
     }
 }
\ No newline at end of file
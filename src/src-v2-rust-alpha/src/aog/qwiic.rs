@@ -4,6 +4,11 @@ use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::thread;
 use std::process::Command;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use chrono::Local;
+use crate::{RelayBoardConfig, RelayBoardType, RelayChannelConfig, RelayRole};
 
 const MIN_FIRMWARE_VERSION: f32 = 1.0;
 const MAX_FIRMWARE_VERSION: f32 = 2.0;
@@ -12,6 +17,16 @@ const INITIAL_RETRY_DELAY_MS: u64 = 100;
 const MAX_RETRY_DELAY_MS: u64 = 5000;
 const HEALTH_CHECK_INTERVAL_SECS: u64 = 30;
 const MAX_CONSECUTIVE_FAILURES: u32 = 10;
+/// Address of the relay board when none are configured
+pub const DEFAULT_RELAY_BOARD: u16 = 0x25;
+/// Writes attempted before a relay that won't read back is declared stuck
+const VERIFY_ATTEMPTS: u32 = 2;
+/// Time for mechanical contacts to settle before reading a relay back
+const RELAY_SETTLE_MS: u64 = 20;
+/// Solid state relays switch without bounce
+const SSR_SETTLE_MS: u64 = 2;
+/// Per-relay switch counters
+pub const RELAY_COUNTERS_PATH: &str = "/opt/aog/relay_counters.json";
 
 #[derive(Debug, Clone)]
 pub enum RelayError {
@@ -19,6 +34,7 @@ pub enum RelayError {
     FirmwareIncompatible(String),
     InitializationFailure(String),
     OperationFailure(String),
+    StuckRelay(String),
 }
 
 impl std::fmt::Display for RelayError {
@@ -28,13 +44,14 @@ impl std::fmt::Display for RelayError {
             RelayError::FirmwareIncompatible(msg) => write!(f, "Firmware incompatible: {}", msg),
             RelayError::InitializationFailure(msg) => write!(f, "Initialization failure: {}", msg),
             RelayError::OperationFailure(msg) => write!(f, "Operation failure: {}", msg),
+            RelayError::StuckRelay(msg) => write!(f, "Stuck relay: {}", msg),
         }
     }
 }
 
 impl std::error::Error for RelayError {}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct RelayHealthStatus {
     pub is_healthy: bool,
     pub last_successful_operation: Option<Instant>,
@@ -43,18 +60,6 @@ pub struct RelayHealthStatus {
     pub last_error: Option<RelayError>,
 }
 
-impl Default for RelayHealthStatus {
-    fn default() -> Self {
-        RelayHealthStatus {
-            is_healthy: false,
-            last_successful_operation: None,
-            consecutive_failures: 0,
-            firmware_version: None,
-            last_error: None,
-        }
-    }
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RecoveryConfig {
     pub enable_auto_recovery: bool,
@@ -81,6 +86,8 @@ impl Default for RecoveryConfig {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct QwiicRelayDevice {
     pub id: u16,
+    #[serde(default)]
+    pub board_type: RelayBoardType,
     pub aux_tank_pump_relay_id: Option<u16>,
     pub grow_light_relay_id: Option<u16>,
     pub water_pump_relay_id: Option<u16>,
@@ -93,39 +100,55 @@ pub struct QwiicRelayDevice {
 }
 
 impl QwiicRelayDevice {
+    /// Device for the board at `id`, with its type and channel roles taken
+    /// from the configured relay layout
     pub fn new(id: u16) -> QwiicRelayDevice {
-        QwiicRelayDevice { 
-            id,
-            aux_tank_pump_relay_id: Some(4),
-            grow_light_relay_id: Some(1),
-            water_pump_relay_id: Some(3),
-            water_drain_relay_id: Some(2),
-            air_circulation_relay_id: Some(1),
-            health_status: Arc::new(Mutex::new(RelayHealthStatus::default())),
-            recovery_config: RecoveryConfig::default(),
-        }
+        Self::new_with_config(id, RecoveryConfig::default())
     }
 
     pub fn new_with_config(id: u16, recovery_config: RecoveryConfig) -> QwiicRelayDevice {
+        let board = relay_board(id);
+        let role = |role: RelayRole| board.as_ref()
+            .and_then(|b| b.channels.iter().find(|c| c.role == role))
+            .map(|c| c.relay_id);
+
         QwiicRelayDevice { 
             id,
-            aux_tank_pump_relay_id: Some(4),
-            grow_light_relay_id: Some(1),
-            water_pump_relay_id: Some(3),
-            water_drain_relay_id: Some(2),
-            air_circulation_relay_id: Some(1),
+            board_type: board.as_ref().map(|b| b.board_type).unwrap_or_default(),
+            aux_tank_pump_relay_id: role(RelayRole::AuxTankPump),
+            grow_light_relay_id: role(RelayRole::GrowLight),
+            water_pump_relay_id: role(RelayRole::FillPump),
+            water_drain_relay_id: role(RelayRole::DrainPump),
+            // Lights and air share a relay unless air has its own channel
+            air_circulation_relay_id: role(RelayRole::AirCirculation).or(role(RelayRole::GrowLight)),
             health_status: Arc::new(Mutex::new(RelayHealthStatus::default())),
             recovery_config,
         }
     }
 
+    fn relay_config(&self) -> QwiicRelayConfig {
+        QwiicRelayConfig::new(self.board_type.channel_count() as u8)
+    }
+
+    fn settle_time(&self) -> Duration {
+        Duration::from_millis(if self.board_type.is_mechanical() { RELAY_SETTLE_MS } else { SSR_SETTLE_MS })
+    }
+
+    /// Firmware version of the board, checked against the supported range.
+    /// Not retried, so probing an absent board doesn't count as a relay failure.
+    pub fn firmware_version(&self) -> Result<f32, RelayError> {
+        let mut qwiic_relay = QwiicRelay::new(self.relay_config(), "/dev/i2c-1", self.id)
+            .map_err(|e| RelayError::InitializationFailure(format!("Failed to initialize relay: {}", e)))?;
+        self.check_firmware_version(&mut qwiic_relay)
+    }
+
     fn check_firmware_version(&self, qwiic_relay: &mut QwiicRelay) -> Result<f32, RelayError> {
         match qwiic_relay.get_version() {
             Ok(version) => {
                 let version_float = version as f32;
                 log::info!("Qwiic Relay Firmware Version: {}", version);
                 
-                if version_float < MIN_FIRMWARE_VERSION || version_float > MAX_FIRMWARE_VERSION {
+                if !(MIN_FIRMWARE_VERSION..=MAX_FIRMWARE_VERSION).contains(&version_float) {
                     let msg = format!(
                         "Firmware version {} is outside supported range [{}, {}]",
                         version_float, MIN_FIRMWARE_VERSION, MAX_FIRMWARE_VERSION
@@ -201,6 +224,8 @@ impl QwiicRelayDevice {
     fn handle_critical_failure(&self, operation_name: &str, error: &RelayError) {
         log::error!("Critical failure in {}: {}", operation_name, error);
         
+        // Only a confirmed stuck relay trips the emergency stop (see handle_stuck_relay);
+        // a board that stops answering is reported so a missing board doesn't latch it
         self.send_alert(&format!("Critical relay failure: {} - {}", operation_name, error));
         
         if let Ok(status) = self.health_status.lock() {
@@ -214,6 +239,31 @@ impl QwiicRelayDevice {
         }
     }
 
+    /// A relay that won't follow its command is a fault on the safety monitor;
+    /// one stuck on may be running a pump, so it also trips the emergency stop
+    fn handle_stuck_relay(&self, relay_id: u16, stuck_on: bool, error: &RelayError) {
+        RELAY_COUNTERS.record_stuck(self.id, relay_id);
+        self.update_health_status(false, Some(error.clone()));
+        self.send_alert(&format!("Stuck relay 0x{:02x}:{} - {}", self.id, relay_id, error));
+
+        crate::aog::pump_safety::SAFETY_MONITOR.set_fault(&relay_fault_id(self.id, relay_id), error.to_string());
+        if stuck_on {
+            crate::aog::emergency_stop::ESTOP.trigger(
+                crate::aog::emergency_stop::TriggerSource::RelayFault,
+                &format!("Relay 0x{:02x}:{} stuck on", self.id, relay_id),
+            );
+        }
+    }
+
+    fn clear_stuck_fault(&self, relay_id: u16) {
+        let fault_id = relay_fault_id(self.id, relay_id);
+        let monitor = &crate::aog::pump_safety::SAFETY_MONITOR;
+        if monitor.fault_reason(&fault_id).is_some() {
+            log::info!("Relay 0x{:02x}:{} switching correctly again, clearing fault", self.id, relay_id);
+            monitor.clear_fault(&fault_id);
+        }
+    }
+
     fn send_alert(&self, message: &str) {
         log::error!("ALERT: {}", message);
         
@@ -252,12 +302,13 @@ impl QwiicRelayDevice {
         let device_id = self.id;
         let interval = self.recovery_config.health_check_interval_secs;
         let recovery_config = self.recovery_config.clone();
+        let relay_count = self.board_type.channel_count() as u8;
         
         thread::spawn(move || {
             loop {
                 thread::sleep(Duration::from_secs(interval));
                 
-                let qwiic_relay_config = QwiicRelayConfig::default();
+                let qwiic_relay_config = QwiicRelayConfig::new(relay_count);
                 match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", device_id) {
                     Ok(mut qwiic_relay) => {
                         match qwiic_relay.get_version() {
@@ -309,7 +360,7 @@ impl QwiicRelayDevice {
 
         let result = self.execute_with_retry(
             || {
-                let qwiic_relay_config = QwiicRelayConfig::default();
+                let qwiic_relay_config = self.relay_config();
                 match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id) {
                     Ok(mut qwiic_relay) => {
                         self.check_firmware_version(&mut qwiic_relay)?;
@@ -336,7 +387,7 @@ impl QwiicRelayDevice {
     }
 
     fn test_legacy(&self) {
-        let qwiic_relay_config = QwiicRelayConfig::default();
+        let qwiic_relay_config = self.relay_config();
         let qwiic_relay_d = QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id);
         match qwiic_relay_d {
             Ok(mut qwiic_relay) => {
@@ -363,15 +414,38 @@ impl QwiicRelayDevice {
         }    
     }
 
-    pub fn all_off(&self) {
+    /// Switch every relay off and confirm each one reads back off
+    pub fn all_off(&self) -> Result<(), RelayError> {
+        self.write_all_off()?;
+        thread::sleep(self.settle_time());
+
+        let mut stuck = Vec::new();
+        for relay_id in 1..=self.board_type.channel_count() {
+            if self.get_relay_state(relay_id)? {
+                stuck.push(relay_id);
+            } else {
+                RELAY_COUNTERS.record(self.id, relay_id, false);
+            }
+        }
+
+        if stuck.is_empty() {
+            return Ok(());
+        }
+        let error = RelayError::StuckRelay(format!("relay 0x{:02x}: {:?} stuck on after all off", self.id, stuck));
+        for relay_id in stuck {
+            self.handle_stuck_relay(relay_id, true, &error);
+        }
+        Err(error)
+    }
+
+    fn write_all_off(&self) -> Result<(), RelayError> {
         if !self.recovery_config.enable_auto_recovery {
-            self.all_off_legacy();
-            return;
+            return self.all_off_legacy();
         }
 
-        let _ = self.execute_with_retry(
+        self.execute_with_retry(
             || {
-                let qwiic_relay_config = QwiicRelayConfig::default();
+                let qwiic_relay_config = self.relay_config();
                 match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id) {
                     Ok(mut qwiic_relay) => {
                         qwiic_relay.set_all_relays_off()
@@ -383,33 +457,67 @@ impl QwiicRelayDevice {
                 }
             },
             "all relays off"
-        );
+        )
     }
 
-    fn all_off_legacy(&self) {
-        let qwiic_relay_config = QwiicRelayConfig::default();
+    fn all_off_legacy(&self) -> Result<(), RelayError> {
+        let qwiic_relay_config = self.relay_config();
         let qwiic_relay_d = QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id);
         match qwiic_relay_d {
             Ok(mut qwiic_relay) => {
                 match qwiic_relay.set_all_relays_off() {
-                    Ok(_) => log::debug!("All relays turned off"),
-                    Err(e) => log::error!("Failed to turn off all relays: {}", e)
+                    Ok(_) => {
+                        log::debug!("All relays turned off");
+                        Ok(())
+                    },
+                    Err(e) => {
+                        log::error!("Failed to turn off all relays: {}", e);
+                        Err(RelayError::OperationFailure(format!("Failed to turn off all relays: {}", e)))
+                    }
                 }
             }, 
             Err(err) => {
                 log::error!("{}", err);
+                Err(RelayError::InitializationFailure(format!("Failed to initialize relay: {}", err)))
             }
         }    
     }
 
+    /// Switch a relay and confirm it by reading it back. A relay that still
+    /// reads wrong after a second write is reported as stuck.
     pub fn set_relay(&self, relay_id: u16, state: bool) -> Result<(), RelayError> {
+        if relay_id == 0 || relay_id > self.board_type.channel_count() {
+            return Err(RelayError::OperationFailure(format!(
+                "relay {} does not exist on {:?} board 0x{:02x}", relay_id, self.board_type, self.id)));
+        }
+
+        for attempt in 1..=VERIFY_ATTEMPTS {
+            self.write_relay(relay_id, state)?;
+            thread::sleep(self.settle_time());
+
+            let actual = self.get_relay_state(relay_id)?;
+            if actual == state {
+                RELAY_COUNTERS.record(self.id, relay_id, state);
+                self.clear_stuck_fault(relay_id);
+                return Ok(());
+            }
+            log::warn!("Relay 0x{:02x}:{} commanded {} but reads {} (attempt {}/{})",
+                self.id, relay_id, on_off(state), on_off(actual), attempt, VERIFY_ATTEMPTS);
+        }
+
+        let error = RelayError::StuckRelay(format!("relay 0x{:02x}:{} stuck {}", self.id, relay_id, on_off(!state)));
+        self.handle_stuck_relay(relay_id, !state, &error);
+        Err(error)
+    }
+
+    fn write_relay(&self, relay_id: u16, state: bool) -> Result<(), RelayError> {
         if !self.recovery_config.enable_auto_recovery {
             return self.set_relay_legacy(relay_id, state);
         }
 
         self.execute_with_retry(
             || {
-                let qwiic_relay_config = QwiicRelayConfig::default();
+                let qwiic_relay_config = self.relay_config();
                 match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id) {
                     Ok(mut qwiic_relay) => {
                         if state {
@@ -430,7 +538,7 @@ impl QwiicRelayDevice {
     }
 
     fn set_relay_legacy(&self, relay_id: u16, state: bool) -> Result<(), RelayError> {
-        let qwiic_relay_config = QwiicRelayConfig::default();
+        let qwiic_relay_config = self.relay_config();
         match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id) {
             Ok(mut qwiic_relay) => {
                 let result = if state {
@@ -455,7 +563,7 @@ impl QwiicRelayDevice {
 
         self.execute_with_retry(
             || {
-                let qwiic_relay_config = QwiicRelayConfig::default();
+                let qwiic_relay_config = self.relay_config();
                 match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id) {
                     Ok(mut qwiic_relay) => {
                         qwiic_relay.get_relay_state(Some(relay_id as u8))
@@ -471,7 +579,7 @@ impl QwiicRelayDevice {
     }
 
     fn get_relay_state_legacy(&self, relay_id: u16) -> Result<bool, RelayError> {
-        let qwiic_relay_config = QwiicRelayConfig::default();
+        let qwiic_relay_config = self.relay_config();
         match QwiicRelay::new(qwiic_relay_config, "/dev/i2c-1", self.id) {
             Ok(mut qwiic_relay) => {
                 qwiic_relay.get_relay_state(Some(relay_id as u8))
@@ -485,6 +593,245 @@ impl QwiicRelayDevice {
     }
 }
 
+fn on_off(state: bool) -> &'static str {
+    if state { "on" } else { "off" }
+}
+
+/// Safety monitor fault id for a relay channel
+pub fn relay_fault_id(board: u16, relay_id: u16) -> String {
+    format!("relay_0x{:02x}_{}", board, relay_id)
+}
+
+/// Switching history of one relay, for contact wear
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RelayCounter {
+    pub switch_count: u64,
+    pub last_state: Option<bool>,
+    pub last_switched: Option<String>,
+    pub stuck_faults: u64,
+}
+
+/// Verified switch counts for every relay, keyed by board and relay
+pub struct RelaySwitchCounters {
+    counters: Mutex<HashMap<String, RelayCounter>>,
+    path: Option<String>,
+}
+
+impl RelaySwitchCounters {
+    pub fn new(path: Option<&str>) -> Self {
+        let counters = path
+            .filter(|path| Path::new(path).exists())
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        RelaySwitchCounters { counters: Mutex::new(counters), path: path.map(|p| p.to_string()) }
+    }
+
+    fn key(board: u16, relay_id: u16) -> String {
+        format!("0x{:02x}:{}", board, relay_id)
+    }
+
+    /// Note a verified relay state, counting it if the relay changed.
+    /// A relay with no history is assumed to have been off.
+    pub fn record(&self, board: u16, relay_id: u16, state: bool) -> u64 {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let counter = counters.entry(Self::key(board, relay_id)).or_default();
+        if counter.last_state.unwrap_or(false) == state {
+            counter.last_state = Some(state);
+            return counter.switch_count;
+        }
+        counter.switch_count += 1;
+        counter.last_state = Some(state);
+        counter.last_switched = Some(Local::now().to_rfc3339());
+        let count = counter.switch_count;
+        self.save(&counters);
+        count
+    }
+
+    pub fn record_stuck(&self, board: u16, relay_id: u16) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.entry(Self::key(board, relay_id)).or_default().stuck_faults += 1;
+        self.save(&counters);
+    }
+
+    pub fn get(&self, board: u16, relay_id: u16) -> RelayCounter {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+            .get(&Self::key(board, relay_id)).cloned().unwrap_or_default()
+    }
+
+    pub fn all(&self) -> BTreeMap<String, RelayCounter> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+            .iter().map(|(key, counter)| (key.clone(), counter.clone())).collect()
+    }
+
+    fn save(&self, counters: &HashMap<String, RelayCounter>) {
+        if let Some(path) = &self.path {
+            let result = serde_json::to_string_pretty(counters)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                log::warn!("Failed to save relay counters: {}", e);
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref RELAY_COUNTERS: RelaySwitchCounters = RelaySwitchCounters::new(Some(RELAY_COUNTERS_PATH));
+}
+
+/// A single quad relay at 0x25 wired as the original kit
+pub fn default_relay_layout() -> Vec<RelayBoardConfig> {
+    let channel = |relay_id, name: &str, role| RelayChannelConfig { relay_id, name: name.to_string(), role };
+    vec![RelayBoardConfig {
+        address: DEFAULT_RELAY_BOARD,
+        board_type: RelayBoardType::QuadMechanical,
+        channels: vec![
+            channel(1, "lights", RelayRole::GrowLight),
+            channel(2, "drain_pump", RelayRole::DrainPump),
+            channel(3, "fill_pump", RelayRole::FillPump),
+            channel(4, "aux_pump", RelayRole::AuxTankPump),
+        ],
+    }]
+}
+
+/// Check addresses, channel numbers and names across all boards
+pub fn validate_layout(boards: &[RelayBoardConfig]) -> Result<(), String> {
+    if boards.is_empty() {
+        return Err("At least one relay board must be configured".to_string());
+    }
+
+    let mut addresses = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+    for board in boards {
+        if board.address > 0x7f {
+            return Err(format!("Relay board address 0x{:02x} is not a 7-bit I2C address", board.address));
+        }
+        if addresses.contains(&board.address) {
+            return Err(format!("Relay board 0x{:02x} is configured twice", board.address));
+        }
+        addresses.push(board.address);
+
+        let mut relay_ids = Vec::new();
+        for channel in &board.channels {
+            if channel.relay_id == 0 || channel.relay_id > board.board_type.channel_count() {
+                return Err(format!("Channel '{}': relay {} does not exist on {:?} board 0x{:02x}",
+                    channel.name, channel.relay_id, board.board_type, board.address));
+            }
+            if relay_ids.contains(&channel.relay_id) {
+                return Err(format!("Relay {} on board 0x{:02x} is named twice", channel.relay_id, board.address));
+            }
+            relay_ids.push(channel.relay_id);
+
+            if channel.name.is_empty() || !channel.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(format!("Invalid relay channel name '{}'", channel.name));
+            }
+            if channel.name.parse::<u16>().is_ok() || names.contains(&channel.name.as_str()) {
+                return Err(format!("Relay channel name '{}' must be unique and not a number", channel.name));
+            }
+            names.push(&channel.name);
+        }
+    }
+    Ok(())
+}
+
+lazy_static::lazy_static! {
+    static ref RELAY_LAYOUT: Mutex<Vec<RelayBoardConfig>> = Mutex::new(default_relay_layout());
+}
+
+/// Replace the relay layout with the configured boards
+pub fn configure_boards(boards: Vec<RelayBoardConfig>) -> Result<(), String> {
+    validate_layout(&boards)?;
+    *RELAY_LAYOUT.lock().unwrap_or_else(|e| e.into_inner()) = boards;
+    Ok(())
+}
+
+pub fn relay_boards() -> Vec<RelayBoardConfig> {
+    RELAY_LAYOUT.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn relay_board(address: u16) -> Option<RelayBoardConfig> {
+    relay_boards().into_iter().find(|board| board.address == address)
+}
+
+/// Board used for bare relay numbers and pump outputs without a board
+pub fn default_board() -> u16 {
+    relay_boards().first().map(|board| board.address).unwrap_or(DEFAULT_RELAY_BOARD)
+}
+
+/// Board address and channel for a logical channel name
+pub fn find_channel(name: &str) -> Option<(u16, RelayChannelConfig)> {
+    relay_boards().into_iter()
+        .flat_map(|board| board.channels.into_iter().map(move |channel| (board.address, channel)))
+        .find(|(_, channel)| channel.name == name)
+}
+
+/// Board address and relay of the first channel in `boards` wired for `role`
+pub fn find_role(boards: &[RelayBoardConfig], role: RelayRole) -> Option<(u16, u16)> {
+    boards.iter()
+        .flat_map(|board| board.channels.iter().map(move |channel| (board.address, channel)))
+        .find(|(_, channel)| channel.role == role)
+        .map(|(address, channel)| (address, channel.relay_id))
+}
+
+/// Resolve a channel name, or a relay number on the default board
+pub fn resolve_channel(channel: &str) -> Result<(u16, u16), String> {
+    if let Some((board, channel)) = find_channel(channel) {
+        return Ok((board, channel.relay_id));
+    }
+    let board = default_board();
+    let count = relay_board(board).map(|b| b.board_type.channel_count()).unwrap_or(0);
+    match channel.parse::<u16>() {
+        Ok(relay_id) if relay_id >= 1 && relay_id <= count => Ok((board, relay_id)),
+        _ => Err(format!("Unknown relay '{}'", channel)),
+    }
+}
+
+fn format_relay_status() -> String {
+    let mut lines = Vec::new();
+    for board in relay_boards() {
+        let device = QwiicRelayDevice::new(board.address);
+        lines.push(format!("Board 0x{:02x} ({:?})", board.address, board.board_type));
+        for relay_id in 1..=board.board_type.channel_count() {
+            let channel = board.channels.iter().find(|c| c.relay_id == relay_id);
+            let label = match channel {
+                Some(channel) => format!("{} ({}, {:?})", channel.name, relay_id, channel.role),
+                None => format!("relay {}", relay_id),
+            };
+            let state = match device.get_relay_state(relay_id) {
+                Ok(state) => on_off(state).to_uppercase(),
+                Err(e) => format!("Error reading state: {}", e),
+            };
+            let counter = RELAY_COUNTERS.get(board.address, relay_id);
+            let stuck = if counter.stuck_faults > 0 { format!(", {} stuck faults", counter.stuck_faults) } else { String::new() };
+            lines.push(format!("  {}: {} ({} switches{})", label, state, counter.switch_count, stuck));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Handle `relay status` and `relay on|off <name|n>`
+pub fn run_command(command: &str) -> String {
+    use crate::aog::actuators::{ActuatorOutput, ActuatorState, ACTUATORS};
+
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.as_slice() {
+        ["relay"] | ["relay", "status"] => format_relay_status(),
+        ["relay", action @ ("on" | "off"), channel] => {
+            let (board, relay_id) = match resolve_channel(channel) {
+                Ok(resolved) => resolved,
+                Err(e) => return e,
+            };
+            let output = ActuatorOutput::Relay { board, relay_id };
+            match ACTUATORS.set_output(output, ActuatorState::from_bool(*action == "on")) {
+                Ok(_) => format!("Relay {} {}", channel, action),
+                Err(e) => format!("Relay {} not switched: {}", channel, e),
+            }
+        }
+        _ => "Usage: relay status | relay on|off <name|number>".to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -529,4 +876,62 @@ mod tests {
             delay = (delay * 2).min(MAX_RETRY_DELAY_MS);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_switch_counters() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("relay_counters.json");
+        let counters = RelaySwitchCounters::new(path.to_str());
+
+        // Off with no history is not a switch
+        assert_eq!(counters.record(0x25, 3, false), 0);
+        assert_eq!(counters.record(0x25, 3, true), 1);
+        assert_eq!(counters.record(0x25, 3, true), 1);
+        assert_eq!(counters.record(0x25, 3, false), 2);
+        assert_eq!(counters.record(0x25, 2, true), 1);
+        counters.record_stuck(0x25, 2);
+
+        let reloaded = RelaySwitchCounters::new(path.to_str());
+        assert_eq!(reloaded.get(0x25, 3).switch_count, 2);
+        assert_eq!(reloaded.get(0x25, 3).last_state, Some(false));
+        assert_eq!(reloaded.get(0x25, 2).stuck_faults, 1);
+        assert_eq!(reloaded.all().len(), 2);
+    }
+
+    #[test]
+    fn test_stuck_relay_error_display() {
+        let error = RelayError::StuckRelay("relay 0x25:3 stuck on".to_string());
+        assert_eq!(format!("{}", error), "Stuck relay: relay 0x25:3 stuck on");
+        assert_eq!(relay_fault_id(0x25, 3), "relay_0x25_3");
+    }
+
+    #[test]
+    fn test_validate_layout() {
+        let mut boards = default_relay_layout();
+        assert!(validate_layout(&boards).is_ok());
+        assert_eq!(find_role(&boards, RelayRole::DrainPump), Some((DEFAULT_RELAY_BOARD, 2)));
+        assert!(validate_layout(&[]).is_err());
+
+        boards.push(RelayBoardConfig {
+            address: 0x18,
+            board_type: RelayBoardType::Dual,
+            channels: vec![RelayChannelConfig { relay_id: 2, name: "heater".to_string(), role: RelayRole::Other }],
+        });
+        assert!(validate_layout(&boards).is_ok());
+
+        // Channel beyond the board's relays
+        boards[1].channels[0].relay_id = 3;
+        assert!(validate_layout(&boards).is_err());
+        boards[1].channels[0].relay_id = 2;
+
+        // Names are unique across boards
+        boards[1].channels[0].name = "fill_pump".to_string();
+        assert!(validate_layout(&boards).is_err());
+        boards[1].channels[0].name = "2".to_string();
+        assert!(validate_layout(&boards).is_err());
+        boards[1].channels[0].name = "heater".to_string();
+
+        boards[1].address = DEFAULT_RELAY_BOARD;
+        assert!(validate_layout(&boards).is_err());
+    }
+}
@@ -0,0 +1,817 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Rules Module - Automation rules linking sensor conditions (thresholds,
+// durations, hysteresis, time of day) to actuator, pump, alert and schedule
+// actions, with cooldowns and a dry-run mode
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::aog::actuators::{ActuatorState, ACTUATORS};
+use crate::aog::climate;
+use crate::aog::gpio::output::GPIO_OUTPUTS;
+use crate::aog::gpio::pwm::PWM;
+use crate::aog::pump_jobs::{JobKind, JOB_MANAGER};
+use crate::aog::sensors;
+use crate::{AutomationConfig, AutomationRule, Config, RuleAction, RuleComparison, RuleCondition};
+
+const MAX_HISTORY: usize = 200;
+
+/// Carries out the actions of fired rules
+pub trait ActionSink {
+    fn run(&self, action: &RuleAction) -> Result<String, String>;
+}
+
+/// Drives the real actuators, pump jobs, alerts and configuration
+pub struct SystemActions {
+    pub alert_webhook_url: Option<String>,
+}
+
+impl ActionSink for SystemActions {
+    fn run(&self, action: &RuleAction) -> Result<String, String> {
+        match action {
+            RuleAction::Actuator { name, on } => ACTUATORS.set(name, ActuatorState::from_bool(*on))
+                .map(|status| format!("{} turned {}", status.name, if *on { "on" } else { "off" })),
+            RuleAction::PumpJob { operation, tank, target, destination } => {
+                JobKind::parse(operation, tank, target, destination.as_deref())
+                    .and_then(|kind| JOB_MANAGER.submit(kind))
+                    .map(|id| format!("Started pump job {}", id))
+            }
+            RuleAction::Alert { message } => {
+                send_alert(message, self.alert_webhook_url.clone());
+                Ok("Alert sent".to_string())
+            }
+            RuleAction::Schedule { photo_cycle_start, photo_cycle_end } => {
+                let mut config = Config::load(0).map_err(|e| format!("Failed to load config: {}", e))?;
+                config.photo_cycle_start = *photo_cycle_start;
+                config.photo_cycle_end = *photo_cycle_end;
+                config.save().map_err(|e| format!("Failed to save config: {}", e))?;
+                climate::set_photo_cycle(*photo_cycle_start, *photo_cycle_end);
+                PWM.set_photo_cycle(*photo_cycle_start, *photo_cycle_end);
+                GPIO_OUTPUTS.set_photo_cycle(*photo_cycle_start, *photo_cycle_end);
+                Ok(format!("Photo cycle set to {}-{}", photo_cycle_start, photo_cycle_end))
+            }
+        }
+    }
+}
+
+fn send_alert(message: &str, webhook_url: Option<String>) {
+    log::error!("ALERT: {}", message);
+
+    if let Some(webhook_url) = webhook_url {
+        let message = message.to_string();
+        thread::spawn(move || {
+            let client = reqwest::blocking::Client::new();
+            let payload = serde_json::json!({
+                "text": format!("AOG Rule Alert: {}", message),
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            });
+            if let Err(e) = client.post(&webhook_url).json(&payload).send() {
+                log::error!("Failed to send alert webhook: {}", e);
+            }
+        });
+    }
+}
+
+pub fn describe(action: &RuleAction) -> String {
+    match action {
+        RuleAction::Actuator { name, on } => format!("turn {} {}", name, if *on { "on" } else { "off" }),
+        RuleAction::PumpJob { operation, tank, target, destination } => match destination {
+            Some(destination) => format!("pump {} {} {} {}", operation, tank, destination, target),
+            None => format!("pump {} {} {}", operation, tank, target),
+        },
+        RuleAction::Alert { message } => format!("alert \"{}\"", message),
+        RuleAction::Schedule { photo_cycle_start, photo_cycle_end } => {
+            format!("set photo cycle {}-{}", photo_cycle_start, photo_cycle_end)
+        }
+    }
+}
+
+/// Whether `hour` falls in the window from `start` up to `end`
+pub fn in_window(start: u8, end: u8, hour: u8) -> bool {
+    if start < end {
+        hour >= start && hour < end
+    } else {
+        // Overnight window, e.g. 22-6
+        hour >= start || hour < end
+    }
+}
+
+/// Check a rule before it is accepted from the configuration or the API
+pub fn validate(rule: &AutomationRule) -> Result<(), String> {
+    if rule.name.is_empty() || rule.name.contains(char::is_whitespace) {
+        return Err(format!("Invalid rule name '{}': must be non-empty without spaces", rule.name));
+    }
+    if rule.conditions.is_empty() {
+        return Err(format!("Rule '{}' has no conditions", rule.name));
+    }
+    if rule.actions.is_empty() && rule.release_actions.is_empty() {
+        return Err(format!("Rule '{}' has no actions", rule.name));
+    }
+    if let Some((start, end)) = rule.active_hours {
+        if start > 24 || end > 24 || start == end {
+            return Err(format!("Rule '{}' has invalid active hours {}-{}", rule.name, start, end));
+        }
+    }
+    for condition in &rule.conditions {
+        if !condition.threshold.is_finite() || !condition.hysteresis.is_finite() || condition.hysteresis < 0.0 {
+            return Err(format!("Rule '{}' has an invalid condition on {}", rule.name, condition.metric));
+        }
+    }
+    for action in rule.actions.iter().chain(&rule.release_actions) {
+        match action {
+            RuleAction::PumpJob { operation, tank, target, destination } => {
+                JobKind::parse(operation, tank, target, destination.as_deref())
+                    .map_err(|e| format!("Rule '{}': {}", rule.name, e))?;
+            }
+            RuleAction::Schedule { photo_cycle_start, photo_cycle_end } if *photo_cycle_start > 24 || *photo_cycle_end > 24 => {
+                return Err(format!("Rule '{}' has an invalid photo cycle", rule.name));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+struct ConditionState {
+    // When the comparison started to hold, for the duration check
+    since: Option<u64>,
+    met: bool,
+}
+
+impl ConditionState {
+    fn update(&mut self, condition: &RuleCondition, value: f32, now: u64) -> bool {
+        let (past, cleared) = match condition.comparison {
+            RuleComparison::Above => (value > condition.threshold, value < condition.threshold - condition.hysteresis),
+            RuleComparison::Below => (value < condition.threshold, value > condition.threshold + condition.hysteresis),
+        };
+        if self.met {
+            if cleared {
+                self.met = false;
+                self.since = None;
+            }
+        } else if past {
+            let since = *self.since.get_or_insert(now);
+            self.met = now.saturating_sub(since) >= condition.for_seconds;
+        } else {
+            self.since = None;
+        }
+        self.met
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RuleState {
+    conditions: Vec<ConditionState>,
+    active: bool,
+    last_fired: Option<u64>,
+    // Actions of the last fire or release that failed, tried again on each evaluation
+    retry: Vec<RuleAction>,
+}
+
+/// Result of evaluating one rule
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuleOutcome {
+    pub rule: String,
+    pub matching: bool,
+    pub active: bool,
+    pub waiting_for: Vec<String>,  // Metrics with no current reading
+    pub cooldown_remaining: u64,
+    pub fired: Vec<String>,
+    pub released: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuleEvent {
+    pub timestamp: u64,
+    pub rule: String,
+    pub action: String,
+    pub result: String,
+    pub dry_run: bool,
+}
+
+/// Advance one rule's state and return its outcome with the actions to run
+fn step<'a>(rule: &'a AutomationRule, state: &mut RuleState, value_of: &dyn Fn(&str) -> Option<f32>, now: u64, hour: u8) -> (RuleOutcome, Vec<&'a RuleAction>) {
+    let mut outcome = RuleOutcome {
+        rule: rule.name.clone(),
+        matching: false,
+        active: state.active,
+        waiting_for: Vec::new(),
+        cooldown_remaining: 0,
+        fired: Vec::new(),
+        released: Vec::new(),
+    };
+    if state.conditions.len() != rule.conditions.len() {
+        state.conditions = vec![ConditionState::default(); rule.conditions.len()];
+    }
+
+    let values: Vec<Option<f32>> = rule.conditions.iter().map(|condition| value_of(&condition.metric)).collect();
+    outcome.waiting_for = rule.conditions.iter().zip(&values)
+        .filter(|(_, value)| value.is_none())
+        .map(|(condition, _)| condition.metric.clone())
+        .collect();
+    // Without a reading the outputs are left as they are rather than
+    // switched on a missing value
+    if !outcome.waiting_for.is_empty() && rule.enabled {
+        return (outcome, Vec::new());
+    }
+
+    let mut all_met = true;
+    for ((condition, value), condition_state) in rule.conditions.iter().zip(&values).zip(state.conditions.iter_mut()) {
+        if let Some(value) = value {
+            all_met &= condition_state.update(condition, *value, now);
+        }
+    }
+    let in_hours = rule.active_hours.map(|(start, end)| in_window(start, end, hour)).unwrap_or(true);
+    outcome.matching = rule.enabled && in_hours && all_met;
+
+    let mut actions = Vec::new();
+    if outcome.matching && !state.active {
+        outcome.cooldown_remaining = state.last_fired
+            .map(|fired| (fired + rule.cooldown_seconds).saturating_sub(now))
+            .unwrap_or(0);
+        if outcome.cooldown_remaining == 0 {
+            state.active = true;
+            state.last_fired = Some(now);
+            outcome.fired = rule.actions.iter().map(describe).collect();
+            actions.extend(rule.actions.iter());
+        }
+    } else if !outcome.matching && state.active {
+        state.active = false;
+        outcome.released = rule.release_actions.iter().map(describe).collect();
+        actions.extend(rule.release_actions.iter());
+    }
+    outcome.active = state.active;
+    (outcome, actions)
+}
+
+pub struct RulesEngine {
+    rules: Mutex<Vec<AutomationRule>>,
+    state: Mutex<HashMap<String, RuleState>>,
+    history: Mutex<VecDeque<RuleEvent>>,
+    dry_run: AtomicBool,
+    alert_webhook_url: Mutex<Option<String>>,
+}
+
+impl RulesEngine {
+    pub fn new() -> Self {
+        RulesEngine {
+            rules: Mutex::new(Vec::new()),
+            state: Mutex::new(HashMap::new()),
+            history: Mutex::new(VecDeque::new()),
+            dry_run: AtomicBool::new(false),
+            alert_webhook_url: Mutex::new(None),
+        }
+    }
+
+    /// Load the rules from the configuration, skipping invalid ones. Active
+    /// rules that are dropped or changed are released first.
+    pub fn configure(&self, config: &AutomationConfig, sink: &dyn ActionSink) {
+        let mut rules: Vec<AutomationRule> = Vec::new();
+        for rule in &config.rules {
+            if let Err(e) = validate(rule) {
+                log::error!("Skipping automation rule: {}", e);
+            } else if rules.iter().any(|existing| existing.name == rule.name) {
+                log::error!("Skipping duplicate automation rule '{}'", rule.name);
+            } else {
+                rules.push(rule.clone());
+            }
+        }
+        let replaced: Vec<(AutomationRule, RuleState)> = {
+            let mut states = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            self.rules().into_iter()
+                .filter(|old| !rules.contains(old))
+                .filter_map(|old| states.remove(&old.name).map(|state| (old, state)))
+                .collect()
+        };
+        for (rule, state) in replaced {
+            self.release(&rule, state, sink);
+        }
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+            .retain(|name, _| rules.iter().any(|rule| &rule.name == name));
+        *self.rules.lock().unwrap_or_else(|e| e.into_inner()) = rules;
+        self.dry_run.store(config.dry_run, Ordering::SeqCst);
+        *self.alert_webhook_url.lock().unwrap_or_else(|e| e.into_inner()) = config.alert_webhook_url.clone();
+    }
+
+    pub fn rules(&self) -> Vec<AutomationRule> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::SeqCst)
+    }
+
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.store(dry_run, Ordering::SeqCst);
+    }
+
+    /// Add a rule, or replace the rule with the same name. A replaced rule
+    /// that is active is released first and the new one starts idle.
+    pub fn upsert(&self, rule: AutomationRule, sink: &dyn ActionSink) -> Result<(), String> {
+        validate(&rule)?;
+        let replaced = {
+            let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+            match rules.iter_mut().find(|existing| existing.name == rule.name) {
+                Some(existing) if *existing == rule => None,
+                Some(existing) => Some(std::mem::replace(existing, rule)),
+                None => {
+                    rules.push(rule);
+                    None
+                }
+            }
+        };
+        if let Some(old) = replaced {
+            let state = self.state.lock().unwrap_or_else(|e| e.into_inner()).remove(&old.name);
+            if let Some(state) = state {
+                self.release(&old, state, sink);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a rule, releasing it first if it is active
+    pub fn remove(&self, name: &str, sink: &dyn ActionSink) -> Result<AutomationRule, String> {
+        let rule = {
+            let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+            let index = rules.iter().position(|rule| rule.name == name)
+                .ok_or_else(|| format!("Unknown rule '{}'", name))?;
+            rules.remove(index)
+        };
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner()).remove(name);
+        if let Some(state) = state {
+            self.release(&rule, state, sink);
+        }
+        Ok(rule)
+    }
+
+    /// Run the release actions of an active rule that is going away, so it
+    /// doesn't leave its outputs switched on
+    fn release(&self, rule: &AutomationRule, state: RuleState, sink: &dyn ActionSink) {
+        if !state.active {
+            return;
+        }
+        let (now, _) = now_and_hour();
+        let dry_run = self.dry_run();
+        for action in &rule.release_actions {
+            self.run_action(&rule.name, action, now, dry_run, sink);
+        }
+    }
+
+    /// Carry out one action and record it in the history. Returns whether it succeeded.
+    fn run_action(&self, rule: &str, action: &RuleAction, now: u64, dry_run: bool, sink: &dyn ActionSink) -> bool {
+        let (result, ok) = if dry_run {
+            log::info!("Rule '{}' would {} (dry run)", rule, describe(action));
+            ("dry run".to_string(), true)
+        } else {
+            match sink.run(action) {
+                Ok(result) => {
+                    log::info!("Rule '{}': {} - {}", rule, describe(action), result);
+                    (result, true)
+                }
+                Err(e) => {
+                    log::error!("Rule '{}' failed to {}: {}", rule, describe(action), e);
+                    (format!("failed: {}", e), false)
+                }
+            }
+        };
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history.push_back(RuleEvent { timestamp: now, rule: rule.to_string(), action: describe(action), result, dry_run });
+        while history.len() > MAX_HISTORY {
+            history.pop_front();
+        }
+        ok
+    }
+
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), String> {
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        let rule = rules.iter_mut().find(|rule| rule.name == name)
+            .ok_or_else(|| format!("Unknown rule '{}'", name))?;
+        rule.enabled = enabled;
+        Ok(())
+    }
+
+    /// Evaluate every rule and run the actions of those that fire or release.
+    /// Actions that fail are tried again on later evaluations until they
+    /// succeed or the rule changes over again. In dry-run mode the state
+    /// advances but the actions are only logged.
+    pub fn evaluate(&self, value_of: &dyn Fn(&str) -> Option<f32>, now: u64, hour: u8, sink: &dyn ActionSink) -> Vec<RuleOutcome> {
+        let rules = self.rules();
+        let dry_run = self.dry_run();
+        let mut pending: Vec<(String, RuleAction)> = Vec::new();
+        let outcomes: Vec<RuleOutcome> = {
+            let mut states = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            rules.iter().map(|rule| {
+                let state = states.entry(rule.name.clone()).or_default();
+                let (outcome, actions) = step(rule, state, value_of, now, hour);
+                if actions.is_empty() {
+                    pending.extend(state.retry.drain(..).map(|action| (rule.name.clone(), action)));
+                } else {
+                    // A new change over supersedes the retries of the previous one
+                    state.retry.clear();
+                    pending.extend(actions.into_iter().map(|action| (rule.name.clone(), action.clone())));
+                }
+                outcome
+            }).collect()
+        };
+
+        // Actions run outside the state lock, as pump jobs and relays can block
+        let mut failed: Vec<(String, RuleAction)> = Vec::new();
+        for (rule, action) in pending {
+            if !self.run_action(&rule, &action, now, dry_run, sink) {
+                failed.push((rule, action));
+            }
+        }
+
+        // Only the failed actions are retried, the ones that succeeded
+        // (pump jobs, alerts) must not run twice
+        if !failed.is_empty() {
+            let mut states = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            for (rule, action) in failed {
+                if let Some(state) = states.get_mut(&rule) {
+                    state.retry.push(action);
+                }
+            }
+        }
+        outcomes
+    }
+
+    /// What each rule would do now, without changing any state
+    pub fn preview(&self, value_of: &dyn Fn(&str) -> Option<f32>, now: u64, hour: u8) -> Vec<RuleOutcome> {
+        let mut states = self.state.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        self.rules().iter()
+            .map(|rule| step(rule, states.entry(rule.name.clone()).or_default(), value_of, now, hour).0)
+            .collect()
+    }
+
+    /// Actions carried out, oldest first
+    pub fn history(&self) -> Vec<RuleEvent> {
+        self.history.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+    }
+
+    /// Write the current rules and dry-run mode back to the configuration
+    pub fn persist(&self) -> Result<(), String> {
+        let mut config = Config::load(0).map_err(|e| format!("Failed to load config: {}", e))?;
+        let mut automation = config.automation_config.take().unwrap_or_default();
+        automation.rules = self.rules();
+        automation.dry_run = self.dry_run();
+        config.automation_config = Some(automation);
+        config.save().map_err(|e| format!("Failed to save config: {}", e))
+    }
+
+    /// Sink that drives the real system, alerting through the configured webhook
+    pub fn actions(&self) -> SystemActions {
+        SystemActions { alert_webhook_url: self.alert_webhook_url.lock().unwrap_or_else(|e| e.into_inner()).clone() }
+    }
+}
+
+impl Default for RulesEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref RULES: RulesEngine = RulesEngine::new();
+}
+
+fn now_and_hour() -> (u64, u8) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    (now, Local::now().hour() as u8)
+}
+
+/// Evaluate the rules now, carrying out their actions
+pub fn evaluate_now() -> Vec<RuleOutcome> {
+    let (now, hour) = now_and_hour();
+    RULES.evaluate(&sensors::current_value, now, hour, &RULES.actions())
+}
+
+/// What the rules would do now, without acting
+pub fn preview_now() -> Vec<RuleOutcome> {
+    let (now, hour) = now_and_hour();
+    RULES.preview(&sensors::current_value, now, hour)
+}
+
+/// Load the configured rules and start evaluating them
+pub fn init(config: &Config) {
+    let automation = config.automation_config.clone().unwrap_or_default();
+    RULES.configure(&automation, &RULES.actions());
+    log::info!("Automation rules: {} loaded{}", RULES.rules().len(), if RULES.dry_run() { " (dry run)" } else { "" });
+
+    let interval = Duration::from_secs(automation.interval_seconds.max(1));
+    let _ = thread::Builder::new().name("rules_thread".to_string()).spawn(move || loop {
+        thread::sleep(interval);
+        evaluate_now();
+    });
+}
+
+fn format_outcome(outcome: &RuleOutcome, rule: Option<&AutomationRule>) -> String {
+    let state = if rule.is_some_and(|rule| !rule.enabled) {
+        "disabled".to_string()
+    } else if !outcome.waiting_for.is_empty() {
+        format!("waiting for {}", outcome.waiting_for.join(", "))
+    } else if outcome.cooldown_remaining > 0 {
+        format!("cooling down, {}s left", outcome.cooldown_remaining)
+    } else if outcome.active {
+        "active".to_string()
+    } else {
+        "idle".to_string()
+    };
+    let mut line = format!("{}: {}", outcome.rule, state);
+    if !outcome.fired.is_empty() {
+        line.push_str(&format!(" - would {}", outcome.fired.join(", ")));
+    }
+    if !outcome.released.is_empty() {
+        line.push_str(&format!(" - would release: {}", outcome.released.join(", ")));
+    }
+    line
+}
+
+/// Handle `rules` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    let persisted = |result: Result<String, String>| match result.and_then(|message| RULES.persist().map(|_| message)) {
+        Ok(message) => message,
+        Err(e) => e,
+    };
+
+    match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["rules"] | ["rules", "status"] | ["rules", "evaluate"] => {
+            let rules = RULES.rules();
+            if rules.is_empty() {
+                return "No automation rules".to_string();
+            }
+            let mode = if RULES.dry_run() { " (dry run)" } else { "" };
+            let lines: Vec<String> = preview_now().iter()
+                .map(|outcome| format_outcome(outcome, rules.iter().find(|rule| rule.name == outcome.rule)))
+                .collect();
+            format!("Automation rules{}:\n{}", mode, lines.join("\n"))
+        }
+        ["rules", "history"] => {
+            let history = RULES.history();
+            if history.is_empty() {
+                return "No rule actions yet".to_string();
+            }
+            history.iter()
+                .map(|event| format!("{} {}: {} - {}", event.timestamp, event.rule, event.action, event.result))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        ["rules", toggle @ ("enable" | "disable"), name] => {
+            let enabled = *toggle == "enable";
+            persisted(RULES.set_enabled(name, enabled).map(|_| format!("Rule '{}' {}d", name, toggle)))
+        }
+        ["rules", "dry-run", mode @ ("on" | "off")] => {
+            RULES.set_dry_run(*mode == "on");
+            persisted(Ok(format!("Dry run {}", mode)))
+        }
+        _ => "Usage: rules [status|evaluate|history] | rules enable|disable <name> | rules dry-run on|off".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct MockSink {
+        actions: RefCell<Vec<RuleAction>>,
+        failing: RefCell<bool>,
+        failing_action: RefCell<Option<RuleAction>>,
+    }
+
+    impl ActionSink for MockSink {
+        fn run(&self, action: &RuleAction) -> Result<String, String> {
+            self.actions.borrow_mut().push(action.clone());
+            if *self.failing.borrow() || self.failing_action.borrow().as_ref() == Some(action) {
+                return Err("relay not responding".to_string());
+            }
+            Ok("ok".to_string())
+        }
+    }
+
+    fn fan(on: bool) -> RuleAction {
+        RuleAction::Actuator { name: "air".to_string(), on }
+    }
+
+    fn hot_rule() -> AutomationRule {
+        AutomationRule {
+            name: "cool".to_string(),
+            enabled: true,
+            conditions: vec![RuleCondition {
+                metric: "temp".to_string(),
+                comparison: RuleComparison::Above,
+                threshold: 30.0,
+                for_seconds: 60,
+                hysteresis: 1.0,
+            }],
+            actions: vec![fan(true)],
+            release_actions: vec![fan(false)],
+            cooldown_seconds: 300,
+            active_hours: None,
+        }
+    }
+
+    fn engine(rule: AutomationRule) -> RulesEngine {
+        let engine = RulesEngine::new();
+        engine.upsert(rule, &MockSink::default()).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_duration_hysteresis_and_cooldown() {
+        let engine = engine(hot_rule());
+        let sink = MockSink::default();
+        let run = |temp: f32, now: u64| engine.evaluate(&|_| Some(temp), now, 12, &sink).remove(0);
+
+        // Must stay above 30 for a minute
+        assert!(!run(31.0, 0).matching);
+        assert!(!run(31.0, 30).matching);
+        assert_eq!(run(31.0, 60).fired, vec!["turn air on"]);
+        assert!(sink.actions.borrow().eq(&[fan(true)]));
+
+        // Inside the hysteresis band it stays on, below it releases
+        assert!(run(29.5, 70).active);
+        assert_eq!(run(28.9, 80).released, vec!["turn air off"]);
+
+        // Matches again a minute later, but the cooldown holds it back until 60 + 300
+        assert!(!run(31.0, 200).matching);
+        let outcome = run(31.0, 260);
+        assert!(outcome.matching && !outcome.active);
+        assert_eq!(outcome.cooldown_remaining, 100);
+        assert!(run(31.0, 360).active);
+        assert_eq!(sink.actions.borrow().len(), 3);
+        assert_eq!(engine.history().len(), 3);
+    }
+
+    #[test]
+    fn test_missing_reading_and_time_window() {
+        let mut rule = hot_rule();
+        rule.conditions[0].for_seconds = 0;
+        rule.active_hours = Some((22, 6));
+        let engine = engine(rule);
+        let sink = MockSink::default();
+
+        let outcome = engine.evaluate(&|_| None, 0, 23, &sink).remove(0);
+        assert_eq!(outcome.waiting_for, vec!["temp"]);
+        assert!(!engine.evaluate(&|_| Some(35.0), 10, 12, &sink)[0].matching);
+        assert!(engine.evaluate(&|_| Some(35.0), 20, 2, &sink)[0].active);
+
+        // A lost reading leaves the output on rather than releasing it
+        assert!(engine.evaluate(&|_| None, 30, 3, &sink)[0].active);
+        assert_eq!(sink.actions.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_actions_are_retried() {
+        let mut rule = hot_rule();
+        rule.conditions[0].for_seconds = 0;
+        let engine = engine(rule);
+        let sink = MockSink::default();
+
+        // A failed action is tried again on each evaluation until it succeeds
+        *sink.failing.borrow_mut() = true;
+        let outcome = engine.evaluate(&|_| Some(35.0), 0, 12, &sink).remove(0);
+        assert_eq!(outcome.fired, vec!["turn air on"]);
+        assert!(outcome.active);
+        assert!(engine.history()[0].result.starts_with("failed"));
+        engine.evaluate(&|_| Some(35.0), 5, 12, &sink);
+        assert_eq!(sink.actions.borrow().len(), 2);
+
+        *sink.failing.borrow_mut() = false;
+        assert!(engine.evaluate(&|_| Some(35.0), 10, 12, &sink)[0].active);
+        engine.evaluate(&|_| Some(35.0), 15, 12, &sink);
+        assert_eq!(sink.actions.borrow().len(), 3);
+
+        // The same goes for releasing
+        *sink.failing.borrow_mut() = true;
+        assert!(!engine.evaluate(&|_| Some(20.0), 20, 12, &sink)[0].active);
+        *sink.failing.borrow_mut() = false;
+        engine.evaluate(&|_| Some(20.0), 30, 12, &sink);
+        engine.evaluate(&|_| Some(20.0), 40, 12, &sink);
+        assert!(sink.actions.borrow().eq(&[fan(true), fan(true), fan(true), fan(false), fan(false)]));
+    }
+
+    #[test]
+    fn test_only_failed_actions_are_retried() {
+        let mut rule = hot_rule();
+        rule.conditions[0].for_seconds = 0;
+        let alert = RuleAction::Alert { message: "Too hot".to_string() };
+        rule.actions.push(alert.clone());
+        let engine = engine(rule);
+        let sink = MockSink::default();
+
+        *sink.failing_action.borrow_mut() = Some(alert.clone());
+        engine.evaluate(&|_| Some(35.0), 0, 12, &sink);
+        engine.evaluate(&|_| Some(35.0), 10, 12, &sink);
+        assert!(sink.actions.borrow().eq(&[fan(true), alert.clone(), alert.clone()]));
+
+        // Releasing drops the retries left over from firing
+        engine.evaluate(&|_| Some(20.0), 20, 12, &sink);
+        *sink.failing_action.borrow_mut() = None;
+        engine.evaluate(&|_| Some(20.0), 30, 12, &sink);
+        assert!(sink.actions.borrow().eq(&[fan(true), alert.clone(), alert, fan(false)]));
+    }
+
+    #[test]
+    fn test_dry_run_and_preview_do_not_act() {
+        let mut rule = hot_rule();
+        rule.conditions[0].for_seconds = 0;
+        let engine = engine(rule);
+        let sink = MockSink::default();
+
+        let preview = engine.preview(&|_| Some(35.0), 0, 12);
+        assert_eq!(preview[0].fired, vec!["turn air on"]);
+        assert_eq!(engine.preview(&|_| Some(35.0), 0, 12), preview);
+        assert!(engine.history().is_empty());
+
+        engine.set_dry_run(true);
+        assert!(engine.evaluate(&|_| Some(35.0), 0, 12, &sink)[0].active);
+        assert!(sink.actions.borrow().is_empty());
+        let history = engine.history();
+        assert!(history[0].dry_run);
+        assert_eq!(history[0].result, "dry run");
+    }
+
+    #[test]
+    fn test_validation_and_management() {
+        let mut rule = hot_rule();
+        rule.name = "two words".to_string();
+        assert!(validate(&rule).is_err());
+
+        let mut rule = hot_rule();
+        rule.actions = vec![RuleAction::PumpJob {
+            operation: "fill".to_string(),
+            tank: "tank1".to_string(),
+            target: "150%".to_string(),
+            destination: None,
+        }];
+        assert!(validate(&rule).is_err());
+
+        let engine = engine(hot_rule());
+        let mut replacement = hot_rule();
+        replacement.cooldown_seconds = 10;
+        engine.upsert(replacement, &MockSink::default()).unwrap();
+        assert_eq!(engine.rules().len(), 1);
+        assert_eq!(engine.rules()[0].cooldown_seconds, 10);
+
+        engine.set_enabled("cool", false).unwrap();
+        assert!(!engine.evaluate(&|_| Some(35.0), 100, 12, &MockSink::default())[0].matching);
+        assert!(engine.remove("cool", &MockSink::default()).is_ok());
+        assert!(engine.remove("cool", &MockSink::default()).is_err());
+    }
+
+    #[test]
+    fn test_active_rule_released_when_removed_or_replaced() {
+        let mut rule = hot_rule();
+        rule.conditions[0].for_seconds = 0;
+        let engine = engine(rule.clone());
+        let sink = MockSink::default();
+        assert!(engine.evaluate(&|_| Some(35.0), 0, 12, &sink)[0].active);
+
+        // Saving the rule unchanged keeps it active
+        engine.upsert(rule.clone(), &sink).unwrap();
+        assert_eq!(sink.actions.borrow().len(), 1);
+
+        // A changed rule releases the old one and starts idle
+        let mut changed = rule.clone();
+        changed.cooldown_seconds = 0;
+        engine.upsert(changed, &sink).unwrap();
+        assert!(sink.actions.borrow().eq(&[fan(true), fan(false)]));
+        assert!(engine.evaluate(&|_| Some(35.0), 10, 12, &sink)[0].active);
+
+        engine.remove("cool", &sink).unwrap();
+        assert!(sink.actions.borrow().eq(&[fan(true), fan(false), fan(true), fan(false)]));
+
+        // Reloading the configuration without an active rule releases it too
+        engine.configure(&AutomationConfig { rules: vec![rule.clone()], ..Default::default() }, &sink);
+        assert!(engine.evaluate(&|_| Some(35.0), 20, 12, &sink)[0].active);
+        engine.configure(&AutomationConfig::default(), &sink);
+        assert_eq!(sink.actions.borrow().last(), Some(&fan(false)));
+        assert_eq!(sink.actions.borrow().len(), 6);
+        assert!(engine.rules().is_empty());
+    }
+
+    #[test]
+    fn test_rule_config_format() {
+        let json = r#"{
+            "name": "co2_dose",
+            "conditions": [{"metric": "co2", "comparison": "below", "threshold": 400, "for_seconds": 600}],
+            "actions": [{"type": "actuator", "name": "co2_valve", "on": true}, {"type": "alert", "message": "Dosing CO2"}],
+            "active_hours": [6, 20]
+        }"#;
+        let rule: AutomationRule = serde_json::from_str(json).unwrap();
+        assert!(rule.enabled);
+        assert_eq!(rule.conditions[0].comparison, RuleComparison::Below);
+        assert_eq!(describe(&rule.actions[0]), "turn co2_valve on");
+        assert!(validate(&rule).is_ok());
+    }
+}
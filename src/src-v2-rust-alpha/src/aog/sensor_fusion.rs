@@ -0,0 +1,326 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Sensor Fusion Module - Combines redundant CO2, temperature and humidity
+// sources, leaving out stale, stuck or drifting ones
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::SensorFusionConfig;
+
+// The CCS811 estimates CO2 from VOCs, so it counts for less than a true NDIR reading
+const ESTIMATED_CO2_WEIGHT: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FusedReading {
+    pub metric: String,
+    pub value: f32,
+    pub confidence: f32,  // 0-1: share of sources used and how well they agree
+    pub sources: Vec<String>,  // Sources in the fused value
+    pub excluded: Vec<String>,  // "<source>: <reason>" for sources left out
+    pub spread: f32,  // Largest difference between the sources used
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SourceState {
+    Ok,
+    Stale,
+    Stuck,
+    Excluded,  // Disagreed with the other sources for too long
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceHealth {
+    pub metric: String,
+    pub source: String,
+    pub state: SourceState,
+    pub value: f32,  // Calibrated
+    pub raw: f32,
+    pub offset: f32,
+    pub weight: f32,
+    pub last_update: u64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct SourceTrack {
+    value: f32,
+    raw: f32,
+    timestamp: u64,
+    unchanged: u32,  // Readings in a row identical to the previous one
+    disagreements: u32,
+    agreements: u32,
+    excluded: Option<String>,
+}
+
+pub struct SensorFusion {
+    config: Mutex<SensorFusionConfig>,
+    // metric -> source -> track
+    sources: Mutex<HashMap<String, HashMap<String, SourceTrack>>>,
+}
+
+impl SensorFusion {
+    pub fn new(config: SensorFusionConfig) -> Self {
+        SensorFusion { config: Mutex::new(config), sources: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn configure(&self, config: SensorFusionConfig) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    fn calibration(config: &SensorFusionConfig, metric: &str, source: &str) -> (f32, f32) {
+        match config.sources.iter().find(|s| s.metric == metric && s.source == source) {
+            Some(source) => (source.offset, source.weight.max(0.0)),
+            None if metric == "co2" && source == "ccs811" => (0.0, ESTIMATED_CO2_WEIGHT),
+            None => (0.0, 1.0),
+        }
+    }
+
+    fn tolerance(config: &SensorFusionConfig, metric: &str) -> f32 {
+        match metric {
+            "co2" => config.co2_tolerance_ppm,
+            "temp" => config.temp_tolerance_c,
+            "hum" => config.hum_tolerance_pct,
+            _ => f32::MAX,
+        }
+    }
+
+    /// Record a raw reading from one source
+    pub fn submit(&self, metric: &str, source: &str, raw: f32, now: u64) {
+        let config = self.config.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let (offset, _) = Self::calibration(&config, metric, source);
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        let tracks = sources.entry(metric.to_string()).or_default();
+        match tracks.get_mut(source) {
+            Some(track) => {
+                track.unchanged = if track.raw == raw { track.unchanged + 1 } else { 0 };
+                track.raw = raw;
+                track.value = raw + offset;
+                track.timestamp = now;
+            }
+            None => {
+                tracks.insert(source.to_string(), SourceTrack {
+                    value: raw + offset,
+                    raw,
+                    timestamp: now,
+                    unchanged: 0,
+                    disagreements: 0,
+                    agreements: 0,
+                    excluded: None,
+                });
+            }
+        }
+    }
+
+    /// Fuse the current readings of `metric`, updating which sources are
+    /// excluded. Call once per sampling round.
+    pub fn fuse(&self, metric: &str, now: u64) -> Option<FusedReading> {
+        let config = self.config.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let tolerance = Self::tolerance(&config, metric);
+        let mut all = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        let sources = all.get_mut(metric)?;
+        let known = sources.len();
+
+        let mut excluded = Vec::new();
+        let fresh: Vec<String> = sources.iter()
+            .filter(|(_, track)| now.saturating_sub(track.timestamp) <= config.stale_after_seconds)
+            .map(|(source, _)| source.clone())
+            .collect();
+        for source in sources.keys().filter(|source| !fresh.contains(source)) {
+            excluded.push(format!("{}: stale", source));
+        }
+
+        // Stuck: unchanged for a long time while another source is moving
+        let moving = fresh.iter().any(|source| sources[source].unchanged < config.stuck_after_readings);
+        let (stuck, live): (Vec<String>, Vec<String>) = fresh.into_iter()
+            .partition(|source| moving && sources[source].unchanged >= config.stuck_after_readings);
+        for source in &stuck {
+            excluded.push(format!("{}: stuck at {}", source, sources[source].raw));
+        }
+
+        // Reference: the median of three or more, or the heavier of two
+        let reference = match live.len() {
+            0 => return None,
+            1 | 2 => {
+                let weights: Vec<f32> = live.iter().map(|source| Self::calibration(&config, metric, source).1).collect();
+                if live.len() == 2 && weights[0] != weights[1] {
+                    let heavier = if weights[0] > weights[1] { &live[0] } else { &live[1] };
+                    Some(sources[heavier].value)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                let mut values: Vec<f32> = live.iter().map(|source| sources[source].value).collect();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                Some(values[values.len() / 2])
+            }
+        };
+
+        let mut used: Vec<(String, f32, f32)> = Vec::new();
+        let mut live_sorted = live;
+        live_sorted.sort();
+        for source in live_sorted {
+            let (_, weight) = Self::calibration(&config, metric, &source);
+            let track = sources.get_mut(&source)?;
+            if let Some(reference) = reference {
+                if (track.value - reference).abs() > tolerance {
+                    track.agreements = 0;
+                    track.disagreements += 1;
+                    if track.disagreements >= config.exclude_after && track.excluded.is_none() {
+                        log::warn!("Excluding {} {} reading {:.1}, {:.1} from the other sources", source, metric, track.value, reference);
+                        track.excluded = Some(format!("disagrees by {:.1}", track.value - reference));
+                    }
+                } else {
+                    track.disagreements = 0;
+                    track.agreements += 1;
+                    if track.excluded.is_some() && track.agreements >= config.exclude_after {
+                        log::info!("Readmitting {} {} readings", source, metric);
+                        track.excluded = None;
+                    }
+                }
+            }
+            match &track.excluded {
+                Some(reason) => excluded.push(format!("{}: {}", source, reason)),
+                None if weight > 0.0 => used.push((source, track.value, weight)),
+                None => (),
+            }
+        }
+        if used.is_empty() {
+            return None;
+        }
+
+        let total_weight: f32 = used.iter().map(|(_, _, weight)| weight).sum();
+        let value = used.iter().map(|(_, value, weight)| value * weight).sum::<f32>() / total_weight;
+        let max = used.iter().map(|(_, value, _)| *value).fold(f32::MIN, f32::max);
+        let min = used.iter().map(|(_, value, _)| *value).fold(f32::MAX, f32::min);
+        let spread = max - min;
+        let agreement = if used.len() > 1 { 1.0 - 0.5 * (spread / tolerance).min(1.0) } else { 0.75 };
+        let confidence = used.len() as f32 / known as f32 * agreement;
+
+        excluded.sort();
+        Some(FusedReading {
+            metric: metric.to_string(),
+            value,
+            confidence: (confidence * 100.0).round() / 100.0,
+            sources: used.into_iter().map(|(source, ..)| source).collect(),
+            excluded,
+            spread,
+            timestamp: now,
+        })
+    }
+
+    pub fn health(&self, now: u64) -> Vec<SourceHealth> {
+        let config = self.config.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        let mut health: Vec<SourceHealth> = sources.iter()
+            .flat_map(|(metric, tracks)| tracks.iter().map(move |(source, track)| (metric, source, track)))
+            .map(|(metric, source, track)| {
+                let (offset, weight) = Self::calibration(&config, metric, source);
+                let (state, reason) = if now.saturating_sub(track.timestamp) > config.stale_after_seconds {
+                    (SourceState::Stale, None)
+                } else if track.excluded.is_some() {
+                    (SourceState::Excluded, track.excluded.clone())
+                } else if track.unchanged >= config.stuck_after_readings {
+                    (SourceState::Stuck, Some(format!("unchanged for {} readings", track.unchanged)))
+                } else {
+                    (SourceState::Ok, None)
+                };
+                SourceHealth {
+                    metric: metric.clone(),
+                    source: source.clone(),
+                    state,
+                    value: track.value,
+                    raw: track.raw,
+                    offset,
+                    weight,
+                    last_update: track.timestamp,
+                    reason,
+                }
+            })
+            .collect();
+        health.sort_by(|a, b| (&a.metric, &a.source).cmp(&(&b.metric, &b.source)));
+        health
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref SENSOR_FUSION: SensorFusion = SensorFusion::new(SensorFusionConfig::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FusionSourceConfig;
+
+    fn fusion() -> SensorFusion {
+        SensorFusion::new(SensorFusionConfig { stuck_after_readings: 5, ..SensorFusionConfig::default() })
+    }
+
+    #[test]
+    fn test_weighted_fusion_with_offsets() {
+        let fusion = fusion();
+        fusion.configure(SensorFusionConfig {
+            sources: vec![FusionSourceConfig { metric: "co2".to_string(), source: "scd30".to_string(), offset: -20.0, weight: 1.0 }],
+            ..SensorFusionConfig::default()
+        });
+        fusion.submit("co2", "scd30", 820.0, 0);
+        fusion.submit("co2", "ccs811", 900.0, 0);
+
+        // (800 * 1.0 + 900 * 0.5) / 1.5
+        let fused = fusion.fuse("co2", 0).unwrap();
+        assert!((fused.value - 833.33).abs() < 0.01);
+        assert_eq!(fused.sources, vec!["ccs811".to_string(), "scd30".to_string()]);
+        assert!(fused.confidence > 0.6 && fused.confidence < 1.0);
+    }
+
+    #[test]
+    fn test_drifting_sensor_excluded_and_readmitted() {
+        let fusion = fusion();
+        for round in 0..3u64 {
+            fusion.submit("temp", "dht22", 21.0 + round as f32 * 0.1, round);
+            fusion.submit("temp", "scd30", 21.3 + round as f32 * 0.1, round);
+            fusion.submit("temp", "scd41", 25.0 + round as f32 * 0.1, round);
+            fusion.fuse("temp", round);
+        }
+        let fused = fusion.fuse("temp", 3).unwrap();
+        assert_eq!(fused.sources, vec!["dht22".to_string(), "scd30".to_string()]);
+        assert!(fused.excluded[0].starts_with("scd41: disagrees"));
+        assert!((fused.value - 21.35).abs() < 0.01);
+
+        // Back in line for long enough and it is used again
+        for round in 4..7u64 {
+            fusion.submit("temp", "scd41", 21.5, round);
+            fusion.submit("temp", "dht22", 21.4, round);
+            fusion.submit("temp", "scd30", 21.6, round);
+            fusion.fuse("temp", round);
+        }
+        assert_eq!(fusion.fuse("temp", 7).unwrap().sources.len(), 3);
+    }
+
+    #[test]
+    fn test_stuck_and_stale_sources_left_out() {
+        let fusion = fusion();
+        for round in 0..6u64 {
+            fusion.submit("co2", "ccs811", 400.0, round);
+            fusion.submit("co2", "scd30", 600.0 + round as f32, round);
+        }
+        let fused = fusion.fuse("co2", 5).unwrap();
+        assert_eq!(fused.sources, vec!["scd30".to_string()]);
+        assert_eq!(fused.excluded, vec!["ccs811: stuck at 400".to_string()]);
+        assert_eq!(fused.confidence, 0.38);
+
+        // scd30 stops reporting
+        fusion.submit("co2", "ccs811", 400.0, 100);
+        let fused = fusion.fuse("co2", 100).unwrap_or_else(|| panic!("ccs811 alone should be fused"));
+        assert_eq!(fused.sources, vec!["ccs811".to_string()]);
+        assert_eq!(fused.excluded, vec!["scd30: stale".to_string()]);
+
+        let health = fusion.health(100);
+        assert_eq!(health.iter().find(|h| h.source == "scd30").unwrap().state, SourceState::Stale);
+    }
+}
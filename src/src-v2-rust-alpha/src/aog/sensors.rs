@@ -20,18 +20,20 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use sds011::{SDS011};
-
 use std::path::Path;
 
 
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use std::str;
 
 
 use std::thread;
-use std::sync::mpsc;
+use std::sync::Mutex;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::Config;
 
 
 // TODO - ADD PH Sensor
@@ -51,38 +53,35 @@ use std::fs::File;
 use std::io::Write;
 use std::io::Read;
 
+use crate::aog::native_sensors;
+use crate::aog::particulate;
+use crate::aog::sensor_fusion;
 use crate::aog::ph_sensor;
+use crate::aog::plausibility::{self, PLAUSIBILITY};
+use crate::aog::serial::{SerialRole, BAUD_RATE, SERIAL_DEVICES};
 
-pub fn init(){
+pub fn init(config: &Config){
 
-    let _ = thread::Builder::new().name("pm1025_thread".to_string()).spawn(move || loop {
-        let pm10 = fetch_pm10();
-        let pm25 = fetch_pm25();
-
-        if pm10.len() > 0 {
-            if let Ok(mut f) = File::create("/opt/aog/sensors/pm10") {
-                let _ = f.write_all(pm10.as_bytes());
-            }
-        }
+    // Sanity limits applied to every reading
+    PLAUSIBILITY.configure(config.plausibility_rules.clone().unwrap_or_default());
 
-        if pm25.len() > 0 {
-            if let Ok(mut f) = File::create("/opt/aog/sensors/pm25") {
-                let _ = f.write_all(pm25.as_bytes());
-            }
-        }
-        
-        // Add sleep to prevent CPU spinning
-        thread::sleep(Duration::from_secs(10));
+    // SDS011 particulate sensor
+    particulate::init(config);
 
-    });
+    // DHT, CCS811 and SCD sensors wired directly to the Pi, fused where
+    // more than one measures the same thing
+    sensor_fusion::SENSOR_FUSION.configure(config.sensor_fusion_config.clone().unwrap_or_default());
+    if let Some(sensor_kit) = &config.sensor_kit_config {
+        native_sensors::init(sensor_kit);
+    }
 
 
 
 
     let _ = thread::Builder::new().name("ovf_thread".to_string()).spawn(move || {
 
-        fetch_arduino(format!("DUAL_OVF_SENSOR"));
-        fetch_arduino(format!("SENSORKIT_MK1"));
+        fetch_arduino("DUAL_OVF_SENSOR".to_string());
+        fetch_arduino("SENSORKIT_MK1".to_string());
 
  
 
@@ -96,58 +95,101 @@ pub fn init(){
 }
 
 pub fn fetch_pm25() -> String {
-    let mut tty_port = 0;
-    let tty_quit = 10;
-    while tty_port < tty_quit{
-        match SDS011::new(format!("/dev/ttyUSB{}", tty_port).as_str()) {
-            Ok(mut sensor) => {
-                match sensor.set_work_period(10){
-                    Ok(_wp) => {
-                        if let Ok(m) = sensor.query() {
-                            return format!("{}", m.pm25);
-                        } else {
-                            return String::new();
-                        }
-                    },
-                    Err(_err) => {
-                        
-                    }
-                }
-            },
-            Err(_e) => {
-                tty_port += 1;
-            }
-        };
+    match particulate::PARTICULATE.latest() {
+        Some(reading) => format!("{}", reading.pm25),
+        None => String::new(),
     }
-    return String::new();
 }
 
 pub fn fetch_pm10() -> String {
-    let mut tty_port = 0;
-    let tty_quit = 10;
-    while tty_port < tty_quit{
-        match SDS011::new(format!("/dev/ttyUSB{}", tty_port).as_str()) {
-            Ok(mut sensor) => {
-                match sensor.set_work_period(10){
-                    Ok(_wp) => {
-                        if let Ok(m) = sensor.query() {
-                            return format!("{}", m.pm10);
-                        } else {
-                            return String::new();
-                        }
-                    },
-                    Err(_err) => {
+    match particulate::PARTICULATE.latest() {
+        Some(reading) => format!("{}", reading.pm10),
+        None => String::new(),
+    }
+}
 
-                    }
-                }
-     
-            },
-            Err(_e) => {
-                tty_port += 1
-            }
-        };
+/// Typed value published by a sensor driver, kept alongside its file in
+/// /opt/aog/sensors
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensorReading {
+    pub value: f32,
+    pub unit: String,
+    pub source: String,
+    pub timestamp: u64,
+}
+
+// Typed readings older than this are too old to control anything with
+pub const READING_STALE_SECS: u64 = 300;
+
+lazy_static::lazy_static! {
+    static ref SENSOR_READINGS: Mutex<HashMap<String, SensorReading>> = Mutex::new(HashMap::new());
+}
+
+/// Publish a reading under `name` once it passes the plausibility rules,
+/// writing /opt/aog/sensors/<name> for the consumers that read the files.
+/// Returns whether the reading was accepted.
+pub fn publish(name: &str, value: f32, unit: &str, source: &str) -> bool {
+    if accept(name, source, value).is_none() {
+        return false;
+    }
+    record(name, value, unit, source);
+    true
+}
+
+/// Run a new reading from `source` through the plausibility rules
+pub fn accept(name: &str, source: &str, value: f32) -> Option<f32> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    PLAUSIBILITY.check(name, source, value, now).ok()
+}
+
+/// Store a reading built from already accepted ones, such as a fused value
+pub fn record(name: &str, value: f32, unit: &str, source: &str) {
+    let reading = SensorReading {
+        value,
+        unit: unit.to_string(),
+        source: source.to_string(),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+    };
+    if let Ok(mut readings) = SENSOR_READINGS.lock() {
+        readings.insert(name.to_string(), reading);
+    }
+    if let Ok(mut f) = File::create(format!("/opt/aog/sensors/{}", name)) {
+        let _ = f.write_all(format!("{}", value).as_bytes());
+    }
+}
+
+/// Withdraw a reading whose sensor has failed, so a stale value is not shown
+pub fn withdraw(name: &str) {
+    if let Ok(mut readings) = SENSOR_READINGS.lock() {
+        readings.remove(name);
+    }
+    let _ = std::fs::remove_file(format!("/opt/aog/sensors/{}", name));
+}
+
+pub fn reading(name: &str) -> Option<SensorReading> {
+    SENSOR_READINGS.lock().ok().and_then(|readings| readings.get(name).cloned())
+}
+
+pub fn readings() -> HashMap<String, SensorReading> {
+    SENSOR_READINGS.lock().map(|readings| readings.clone()).unwrap_or_default()
+}
+
+/// Latest accepted value of a metric, from the typed readings or the kit's
+/// file. Typed readings older than `READING_STALE_SECS` count as missing.
+pub fn current_value(name: &str) -> Option<f32> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    match reading(name) {
+        Some(reading) if now.saturating_sub(reading.timestamp) <= READING_STALE_SECS => Some(reading.value),
+        Some(_) => None,
+        None => plausibility::parse_number(&get_value(name)).ok(),
     }
-    return String::new();
+}
+
+/// Relative humidity in percent from the published reading, or the kit's file
+pub fn current_humidity() -> Option<f32> {
+    reading("hum").map(|reading| reading.value)
+        .or_else(|| get_value("hum").trim().trim_end_matches('%').parse().ok())
+        .filter(|humidity| (0.0..=100.0).contains(humidity))
 }
 
 
@@ -169,7 +211,7 @@ pub fn parse_arduino(raw: String, line_key: &str, on_fail_string: String) -> Str
         }
     }
 
-    return on_fail_string;
+    on_fail_string
 }
 
 
@@ -180,56 +222,48 @@ pub fn get_value(sensor: &str) -> String {
         match File::open(format!("/opt/aog/sensors/{}", sensor).as_str()) {
             Ok(mut f) => {
                 let _ = f.read_to_string(&mut data);
-                return data;
+                // Files written by the Arduino kit have not been checked yet
+                if PLAUSIBILITY.has_rule(sensor) && PLAUSIBILITY.check_stored(sensor, &data).is_err() {
+                    return "N/A".to_string();
+                }
+                data
             },
-            Err(_) => return format!("N/A"),
+            Err(_) => "N/A".to_string(),
         }
     } else {
-        return format!("N/A");
+        "N/A".to_string()
     }
 }
 
 use serial2::SerialPort;
 
-#[cfg(test)]
-use mockall::automock;
-
 // device_type: DUAL_OVF_SENSOR, SENSORKIT_MK1
 pub fn fetch_arduino(device_type: String) {
 
  
     let _ = thread::Builder::new().name("fetch_arduino_thread".to_string()).spawn(move || {
-        let mut tty_port = 0;
-        let tty_quit = 10;
-        let mut tty_found = false;
-
-        // let ports = serialport::available_ports().expect("No ports found!");
-        // for p in ports {
-        //     log::info!("{}", p.port_name);
-        // }
-
-
-        while !tty_found{
-
-            if tty_port > 10 {
-                tty_port = 0;
+        let role = match SerialRole::from_device_id(&device_type) {
+            Some(role) => role,
+            None => {
+                log::error!("Unknown Arduino device type: {}", device_type);
+                return;
             }
-    
-            let port_name = format!("/dev/ttyUSB{}", tty_port);
+        };
 
-            if !std::path::Path::new(port_name.clone().as_str()).exists(){
-                tty_port += 1;
-            } else {
-                let baud_rate = 9600;
-                let mut ttsport = SerialPort::open(port_name.clone(), baud_rate);
+        // Read from the port the serial device manager bound to this kit,
+        // handing it back when the kit stops answering or is unplugged
+        loop {
+
+            {
+                let port_name = SERIAL_DEVICES.wait_for(role).port_name;
+                let ttsport = SerialPort::open(port_name.clone(), BAUD_RATE);
     
             
                 match ttsport {
-                    Ok(mut port) => {
+                    Ok(port) => {
                         
                   
                             let mut serial_buf: Vec<u8> = vec![0; 256];
-                            let mut response = String::new();
         
                             loop {
                                 match port.read(serial_buf.as_mut_slice()) {
@@ -391,7 +425,6 @@ pub fn fetch_arduino(device_type: String) {
                                         }
 
 
-                                        tty_port = 0;  // Reset to 0 instead of -1 for u8 type
                                         break;
                                     },
                                     Err(e) => {
@@ -422,6 +455,11 @@ pub fn fetch_arduino(device_type: String) {
                                                 let _ = f.write_all(error_msg.as_bytes());
                                             }
                                         }
+
+                                        // Unplugged: re-discover it wherever it comes back
+                                        if !std::path::Path::new(port_name.as_str()).exists() {
+                                            break;
+                                        }
                                     }
                                 }
                    
@@ -436,7 +474,7 @@ pub fn fetch_arduino(device_type: String) {
                     
                 }
     
-                tty_port += 1;
+                SERIAL_DEVICES.release(role);
             }
 
 
@@ -458,14 +496,6 @@ mod tests {
     use tempfile::TempDir;
     use std::io::Write;
 
-    fn setup_test_sensor_dir() -> TempDir {
-        let temp_dir = TempDir::new().expect("Failed to create temp directory for test");
-        let sensor_dir = temp_dir.path().join("sensors");
-        fs::create_dir_all(&sensor_dir).expect("Failed to create sensor directory for test");
-        std::env::set_var("AOG_SENSOR_DIR", sensor_dir.to_str().expect("Failed to convert path to string"));
-        temp_dir
-    }
-
     #[test]
     fn test_parse_arduino_valid_input() {
         let raw = "BEGIN\nDEVICE_ID: SENSORKIT_MK1\nCO2: 450ppm\nTEMP: 25C\nEND".to_string();
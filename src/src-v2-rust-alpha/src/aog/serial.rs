@@ -0,0 +1,368 @@
+// Copyright (c) 2024 Terragon Labs
+//
+// MIT License
+//
+// Serial Module - Finds the USB serial devices (Arduino sensor kits and the
+// SDS011), binds each one to its role and re-discovers them after a replug
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serial2::SerialPort;
+use sds011::SDS011;
+
+pub const SERIAL_BINDINGS_PATH: &str = "/opt/aog/serial_bindings.json";
+pub const BAUD_RATE: u32 = 9600;
+// The Arduinos reset when the port is opened and then send a frame every 1-2 s
+const HANDSHAKE_TIMEOUT_SECS: u64 = 5;
+const DISCOVERY_RETRY_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SerialRole {
+    SensorKit,
+    OverflowSensor,
+    ParticulateSensor,
+}
+
+impl SerialRole {
+    /// DEVICE_ID the device reports in its frames
+    pub fn device_id(&self) -> &'static str {
+        match self {
+            SerialRole::SensorKit => "SENSORKIT_MK1",
+            SerialRole::OverflowSensor => "DUAL_OVF_SENSOR",
+            SerialRole::ParticulateSensor => "SDS011",
+        }
+    }
+
+    pub fn from_device_id(device_id: &str) -> Option<SerialRole> {
+        [SerialRole::SensorKit, SerialRole::OverflowSensor, SerialRole::ParticulateSensor]
+            .into_iter()
+            .find(|role| role.device_id() == device_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerialPortDescriptor {
+    pub port_name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+}
+
+impl SerialPortDescriptor {
+    /// Identity that survives the device moving to another ttyUSB number,
+    /// falling back to the port name for adapters without a serial number
+    pub fn identity(&self) -> String {
+        match (self.vid, self.pid, &self.serial_number) {
+            (Some(vid), Some(pid), Some(serial)) => format!("{:04x}:{:04x}:{}", vid, pid, serial),
+            _ => self.port_name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerialBinding {
+    pub role: SerialRole,
+    pub port: SerialPortDescriptor,
+    pub bound_at: String,
+}
+
+/// Port enumeration and device handshakes, so discovery can be tested
+/// without hardware
+pub trait SerialProbe: Send {
+    fn ports(&mut self) -> Vec<SerialPortDescriptor>;
+    fn identify(&mut self, port: &SerialPortDescriptor) -> Option<SerialRole>;
+}
+
+/// USB serial ports on this machine
+pub struct SystemProbe;
+
+impl SerialProbe for SystemProbe {
+    fn ports(&mut self) -> Vec<SerialPortDescriptor> {
+        let ports = match serialport::available_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                log::warn!("Failed to list serial ports: {}", e);
+                return Vec::new();
+            }
+        };
+        ports.into_iter()
+            .filter(|port| port.port_name.contains("ttyUSB") || port.port_name.contains("ttyACM"))
+            .map(|port| match port.port_type {
+                serialport::SerialPortType::UsbPort(usb) => SerialPortDescriptor {
+                    port_name: port.port_name,
+                    vid: Some(usb.vid),
+                    pid: Some(usb.pid),
+                    serial_number: usb.serial_number,
+                    product: usb.product,
+                },
+                _ => SerialPortDescriptor {
+                    port_name: port.port_name,
+                    vid: None,
+                    pid: None,
+                    serial_number: None,
+                    product: None,
+                },
+            })
+            .collect()
+    }
+
+    fn identify(&mut self, port: &SerialPortDescriptor) -> Option<SerialRole> {
+        // Listening first leaves an SDS011 undisturbed if that is what is there
+        identify_arduino(&port.port_name).or_else(|| identify_sds011(&port.port_name))
+    }
+}
+
+/// Listen for an Arduino frame and read its DEVICE_ID
+fn identify_arduino(port_name: &str) -> Option<SerialRole> {
+    let mut port = SerialPort::open(port_name, BAUD_RATE).ok()?;
+    port.set_read_timeout(Duration::from_millis(500)).ok()?;
+
+    let deadline = Instant::now() + Duration::from_secs(HANDSHAKE_TIMEOUT_SECS);
+    let mut buffer = [0u8; 256];
+    let mut response = String::new();
+    while Instant::now() < deadline {
+        match port.read(&mut buffer) {
+            Ok(count) => {
+                response.push_str(&String::from_utf8_lossy(&buffer[..count]));
+                if let Some(role) = parse_device_id(&response) {
+                    return Some(role);
+                }
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => (),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// The SDS011 only speaks its binary protocol, so ask it for a reading
+fn identify_sds011(port_name: &str) -> Option<SerialRole> {
+    let mut sensor = SDS011::new(port_name).ok()?;
+    sensor.query().ok().map(|_| SerialRole::ParticulateSensor)
+}
+
+/// Find a complete `DEVICE_ID: <id>` line in the text read so far
+pub fn parse_device_id(response: &str) -> Option<SerialRole> {
+    response.lines()
+        .filter_map(|line| line.trim().strip_prefix("DEVICE_ID:"))
+        .find_map(|device_id| SerialRole::from_device_id(device_id.trim()))
+}
+
+/// Owns the role -> port mapping so each device is opened by one reader
+pub struct SerialDeviceManager {
+    // Held for the whole of a discovery pass so only one thread probes ports
+    probe: Mutex<Box<dyn SerialProbe>>,
+    bindings: Mutex<HashMap<SerialRole, SerialBinding>>,
+    // Roles last seen for each device identity, tried first on the next pass
+    known: Mutex<HashMap<String, SerialRole>>,
+    path: Option<String>,
+}
+
+impl SerialDeviceManager {
+    pub fn new(probe: Box<dyn SerialProbe>, path: Option<&str>) -> Self {
+        let known = path
+            .filter(|path| Path::new(path).exists())
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        SerialDeviceManager {
+            probe: Mutex::new(probe),
+            bindings: Mutex::new(HashMap::new()),
+            known: Mutex::new(known),
+            path: path.map(|p| p.to_string()),
+        }
+    }
+
+    pub fn binding(&self, role: SerialRole) -> Option<SerialBinding> {
+        self.bindings.lock().unwrap_or_else(|e| e.into_inner()).get(&role).cloned()
+    }
+
+    pub fn bindings(&self) -> Vec<SerialBinding> {
+        let mut bindings: Vec<SerialBinding> = self.bindings.lock().unwrap_or_else(|e| e.into_inner())
+            .values().cloned().collect();
+        bindings.sort_by(|a, b| a.port.port_name.cmp(&b.port.port_name));
+        bindings
+    }
+
+    /// Enumerate the ports once and handshake with every port that is not
+    /// already bound. Returns the roles bound by this pass.
+    pub fn discover(&self) -> Vec<SerialRole> {
+        let mut probe = self.probe.lock().unwrap_or_else(|e| e.into_inner());
+        let bound_ports: Vec<String> = self.bindings().into_iter().map(|b| b.port.port_name).collect();
+        let known = self.known.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+        let mut ports: Vec<SerialPortDescriptor> = probe.ports().into_iter()
+            .filter(|port| !bound_ports.contains(&port.port_name))
+            .collect();
+        ports.sort_by_key(|port| !known.contains_key(&port.identity()));
+
+        let mut bound = Vec::new();
+        for port in ports {
+            let role = match probe.identify(&port) {
+                Some(role) => role,
+                None => continue,
+            };
+            if self.binding(role).is_some() {
+                log::warn!("Second {} found at {}, ignoring it", role.device_id(), port.port_name);
+                continue;
+            }
+            log::info!("Serial device {} bound to {}", role.device_id(), port.port_name);
+            self.record_known(&port, role);
+            self.bindings.lock().unwrap_or_else(|e| e.into_inner()).insert(role, SerialBinding {
+                role,
+                port,
+                bound_at: Local::now().to_rfc3339(),
+            });
+            bound.push(role);
+        }
+        bound
+    }
+
+    /// Port bound to `role`, discovering until the device turns up
+    pub fn wait_for(&self, role: SerialRole) -> SerialPortDescriptor {
+        loop {
+            if let Some(binding) = self.binding(role) {
+                return binding.port;
+            }
+            self.discover();
+            if let Some(binding) = self.binding(role) {
+                return binding.port;
+            }
+            thread::sleep(Duration::from_secs(DISCOVERY_RETRY_SECS));
+        }
+    }
+
+    /// Give up the port of a device that stopped answering or was unplugged
+    pub fn release(&self, role: SerialRole) {
+        if let Some(binding) = self.bindings.lock().unwrap_or_else(|e| e.into_inner()).remove(&role) {
+            log::warn!("Serial device {} released from {}", role.device_id(), binding.port.port_name);
+        }
+    }
+
+    fn record_known(&self, port: &SerialPortDescriptor, role: SerialRole) {
+        let mut known = self.known.lock().unwrap_or_else(|e| e.into_inner());
+        if known.insert(port.identity(), role) == Some(role) {
+            return;
+        }
+        if let Some(path) = &self.path {
+            let result = serde_json::to_string_pretty(&*known)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                log::warn!("Failed to save serial bindings: {}", e);
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref SERIAL_DEVICES: SerialDeviceManager =
+        SerialDeviceManager::new(Box::new(SystemProbe), Some(SERIAL_BINDINGS_PATH));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Ports present and the device behind each one
+    #[derive(Default)]
+    struct MockProbe {
+        devices: Arc<Mutex<Vec<(SerialPortDescriptor, SerialRole)>>>,
+        handshakes: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SerialProbe for MockProbe {
+        fn ports(&mut self) -> Vec<SerialPortDescriptor> {
+            self.devices.lock().unwrap().iter().map(|(port, _)| port.clone()).collect()
+        }
+
+        fn identify(&mut self, port: &SerialPortDescriptor) -> Option<SerialRole> {
+            self.handshakes.lock().unwrap().push(port.port_name.clone());
+            self.devices.lock().unwrap().iter()
+                .find(|(present, _)| present.port_name == port.port_name)
+                .map(|(_, role)| *role)
+        }
+    }
+
+    fn usb_port(port_name: &str, serial_number: &str) -> SerialPortDescriptor {
+        SerialPortDescriptor {
+            port_name: port_name.to_string(),
+            vid: Some(0x1a86),
+            pid: Some(0x7523),
+            serial_number: Some(serial_number.to_string()),
+            product: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_device_id() {
+        assert_eq!(parse_device_id("END\nBEGIN\nDEVICE_ID: SENSORKIT_MK1\nCO2: 450ppm\n"), Some(SerialRole::SensorKit));
+        assert_eq!(parse_device_id("BEGIN\r\nDEVICE_ID: DUAL_OVF_SENSOR\r\n"), Some(SerialRole::OverflowSensor));
+        // Partial id at the end of a read
+        assert_eq!(parse_device_id("BEGIN\nDEVICE_ID: SENSORKIT_M"), None);
+        assert_eq!(parse_device_id("garbage"), None);
+    }
+
+    #[test]
+    fn test_discover_binds_each_role_once() {
+        let probe = MockProbe::default();
+        let devices = probe.devices.clone();
+        let handshakes = probe.handshakes.clone();
+        devices.lock().unwrap().extend([
+            (usb_port("/dev/ttyUSB0", "A"), SerialRole::ParticulateSensor),
+            (usb_port("/dev/ttyUSB1", "B"), SerialRole::OverflowSensor),
+            (usb_port("/dev/ttyUSB2", "C"), SerialRole::SensorKit),
+        ]);
+        let manager = SerialDeviceManager::new(Box::new(probe), None);
+
+        assert_eq!(manager.discover().len(), 3);
+        assert_eq!(manager.binding(SerialRole::OverflowSensor).unwrap().port.port_name, "/dev/ttyUSB1");
+
+        // Bound ports are left alone by the next pass
+        handshakes.lock().unwrap().clear();
+        assert!(manager.discover().is_empty());
+        assert!(handshakes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rediscover_after_replug() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("serial_bindings.json");
+        let probe = MockProbe::default();
+        let devices = probe.devices.clone();
+        let handshakes = probe.handshakes.clone();
+        devices.lock().unwrap().extend([
+            (usb_port("/dev/ttyUSB0", "A"), SerialRole::SensorKit),
+            (usb_port("/dev/ttyUSB1", "B"), SerialRole::OverflowSensor),
+        ]);
+        let manager = SerialDeviceManager::new(Box::new(probe), path.to_str());
+        manager.discover();
+
+        // The overflow sensor is unplugged and comes back as ttyUSB3
+        devices.lock().unwrap().retain(|(_, role)| *role != SerialRole::OverflowSensor);
+        manager.release(SerialRole::OverflowSensor);
+        assert!(manager.binding(SerialRole::OverflowSensor).is_none());
+        devices.lock().unwrap().insert(0, (usb_port("/dev/ttyUSB2", "D"), SerialRole::ParticulateSensor));
+        devices.lock().unwrap().push((usb_port("/dev/ttyUSB3", "B"), SerialRole::OverflowSensor));
+
+        handshakes.lock().unwrap().clear();
+        manager.discover();
+        assert_eq!(manager.binding(SerialRole::OverflowSensor).unwrap().port.port_name, "/dev/ttyUSB3");
+        // The known device is tried before the new one
+        assert_eq!(handshakes.lock().unwrap()[0], "/dev/ttyUSB3");
+        assert_eq!(manager.binding(SerialRole::SensorKit).unwrap().port.port_name, "/dev/ttyUSB0");
+
+        // Identities survive a restart
+        let reloaded = SerialDeviceManager::new(Box::new(MockProbe::default()), path.to_str());
+        assert_eq!(reloaded.known.lock().unwrap().get("1a86:7523:B"), Some(&SerialRole::OverflowSensor));
+    }
+}
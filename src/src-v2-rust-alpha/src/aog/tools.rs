@@ -8,6 +8,8 @@
 // Licensed under GPLv3....see LICENSE file.
 
 
+// error_chain! checks a cfg that this crate doesn't declare
+#![allow(unexpected_cfgs)]
 
 use std::fs::File;
 use std::io::Write;
@@ -37,14 +39,14 @@ pub fn hash_check(file_path: &str) -> Result<String>{
     let _bytes_written = io::copy(&mut file, &mut hasher)?;
     let hashh: String = format!("{:X}", hasher.finalize());
     log::warn!("done hasging file: {}", file_path);
-    return Ok(hashh.to_string().to_lowercase());
+    Ok(hashh.to_string().to_lowercase())
 }
 
 
 pub fn get_file_size(file_path: &str) -> Result<i64>{
     let f = File::open(file_path)?;
     let x = f.metadata()?.len();
-    return Ok(x as i64);
+    Ok(x as i64)
 }
 
 
@@ -57,14 +59,14 @@ pub fn dbash(command: &str) -> Result<String>{
     .arg(command)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn bash(command: &str) -> Result<String>{
@@ -73,14 +75,14 @@ pub fn bash(command: &str) -> Result<String>{
     .arg(command)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn brew_install(package: &str) -> Result<String>{
@@ -93,14 +95,14 @@ pub fn brew_install(package: &str) -> Result<String>{
     .arg(package)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn untar(file_path: &str) -> Result<String>{
@@ -110,14 +112,14 @@ pub fn untar(file_path: &str) -> Result<String>{
     .arg(file_path)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn apt_install(package: &str) -> Result<String>{
@@ -128,14 +130,14 @@ pub fn apt_install(package: &str) -> Result<String>{
     .arg("-y")
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn dnf_install(package: &str) -> Result<String>{
@@ -146,14 +148,14 @@ pub fn dnf_install(package: &str) -> Result<String>{
     .arg("-y")
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn brew_uninstall(package: &str) -> Result<String>{
@@ -165,14 +167,14 @@ pub fn brew_uninstall(package: &str) -> Result<String>{
     .arg(package)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn ln(path: &str, link: &str) -> Result<String>{
@@ -182,14 +184,14 @@ pub fn ln(path: &str, link: &str) -> Result<String>{
     .arg(link)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn mv(source: &str, destination: &str) -> Result<String>{
@@ -198,14 +200,14 @@ pub fn mv(source: &str, destination: &str) -> Result<String>{
     .arg(destination)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn cp(source: &str, destination: &str) -> Result<String>{
@@ -214,14 +216,14 @@ pub fn cp(source: &str, destination: &str) -> Result<String>{
     .arg(destination)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn launchd_bootstrap(destination: &str) -> Result<String>{
@@ -231,14 +233,14 @@ pub fn launchd_bootstrap(destination: &str) -> Result<String>{
     .arg(destination)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn launchd_bootout(destination: &str) -> Result<String>{
@@ -248,14 +250,14 @@ pub fn launchd_bootout(destination: &str) -> Result<String>{
     .arg(destination)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn launchd_enable(destination: &str) -> Result<String>{
@@ -264,14 +266,14 @@ pub fn launchd_enable(destination: &str) -> Result<String>{
     .arg(destination)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn launchd_kickstart(destination: &str) -> Result<String>{
@@ -281,14 +283,14 @@ pub fn launchd_kickstart(destination: &str) -> Result<String>{
     .arg(destination)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn systemctl_reload() -> Result<String>{
@@ -296,14 +298,14 @@ pub fn systemctl_reload() -> Result<String>{
     .arg("daemon-reload")
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn systemctl_start(service_name: &str) -> Result<String>{
@@ -312,14 +314,14 @@ pub fn systemctl_start(service_name: &str) -> Result<String>{
     .arg(service_name)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn systemctl_stop(service_name: &str) -> Result<String>{
@@ -328,14 +330,14 @@ pub fn systemctl_stop(service_name: &str) -> Result<String>{
     .arg(service_name)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn systemctl_enable(service_name: &str) -> Result<String>{
@@ -344,14 +346,14 @@ pub fn systemctl_enable(service_name: &str) -> Result<String>{
     .arg(service_name)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn rm(path: &str) -> Result<String>{
@@ -359,14 +361,14 @@ pub fn rm(path: &str) -> Result<String>{
     .arg(path)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn rmd(path: &str) -> Result<String>{
@@ -375,14 +377,14 @@ pub fn rmd(path: &str) -> Result<String>{
     .arg(path)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 
@@ -399,14 +401,14 @@ pub fn whisper(model: &str, file_path: &str) -> Result<String>{
     .arg("-otxt")
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
     
 }
 
@@ -423,14 +425,14 @@ pub fn whisper_owts(model: &str, file_path: &str) -> Result<String>{
     .arg("-owts")
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
     
 }
             
@@ -444,14 +446,14 @@ pub fn mkdir(apath: &str) -> Result<String>{
     .arg(apath)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 /// Sets secure permissions for AOG files and directories
@@ -466,10 +468,8 @@ pub fn fix_permissions(apath: &str) -> Result<String>{
         "755"
     } else if apath.ends_with(".log") {
         "664"  // Log files need group write for rotation
-    } else if apath.ends_with(".json") || apath.ends_with(".conf") {
-        "644"  // Config files: owner write, others read
     } else {
-        "644"  // Default for other files
+        "644"  // Config and other files: owner write, others read
     };
     
     let child = Command::new("/bin/chmod")
@@ -479,20 +479,19 @@ pub fn fix_permissions(apath: &str) -> Result<String>{
     .stdout(Stdio::piped())
     .stderr(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute chmod: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute chmod: {}", e)))?;
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
     
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, 
-            format!("chmod failed: {}", stderr)).into());
+        return Err(std::io::Error::other(format!("chmod failed: {}", stderr)).into());
     }
     
     log::info!("Set permissions {} on {}", dir_perms, apath);
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string()); 
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 /// Sets ownership to the aog user and group
@@ -504,20 +503,19 @@ pub fn set_ownership(apath: &str, user: &str, group: &str) -> Result<String> {
     .stdout(Stdio::piped())
     .stderr(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute chown: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute chown: {}", e)))?;
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
     
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, 
-            format!("chown failed: {}", stderr)).into());
+        return Err(std::io::Error::other(format!("chown failed: {}", stderr)).into());
     }
     
     log::info!("Set ownership {}:{} on {}", user, group, apath);
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 /// Validates that file permissions are secure (not world-writable)
@@ -537,7 +535,7 @@ pub fn validate_permissions(apath: &str) -> Result<bool> {
     }
     
     log::debug!("Permissions validated for {} (mode: {:o})", apath, mode);
-    return Ok(true);
+    Ok(true)
 }
 
 pub fn mark_as_executable(apath: &str) -> Result<String>{
@@ -546,14 +544,14 @@ pub fn mark_as_executable(apath: &str) -> Result<String>{
     .arg(apath)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string()); 
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn sh(script: &str) -> Result<String>{
@@ -561,14 +559,14 @@ pub fn sh(script: &str) -> Result<String>{
     .arg(script)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string()); 
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 pub fn srgan(input: &str, output: &str) -> Result<String>{
@@ -577,14 +575,14 @@ pub fn srgan(input: &str, output: &str) -> Result<String>{
     .arg(output)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string()); 
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 
@@ -595,21 +593,21 @@ pub fn download(file_path: &str, url: &str) -> Result<bool>{
     .arg(url)
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let _output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(true);
+    Ok(true)
 }
 
 pub fn wav_to_16000(input: String) -> Result<String>{
     let child = Command::new("/opt/thalamus/bin/ffmpeg")
     .arg("-y")
     .arg("-i")
-    .arg(format!("{}", input))
+    .arg(&input)
     .arg("-ar")
     .arg("16000")
     .arg("-ac")
@@ -619,14 +617,14 @@ pub fn wav_to_16000(input: String) -> Result<String>{
     .arg(format!("{}.16.wav", input))
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 
@@ -650,19 +648,19 @@ pub fn llama(model: &str, prompt: &str) -> Result<String>{
     .arg(format!("\"{}\"", prompt))
     .stdout(Stdio::piped())
     .spawn()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to execute command: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to execute command: {}", e)))?;
 
 
     let output = child
     .wait_with_output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to wait on child: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to wait on child: {}", e)))?;
 
-    return Ok(String::from_utf8_lossy(&output.stdout).to_string());    
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
     
 }
 
 
-pub fn find_mimetype(filename: &String) -> String{
+pub fn find_mimetype(filename: &str) -> String{
     let parts : Vec<&str> = filename.split('.').collect();
     let res = match parts.last() {
             Some(v) =>
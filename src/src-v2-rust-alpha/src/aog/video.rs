@@ -25,8 +25,6 @@ use std::fs::File;
 use std::io::Write;
 use std::thread;
 use std::time::Duration;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
 
 pub fn init_all(){
     // Start video0 Thread
@@ -99,7 +97,7 @@ pub fn init(channel: String) {
                     Ok(frame) => {
                         // println!("resolution: {:?}, timestamp: {:?}", frame.resolution, frame.get_timestamp());
                         
-                        if let Ok(mut file) = File::create(&format!("/opt/aog/dat/{}.jpg", channel)) {
+                        if let Ok(mut file) = File::create(format!("/opt/aog/dat/{}.jpg", channel)) {
                             let _ = file.write_all(&frame[..]);
                         }
                         
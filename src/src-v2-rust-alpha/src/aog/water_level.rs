@@ -6,15 +6,32 @@
 // for tanks with support for multiple sensor types and safety features
 
 use std::sync::{Arc, Mutex};
-use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
 use std::thread;
 use std::fs;
 use std::io::Write;
 use chrono::Local;
 use serde::{Deserialize, Serialize};
-use rppal::gpio::{Gpio, InputPin, OutputPin, Level};
+use rppal::gpio::{Gpio, InputPin, OutputPin, Level, Trigger};
 use crate::{WaterLevelConfig, WaterLevelSensorType};
+use crate::aog::pump_safety::{CRITICAL_HIGH_LEVEL, WARNING_HIGH_LEVEL, CRITICAL_LOW_LEVEL};
+
+/// Level must move this many percent back past a threshold before an alert clears
+pub const LEVEL_ALERT_HYSTERESIS: f32 = 2.0;
+
+/// Number of level alert events retained by the system
+const MAX_LEVEL_EVENTS: usize = 100;
+
+/// Classification of a distance reading against the sensor's usable range
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum RangeStatus {
+    #[default]
+    InRange,
+    DeadZone,    // Water surface is inside the sensor's blind zone (tank at or above max measurable level)
+    OutOfRange,  // Distance beyond the sensor range or the tank depth
+}
 
 /// Water level reading with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,16 +43,178 @@ pub struct WaterLevelReading {
     pub sensor_type: WaterLevelSensorType,
     pub is_valid: bool,
     pub error_message: Option<String>,
+    #[serde(default)]
+    pub range_status: RangeStatus,
+}
+
+pub const CALIBRATION_PATH: &str = "/opt/aog/water_level_calibration.json";
+
+/// A single calibration point: raw sensor distance against a known water level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationPoint {
+    pub raw_distance_cm: f32,
+    pub actual_level_cm: f32,
+    pub timestamp: String,
+}
+
+/// Calibration for one tank, fitted from the captured points
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TankCalibration {
+    pub points: Vec<CalibrationPoint>,
+    pub offset: f32,
+    pub factor: f32,
+    pub last_calibration: Option<String>,
+}
+
+impl Default for TankCalibration {
+    fn default() -> Self {
+        TankCalibration {
+            points: Vec::new(),
+            offset: 0.0,
+            factor: 1.0,
+            last_calibration: None,
+        }
+    }
+}
+
+impl TankCalibration {
+    /// Add a point, replacing any existing point at (nearly) the same level
+    pub fn add_point(&mut self, point: CalibrationPoint) {
+        self.points.retain(|p| (p.actual_level_cm - point.actual_level_cm).abs() > 0.5);
+        self.last_calibration = Some(point.timestamp.clone());
+        self.points.push(point);
+    }
+
+    /// Fit offset and factor so that `(raw + offset) * factor` gives the true
+    /// distance to the water surface. A single point only corrects the offset,
+    /// two or more points are fitted with least squares.
+    pub fn fit(&mut self, tank_height_cm: f32) -> Result<(), String> {
+        if self.points.is_empty() {
+            self.offset = 0.0;
+            self.factor = 1.0;
+            return Ok(());
+        }
+
+        let pairs: Vec<(f32, f32)> = self.points.iter()
+            .map(|p| (p.raw_distance_cm, tank_height_cm - p.actual_level_cm))
+            .collect();
+
+        if pairs.len() == 1 {
+            let (raw, actual) = pairs[0];
+            self.factor = 1.0;
+            self.offset = actual - raw;
+            return Ok(());
+        }
+
+        let n = pairs.len() as f32;
+        let mean_raw = pairs.iter().map(|(r, _)| r).sum::<f32>() / n;
+        let mean_actual = pairs.iter().map(|(_, a)| a).sum::<f32>() / n;
+        let covariance: f32 = pairs.iter().map(|(r, a)| (r - mean_raw) * (a - mean_actual)).sum();
+        let variance: f32 = pairs.iter().map(|(r, _)| (r - mean_raw).powi(2)).sum();
+
+        if variance < 1.0 {
+            return Err("Calibration points are too close together to fit a factor".to_string());
+        }
+
+        let factor = covariance / variance;
+        if !(0.5..=2.0).contains(&factor) {
+            return Err(format!("Fitted calibration factor {:.3} is implausible, check the calibration points", factor));
+        }
+
+        let intercept = mean_actual - factor * mean_raw;
+        self.factor = factor;
+        self.offset = intercept / factor;
+        Ok(())
+    }
+}
+
+/// Load saved calibrations for all tanks
+pub fn load_calibrations(path: &str) -> HashMap<String, TankCalibration> {
+    if std::path::Path::new(path).exists() {
+        if let Ok(data) = fs::read_to_string(path) {
+            match serde_json::from_str(&data) {
+                Ok(calibrations) => return calibrations,
+                Err(e) => log::error!("Failed to parse water level calibration {}: {}", path, e),
+            }
+        }
+    }
+    HashMap::new()
+}
+
+/// Save calibrations for all tanks
+pub fn save_calibrations(path: &str, calibrations: &HashMap<String, TankCalibration>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(calibrations)
+        .map_err(|e| format!("Failed to serialize calibration: {}", e))?;
+    // Write a temp file and rename it so a crash never leaves a truncated file
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, json)
+        .map_err(|e| format!("Failed to save calibration: {}", e))?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to replace calibration: {}", e))
 }
 
 /// Water level sensor trait for different sensor implementations
 pub trait WaterLevelSensor: Send + Sync {
+    /// Calibrated distance from the sensor to the water surface in cm
     fn read(&mut self) -> Result<f32, String>;
-    fn calibrate(&mut self, actual_level_cm: f32) -> Result<(), String>;
+    /// Distance in cm before the calibration offset and factor are applied
+    fn read_raw(&mut self) -> Result<f32, String>;
+    fn set_calibration(&mut self, offset: f32, factor: f32);
     fn get_sensor_type(&self) -> WaterLevelSensorType;
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
 }
 
+/// Speed of sound in air (m/s) at the given temperature in Celsius
+pub fn speed_of_sound(temperature_c: f32) -> f32 {
+    331.3 + 0.606 * temperature_c
+}
+
+/// Ambient temperature from the sensor kit, if a plausible value is available
+fn ambient_temperature() -> Option<f32> {
+    let raw = crate::aog::sensors::get_value("temp");
+    let numeric: String = raw.trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+        .collect();
+
+    numeric.parse::<f32>().ok().filter(|t| *t > -40.0 && *t < 80.0)
+}
+
+/// Median of the samples after discarding any sample further than
+/// `threshold_cm` from the median of the full set. Returns None when fewer
+/// than half of the samples survive the outlier rejection.
+pub fn filter_samples(samples: &[f32], threshold_cm: f32) -> Option<f32> {
+    let median = median(samples)?;
+    let inliers: Vec<f32> = samples.iter()
+        .cloned()
+        .filter(|s| (s - median).abs() <= threshold_cm)
+        .collect();
+
+    if inliers.len() * 2 < samples.len() {
+        return None;
+    }
+
+    median_of(inliers)
+}
+
+fn median(samples: &[f32]) -> Option<f32> {
+    median_of(samples.to_vec())
+}
+
+fn median_of(mut samples: Vec<f32>) -> Option<f32> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = samples.len() / 2;
+    if samples.len().is_multiple_of(2) {
+        Some((samples[mid - 1] + samples[mid]) / 2.0)
+    } else {
+        Some(samples[mid])
+    }
+}
+
 /// Ultrasonic sensor implementation (HC-SR04)
 pub struct UltrasonicSensor {
     trigger_pin: OutputPin,
@@ -43,6 +222,9 @@ pub struct UltrasonicSensor {
     calibration_offset: f32,
     calibration_factor: f32,
     timeout_ms: u64,
+    samples: usize,
+    outlier_threshold_cm: f32,
+    temperature_compensation: bool,
 }
 
 impl UltrasonicSensor {
@@ -63,10 +245,20 @@ impl UltrasonicSensor {
             calibration_offset: config.calibration_offset,
             calibration_factor: config.calibration_factor,
             timeout_ms: config.sensor_timeout_ms,
+            samples: config.ultrasonic_samples.max(1),
+            outlier_threshold_cm: config.outlier_threshold_cm,
+            temperature_compensation: config.temperature_compensation,
         })
     }
     
-    fn measure_distance(&mut self) -> Result<f32, String> {
+    /// Measure the echo pulse width in microseconds using edge interrupts
+    fn measure_echo_us(&mut self) -> Result<f32, String> {
+        let timeout = Duration::from_millis(self.timeout_ms);
+
+        // Re-arming the interrupt discards any stale edges from a previous ping
+        self.echo_pin.set_interrupt(Trigger::Both)
+            .map_err(|e| format!("Failed to configure echo interrupt: {}", e))?;
+
         // Send trigger pulse
         self.trigger_pin.set_low();
         thread::sleep(Duration::from_micros(2));
@@ -74,55 +266,71 @@ impl UltrasonicSensor {
         thread::sleep(Duration::from_micros(10));
         self.trigger_pin.set_low();
         
-        // Wait for echo to go high
-        let start_wait = Instant::now();
-        while self.echo_pin.is_low() {
-            if start_wait.elapsed() > Duration::from_millis(self.timeout_ms) {
-                return Err("Timeout waiting for echo pulse".to_string());
-            }
-        }
+        // Rising edge marks the start of the echo pulse
+        let pulse_start = match self.echo_pin.poll_interrupt(false, Some(timeout)) {
+            Ok(Some(Level::High)) => Instant::now(),
+            Ok(Some(Level::Low)) => return Err("Unexpected falling edge before echo pulse".to_string()),
+            Ok(None) => return Err("Timeout waiting for echo pulse".to_string()),
+            Err(e) => return Err(format!("Echo interrupt failed: {}", e)),
+        };
         
-        // Measure echo pulse duration
-        let pulse_start = Instant::now();
-        while self.echo_pin.is_high() {
-            if pulse_start.elapsed() > Duration::from_millis(self.timeout_ms) {
-                return Err("Timeout measuring echo pulse".to_string());
-            }
-        }
-        let pulse_duration = pulse_start.elapsed();
+        // Falling edge marks the end of the echo pulse
+        let pulse_end = match self.echo_pin.poll_interrupt(false, Some(timeout)) {
+            Ok(Some(Level::Low)) => Instant::now(),
+            Ok(Some(Level::High)) => return Err("Missed falling edge of echo pulse".to_string()),
+            Ok(None) => return Err("Timeout measuring echo pulse".to_string()),
+            Err(e) => return Err(format!("Echo interrupt failed: {}", e)),
+        };
+
+        let _ = self.echo_pin.clear_interrupt();
         
-        // Calculate distance (speed of sound = 343 m/s at room temperature)
-        // Distance = (time * speed) / 2 (divide by 2 for round trip)
-        let distance_cm = (pulse_duration.as_micros() as f32 * 0.0343) / 2.0;
+        Ok(pulse_end.duration_since(pulse_start).as_micros() as f32)
+    }
+
+    fn measure_distance(&mut self, speed_m_s: f32) -> Result<f32, String> {
+        let pulse_us = self.measure_echo_us()?;
         
-        Ok((distance_cm + self.calibration_offset) * self.calibration_factor)
+        // Distance = (time * speed) / 2 (divide by 2 for round trip)
+        // m/s -> cm/us is a factor of 1e-4
+        Ok((pulse_us * speed_m_s * 0.0001) / 2.0)
     }
 }
 
 impl WaterLevelSensor for UltrasonicSensor {
     fn read(&mut self) -> Result<f32, String> {
-        // Take multiple readings and average them
-        let mut readings = Vec::new();
-        for _ in 0..3 {
-            match self.measure_distance() {
+        let raw = self.read_raw()?;
+        Ok((raw + self.calibration_offset) * self.calibration_factor)
+    }
+    
+    fn read_raw(&mut self) -> Result<f32, String> {
+        let temperature = if self.temperature_compensation {
+            ambient_temperature().unwrap_or(20.0)
+        } else {
+            20.0
+        };
+        let speed = speed_of_sound(temperature);
+
+        let mut readings = Vec::with_capacity(self.samples);
+        for _ in 0..self.samples {
+            match self.measure_distance(speed) {
                 Ok(distance) => readings.push(distance),
                 Err(e) => log::warn!("Ultrasonic reading failed: {}", e),
             }
-            thread::sleep(Duration::from_millis(50));
+            // HC-SR04 needs ~60ms between pings to avoid picking up the previous echo
+            thread::sleep(Duration::from_millis(60));
         }
         
         if readings.is_empty() {
             return Err("All ultrasonic readings failed".to_string());
         }
         
-        let avg = readings.iter().sum::<f32>() / readings.len() as f32;
-        Ok(avg)
+        filter_samples(&readings, self.outlier_threshold_cm)
+            .ok_or_else(|| format!("Too many outliers in ultrasonic samples: {:?}", readings))
     }
     
-    fn calibrate(&mut self, actual_level_cm: f32) -> Result<(), String> {
-        let measured = self.read()?;
-        self.calibration_offset = actual_level_cm - measured;
-        Ok(())
+    fn set_calibration(&mut self, offset: f32, factor: f32) {
+        self.calibration_offset = offset;
+        self.calibration_factor = factor;
     }
     
     fn get_sensor_type(&self) -> WaterLevelSensorType {
@@ -138,6 +346,8 @@ impl WaterLevelSensor for UltrasonicSensor {
 pub struct MockSensor {
     level: f32,
     sensor_type: WaterLevelSensorType,
+    calibration_offset: f32,
+    calibration_factor: f32,
 }
 
 impl MockSensor {
@@ -145,6 +355,8 @@ impl MockSensor {
         MockSensor {
             level: initial_level,
             sensor_type: WaterLevelSensorType::Mock,
+            calibration_offset: 0.0,
+            calibration_factor: 1.0,
         }
     }
     
@@ -155,11 +367,16 @@ impl MockSensor {
 
 impl WaterLevelSensor for MockSensor {
     fn read(&mut self) -> Result<f32, String> {
+        Ok((self.level + self.calibration_offset) * self.calibration_factor)
+    }
+    
+    fn read_raw(&mut self) -> Result<f32, String> {
         Ok(self.level)
     }
     
-    fn calibrate(&mut self, _actual_level_cm: f32) -> Result<(), String> {
-        Ok(())
+    fn set_calibration(&mut self, offset: f32, factor: f32) {
+        self.calibration_offset = offset;
+        self.calibration_factor = factor;
     }
     
     fn get_sensor_type(&self) -> WaterLevelSensorType {
@@ -171,7 +388,51 @@ impl WaterLevelSensor for MockSensor {
     }
 }
 
+/// Alert state of a tank based on its sampled level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LevelAlert {
+    Normal,
+    WarningHigh,
+    CriticalHigh,
+    CriticalLow,
+}
+
+impl LevelAlert {
+    /// Next alert state for a level, with hysteresis so readings hovering
+    /// around a threshold don't flap between states
+    pub fn evaluate(previous: LevelAlert, level_percent: f32) -> LevelAlert {
+        let raw = if level_percent >= CRITICAL_HIGH_LEVEL {
+            LevelAlert::CriticalHigh
+        } else if level_percent >= WARNING_HIGH_LEVEL {
+            LevelAlert::WarningHigh
+        } else if level_percent <= CRITICAL_LOW_LEVEL {
+            LevelAlert::CriticalLow
+        } else {
+            LevelAlert::Normal
+        };
+
+        match previous {
+            LevelAlert::CriticalHigh if level_percent > CRITICAL_HIGH_LEVEL - LEVEL_ALERT_HYSTERESIS => LevelAlert::CriticalHigh,
+            LevelAlert::CriticalHigh if raw == LevelAlert::Normal && level_percent > WARNING_HIGH_LEVEL - LEVEL_ALERT_HYSTERESIS => LevelAlert::WarningHigh,
+            LevelAlert::WarningHigh if raw == LevelAlert::Normal && level_percent > WARNING_HIGH_LEVEL - LEVEL_ALERT_HYSTERESIS => LevelAlert::WarningHigh,
+            LevelAlert::CriticalLow if level_percent < CRITICAL_LOW_LEVEL + LEVEL_ALERT_HYSTERESIS => LevelAlert::CriticalLow,
+            _ => raw,
+        }
+    }
+}
+
+/// Raised when a tank changes alert state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelEvent {
+    pub tank_id: String,
+    pub previous: LevelAlert,
+    pub alert: LevelAlert,
+    pub level_percent: f32,
+    pub timestamp: String,
+}
+
 /// Water level monitor with moving average and failure detection
+#[derive(Clone)]
 pub struct WaterLevelMonitor {
     tank_id: String,
     sensor: Arc<Mutex<Box<dyn WaterLevelSensor>>>,
@@ -180,6 +441,10 @@ pub struct WaterLevelMonitor {
     last_valid_reading: Arc<Mutex<Option<WaterLevelReading>>>,
     consecutive_failures: Arc<Mutex<u32>>,
     max_consecutive_failures: u32,
+    calibration: Arc<Mutex<TankCalibration>>,
+    cached_reading: Arc<Mutex<Option<(Instant, WaterLevelReading)>>>,
+    level_history: Arc<Mutex<VecDeque<WaterLevelReading>>>,
+    alert: Arc<Mutex<LevelAlert>>,
 }
 
 impl WaterLevelMonitor {
@@ -196,91 +461,229 @@ impl WaterLevelMonitor {
             last_valid_reading: Arc::new(Mutex::new(None)),
             consecutive_failures: Arc::new(Mutex::new(0)),
             max_consecutive_failures: 3,
+            calibration: Arc::new(Mutex::new(TankCalibration::default())),
+            cached_reading: Arc::new(Mutex::new(None)),
+            level_history: Arc::new(Mutex::new(VecDeque::new())),
+            alert: Arc::new(Mutex::new(LevelAlert::Normal)),
         }
     }
     
+    /// Classify a raw distance against the sensor dead zone and usable range
+    pub fn classify_distance(&self, distance_cm: f32) -> RangeStatus {
+        if distance_cm > self.config.max_range_cm
+            || distance_cm > self.config.tank_height_cm + self.config.outlier_threshold_cm {
+            RangeStatus::OutOfRange
+        } else if distance_cm < self.config.dead_zone_cm {
+            RangeStatus::DeadZone
+        } else {
+            RangeStatus::InRange
+        }
+    }
+
     /// Get current water level with moving average
     pub fn get_level(&self) -> WaterLevelReading {
         let mut sensor = self.sensor.lock().unwrap();
+        let sensor_type = sensor.get_sensor_type();
         
         match sensor.read() {
-            Ok(raw_level) => {
-                // Reset failure counter
-                *self.consecutive_failures.lock().unwrap() = 0;
-                
-                // Add to history for moving average
-                let mut history = self.reading_history.lock().unwrap();
-                history.push_back(raw_level);
-                
-                // Keep only the configured number of samples
-                while history.len() > self.config.moving_average_samples {
-                    history.pop_front();
+            Ok(raw_level) => match self.classify_distance(raw_level) {
+                RangeStatus::InRange => self.in_range_reading(raw_level, sensor_type),
+                RangeStatus::DeadZone => self.dead_zone_reading(raw_level, sensor_type),
+                RangeStatus::OutOfRange => {
+                    let msg = format!("Distance {:.1}cm is out of sensor range", raw_level);
+                    let mut reading = self.failed_reading(sensor_type, msg);
+                    reading.range_status = RangeStatus::OutOfRange;
+                    reading
                 }
-                
-                // Calculate moving average
-                let avg_level = if !history.is_empty() {
-                    history.iter().sum::<f32>() / history.len() as f32
-                } else {
-                    raw_level
-                };
-                
-                // Convert to percentage
-                let level_percent = ((self.config.tank_height_cm - avg_level) / self.config.tank_height_cm * 100.0)
-                    .max(0.0)
-                    .min(100.0);
-                
-                let reading = WaterLevelReading {
-                    tank_id: self.tank_id.clone(),
-                    level_cm: self.config.tank_height_cm - avg_level,
-                    level_percent,
-                    timestamp: Local::now().to_rfc3339(),
-                    sensor_type: sensor.get_sensor_type(),
-                    is_valid: true,
-                    error_message: None,
-                };
-                
-                // Save as last valid reading
-                *self.last_valid_reading.lock().unwrap() = Some(reading.clone());
-                
-                // Write to sensor file
-                self.write_sensor_file(level_percent);
-                
-                reading
+            },
+            Err(e) => self.failed_reading(sensor_type, e),
+        }
+    }
+
+    fn in_range_reading(&self, raw_level: f32, sensor_type: WaterLevelSensorType) -> WaterLevelReading {
+        // Reset failure counter
+        *self.consecutive_failures.lock().unwrap() = 0;
+        
+        // Add to history for moving average
+        let mut history = self.reading_history.lock().unwrap();
+        history.push_back(raw_level);
+        
+        // Keep only the configured number of samples
+        while history.len() > self.config.moving_average_samples {
+            history.pop_front();
+        }
+        
+        // Calculate moving average
+        let avg_level = if !history.is_empty() {
+            history.iter().sum::<f32>() / history.len() as f32
+        } else {
+            raw_level
+        };
+        
+        // Convert to percentage
+        let level_percent = ((self.config.tank_height_cm - avg_level) / self.config.tank_height_cm * 100.0)
+            .clamp(0.0, 100.0);
+        
+        let reading = WaterLevelReading {
+            tank_id: self.tank_id.clone(),
+            level_cm: self.config.tank_height_cm - avg_level,
+            level_percent,
+            timestamp: Local::now().to_rfc3339(),
+            sensor_type,
+            is_valid: true,
+            error_message: None,
+            range_status: RangeStatus::InRange,
+        };
+        
+        // Save as last valid reading
+        *self.last_valid_reading.lock().unwrap() = Some(reading.clone());
+        
+        // Write to sensor file
+        self.write_sensor_file(level_percent);
+        
+        reading
+    }
+
+    /// Water is inside the blind zone: report the maximum measurable level
+    /// rather than the bogus short distance the sensor returns. How far above
+    /// it the water is can't be known, so the percentage is at least critical
+    /// high and fills are refused.
+    fn dead_zone_reading(&self, raw_level: f32, sensor_type: WaterLevelSensorType) -> WaterLevelReading {
+        *self.consecutive_failures.lock().unwrap() = 0;
+        
+        let level_cm = self.config.tank_height_cm - self.config.dead_zone_cm;
+        let level_percent = (level_cm / self.config.tank_height_cm * 100.0)
+            .clamp(CRITICAL_HIGH_LEVEL, 100.0);
+        
+        log::warn!("Water level sensor {} in dead zone ({:.1}cm), tank at or above {:.1}cm", 
+            self.tank_id, raw_level, level_cm);
+        
+        let reading = WaterLevelReading {
+            tank_id: self.tank_id.clone(),
+            level_cm,
+            level_percent,
+            timestamp: Local::now().to_rfc3339(),
+            sensor_type,
+            is_valid: true,
+            error_message: Some("Water level in sensor dead zone, reporting maximum measurable level".to_string()),
+            range_status: RangeStatus::DeadZone,
+        };
+        
+        *self.last_valid_reading.lock().unwrap() = Some(reading.clone());
+        self.write_sensor_file(level_percent);
+        
+        reading
+    }
+
+    fn failed_reading(&self, sensor_type: WaterLevelSensorType, e: String) -> WaterLevelReading {
+        // Increment failure counter
+        let mut failures = self.consecutive_failures.lock().unwrap();
+        *failures += 1;
+        
+        log::error!("Water level sensor {} failed: {} (failure #{}/{})", 
+            self.tank_id, e, *failures, self.max_consecutive_failures);
+        
+        // Check if we should use fallback
+        if *failures >= self.max_consecutive_failures && self.config.enable_fallback_mode {
+            self.use_fallback_reading()
+        } else if let Some(last) = self.last_valid_reading.lock().unwrap().clone() {
+            // Use last valid reading
+            WaterLevelReading {
+                is_valid: false,
+                error_message: Some(format!("Using last valid reading due to: {}", e)),
+                ..last
             }
-            Err(e) => {
-                // Increment failure counter
-                let mut failures = self.consecutive_failures.lock().unwrap();
-                *failures += 1;
-                
-                log::error!("Water level sensor {} failed: {} (failure #{}/{})", 
-                    self.tank_id, e, *failures, self.max_consecutive_failures);
-                
-                // Check if we should use fallback
-                if *failures >= self.max_consecutive_failures && self.config.enable_fallback_mode {
-                    self.use_fallback_reading()
-                } else if let Some(last) = self.last_valid_reading.lock().unwrap().clone() {
-                    // Use last valid reading
-                    WaterLevelReading {
-                        is_valid: false,
-                        error_message: Some(format!("Using last valid reading due to: {}", e)),
-                        ..last
-                    }
-                } else {
-                    // No valid reading available
-                    WaterLevelReading {
-                        tank_id: self.tank_id.clone(),
-                        level_cm: 0.0,
-                        level_percent: 0.0,
-                        timestamp: Local::now().to_rfc3339(),
-                        sensor_type: sensor.get_sensor_type(),
-                        is_valid: false,
-                        error_message: Some(e),
-                    }
+        } else {
+            // No valid reading available
+            WaterLevelReading {
+                tank_id: self.tank_id.clone(),
+                level_cm: 0.0,
+                level_percent: 0.0,
+                timestamp: Local::now().to_rfc3339(),
+                sensor_type,
+                is_valid: false,
+                error_message: Some(e),
+                range_status: RangeStatus::InRange,
+            }
+        }
+    }
+    
+    /// Take a reading and update the cache, level history and alert state.
+    /// Returns the reading and an event if the alert state changed.
+    pub fn sample(&self) -> (WaterLevelReading, Option<LevelEvent>) {
+        let reading = self.get_level();
+        
+        *self.cached_reading.lock().unwrap() = Some((Instant::now(), reading.clone()));
+        
+        {
+            let mut history = self.level_history.lock().unwrap();
+            history.push_back(reading.clone());
+            while history.len() > self.config.level_history_size {
+                history.pop_front();
+            }
+        }
+        
+        // Alerts are only evaluated on trustworthy readings
+        if !reading.is_valid {
+            return (reading, None);
+        }
+        
+        let mut alert = self.alert.lock().unwrap();
+        let next = LevelAlert::evaluate(*alert, reading.level_percent);
+        if next == *alert {
+            return (reading, None);
+        }
+        
+        let event = LevelEvent {
+            tank_id: self.tank_id.clone(),
+            previous: *alert,
+            alert: next,
+            level_percent: reading.level_percent,
+            timestamp: reading.timestamp.clone(),
+        };
+        *alert = next;
+        
+        (reading, Some(event))
+    }
+    
+    /// Most recent sampled reading without touching the sensor. Readings older
+    /// than three sampling intervals are marked invalid.
+    pub fn cached_level(&self) -> Option<WaterLevelReading> {
+        let cached = self.cached_reading.lock().unwrap();
+        cached.as_ref().map(|(taken, reading)| {
+            let max_age = Duration::from_millis(self.config.sampling_interval_ms * 3);
+            if taken.elapsed() > max_age {
+                WaterLevelReading {
+                    is_valid: false,
+                    error_message: Some(format!("Reading is stale ({}s old)", taken.elapsed().as_secs())),
+                    ..reading.clone()
                 }
+            } else {
+                reading.clone()
             }
+        })
+    }
+    
+    /// Sampled readings, oldest first
+    pub fn get_history(&self) -> Vec<WaterLevelReading> {
+        self.level_history.lock().unwrap().iter().cloned().collect()
+    }
+    
+    /// Single calibrated water level measurement in cm, bypassing the moving
+    /// average so short-term changes (e.g. during flow calibration) are visible
+    pub fn measure_level_cm(&self) -> Result<f32, String> {
+        let distance = self.sensor.lock().unwrap().read()?;
+        match self.classify_distance(distance) {
+            RangeStatus::InRange => Ok(self.config.tank_height_cm - distance),
+            status => Err(format!("Distance {:.1}cm is not usable ({:?})", distance, status)),
         }
     }
     
+    /// Current alert state
+    pub fn get_alert(&self) -> LevelAlert {
+        *self.alert.lock().unwrap()
+    }
+    
     /// Use overflow sensor as fallback
     fn use_fallback_reading(&self) -> WaterLevelReading {
         let ovf_value = match self.tank_id.as_str() {
@@ -306,6 +709,7 @@ impl WaterLevelMonitor {
             sensor_type: WaterLevelSensorType::Float,
             is_valid: false,
             error_message: Some("Fallback to overflow sensor".to_string()),
+            range_status: RangeStatus::InRange,
         }
     }
     
@@ -317,16 +721,45 @@ impl WaterLevelMonitor {
         }
     }
     
-    /// Calibrate the sensor
-    pub fn calibrate(&self, actual_level_cm: f32) -> Result<(), String> {
+    /// Apply a previously fitted calibration to the sensor
+    pub fn apply_calibration(&self, calibration: TankCalibration) {
+        self.sensor.lock().unwrap().set_calibration(calibration.offset, calibration.factor);
+        *self.calibration.lock().unwrap() = calibration;
+        self.reading_history.lock().unwrap().clear();
+    }
+    
+    /// Capture a calibration point at the given known water level and refit
+    pub fn calibrate(&self, actual_level_cm: f32) -> Result<TankCalibration, String> {
+        if actual_level_cm < 0.0 || actual_level_cm > self.config.tank_height_cm {
+            return Err(format!("Calibration level {:.1}cm is outside the tank (0-{:.1}cm)", 
+                actual_level_cm, self.config.tank_height_cm));
+        }
+        
         let mut sensor = self.sensor.lock().unwrap();
-        sensor.calibrate(actual_level_cm)?;
+        let raw_distance_cm = sensor.read_raw()?;
+        
+        let mut calibration = self.calibration.lock().unwrap().clone();
+        calibration.add_point(CalibrationPoint {
+            raw_distance_cm,
+            actual_level_cm,
+            timestamp: Local::now().to_rfc3339(),
+        });
+        calibration.fit(self.config.tank_height_cm)?;
+        
+        sensor.set_calibration(calibration.offset, calibration.factor);
+        *self.calibration.lock().unwrap() = calibration.clone();
         
         // Clear history after calibration
         self.reading_history.lock().unwrap().clear();
         
-        log::info!("Water level sensor {} calibrated to {}cm", self.tank_id, actual_level_cm);
-        Ok(())
+        log::info!("Water level sensor {} calibrated at {}cm (offset {:.2}, factor {:.3}, {} points)", 
+            self.tank_id, actual_level_cm, calibration.offset, calibration.factor, calibration.points.len());
+        Ok(calibration)
+    }
+    
+    /// Current calibration for this tank
+    pub fn get_calibration(&self) -> TankCalibration {
+        self.calibration.lock().unwrap().clone()
     }
     
     /// Get sensor statistics
@@ -341,6 +774,9 @@ impl WaterLevelMonitor {
             "consecutive_failures": *failures,
             "max_failures_before_fallback": self.max_consecutive_failures,
             "fallback_enabled": self.config.enable_fallback_mode,
+            "calibration_points": self.calibration.lock().unwrap().points.len(),
+            "alert": format!("{:?}", *self.alert.lock().unwrap()),
+            "history_samples": self.level_history.lock().unwrap().len(),
         })
     }
 }
@@ -349,6 +785,10 @@ impl WaterLevelMonitor {
 pub struct WaterLevelSystem {
     monitors: Arc<Mutex<Vec<WaterLevelMonitor>>>,
     config: WaterLevelConfig,
+    calibration_path: String,
+    /// Stop flag of the running sampling generation, if any
+    sampling: Mutex<Option<Arc<AtomicBool>>>,
+    events: Arc<Mutex<VecDeque<LevelEvent>>>,
 }
 
 impl WaterLevelSystem {
@@ -356,56 +796,69 @@ impl WaterLevelSystem {
         WaterLevelSystem {
             monitors: Arc::new(Mutex::new(Vec::new())),
             config,
+            calibration_path: CALIBRATION_PATH.to_string(),
+            sampling: Mutex::new(None),
+            events: Arc::new(Mutex::new(VecDeque::new())),
         }
     }
     
+    /// Use a different file for persisted calibrations
+    pub fn with_calibration_path(mut self, path: &str) -> Self {
+        self.calibration_path = path.to_string();
+        self
+    }
+    
+    /// Create the configured sensor for a tank
+    fn create_sensor(&self, trigger_pin: u8, echo_pin: Option<u8>) -> Result<Box<dyn WaterLevelSensor>, String> {
+        let sensor: Box<dyn WaterLevelSensor> = match self.config.sensor_type {
+            WaterLevelSensorType::Ultrasonic => {
+                let echo = match echo_pin {
+                    Some(echo) => echo,
+                    None => {
+                        // Without an echo pin, trigger and echo are wired to consecutive pins
+                        log::debug!("No echo pin configured for trigger pin {}, assuming {}", trigger_pin, trigger_pin + 1);
+                        trigger_pin + 1
+                    }
+                };
+                Box::new(UltrasonicSensor::new(trigger_pin, echo, &self.config)?)
+            }
+            WaterLevelSensorType::Mock => {
+                Box::new(MockSensor::new(50.0))
+            }
+            _ => {
+                log::warn!("Sensor type {:?} not yet implemented, using mock", self.config.sensor_type);
+                Box::new(MockSensor::new(50.0))
+            }
+        };
+        Ok(sensor)
+    }
+
     /// Initialize water level monitoring for all tanks
     pub fn init(&mut self) -> Result<(), String> {
         let mut monitors = Vec::new();
+        let calibrations = load_calibrations(&self.calibration_path);
         
-        // Initialize tank 1 sensor
-        if let Some(pin) = self.config.tank1_sensor_pin {
-            let sensor: Box<dyn WaterLevelSensor> = match self.config.sensor_type {
-                WaterLevelSensorType::Ultrasonic => {
-                    // Assuming trigger and echo pins are consecutive
-                    Box::new(UltrasonicSensor::new(pin, pin + 1, &self.config)?)
-                }
-                WaterLevelSensorType::Mock => {
-                    Box::new(MockSensor::new(50.0))
-                }
-                _ => {
-                    log::warn!("Sensor type {:?} not yet implemented, using mock", self.config.sensor_type);
-                    Box::new(MockSensor::new(50.0))
-                }
-            };
-            
-            monitors.push(WaterLevelMonitor::new(
-                "tank1".to_string(),
-                sensor,
-                self.config.clone(),
-            ));
-        }
-        
-        // Initialize tank 2 sensor
-        if let Some(pin) = self.config.tank2_sensor_pin {
-            let sensor: Box<dyn WaterLevelSensor> = match self.config.sensor_type {
-                WaterLevelSensorType::Ultrasonic => {
-                    Box::new(UltrasonicSensor::new(pin, pin + 1, &self.config)?)
-                }
-                WaterLevelSensorType::Mock => {
-                    Box::new(MockSensor::new(50.0))
-                }
-                _ => {
-                    log::warn!("Sensor type {:?} not yet implemented, using mock", self.config.sensor_type);
-                    Box::new(MockSensor::new(50.0))
+        let tanks = [
+            ("tank1", self.config.tank1_sensor_pin, self.config.tank1_echo_pin),
+            ("tank2", self.config.tank2_sensor_pin, self.config.tank2_echo_pin),
+        ];
+        
+        for (tank_id, trigger_pin, echo_pin) in tanks.iter() {
+            if let Some(pin) = trigger_pin {
+                let sensor = self.create_sensor(*pin, *echo_pin)?;
+                let monitor = WaterLevelMonitor::new(
+                    tank_id.to_string(),
+                    sensor,
+                    self.config.clone(),
+                );
+                
+                if let Some(calibration) = calibrations.get(*tank_id) {
+                    log::info!("Loaded water level calibration for {} ({} points)", tank_id, calibration.points.len());
+                    monitor.apply_calibration(calibration.clone());
                 }
-            };
-            
-            monitors.push(WaterLevelMonitor::new(
-                "tank2".to_string(),
-                sensor,
-                self.config.clone(),
-            ));
+                
+                monitors.push(monitor);
+            }
         }
         
         *self.monitors.lock().unwrap() = monitors;
@@ -416,27 +869,172 @@ impl WaterLevelSystem {
         Ok(())
     }
     
-    /// Get water level for specific tank
-    pub fn get_tank_level(&self, tank_id: &str) -> Option<WaterLevelReading> {
-        let monitors = self.monitors.lock().unwrap();
-        monitors.iter()
+    /// Start one background sampling thread per tank
+    pub fn start_sampling(&self) {
+        // Each start gets its own flag so threads from an earlier
+        // generation still see their stop signal after a quick restart
+        let mut sampling = self.sampling.lock().unwrap_or_else(|e| e.into_inner());
+        if sampling.is_some() {
+            return;
+        }
+        let generation = Arc::new(AtomicBool::new(true));
+        *sampling = Some(Arc::clone(&generation));
+        drop(sampling);
+        
+        let monitors = self.monitors.lock().unwrap().clone();
+        for monitor in monitors {
+            let running = Arc::clone(&generation);
+            let events = Arc::clone(&self.events);
+            let interval = Duration::from_millis(self.config.sampling_interval_ms.max(100));
+            
+            thread::Builder::new()
+                .name(format!("water-level-{}", monitor.tank_id))
+                .spawn(move || {
+                    while running.load(Ordering::SeqCst) {
+                        let (_, event) = monitor.sample();
+                        if let Some(event) = event {
+                            Self::raise_event(&events, event);
+                        }
+                        thread::sleep(interval);
+                    }
+                    log::info!("Water level sampling for {} stopped", monitor.tank_id);
+                })
+                .map_err(|e| log::error!("Failed to start water level sampling thread: {}", e))
+                .ok();
+        }
+        
+        log::info!("Water level sampling started every {}ms", self.config.sampling_interval_ms);
+    }
+    
+    /// Signal the sampling threads to stop after their current sample
+    pub fn stop_sampling(&self) {
+        let generation = self.sampling.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(running) = generation {
+            running.store(false, Ordering::SeqCst);
+        }
+    }
+    
+    pub fn is_sampling(&self) -> bool {
+        self.sampling.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+    
+    fn raise_event(events: &Mutex<VecDeque<LevelEvent>>, event: LevelEvent) {
+        match event.alert {
+            LevelAlert::CriticalHigh | LevelAlert::CriticalLow => log::error!(
+                "CRITICAL: {} water level {:.1}% ({:?})", event.tank_id, event.level_percent, event.alert),
+            LevelAlert::WarningHigh => log::warn!(
+                "WARNING: {} water level {:.1}% ({:?})", event.tank_id, event.level_percent, event.alert),
+            LevelAlert::Normal => log::info!(
+                "{} water level back to normal at {:.1}%", event.tank_id, event.level_percent),
+        }
+        
+        // Alert state file for the LCD/web UI, like the overflow sensors
+        let filename = format!("/opt/aog/sensors/{}_level_alert", event.tank_id);
+        if let Ok(mut file) = fs::File::create(&filename) {
+            let _ = write!(file, "{:?}", event.alert);
+        }
+        
+        let mut events = events.lock().unwrap();
+        events.push_back(event);
+        while events.len() > MAX_LEVEL_EVENTS {
+            events.pop_front();
+        }
+    }
+    
+    /// Water level configuration the system was created with
+    pub fn config(&self) -> &WaterLevelConfig {
+        &self.config
+    }
+    
+    /// Handle to a tank's monitor, usable without holding the system lock
+    pub fn monitor(&self, tank_id: &str) -> Option<WaterLevelMonitor> {
+        self.find_monitor(tank_id)
+    }
+    
+    fn find_monitor(&self, tank_id: &str) -> Option<WaterLevelMonitor> {
+        self.monitors.lock().unwrap().iter()
             .find(|m| m.tank_id == tank_id)
-            .map(|m| m.get_level())
+            .cloned()
+    }
+    
+    /// Get water level for specific tank from the sampling threads' cache.
+    /// Never touches the sensor, so it is safe to call while holding the
+    /// system lock; before the first sample the reading is marked invalid.
+    pub fn get_tank_level(&self, tank_id: &str) -> Option<WaterLevelReading> {
+        let monitor = self.find_monitor(tank_id)?;
+        Some(monitor.cached_level().unwrap_or_else(|| WaterLevelReading {
+            tank_id: tank_id.to_string(),
+            level_cm: 0.0,
+            level_percent: 0.0,
+            timestamp: Local::now().to_rfc3339(),
+            sensor_type: self.config.sensor_type.clone(),
+            is_valid: false,
+            error_message: Some("No sampled reading yet".to_string()),
+            range_status: RangeStatus::InRange,
+        }))
     }
     
     /// Get all tank levels
     pub fn get_all_levels(&self) -> Vec<WaterLevelReading> {
+        let tank_ids: Vec<String> = self.monitors.lock().unwrap().iter()
+            .map(|m| m.tank_id.clone())
+            .collect();
+        tank_ids.iter().filter_map(|id| self.get_tank_level(id)).collect()
+    }
+    
+    /// Sampled level history for a tank
+    pub fn get_history(&self, tank_id: &str) -> Option<Vec<WaterLevelReading>> {
+        self.find_monitor(tank_id).map(|m| m.get_history())
+    }
+    
+    /// Recent alert state changes, oldest first
+    pub fn get_events(&self) -> Vec<LevelEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+    
+    /// Calibrate specific tank sensor and persist the fitted calibration
+    pub fn calibrate_tank(&self, tank_id: &str, actual_level_cm: f32) -> Result<TankCalibration, String> {
         let monitors = self.monitors.lock().unwrap();
-        monitors.iter().map(|m| m.get_level()).collect()
+        let calibration = monitors.iter()
+            .find(|m| m.tank_id == tank_id)
+            .ok_or_else(|| format!("Tank {} not found", tank_id))?
+            .calibrate(actual_level_cm)?;
+        
+        self.save_calibrations(&monitors)?;
+        Ok(calibration)
+    }
+    
+    /// Calibrate a tank using `empty`, `full` or a level in cm
+    pub fn calibrate_tank_target(&self, tank_id: &str, target: &str) -> Result<TankCalibration, String> {
+        let actual_level_cm = parse_calibration_target(target, &self.config)?;
+        self.calibrate_tank(tank_id, actual_level_cm)
     }
     
-    /// Calibrate specific tank sensor
-    pub fn calibrate_tank(&self, tank_id: &str, actual_level_cm: f32) -> Result<(), String> {
+    /// Discard all calibration points for a tank
+    pub fn reset_calibration(&self, tank_id: &str) -> Result<(), String> {
         let monitors = self.monitors.lock().unwrap();
         monitors.iter()
             .find(|m| m.tank_id == tank_id)
             .ok_or_else(|| format!("Tank {} not found", tank_id))?
-            .calibrate(actual_level_cm)
+            .apply_calibration(TankCalibration::default());
+        
+        log::info!("Water level calibration for {} reset", tank_id);
+        self.save_calibrations(&monitors)
+    }
+    
+    /// Calibrations for all tanks
+    pub fn get_calibrations(&self) -> HashMap<String, TankCalibration> {
+        let monitors = self.monitors.lock().unwrap();
+        monitors.iter()
+            .map(|m| (m.tank_id.clone(), m.get_calibration()))
+            .collect()
+    }
+    
+    fn save_calibrations(&self, monitors: &[WaterLevelMonitor]) -> Result<(), String> {
+        let calibrations: HashMap<String, TankCalibration> = monitors.iter()
+            .map(|m| (m.tank_id.clone(), m.get_calibration()))
+            .collect();
+        save_calibrations(&self.calibration_path, &calibrations)
     }
     
     /// Get system statistics
@@ -452,6 +1050,13 @@ impl WaterLevelSystem {
                 "max_fill_level_cm": self.config.max_fill_level_cm,
                 "min_level_cm": self.config.min_level_cm,
                 "moving_average_samples": self.config.moving_average_samples,
+                "sampling_interval_ms": self.config.sampling_interval_ms,
+                "sampling": self.is_sampling(),
+                "ultrasonic_samples": self.config.ultrasonic_samples,
+                "outlier_threshold_cm": self.config.outlier_threshold_cm,
+                "temperature_compensation": self.config.temperature_compensation,
+                "dead_zone_cm": self.config.dead_zone_cm,
+                "max_range_cm": self.config.max_range_cm,
             }
         })
     }
@@ -466,10 +1071,58 @@ lazy_static::lazy_static! {
 pub fn init_water_level_system(config: WaterLevelConfig) -> Result<(), String> {
     let mut system = WaterLevelSystem::new(config);
     system.init()?;
-    *WATER_LEVEL_SYSTEM.lock().unwrap() = Some(system);
+    system.start_sampling();
+    if let Some(old) = WATER_LEVEL_SYSTEM.lock().unwrap().replace(system) {
+        old.stop_sampling();
+    }
     Ok(())
 }
 
+/// Convert a calibration target (`empty`, `full` or a level in cm) to a water level in cm.
+/// `full` is the configured maximum fill level.
+pub fn parse_calibration_target(target: &str, config: &WaterLevelConfig) -> Result<f32, String> {
+    match target.trim().to_lowercase().as_str() {
+        "empty" => Ok(0.0),
+        "full" => Ok(config.max_fill_level_cm),
+        other => other.trim_end_matches("cm").parse::<f32>()
+            .map_err(|_| format!("Invalid calibration target '{}', expected empty, full or a level in cm", target)),
+    }
+}
+
+/// Handle `water` console commands and return the output
+pub fn run_command(command: &str) -> String {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let guard = WATER_LEVEL_SYSTEM.lock().unwrap();
+    let system = match guard.as_ref() {
+        Some(system) => system,
+        None => return "Water level system is not initialized".to_string(),
+    };
+    
+    match parts.as_slice() {
+        ["water", "calibrate", tank, "reset"] => match system.reset_calibration(tank) {
+            Ok(()) => format!("Calibration for {} reset", tank),
+            Err(e) => format!("Calibration reset failed: {}", e),
+        },
+        ["water", "calibrate", tank, target] => match system.calibrate_tank_target(tank, target) {
+            Ok(cal) => format!("{} calibrated: offset {:.2}cm, factor {:.3} ({} points)", 
+                tank, cal.offset, cal.factor, cal.points.len()),
+            Err(e) => format!("Calibration failed: {}", e),
+        },
+        ["water", "calibration"] => {
+            serde_json::to_string_pretty(&system.get_calibrations()).unwrap_or_default()
+        }
+        ["water", "status"] => {
+            let mut output = String::new();
+            for reading in system.get_all_levels() {
+                output.push_str(&format!("{}: {:.1}cm ({:.1}%){}\n", reading.tank_id, reading.level_cm, 
+                    reading.level_percent, if reading.is_valid { "" } else { " [invalid]" }));
+            }
+            output
+        }
+        _ => "Usage: water status | water calibration | water calibrate <tank> empty|full|<cm>|reset".to_string(),
+    }
+}
+
 /// Get water level for a specific tank (percentage)
 pub fn get_water_level_percent(tank_id: &str) -> f32 {
     if let Some(system) = WATER_LEVEL_SYSTEM.lock().unwrap().as_ref() {
@@ -520,8 +1173,10 @@ mod tests {
     
     #[test]
     fn test_moving_average() {
-        let mut config = WaterLevelConfig::default();
-        config.moving_average_samples = 3;
+        let config = WaterLevelConfig {
+            moving_average_samples: 3,
+            ..Default::default()
+        };
         
         let sensor = Box::new(MockSensor::new(30.0));
         let monitor = WaterLevelMonitor::new("test_tank".to_string(), sensor, config);
@@ -552,8 +1207,10 @@ mod tests {
     
     #[test]
     fn test_water_level_system() {
-        let mut config = WaterLevelConfig::default();
-        config.sensor_type = WaterLevelSensorType::Mock;
+        let config = WaterLevelConfig {
+            sensor_type: WaterLevelSensorType::Mock,
+            ..Default::default()
+        };
         
         let mut system = WaterLevelSystem::new(config);
         assert!(system.init().is_ok());
@@ -561,4 +1218,183 @@ mod tests {
         let levels = system.get_all_levels();
         assert!(!levels.is_empty());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_speed_of_sound() {
+        assert!((speed_of_sound(0.0) - 331.3).abs() < 0.01);
+        assert!((speed_of_sound(20.0) - 343.42).abs() < 0.01);
+    }
+    
+    #[test]
+    fn test_filter_samples_rejects_outliers() {
+        let samples = [30.0, 30.4, 29.8, 85.0, 30.1];
+        let filtered = filter_samples(&samples, 2.0).unwrap();
+        assert!((filtered - 30.05).abs() < 0.01);
+        
+        // Too many disagreeing samples
+        assert!(filter_samples(&[10.0, 50.0, 90.0], 2.0).is_none());
+        assert!(filter_samples(&[], 2.0).is_none());
+    }
+    
+    #[test]
+    fn test_dead_zone_and_out_of_range() {
+        let config = WaterLevelConfig {
+            tank_height_cm: 100.0,
+            dead_zone_cm: 20.0,
+            ..Default::default()
+        };
+        let sensor = Box::new(MockSensor::new(1.0));
+        let monitor = WaterLevelMonitor::new("test_tank".to_string(), sensor, config.clone());
+        
+        let reading = monitor.get_level();
+        assert_eq!(reading.range_status, RangeStatus::DeadZone);
+        assert!(reading.is_valid);
+        assert_eq!(reading.level_cm, config.tank_height_cm - config.dead_zone_cm);
+        // A 20cm dead zone in a 100cm tank must not read as a safe 80%
+        assert!(reading.level_percent >= CRITICAL_HIGH_LEVEL);
+        assert!(reading.level_percent > WARNING_HIGH_LEVEL);
+        
+        let sensor = Box::new(MockSensor::new(config.max_range_cm + 50.0));
+        let monitor = WaterLevelMonitor::new("test_tank".to_string(), sensor, config);
+        
+        let reading = monitor.get_level();
+        assert_eq!(reading.range_status, RangeStatus::OutOfRange);
+        assert!(!reading.is_valid);
+    }
+    
+    #[test]
+    fn test_two_point_calibration_fit() {
+        // True distance = 0.95 * raw + 2cm
+        let mut cal = TankCalibration::default();
+        for (raw, level) in [(98.0 / 0.95, 0.0), (8.0 / 0.95, 90.0)].iter() {
+            cal.add_point(CalibrationPoint {
+                raw_distance_cm: *raw,
+                actual_level_cm: *level,
+                timestamp: String::new(),
+            });
+        }
+        
+        assert!(cal.fit(100.0).is_ok());
+        assert!((cal.factor - 0.95).abs() < 0.001);
+        let corrected = (50.0 / 0.95 + cal.offset) * cal.factor;
+        assert!((corrected - 52.0).abs() < 0.01);
+    }
+    
+    #[test]
+    fn test_monitor_calibration_applies_to_readings() {
+        let config = WaterLevelConfig::default();
+        let sensor = Box::new(MockSensor::new(32.0));
+        let monitor = WaterLevelMonitor::new("test_tank".to_string(), sensor, config.clone());
+        
+        // Water is actually at 70cm, sensor reads 2cm long
+        let cal = monitor.calibrate(70.0).unwrap();
+        assert!((cal.offset + 2.0).abs() < 0.01);
+        assert!((monitor.get_level().level_cm - 70.0).abs() < 0.01);
+        
+        assert!(monitor.calibrate(config.tank_height_cm + 10.0).is_err());
+    }
+    
+    #[test]
+    fn test_parse_calibration_target() {
+        let config = WaterLevelConfig::default();
+        assert_eq!(parse_calibration_target("empty", &config).unwrap(), 0.0);
+        assert_eq!(parse_calibration_target("full", &config).unwrap(), config.max_fill_level_cm);
+        assert_eq!(parse_calibration_target("42.5cm", &config).unwrap(), 42.5);
+        assert!(parse_calibration_target("half", &config).is_err());
+    }
+    
+    #[test]
+    fn test_calibration_persistence() {
+        let path = std::env::temp_dir().join("aog_water_cal_test.json");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        
+        let config = WaterLevelConfig {
+            sensor_type: WaterLevelSensorType::Mock,
+            ..Default::default()
+        };
+        
+        let mut system = WaterLevelSystem::new(config.clone()).with_calibration_path(path);
+        system.init().unwrap();
+        system.calibrate_tank("tank1", 45.0).unwrap();
+        
+        let mut reloaded = WaterLevelSystem::new(config).with_calibration_path(path);
+        reloaded.init().unwrap();
+        let calibrations = reloaded.get_calibrations();
+        assert_eq!(calibrations["tank1"].points.len(), 1);
+        assert!((calibrations["tank1"].offset - 5.0).abs() < 0.01);
+        
+        let _ = fs::remove_file(path);
+    }
+    
+    #[test]
+    fn test_level_alert_hysteresis() {
+        assert_eq!(LevelAlert::evaluate(LevelAlert::Normal, 50.0), LevelAlert::Normal);
+        assert_eq!(LevelAlert::evaluate(LevelAlert::Normal, WARNING_HIGH_LEVEL), LevelAlert::WarningHigh);
+        assert_eq!(LevelAlert::evaluate(LevelAlert::WarningHigh, CRITICAL_HIGH_LEVEL + 1.0), LevelAlert::CriticalHigh);
+        
+        // Hovering just under the threshold keeps the alert
+        assert_eq!(LevelAlert::evaluate(LevelAlert::CriticalHigh, CRITICAL_HIGH_LEVEL - 1.0), LevelAlert::CriticalHigh);
+        assert_eq!(LevelAlert::evaluate(LevelAlert::WarningHigh, WARNING_HIGH_LEVEL - 1.0), LevelAlert::WarningHigh);
+        assert_eq!(LevelAlert::evaluate(LevelAlert::WarningHigh, WARNING_HIGH_LEVEL - 5.0), LevelAlert::Normal);
+        
+        assert_eq!(LevelAlert::evaluate(LevelAlert::Normal, CRITICAL_LOW_LEVEL), LevelAlert::CriticalLow);
+        assert_eq!(LevelAlert::evaluate(LevelAlert::CriticalLow, CRITICAL_LOW_LEVEL + 1.0), LevelAlert::CriticalLow);
+        assert_eq!(LevelAlert::evaluate(LevelAlert::CriticalLow, 20.0), LevelAlert::Normal);
+    }
+    
+    #[test]
+    fn test_sample_updates_cache_history_and_alerts() {
+        let config = WaterLevelConfig {
+            moving_average_samples: 1,
+            ..Default::default()
+        };
+        let sensor = Box::new(MockSensor::new(50.0));
+        let monitor = WaterLevelMonitor::new("test_tank".to_string(), sensor, config);
+        
+        assert!(monitor.cached_level().is_none());
+        let (_, event) = monitor.sample();
+        assert!(event.is_none());
+        
+        if let Some(mock_sensor) = monitor.sensor.lock().unwrap().as_any_mut().downcast_mut::<MockSensor>() {
+            mock_sensor.set_level(4.0);
+        }
+        
+        let (reading, event) = monitor.sample();
+        let event = event.expect("crossing the critical level should raise an event");
+        assert_eq!(event.alert, LevelAlert::CriticalHigh);
+        assert_eq!(monitor.get_alert(), LevelAlert::CriticalHigh);
+        assert_eq!(monitor.cached_level().unwrap().level_percent, reading.level_percent);
+        assert_eq!(monitor.get_history().len(), 2);
+    }
+    
+    #[test]
+    fn test_background_sampling() {
+        let config = WaterLevelConfig {
+            sensor_type: WaterLevelSensorType::Mock,
+            sampling_interval_ms: 100,
+            ..Default::default()
+        };
+        
+        let path = std::env::temp_dir().join("aog_water_sampling_test.json");
+        let mut system = WaterLevelSystem::new(config).with_calibration_path(path.to_str().unwrap());
+        system.init().unwrap();
+        system.start_sampling();
+        
+        let started = Instant::now();
+        while system.get_history("tank1").unwrap().len() < 2 && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(system.get_history("tank1").unwrap().len() >= 2);
+        assert!(system.get_tank_level("tank1").unwrap().is_valid);
+        
+        system.stop_sampling();
+        assert!(!system.is_sampling());
+        
+        // A quick restart must not revive the previous generation
+        system.start_sampling();
+        assert!(system.is_sampling());
+        system.stop_sampling();
+        assert!(!system.is_sampling());
+    }
+}
@@ -67,12 +67,30 @@ pub struct Config {
     pub pump_config: Option<PumpConfig>,  // New pump configuration
     pub ph_config: Option<PhConfig>,  // pH sensor configuration
     pub water_level_config: Option<WaterLevelConfig>,  // Water level sensor configuration
+    pub emergency_stop_config: Option<EmergencyStopConfig>,  // Emergency stop button and trip settings
+    pub relay_boards: Option<Vec<RelayBoardConfig>>,  // Qwiic relay boards and their named channels (default: quad board at 0x25)
+    pub particulate_config: Option<ParticulateConfig>,  // SDS011 duty cycle and humidity correction
+    pub sensor_fusion_config: Option<SensorFusionConfig>,  // Calibration, weighting and disagreement limits for redundant sensors
+    pub plausibility_rules: Option<Vec<PlausibilityRule>>,  // Per-metric sanity limits, replacing the built-in rule for that metric
+    pub automation_config: Option<AutomationConfig>,  // Rules linking sensor conditions to actuator, pump, alert and schedule actions
+    pub hvac_config: Option<HvacConfig>,  // Climate control outputs, setpoints and bands for the HVAC kit
+    pub pid_loops: Option<Vec<PidLoopConfig>>,  // Proportional control loops, e.g. pH dosing, heating or CO2
+    pub pwm_channels: Option<Vec<PwmChannelConfig>>,  // Named PWM outputs for dimmable lights and variable-speed pumps and fans
+    pub gpio_outputs: Option<Vec<GpioOutputConfig>>,  // Named on/off GPIO outputs with photo cycle and interlock rules
+    pub gpio_inputs: Option<Vec<GpioInputConfig>>,  // Switches and interlocks watched with edge interrupts
     pub https_bind_address: Option<String>,  // HTTPS server bind address (default: 127.0.0.1)
     pub https_bind_port: Option<u16>,  // HTTPS server port (default: 8443)
     pub command_api_bind_address: Option<String>,  // Command API bind address (default: 127.0.0.1)
     pub command_api_bind_port: Option<u16>,  // Command API port (default: 9443)
     pub command_api_token: Option<String>,  // API token for command API authentication
 }
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Config {
     pub fn new() -> Config {
         let sensor_logs :Vec<SensorLog> = Vec::new();
@@ -114,6 +132,17 @@ impl Config {
             pump_config: None,
             ph_config: None, 
             water_level_config: None,
+            emergency_stop_config: None,
+            relay_boards: None,
+            particulate_config: None,
+            sensor_fusion_config: None,
+            plausibility_rules: None,
+            automation_config: None,
+            hvac_config: None,
+            pid_loops: None,
+            pwm_channels: None,
+            gpio_outputs: None,
+            gpio_inputs: None,
             power_type: "".to_string(), 
             tank_one_to_two_pump_pin: 17, 
             uv_light_pin: 27, 
@@ -133,7 +162,7 @@ impl Config {
         std::fs::write("/opt/aog/data.json", &j)
             .map_err(|e| format!("Failed to write data.json: {}", e))?;
 
-        if self.sensor_logs.len() > 0 {
+        if !self.sensor_logs.is_empty() {
             if let Err(e) = std::fs::File::create("/opt/aog/data.bak.json") {
                 log::warn!("Failed to create backup file: {}", e);
             } else {
@@ -163,22 +192,22 @@ impl Config {
                 let v: Result<Config, _> = serde_json::from_str(&save_data);
                 match v {
                     Ok(v2) => {
-                        return Ok(v2);
+                        Ok(v2)
                     },
                     Err(e) => {
-                        log::error!("{}", format!("Unable to parse save file: {}", e));
+                        log::error!("Unable to parse save file: {}", e);
                         
                         if retries < 10 {
                             std::fs::copy("/opt/aog/data.bak.json", "/opt/aog/data.json")?;
                             std::thread::sleep(std::time::Duration::from_secs(2));
-                            return Self::load(retries + 1);
+                            Self::load(retries + 1)
                         } else {
                             log::warn!("Unable to parse save file after 10 attempts....creating new save file.");
                             let new_c = Config::new();
                             if let Err(e) = new_c.save() {
                                 log::warn!("Failed to save new config: {}", e);
                             }
-                            return Ok(new_c);
+                            Ok(new_c)
                         }
                  
                     }
@@ -186,16 +215,18 @@ impl Config {
                 
             },
             Err(e) => {
-                log::error!("{}", format!("Unable to read save file: {}", e));
+                log::error!("Unable to read save file: {}", e);
                 if retries < 10 {
                     std::fs::copy("/opt/aog/data.bak.json", "/opt/aog/data.json")?;
                     std::thread::sleep(std::time::Duration::from_secs(2));
-                    return Self::load(retries + 1);
+                    Self::load(retries + 1)
                 } else {
                     log::warn!("Unable to read save file after 10 attempts....creating new save file.");
                     let new_c = Config::new();
-                    new_c.save();
-                    return Ok(new_c);
+                    if let Err(e) = new_c.save() {
+                        log::warn!("Failed to save new config: {}", e);
+                    }
+                    Ok(new_c)
                 }
             }
         }
@@ -211,6 +242,36 @@ pub struct SensorKitConfig {
     pub enable_dht11: bool,
     pub enable_analog_co2: bool,
     pub enable_ccs811: bool, 
+    #[serde(default)]
+    pub dht_gpio_pin: Option<u8>,  // BCM pin of a DHT wired to the Pi, read natively instead of through the Arduino kit
+    #[serde(default)]
+    pub dht_model: DhtModel,  // DHT11 or DHT22 on dht_gpio_pin
+    #[serde(default)]
+    pub ccs811_i2c_address: Option<u8>,  // CCS811 on the Pi's I2C bus (0x5A or 0x5B)
+    #[serde(default)]
+    pub co2_sensor: Option<Co2SensorModel>,  // Sensirion CO2 sensor on the Pi's I2C bus
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum DhtModel {
+    #[default]
+    Dht11,
+    Dht22,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Co2SensorModel {
+    Scd30,
+    Scd4x,
+}
+
+impl Co2SensorModel {
+    pub fn i2c_address(&self) -> u16 {
+        match self {
+            Co2SensorModel::Scd30 => 0x61,
+            Co2SensorModel::Scd4x => 0x62,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -220,8 +281,102 @@ pub struct PumpConfig {
     pub photo_cycle_start_hour: u8,  // Hour to start (0-23)
     pub photo_cycle_end_hour: u8,  // Hour to end (0-23)
     pub safety_gpio_pin: Option<u8>,  // Safety GPIO pin for external switches
-    pub pump_runtime_limit_seconds: u64,  // Maximum runtime in seconds (safety)
-    pub pump_cooldown_seconds: u64,  // Cooldown period between runs
+    #[serde(default)]
+    pub pump_runtime_limit_seconds: Option<u64>,  // Maximum runtime in seconds for every pump (None = per-type default)
+    #[serde(default)]
+    pub pump_cooldown_seconds: Option<u64>,  // Cooldown between runs for every pump (None = per-type default)
+    #[serde(default)]
+    pub pumps: Vec<PumpDefinition>,  // Named pumps with their outputs and safety profiles
+}
+
+/// A named pump, where it is wired and any limits that differ from its type's defaults
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PumpDefinition {
+    pub id: String,  // e.g. "fill_pump", as used by the safety monitor and pump jobs
+    pub pump_type: aog::pump_safety::PumpType,
+    pub output: PumpOutput,
+    #[serde(default)]
+    pub safety: PumpSafetyConfig,  // Overrides for the pump's safety profile
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PumpOutput {
+    Relay {
+        relay_id: u16,
+        #[serde(default)]
+        board: Option<u16>,  // I2C address of the relay board (default: first configured board)
+    },  // Qwiic relay channel
+    Gpio { pin: u8 },  // GPIO pin (active low)
+}
+
+/// A Qwiic relay board and the names given to its channels
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelayBoardConfig {
+    pub address: u16,  // I2C address, e.g. 0x25 (37)
+    pub board_type: RelayBoardType,
+    #[serde(default)]
+    pub channels: Vec<RelayChannelConfig>,  // Named channels; unnamed channels are still switched off on a trip
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RelayBoardType {
+    Single,  // Qwiic Single Relay
+    Dual,  // Qwiic Dual Solid State Relay
+    QuadSolidState,  // Qwiic Quad Solid State Relay
+    #[default]
+    QuadMechanical,  // Qwiic Quad Relay
+}
+
+impl RelayBoardType {
+    pub fn channel_count(&self) -> u16 {
+        match self {
+            RelayBoardType::Single => 1,
+            RelayBoardType::Dual => 2,
+            RelayBoardType::QuadSolidState | RelayBoardType::QuadMechanical => 4,
+        }
+    }
+
+    /// Mechanical contacts need time to settle and wear with every switch
+    pub fn is_mechanical(&self) -> bool {
+        matches!(self, RelayBoardType::Single | RelayBoardType::QuadMechanical)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RelayChannelConfig {
+    pub relay_id: u16,  // Channel on the board, starting at 1
+    pub name: String,  // Logical name used by the console and API, e.g. "fill_pump"
+    #[serde(default)]
+    pub role: RelayRole,
+}
+
+/// What a relay channel drives; pump jobs and legacy code find channels by role
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RelayRole {
+    GrowLight,
+    AirCirculation,
+    FillPump,
+    DrainPump,
+    AuxTankPump,
+    #[default]
+    Other,
+}
+
+/// Safety limits for a single pump; unset values fall back to the pump type's defaults
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PumpSafetyConfig {
+    #[serde(default)]
+    pub max_runtime_seconds: Option<u64>,  // Longest a single run may last
+    #[serde(default)]
+    pub cooldown_seconds: Option<u64>,  // Minimum rest between runs
+    #[serde(default)]
+    pub max_oscillation_cycles: Option<u32>,  // Oscillation cycles allowed per run
+    #[serde(default)]
+    pub oscillation_speed_min_ms: Option<u64>,  // Fastest allowed oscillation period
+    #[serde(default)]
+    pub oscillation_speed_max_ms: Option<u64>,  // Slowest allowed oscillation period
+    #[serde(default)]
+    pub maintenance_interval_hours: Option<u64>,  // Runtime hours before service is due
 }
 
 impl Default for PumpConfig {
@@ -232,8 +387,9 @@ impl Default for PumpConfig {
             photo_cycle_start_hour: 6,
             photo_cycle_end_hour: 24,
             safety_gpio_pin: None,
-            pump_runtime_limit_seconds: 300,  // 5 minutes default
-            pump_cooldown_seconds: 60,  // 1 minute cooldown
+            pump_runtime_limit_seconds: None,  // Use the limit for each pump type
+            pump_cooldown_seconds: None,  // Use the cooldown for each pump type
+            pumps: Vec::new(),
         }
     }
 }
@@ -290,8 +446,37 @@ pub struct WaterLevelConfig {
     pub moving_average_samples: usize,  // Number of samples for moving average
     pub sensor_timeout_ms: u64,  // Timeout for sensor readings in milliseconds
     pub enable_fallback_mode: bool,  // Enable fallback to overflow sensors if primary fails
+    #[serde(default)]
+    pub tank1_echo_pin: Option<u8>,  // Echo GPIO pin for tank 1 ultrasonic sensor (trigger is tank1_sensor_pin)
+    #[serde(default)]
+    pub tank2_echo_pin: Option<u8>,  // Echo GPIO pin for tank 2 ultrasonic sensor (trigger is tank2_sensor_pin)
+    #[serde(default = "default_ultrasonic_samples")]
+    pub ultrasonic_samples: usize,  // Echo samples taken per reading (median-of-N)
+    #[serde(default = "default_outlier_threshold_cm")]
+    pub outlier_threshold_cm: f32,  // Samples further than this from the median are rejected
+    #[serde(default = "default_temperature_compensation")]
+    pub temperature_compensation: bool,  // Correct speed of sound using the ambient temperature
+    #[serde(default = "default_dead_zone_cm")]
+    pub dead_zone_cm: f32,  // Blind zone in front of the sensor in cm
+    #[serde(default = "default_max_range_cm")]
+    pub max_range_cm: f32,  // Maximum distance the sensor can measure in cm
+    #[serde(default = "default_sampling_interval_ms")]
+    pub sampling_interval_ms: u64,  // Interval between background level samples in milliseconds
+    #[serde(default = "default_level_history_size")]
+    pub level_history_size: usize,  // Number of sampled readings kept per tank
+    #[serde(default = "default_litres_per_cm")]
+    pub litres_per_cm: f32,  // Tank volume per cm of water height, used for flow calibration
 }
 
+fn default_ultrasonic_samples() -> usize { 5 }
+fn default_outlier_threshold_cm() -> f32 { 2.0 }
+fn default_temperature_compensation() -> bool { true }
+fn default_dead_zone_cm() -> f32 { 3.0 }
+fn default_max_range_cm() -> f32 { 400.0 }
+fn default_sampling_interval_ms() -> u64 { 2000 }
+fn default_level_history_size() -> usize { 720 }
+fn default_litres_per_cm() -> f32 { 1.0 }
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum WaterLevelSensorType {
     Ultrasonic,  // HC-SR04 or similar
@@ -319,19 +504,393 @@ impl Default for WaterLevelConfig {
             moving_average_samples: 5,
             sensor_timeout_ms: 1000,
             enable_fallback_mode: true,
+            tank1_echo_pin: None,  // Echo on the pin after the trigger (24)
+            tank2_echo_pin: None,  // Echo on the pin after the trigger (25)
+            ultrasonic_samples: default_ultrasonic_samples(),
+            outlier_threshold_cm: default_outlier_threshold_cm(),
+            temperature_compensation: default_temperature_compensation(),
+            dead_zone_cm: default_dead_zone_cm(),
+            max_range_cm: default_max_range_cm(),
+            sampling_interval_ms: default_sampling_interval_ms(),
+            level_history_size: default_level_history_size(),
+            litres_per_cm: default_litres_per_cm(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmergencyStopConfig {
+    #[serde(default)]
+    pub button_pin: Option<u8>,  // GPIO pin of the physical E-stop button
+    #[serde(default = "default_button_active_low")]
+    pub button_active_low: bool,  // Button pulls the pin to ground (internal pull-up enabled)
+    #[serde(default = "default_trip_on_overflow")]
+    pub trip_on_overflow: bool,  // Trip on an overflow sensor or critical water level
+    #[serde(default)]
+    pub relay_boards: Vec<u16>,  // Extra relay board addresses switched off on a trip, beyond the configured boards
+}
+
+fn default_button_active_low() -> bool { true }
+fn default_trip_on_overflow() -> bool { true }
+
+impl Default for EmergencyStopConfig {
+    fn default() -> Self {
+        EmergencyStopConfig {
+            button_pin: None,
+            button_active_low: default_button_active_low(),
+            trip_on_overflow: default_trip_on_overflow(),
+            relay_boards: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParticulateConfig {
+    #[serde(default = "default_work_period_minutes")]
+    pub work_period_minutes: u8,  // SDS011 work period: 0 = continuous, 1-30 = one sample every N minutes with the laser off in between
+    #[serde(default = "default_pm_query_interval_seconds")]
+    pub query_interval_seconds: u64,  // How often to ask the sensor for a reading
+    #[serde(default = "default_humidity_correction")]
+    pub humidity_correction: bool,  // Correct for particle growth at high humidity using the DHT humidity
+}
+
+fn default_work_period_minutes() -> u8 { 10 }
+fn default_pm_query_interval_seconds() -> u64 { 10 }
+fn default_humidity_correction() -> bool { true }
+
+impl Default for ParticulateConfig {
+    fn default() -> Self {
+        ParticulateConfig {
+            work_period_minutes: default_work_period_minutes(),
+            query_interval_seconds: default_pm_query_interval_seconds(),
+            humidity_correction: default_humidity_correction(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensorFusionConfig {
+    #[serde(default)]
+    pub sources: Vec<FusionSourceConfig>,  // Per-source calibration offsets and weights
+    #[serde(default = "default_co2_tolerance_ppm")]
+    pub co2_tolerance_ppm: f32,  // Largest expected spread between CO2 sensors
+    #[serde(default = "default_temp_tolerance_c")]
+    pub temp_tolerance_c: f32,  // Largest expected spread between temperature sensors
+    #[serde(default = "default_hum_tolerance_pct")]
+    pub hum_tolerance_pct: f32,  // Largest expected spread between humidity sensors
+    #[serde(default = "default_fusion_stale_after_seconds")]
+    pub stale_after_seconds: u64,  // Readings older than this are left out
+    #[serde(default = "default_stuck_after_readings")]
+    pub stuck_after_readings: u32,  // Unchanged readings, while another source moves, before a source counts as stuck
+    #[serde(default = "default_exclude_after")]
+    pub exclude_after: u32,  // Fusions in a row a source must disagree before it is excluded (and agree before it is readmitted)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FusionSourceConfig {
+    pub metric: String,  // "co2", "temp" or "hum"
+    pub source: String,  // Driver name, e.g. "scd30" or "ccs811"
+    #[serde(default)]
+    pub offset: f32,  // Added to every reading from this source
+    #[serde(default = "default_fusion_weight")]
+    pub weight: f32,  // Relative weight in the fused value
+}
+
+fn default_co2_tolerance_ppm() -> f32 { 150.0 }
+fn default_temp_tolerance_c() -> f32 { 1.5 }
+fn default_hum_tolerance_pct() -> f32 { 7.0 }
+fn default_fusion_stale_after_seconds() -> u64 { 60 }
+fn default_stuck_after_readings() -> u32 { 20 }
+fn default_exclude_after() -> u32 { 3 }
+fn default_fusion_weight() -> f32 { 1.0 }
+
+impl Default for SensorFusionConfig {
+    fn default() -> Self {
+        SensorFusionConfig {
+            sources: Vec::new(),
+            co2_tolerance_ppm: default_co2_tolerance_ppm(),
+            temp_tolerance_c: default_temp_tolerance_c(),
+            hum_tolerance_pct: default_hum_tolerance_pct(),
+            stale_after_seconds: default_fusion_stale_after_seconds(),
+            stuck_after_readings: default_stuck_after_readings(),
+            exclude_after: default_exclude_after(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlausibilityRule {
+    pub metric: String,  // Sensor name, e.g. "temp", "co2" or "pm25"
+    #[serde(default)]
+    pub min: Option<f32>,  // Lowest physically possible value
+    #[serde(default)]
+    pub max: Option<f32>,  // Highest physically possible value
+    #[serde(default)]
+    pub max_rate_per_minute: Option<f32>,  // Largest change per minute from the last accepted value
+    #[serde(default)]
+    pub stuck_samples: Option<u32>,  // Identical samples in a row after which the sensor is considered stuck
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AutomationConfig {
+    #[serde(default)]
+    pub rules: Vec<AutomationRule>,
+    #[serde(default)]
+    pub dry_run: bool,  // Evaluate and log rules without carrying out their actions
+    #[serde(default = "default_rule_interval_seconds")]
+    pub interval_seconds: u64,  // How often the rules are evaluated
+    pub alert_webhook_url: Option<String>,  // Optional webhook for alert actions
+}
+
+fn default_rule_interval_seconds() -> u64 { 10 }
+
+impl Default for AutomationConfig {
+    fn default() -> Self {
+        AutomationConfig {
+            rules: Vec::new(),
+            dry_run: false,
+            interval_seconds: default_rule_interval_seconds(),
+            alert_webhook_url: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AutomationRule {
+    pub name: String,  // Unique rule name
+    #[serde(default = "default_rule_enabled")]
+    pub enabled: bool,
+    pub conditions: Vec<RuleCondition>,  // All must hold for the rule to fire
+    pub actions: Vec<RuleAction>,  // Run when the rule starts to match
+    #[serde(default)]
+    pub release_actions: Vec<RuleAction>,  // Run when the rule stops matching, e.g. turning a fan back off
+    #[serde(default)]
+    pub cooldown_seconds: u64,  // Minimum time between two firings
+    #[serde(default)]
+    pub active_hours: Option<(u8, u8)>,  // Only match between these hours (0-24), wrapping past midnight if start > end
+}
+
+fn default_rule_enabled() -> bool { true }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleComparison {
+    Above,
+    Below,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuleCondition {
+    pub metric: String,  // Sensor name, e.g. "temp", "co2" or "hum"
+    pub comparison: RuleComparison,
+    pub threshold: f32,
+    #[serde(default)]
+    pub for_seconds: u64,  // How long the comparison must hold before the condition is met
+    #[serde(default)]
+    pub hysteresis: f32,  // How far back past the threshold the value must go before the condition clears
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    Actuator { name: String, on: bool },  // Registered relay or GPIO output
+    PumpJob { operation: String, tank: String, target: String, #[serde(default)] destination: Option<String> },
+    Alert { message: String },
+    Schedule { photo_cycle_start: u8, photo_cycle_end: u8 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HvacConfig {
+    #[serde(default)]
+    pub heater: Option<ClimateOutputConfig>,
+    #[serde(default)]
+    pub cooler: Option<ClimateOutputConfig>,
+    #[serde(default)]
+    pub fan: Option<ClimateOutputConfig>,
+    #[serde(default)]
+    pub humidifier: Option<ClimateOutputConfig>,
+    #[serde(default = "default_day_setpoints")]
+    pub day: ClimateSetpoints,  // Used inside the photo cycle
+    #[serde(default = "default_night_setpoints")]
+    pub night: ClimateSetpoints,  // Used outside the photo cycle
+    #[serde(default = "default_temperature_band_c")]
+    pub temperature_band_c: f32,  // Heating starts this far below the setpoint, cooling this far above, each running until the setpoint
+    #[serde(default = "default_humidity_band_pct")]
+    pub humidity_band_pct: f32,  // Humidifying starts this far below the setpoint, venting with the fan this far above
+    #[serde(default)]
+    pub fan_continuous: bool,  // Run the fan all the time, not just with the heater or cooler
+    #[serde(default = "default_heater_requires_fan")]
+    pub heater_requires_fan: bool,  // Only heat once the fan is confirmed running
+    #[serde(default = "default_hvac_interval_seconds")]
+    pub interval_seconds: u64,  // How often the loops run
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ClimateSetpoints {
+    pub temperature_c: f32,
+    pub humidity_pct: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClimateOutputConfig {
+    pub output: PumpOutput,  // Relay channel or GPIO pin driving the device
+    #[serde(default)]
+    pub min_on_seconds: u64,  // Shortest run once switched on
+    #[serde(default)]
+    pub min_off_seconds: u64,  // Shortest rest once switched off, e.g. 180 for a compressor
+}
+
+fn default_day_setpoints() -> ClimateSetpoints { ClimateSetpoints { temperature_c: 25.0, humidity_pct: 60.0 } }
+fn default_night_setpoints() -> ClimateSetpoints { ClimateSetpoints { temperature_c: 20.0, humidity_pct: 65.0 } }
+fn default_temperature_band_c() -> f32 { 1.0 }
+fn default_humidity_band_pct() -> f32 { 5.0 }
+fn default_heater_requires_fan() -> bool { true }
+fn default_hvac_interval_seconds() -> u64 { 10 }
+
+impl Default for HvacConfig {
+    fn default() -> Self {
+        HvacConfig {
+            heater: None,
+            cooler: None,
+            fan: None,
+            humidifier: None,
+            day: default_day_setpoints(),
+            night: default_night_setpoints(),
+            temperature_band_c: default_temperature_band_c(),
+            humidity_band_pct: default_humidity_band_pct(),
+            fan_continuous: false,
+            heater_requires_fan: default_heater_requires_fan(),
+            interval_seconds: default_hvac_interval_seconds(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PidLoopConfig {
+    pub name: String,  // Unique loop name
+    pub metric: String,  // Sensor driving the loop, e.g. "ph", "temp" or "co2"
+    pub setpoint: f32,
+    pub kp: f32,
+    #[serde(default)]
+    pub ki: f32,  // Per second
+    #[serde(default)]
+    pub kd: f32,  // Seconds
+    #[serde(default = "default_pid_sample_seconds")]
+    pub sample_seconds: f32,  // Time between controller updates
+    #[serde(default)]
+    pub reverse: bool,  // Output lowers the metric, e.g. acid dosing or cooling
+    #[serde(default)]
+    pub output_min: f32,  // Percent
+    #[serde(default = "default_pid_output_max")]
+    pub output_max: f32,  // Percent
+    #[serde(default)]
+    pub derivative_filter: f32,  // 0 for none, closer to 1 for heavier smoothing of the derivative
+    pub output: PidOutputConfig,
+    #[serde(default)]
+    pub manual_output: Option<f32>,  // Run in manual at this output instead of automatic
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PidOutputConfig {
+    TimeProportional {
+        output: PumpOutput,
+        #[serde(default = "default_pid_cycle_seconds")]
+        cycle_seconds: f32,  // Output percent is the share of each cycle spent on
+    },  // Relay channel or GPIO switched on for part of each cycle
+    PwmChannel { channel: String },  // Named PWM channel, with its ramp and schedule
+}
+
+fn default_pid_sample_seconds() -> f32 { 5.0 }
+fn default_pid_output_max() -> f32 { 100.0 }
+fn default_pid_cycle_seconds() -> f32 { 60.0 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PwmChannelConfig {
+    pub name: String,  // e.g. "grow_light" or "circulation_pump"
+    pub pin: u8,  // BCM pin; 12 and 18 (PWM0) or 13 and 19 (PWM1) can use hardware PWM, one pin per channel
+    #[serde(default)]
+    pub mode: PwmMode,
+    #[serde(default = "default_pwm_frequency_hz")]
+    pub frequency_hz: f64,  // Software PWM gets less accurate above a few hundred Hz
+    #[serde(default)]
+    pub invert: bool,  // Driver is active low
+    #[serde(default)]
+    pub ramp_seconds: f32,  // Time to ramp from off to full, for soft start and stop
+    #[serde(default)]
+    pub min_percent: f32,  // Lowest duty when on, e.g. where a DC pump stalls
+    #[serde(default)]
+    pub schedule: Option<PwmSchedule>,  // Follow the photo cycle, e.g. for grow lights
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum PwmMode {
+    #[default]
+    Auto,  // Hardware PWM if the pin has it, software otherwise
+    Hardware,
+    Software,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PwmSchedule {
+    #[serde(default)]
+    pub sunrise_minutes: u32,  // Ramp up from the start of the photo cycle
+    #[serde(default)]
+    pub sunset_minutes: u32,  // Ramp down to the end of the photo cycle
+    #[serde(default = "default_pwm_max_percent")]
+    pub max_percent: f32,  // Level between sunrise and sunset
+}
+
+fn default_pwm_frequency_hz() -> f64 { 1000.0 }
+fn default_pwm_max_percent() -> f32 { 100.0 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GpioOutputConfig {
+    pub name: String,  // e.g. "grow_light"
+    pub pin: u8,  // BCM pin, driven active low like the other relay outputs
+    #[serde(default)]
+    pub photo_cycle: bool,  // Only on between photo_cycle_start and photo_cycle_end
+    #[serde(default)]
+    pub safety_gpio_pin: Option<u8>,  // Interlock input that must read high for the output to be on
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GpioInputConfig {
+    pub name: String,  // e.g. "float_switch"
+    pub pin: u8,  // BCM pin
+    #[serde(default)]
+    pub pull: InputPull,
+    #[serde(default = "default_input_debounce_ms")]
+    pub debounce_ms: u64,  // A level must hold this long to count as an edge
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InputPull {
+    #[default]
+    Off,  // Externally pulled, like the float and safety switches
+    Up,
+    Down,
+}
+
+fn default_input_debounce_ms() -> u64 { 50 }
+
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sessions {
     pub sessions: Vec<Session>,
 }
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Sessions {
     pub fn new() -> Sessions {
         let sessions :Vec<Session> = Vec::new();
-        return Sessions{sessions};
+        Sessions{sessions}
     }
     pub fn save(&self) -> Result<(), Box<dyn Error>>{
         std::fs::File::create("/opt/aog/sessions.json")
@@ -341,7 +900,7 @@ impl Sessions {
         std::fs::write("/opt/aog/sessions.json", &j)
             .map_err(|e| format!("Failed to write sessions.json: {}", e))?;
 
-        if self.sessions.len() > 0 {
+        if !self.sessions.is_empty() {
             if let Err(e) = std::fs::File::create("/opt/aog/sessions.bak.json") {
                 log::warn!("Failed to create sessions backup file: {}", e);
             } else {
@@ -371,22 +930,22 @@ impl Sessions {
                 let v: Result<Sessions, _> = serde_json::from_str(&save_data);
                 match v {
                     Ok(v2) => {
-                        return Ok(v2);
+                        Ok(v2)
                     },
                     Err(e) => {
-                        log::error!("{}", format!("Unable to parse save file: {}", e));
+                        log::error!("Unable to parse save file: {}", e);
                         
                         if retries < 10 {
                             std::fs::copy("/opt/aog/sessions.bak.json", "/opt/aog/sessions.json")?;
                             std::thread::sleep(std::time::Duration::from_secs(2));
-                            return Self::load(retries + 1);
+                            Self::load(retries + 1)
                         } else {
                             log::warn!("Unable to parse save file after 10 attempts....creating new save file.");
                             let new_c = Sessions::new();
                             if let Err(e) = new_c.save() {
                                 log::warn!("Failed to save new sessions: {}", e);
                             }
-                            return Ok(new_c);
+                            Ok(new_c)
                         }
                  
                     }
@@ -394,16 +953,18 @@ impl Sessions {
                 
             },
             Err(e) => {
-                log::error!("{}", format!("Unable to read save file: {}", e));
+                log::error!("Unable to read save file: {}", e);
                 if retries < 10 {
                     std::fs::copy("/opt/aog/sessions.bak.json", "/opt/aog/sessions.json")?;
                     std::thread::sleep(std::time::Duration::from_secs(2));
-                    return Self::load(retries + 1);
+                    Self::load(retries + 1)
                 } else {
                     log::warn!("Unable to read save file after 10 attempts....creating new save file.");
                     let new_c = Sessions::new();
-                    new_c.save();
-                    return Ok(new_c);
+                    if let Err(e) = new_c.save() {
+                        log::warn!("Failed to save new sessions: {}", e);
+                    }
+                    Ok(new_c)
                 }
             }
         }
@@ -413,7 +974,9 @@ impl Sessions {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Session {
     pub id: String,
-    pub delta: u8
+    pub delta: u8,
+    #[serde(default)]
+    pub username: String,  // User who authenticated the session
 }
 
 #[cfg(test)]
@@ -445,10 +1008,10 @@ mod tests {
 
     #[test]
     fn test_args_default_values() {
-        let args = Args::parse_from(&["test"]);
+        let args = Args::parse_from(["test"]);
         assert_eq!(args.max_threads, 6);
         assert_eq!(args.port, 8443);
-        assert_eq!(args.encrypt, false);
+        assert!(!args.encrypt);
         assert_eq!(args.key, "aog");
     }
 
@@ -465,8 +1028,8 @@ mod tests {
         assert_eq!(config.tank_one_to_two_pump_pin, 17);
         assert_eq!(config.uv_light_pin, 27);
         assert_eq!(config.air_circulation_pin, 22);
-        assert_eq!(config.is_hvac_kit_installed, false);
-        assert_eq!(config.is_sensor_kit_installed, false);
+        assert!(!config.is_hvac_kit_installed);
+        assert!(!config.is_sensor_kit_installed);
         assert!(config.sensor_logs.is_empty());
         assert!(config.pump_config.is_none());
     }
@@ -533,8 +1096,8 @@ mod tests {
         assert_eq!(sensor_log.id, "test_sensor");
         assert_eq!(sensor_log.timestamp, 1234567890);
         assert_eq!(sensor_log.s1_co2, "500");
-        assert_eq!(sensor_log.is_tank_one_overflowed, true);
-        assert_eq!(sensor_log.is_tank_two_overflowed, false);
+        assert!(sensor_log.is_tank_one_overflowed);
+        assert!(!sensor_log.is_tank_two_overflowed);
     }
 
     #[test]
@@ -552,6 +1115,7 @@ mod tests {
         sessions.sessions.push(Session {
             id: "session1".to_string(),
             delta: 10,
+            username: "admin".to_string(),
         });
         
         sessions.save().expect("Failed to save sessions");
@@ -576,6 +1140,10 @@ mod tests {
             enable_dht11: true,
             enable_analog_co2: true,
             enable_ccs811: false,
+            dht_gpio_pin: None,
+            dht_model: DhtModel::Dht11,
+            ccs811_i2c_address: None,
+            co2_sensor: None,
         };
         
         assert_eq!(sensor_kit.dht11_pin, 7);
@@ -598,6 +1166,10 @@ mod tests {
             enable_dht11: false,
             enable_analog_co2: true,
             enable_ccs811: true,
+            dht_gpio_pin: None,
+            dht_model: DhtModel::Dht11,
+            ccs811_i2c_address: None,
+            co2_sensor: None,
         });
         
         assert!(config.sensor_kit_config.is_some());
@@ -620,13 +1192,13 @@ mod tests {
     #[test]
     fn test_pump_config_default() {
         let pump_config = PumpConfig::default();
-        assert_eq!(pump_config.continuous_mode, false);
-        assert_eq!(pump_config.photo_cycle_enabled, false);
+        assert!(!pump_config.continuous_mode);
+        assert!(!pump_config.photo_cycle_enabled);
         assert_eq!(pump_config.photo_cycle_start_hour, 6);
         assert_eq!(pump_config.photo_cycle_end_hour, 24);
         assert_eq!(pump_config.safety_gpio_pin, None);
-        assert_eq!(pump_config.pump_runtime_limit_seconds, 300);
-        assert_eq!(pump_config.pump_cooldown_seconds, 60);
+        assert_eq!(pump_config.pump_runtime_limit_seconds, None);
+        assert_eq!(pump_config.pump_cooldown_seconds, None);
     }
 
     #[test]
@@ -638,8 +1210,9 @@ mod tests {
             photo_cycle_start_hour: 8,
             photo_cycle_end_hour: 20,
             safety_gpio_pin: Some(25),
-            pump_runtime_limit_seconds: 600,
-            pump_cooldown_seconds: 120,
+            pump_runtime_limit_seconds: Some(600),
+            pump_cooldown_seconds: Some(120),
+            pumps: Vec::new(),
         });
         
         assert!(config.pump_config.is_some());
@@ -649,8 +1222,8 @@ mod tests {
         assert_eq!(pump_config.photo_cycle_start_hour, 8);
         assert_eq!(pump_config.photo_cycle_end_hour, 20);
         assert_eq!(pump_config.safety_gpio_pin, Some(25));
-        assert_eq!(pump_config.pump_runtime_limit_seconds, 600);
-        assert_eq!(pump_config.pump_cooldown_seconds, 120);
+        assert_eq!(pump_config.pump_runtime_limit_seconds, Some(600));
+        assert_eq!(pump_config.pump_cooldown_seconds, Some(120));
     }
 }
 
@@ -25,10 +25,12 @@
 // Relay 3: Fill
 // Relay 4: Aux Tank Pump
 
+// error_chain! checks a cfg that this crate doesn't declare
+#![allow(unexpected_cfgs)]
+
 pub mod setup;
-pub mod aog;
+use ::aog::aog;
 
-use ::aog::error::{AogError, AogResult};
 
 // const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 extern crate qwiic_lcd_rs;
@@ -36,7 +38,6 @@ extern crate qwiic_relay_rs;
 
 use clap::Parser;
 
-use qwiic_relay_rs::*;
 use signal_hook::consts::TERM_SIGNALS;
 use signal_hook::flag;
 use simple_logger::SimpleLogger;
@@ -46,7 +47,7 @@ use std::io::{stdin,stdout,Write};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
-use ::aog::{Config, Session, Sessions};
+use ::aog::Config;
 use std::thread;
 
 use error_chain::error_chain;
@@ -87,8 +88,26 @@ fn main() -> Result<()> {
     let config = Arc::new(Mutex::new(Config::load(0)
         .map_err(|e| format!("Failed to load config: {}", e))?));
 
-    crate::aog::sensors::init();
-    
+    // Apply the configured relay boards, register every relay and GPIO output
+    // and drive them to their safe state before anything else touches the
+    // hardware, and again if we panic
+    if let Some(relay_boards) = config.lock().unwrap().relay_boards.clone() {
+        if let Err(e) = crate::aog::qwiic::configure_boards(relay_boards) {
+            log::error!("Invalid relay board configuration, using the default board: {}", e);
+        }
+    }
+    crate::aog::actuators::init(&config.lock().unwrap());
+    crate::aog::actuators::install_panic_hook();
+
+    crate::aog::sensors::init(&config.lock().unwrap());
+
+    // Apply configured pump definitions and safety profiles
+    if let Some(pump_config) = config.lock().unwrap().pump_config.clone() {
+        if let Err(e) = crate::aog::pump_safety::SAFETY_MONITOR.configure_pumps(&pump_config) {
+            log::error!("Invalid pump configuration, using default safety limits: {}", e);
+        }
+    }
+
     // Initialize water level monitoring system
     if let Some(water_config) = config.lock().unwrap().water_level_config.clone() {
         if let Err(e) = crate::aog::water_level::init_water_level_system(water_config) {
@@ -110,9 +129,34 @@ fn main() -> Result<()> {
         .map_err(|e| format!("Failed to initialize logger: {}", e))?;
 
 
-    // Turn off all relays
-    let qwiic_device = crate::aog::qwiic::QwiicRelayDevice::new(0x25);
-    qwiic_device.test();
+    // Check the relay boards respond
+    for board in crate::aog::qwiic::relay_boards() {
+        crate::aog::qwiic::QwiicRelayDevice::new(board.address).test();
+    }
+
+    // Compare the devices on the I2C bus with the ones the configuration expects
+    crate::aog::i2c::check_inventory(&config.lock().unwrap());
+
+    // Watch the configured switches and interlocks
+    crate::aog::gpio::input::init(&config.lock().unwrap());
+
+    // Register actuators with the emergency stop and start its inputs
+    crate::aog::emergency_stop::init(&config.lock().unwrap());
+
+    // Start the HVAC kit's climate loops
+    crate::aog::climate::init(&config.lock().unwrap());
+
+    // Register the named GPIO outputs and start checking their interlocks
+    crate::aog::gpio::output::init(&config.lock().unwrap());
+
+    // Open the PWM channels before the PID loops that drive them
+    crate::aog::gpio::pwm::init(&config.lock().unwrap());
+
+    // Start the configured PID loops
+    crate::aog::pid::init(&config.lock().unwrap());
+
+    // Start evaluating the automation rules once the outputs are registered
+    crate::aog::rules::init(&config.lock().unwrap());
 
 
 
@@ -132,6 +176,20 @@ fn main() -> Result<()> {
         flag::register(*sig, Arc::clone(&term_now))?;
     }
 
+    // The terminal loop blocks on stdin, so shut down from here on SIGTERM
+    let term_watch = Arc::clone(&term_now);
+    thread::spawn(move || {
+        while !term_watch.load(Ordering::Relaxed) {
+            thread::sleep(std::time::Duration::from_millis(100));
+        }
+        log::info!("Termination signal received, driving actuators to safe state");
+        crate::aog::actuators::shutdown("sigterm");
+        if let Err(e) = aog::instance::release_lock() {
+            log::warn!("Failed to release instance lock: {}", e);
+        }
+        std::process::exit(0);
+    });
+
 
 
     thread::spawn(|| {
@@ -212,9 +270,8 @@ fn main() -> Result<()> {
     println!("Exiting...");
 
     // Cleanup
+    crate::aog::actuators::shutdown("exit");
     // aog::pump::stop(Arc::clone(&pump_thread));
-    // aog::gpio::thread::stop(Arc::clone(&gpio_27_thread));
-    // aog::gpio::thread::stop(Arc::clone(&gpio_22_thread));
     
     // Release instance lock
     if let Err(e) = aog::instance::release_lock() {
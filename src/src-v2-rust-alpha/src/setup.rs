@@ -49,18 +49,18 @@ pub fn install(_args: aog::Args) -> Result<()> {
 
     match crate::aog::tools::mkdir("/opt"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to create /opt directory").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to create /opt directory").into()),
     }
 
     match crate::aog::tools::mkdir("/opt/aog"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to create /opt/aog directory").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to create /opt/aog directory").into()),
     }
 
     // Set secure permissions on the AOG directory
     match crate::aog::tools::fix_permissions("/opt/aog"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to set permissions on /opt/aog").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to set permissions on /opt/aog").into()),
     }
     
     // Set ownership to aog user and group
@@ -75,7 +75,7 @@ pub fn install(_args: aog::Args) -> Result<()> {
             Ok(pwd) => pwd,
             Err(e) => {
                 eprintln!("Failed to generate initial password: {}", e);
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to generate initial password: {}", e)).into());
+                return Err(std::io::Error::other(format!("Failed to generate initial password: {}", e)).into());
             }
         };
         
@@ -96,39 +96,39 @@ pub fn install(_args: aog::Args) -> Result<()> {
             Ok(hash) => config.encrypted_password = hash,
             Err(e) => {
                 eprintln!("Failed to hash password: {}", e);
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to hash password: {}", e)).into());
+                return Err(std::io::Error::other(format!("Failed to hash password: {}", e)).into());
             }
         }
         
         if let Err(e) = config.save() {
             eprintln!("Failed to save initial configuration: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to save config: {}", e)).into());
+            return Err(std::io::Error::other(format!("Failed to save config: {}", e)).into());
         }
     }
 
     match crate::aog::tools::mkdir("/opt/aog/bak"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to chmod /opt/aog/bak").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to chmod /opt/aog/bak").into()),
     }
 
     match crate::aog::tools::mkdir("/opt/aog/sensors"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to mkdir /opt/aog/sensors").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to mkdir /opt/aog/sensors").into()),
     }
 
     match crate::aog::tools::mkdir("/opt/aog/crt"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to mkdir /opt/aog/crt").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to mkdir /opt/aog/crt").into()),
     }
 
     match crate::aog::tools::mkdir("/opt/aog/crt/default"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to mkdir /opt/aog/crt/default").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to mkdir /opt/aog/crt/default").into()),
     }
     
     match crate::aog::tools::mkdir("/opt/aog/dat"){
         Ok(_) => {},
-        Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to mkdir /opt/aog/dat").into()),
+        Err(_) => return Err(std::io::Error::other("Failed to mkdir /opt/aog/dat").into()),
     }
     
 
@@ -139,7 +139,7 @@ pub fn install(_args: aog::Args) -> Result<()> {
     -subj '/CN=localhost' -extensions EXT -config <( \
         printf \"[dn]\nCN=localhost\n[req]\ndistinguished_name = dn\n[EXT]\nsubjectAltName=DNS:localhost\nkeyUsage=digitalSignature\nextendedKeyUsage=serverAuth\")")
     .output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to generate SSL certificate: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to generate SSL certificate: {}", e)))?;
     if openssh.status.success() {
         println!();
     } else {
@@ -151,7 +151,7 @@ pub fn install(_args: aog::Args) -> Result<()> {
     .arg("-c")
     .arg("openssl x509 -outform der -in /opt/aog/crt/default/aog.local.cert -out /opt/aog/crt/default/aog.local.der")
     .output()
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to convert certificate to DER: {}", e)))?;
+    .map_err(|e| std::io::Error::other(format!("Failed to convert certificate to DER: {}", e)))?;
     if openssh_der.status.success() {
         println!();
     } else {
@@ -184,7 +184,7 @@ fn create_aog_user_and_group() -> Result<()> {
         .arg("group")
         .arg("aog")
         .output()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to check group: {}", e)))?;
+        .map_err(|e| std::io::Error::other(format!("Failed to check group: {}", e)))?;
     
     if !group_check.status.success() {
         log::info!("Creating aog group...");
@@ -192,13 +192,12 @@ fn create_aog_user_and_group() -> Result<()> {
             .arg("-r")  // System group
             .arg("aog")
             .output()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create group: {}", e)))?;
+            .map_err(|e| std::io::Error::other(format!("Failed to create group: {}", e)))?;
         
         if !group_add.status.success() {
             let stderr = String::from_utf8_lossy(&group_add.stderr);
             if !stderr.contains("already exists") {
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, 
-                    format!("Failed to create aog group: {}", stderr)).into());
+                return Err(std::io::Error::other(format!("Failed to create aog group: {}", stderr)).into());
             }
         }
     }
@@ -207,7 +206,7 @@ fn create_aog_user_and_group() -> Result<()> {
     let user_check = Command::new("id")
         .arg("aog")
         .output()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to check user: {}", e)))?;
+        .map_err(|e| std::io::Error::other(format!("Failed to check user: {}", e)))?;
     
     if !user_check.status.success() {
         log::info!("Creating aog user...");
@@ -223,13 +222,12 @@ fn create_aog_user_and_group() -> Result<()> {
             .arg("AOG System User")
             .arg("aog")
             .output()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create user: {}", e)))?;
+            .map_err(|e| std::io::Error::other(format!("Failed to create user: {}", e)))?;
         
         if !user_add.status.success() {
             let stderr = String::from_utf8_lossy(&user_add.stderr);
             if !stderr.contains("already exists") {
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, 
-                    format!("Failed to create aog user: {}", stderr)).into());
+                return Err(std::io::Error::other(format!("Failed to create aog user: {}", stderr)).into());
             }
         }
     }
@@ -291,7 +289,7 @@ fn rebuild_www() -> std::io::Result<()> {
     }
 
     extract_zip("/opt/aog/www.zip")
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to extract www.zip: {:?}", e)))?;
+        .map_err(|e| std::io::Error::other(format!("Failed to extract www.zip: {:?}", e)))?;
     Ok(())
 }
 
@@ -315,22 +313,22 @@ pub fn uninstall(){
 fn extract_zip(zip_path: &str) -> Result<i32> {
 
     let fname = std::path::Path::new(zip_path);
-    let file = fs::File::open(&fname)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to open zip file: {}", e)))?;
+    let file = fs::File::open(fname)
+        .map_err(|e| std::io::Error::other(format!("Failed to open zip file: {}", e)))?;
 
     let mut archive = zip::ZipArchive::new(file)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to read zip archive: {}", e)))?;
+        .map_err(|e| std::io::Error::other(format!("Failed to read zip archive: {}", e)))?;
 
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to read file from archive: {}", e)))?;
+            .map_err(|e| std::io::Error::other(format!("Failed to read file from archive: {}", e)))?;
         let outpath_end = match file.enclosed_name() {
             Some(path) => path.to_owned(),
             None => continue,
         };
 
         let out_mend = "/opt/aog/".to_owned() + outpath_end.to_str()
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "Invalid path in archive"))?;
+            .ok_or_else(|| std::io::Error::other("Invalid path in archive"))?;
 
         let outpath = Path::new(&(out_mend));
 
@@ -341,10 +339,10 @@ fn extract_zip(zip_path: &str) -> Result<i32> {
             }
         }
 
-        if (&*file.name()).ends_with('/') {
+        if file.name().ends_with('/') {
             // println!("File {} extracted to \"{}\"", i, outpath.display());
-            fs::create_dir_all(&outpath)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create directory: {}", e)))?;
+            fs::create_dir_all(outpath)
+                .map_err(|e| std::io::Error::other(format!("Failed to create directory: {}", e)))?;
         } else {
             // println!(
             //     "File {} extracted to \"{}\" ({} bytes)",
@@ -354,14 +352,14 @@ fn extract_zip(zip_path: &str) -> Result<i32> {
             // );
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(&p)
-                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create parent directory: {}", e)))?;
+                    fs::create_dir_all(p)
+                        .map_err(|e| std::io::Error::other(format!("Failed to create parent directory: {}", e)))?;
                 }
             }
-            let mut outfile = fs::File::create(&outpath)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create output file: {}", e)))?;
+            let mut outfile = fs::File::create(outpath)
+                .map_err(|e| std::io::Error::other(format!("Failed to create output file: {}", e)))?;
             io::copy(&mut file, &mut outfile)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to copy file content: {}", e)))?;
+                .map_err(|e| std::io::Error::other(format!("Failed to copy file content: {}", e)))?;
         }
 
         // Get and Set permissions
@@ -370,7 +368,7 @@ fn extract_zip(zip_path: &str) -> Result<i32> {
             use std::os::unix::fs::PermissionsExt;
 
             if let Some(mode) = file.unix_mode() {
-                if let Err(e) = fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)) {
+                if let Err(e) = fs::set_permissions(outpath, fs::Permissions::from_mode(mode)) {
                     log::warn!("Failed to set permissions on {}: {}", outpath.display(), e);
                 }
             }
@@ -384,19 +382,19 @@ fn extract_zip(zip_path: &str) -> Result<i32> {
 pub fn install_service(args: aog::Args) -> Result<()> {
 
     // Linux
-    #[cfg(all(target_os = "linux"))] {
+    #[cfg(target_os = "linux")] {
         update_linux_service_file(args.clone());
         match crate::aog::tools::systemctl_reload(){
             Ok(_) => {},
-            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to reload systemctl").into()),
+            Err(_) => return Err(std::io::Error::other("Failed to reload systemctl").into()),
         }
         match crate::aog::tools::systemctl_enable("aog.service"){
             Ok(_) => {},
-            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to enable aog as a service").into()),
+            Err(_) => return Err(std::io::Error::other("Failed to enable aog as a service").into()),
         }
         match crate::aog::tools::systemctl_stop("aog.service"){
             Ok(_) => {},
-            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to stop aog as a service").into()),
+            Err(_) => return Err(std::io::Error::other("Failed to stop aog as a service").into()),
         }
         // Copy Files
         match std::env::current_exe() {
@@ -404,14 +402,14 @@ pub fn install_service(args: aog::Args) -> Result<()> {
                 let current_exe_path = format!("{}", exe_path.display());
                 match crate::aog::tools::cp(current_exe_path.as_str(), "/opt/aog/bin"){
                     Ok(_) => {},
-                    Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to copy aog binary").into()),
+                    Err(_) => return Err(std::io::Error::other("Failed to copy aog binary").into()),
                 }
             },
             Err(e) => log::error!("failed to get current exe path: {e}"),
         };
         match crate::aog::tools::systemctl_start("aog.service"){
             Ok(_) => {},
-            Err(_) => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to start aog as a service").into()),
+            Err(_) => return Err(std::io::Error::other("Failed to start aog as a service").into()),
         }
     }
 
@@ -161,19 +161,18 @@ fn test_invalid_hash_format_handling() {
     
     for invalid_hash in invalid_hashes {
         let result = verify_password("any_password", invalid_hash);
-        // Should either return false or error, but not panic
-        match result {
-            Ok(valid) => assert!(!valid),
-            Err(_) => {} // Expected for invalid format
+        // Should either return false or error (expected for invalid format), but not panic
+        if let Ok(valid) = result {
+            assert!(!valid);
         }
     }
 }
 
 #[cfg(test)]
 mod password_reset_tests {
-    use super::*;
-    use std::fs;
-    use std::path::Path;
+    
+    
+    
     
     #[test]
     #[ignore] // This test requires full application context
@@ -7,8 +7,8 @@
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use aog::error::{AogError, AogResult, recover_mutex_lock, safe_mutex_access};
-use aog::aog::pump_safety::{PumpSafetyMonitor, PumpType};
+use aog::error::{AogError, recover_mutex_lock, safe_mutex_access};
+use aog::aog::pump_safety::{PumpSafetyMonitor, PumpType, MockPumpDriver, FlowCalibrationOptions};
 
 #[test]
 fn test_mutex_poisoning_recovery() {
@@ -225,15 +225,18 @@ fn test_concurrent_pump_operations() {
 fn test_calibration_error_handling() {
     let monitor = PumpSafetyMonitor::new();
     
-    // Calibration should work for idle pump
-    let result = monitor.calibrate_pump("cal_test", PumpType::Fill);
-    assert!(result.is_ok());
+    let mut driver = MockPumpDriver::default();
+    
+    // Calibration needs the water level system to measure flow
+    let result = monitor.calibrate_pump("cal_test", PumpType::Fill, &mut driver, FlowCalibrationOptions::default());
+    assert!(result.is_err());
+    assert!(!driver.running);
     
     // Start the pump
     monitor.register_pump_start("cal_test2".to_string(), PumpType::Fill);
     
     // Calibration should fail for running pump
-    let result = monitor.calibrate_pump("cal_test2", PumpType::Fill);
+    let result = monitor.calibrate_pump("cal_test2", PumpType::Fill, &mut driver, FlowCalibrationOptions::default());
     assert!(result.is_err());
 }
 
@@ -14,11 +14,11 @@ mod instance_tests {
         cleanup_instance_files();
         
         // Test that no instance is detected initially
-        assert!(!aog::instance::check_running_instance());
+        assert!(!aog::aog::instance::check_running_instance());
         
         // Test port checking
-        assert!(aog::instance::check_port_available(9443));
-        assert!(aog::instance::check_port_available(8443));
+        assert!(aog::aog::instance::check_port_available(9443));
+        assert!(aog::aog::instance::check_port_available(8443));
     }
     
     #[test]
@@ -26,19 +26,19 @@ mod instance_tests {
         cleanup_instance_files();
         
         // First acquisition should succeed
-        assert!(aog::instance::acquire_lock().unwrap());
+        assert!(aog::aog::instance::acquire_lock().unwrap());
         
         // Second acquisition should fail
-        assert!(!aog::instance::acquire_lock().unwrap());
+        assert!(!aog::aog::instance::acquire_lock().unwrap());
         
         // Release lock
-        aog::instance::release_lock().unwrap();
+        aog::aog::instance::release_lock().unwrap();
         
         // Now acquisition should succeed again
-        assert!(aog::instance::acquire_lock().unwrap());
+        assert!(aog::aog::instance::acquire_lock().unwrap());
         
         // Clean up
-        aog::instance::release_lock().unwrap();
+        aog::aog::instance::release_lock().unwrap();
     }
     
     #[test]
@@ -46,15 +46,15 @@ mod instance_tests {
         cleanup_instance_files();
         
         // Write PID file
-        aog::instance::write_pid_file().unwrap();
+        aog::aog::instance::write_pid_file().unwrap();
         
         // Read it back
-        let info = aog::instance::read_pid_file().unwrap();
+        let info = aog::aog::instance::read_pid_file().unwrap();
         assert_eq!(info.pid, std::process::id());
         assert_eq!(info.port, 9443);
         
         // Remove PID file
-        aog::instance::remove_pid_file().unwrap();
+        aog::aog::instance::remove_pid_file().unwrap();
         
         // Verify it's gone
         assert!(!Path::new("/opt/aog/aog.pid").exists());
@@ -65,7 +65,7 @@ mod instance_tests {
         // This test simulates command forwarding with retries
         // Note: Requires a mock server or actual running instance
         
-        let result = aog::instance::forward_command_with_retry(
+        let result = aog::aog::instance::forward_command_with_retry(
             "test command",
             None,
         );
@@ -79,13 +79,13 @@ mod instance_tests {
         cleanup_instance_files();
         
         // Acquire lock to simulate running instance
-        aog::instance::acquire_lock().unwrap();
+        aog::aog::instance::acquire_lock().unwrap();
         
         // Without force flag, should detect existing instance
-        assert!(!aog::instance::handle_instance_check(false).unwrap());
+        assert!(!aog::aog::instance::handle_instance_check(false).unwrap());
         
         // With force flag, should bypass check
-        assert!(aog::instance::handle_instance_check(true).unwrap());
+        assert!(aog::aog::instance::handle_instance_check(true).unwrap());
         
         // Clean up
         cleanup_instance_files();
@@ -99,7 +99,7 @@ mod instance_tests {
         fs::create_dir_all("/opt/aog").ok();
         
         // Write a PID file with non-existent process
-        let fake_info = aog::instance::InstanceInfo {
+        let fake_info = aog::aog::instance::InstanceInfo {
             pid: 99999,  // Very unlikely to be a real PID
             port: 9443,
             start_time: chrono::Local::now().to_rfc3339(),
@@ -110,10 +110,10 @@ mod instance_tests {
         fs::write("/opt/aog/aog.lock", "").unwrap();
         
         // Should detect stale lock and clean it up
-        assert!(aog::instance::acquire_lock().unwrap());
+        assert!(aog::aog::instance::acquire_lock().unwrap());
         
         // Clean up
-        aog::instance::release_lock().unwrap();
+        aog::aog::instance::release_lock().unwrap();
     }
     
     #[test]
@@ -122,23 +122,23 @@ mod instance_tests {
         
         // Spawn thread that acquires lock
         let handle = thread::spawn(|| {
-            aog::instance::acquire_lock().unwrap();
+            aog::aog::instance::acquire_lock().unwrap();
             thread::sleep(Duration::from_millis(500));
-            aog::instance::release_lock().unwrap();
+            aog::aog::instance::release_lock().unwrap();
         });
         
         // Give first thread time to acquire lock
         thread::sleep(Duration::from_millis(100));
         
         // Try to acquire lock from main thread - should fail
-        assert!(!aog::instance::acquire_lock().unwrap());
+        assert!(!aog::aog::instance::acquire_lock().unwrap());
         
         // Wait for first thread to release
         handle.join().unwrap();
         
         // Now should succeed
-        assert!(aog::instance::acquire_lock().unwrap());
-        aog::instance::release_lock().unwrap();
+        assert!(aog::aog::instance::acquire_lock().unwrap());
+        aog::aog::instance::release_lock().unwrap();
     }
     
     fn cleanup_instance_files() {
@@ -186,7 +186,9 @@ mod integration_tests {
         // Clean up
         thread::sleep(Duration::from_secs(1));
         first_instance.kill().ok();
+        first_instance.wait().ok();
         forced_instance.kill().ok();
+        forced_instance.wait().ok();
     }
     
     #[test]
@@ -230,5 +232,6 @@ mod integration_tests {
         
         // Clean up
         bg_instance.kill().ok();
+        bg_instance.wait().ok();
     }
 }
\ No newline at end of file
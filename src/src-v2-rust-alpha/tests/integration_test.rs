@@ -1,6 +1,5 @@
-use aog::{Config, SensorLog, Sessions, Session, SensorKitConfig, Args};
+use aog::{Config, SensorLog, Sessions, Session, SensorKitConfig};
 use std::fs;
-use std::path::Path;
 use tempfile::TempDir;
 
 fn setup_test_environment() -> TempDir {
@@ -11,22 +10,6 @@ fn setup_test_environment() -> TempDir {
     temp_dir
 }
 
-fn cleanup_test_files(dir: &Path) {
-    let test_files = [
-        "data.json",
-        "data.bak.json",
-        "sessions.json",
-        "sessions.bak.json",
-    ];
-    
-    for file in test_files.iter() {
-        let file_path = dir.join(file);
-        if file_path.exists() {
-            let _ = fs::remove_file(file_path);
-        }
-    }
-}
-
 #[test]
 fn test_config_lifecycle() {
     let _temp_dir = setup_test_environment();
@@ -78,8 +61,8 @@ fn test_sensor_log_processing() {
     let actual_avg: f64 = sensor_log.avg_co2.parse().unwrap_or(0.0);
     
     assert_eq!(expected_avg, actual_avg);
-    assert_eq!(sensor_log.is_tank_one_overflowed, true);
-    assert_eq!(sensor_log.is_tank_two_overflowed, false);
+    assert!(sensor_log.is_tank_one_overflowed);
+    assert!(!sensor_log.is_tank_two_overflowed);
 }
 
 #[test]
@@ -93,6 +76,7 @@ fn test_sessions_management() {
         sessions.sessions.push(Session {
             id: format!("session_{}", i),
             delta: (i * 10) as u8,
+            username: "admin".to_string(),
         });
     }
     
@@ -113,6 +97,10 @@ fn test_sensor_kit_configuration() {
         enable_dht11: true,
         enable_analog_co2: true,
         enable_ccs811: false,
+        dht_gpio_pin: None,
+        dht_model: aog::DhtModel::Dht11,
+        ccs811_i2c_address: None,
+        co2_sensor: None,
     });
     
     let sensor_kit = config.sensor_kit_config.unwrap();
@@ -147,8 +135,8 @@ fn test_config_with_multiple_sensor_logs() {
     // Verify first and last logs
     assert_eq!(config.sensor_logs[0].s1_co2, "400");
     assert_eq!(config.sensor_logs[9].s1_co2, "490");
-    assert_eq!(config.sensor_logs[0].is_tank_one_overflowed, true);
-    assert_eq!(config.sensor_logs[1].is_tank_one_overflowed, false);
+    assert!(config.sensor_logs[0].is_tank_one_overflowed);
+    assert!(!config.sensor_logs[1].is_tank_one_overflowed);
 }
 
 #[test]
@@ -218,10 +206,11 @@ fn test_session_delta_values() {
     // Test various delta values
     let deltas = vec![0, 1, 10, 50, 100, 255];
     
-    for delta in deltas {
+    for &delta in &deltas {
         sessions.sessions.push(Session {
             id: format!("delta_{}", delta),
             delta,
+            username: "admin".to_string(),
         });
     }
     
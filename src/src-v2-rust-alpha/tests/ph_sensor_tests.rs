@@ -2,25 +2,12 @@
 
 use aog::aog::ph_sensor::*;
 use std::fs;
-use std::path::Path;
 use tempfile::TempDir;
 
 #[cfg(test)]
 mod ph_sensor_tests {
     use super::*;
     
-    fn setup_test_environment() -> TempDir {
-        let temp_dir = TempDir::new().unwrap();
-        let sensors_dir = temp_dir.path().join("sensors");
-        fs::create_dir_all(&sensors_dir).unwrap();
-        
-        // Create mock sensor files
-        fs::write(sensors_dir.join("ph"), "7.2").unwrap();
-        fs::write(sensors_dir.join("temp"), "25.0").unwrap();
-        
-        temp_dir
-    }
-    
     #[test]
     fn test_ph_range_validation() {
         // Test normal range
@@ -80,22 +67,22 @@ mod ph_sensor_tests {
     
     #[test]
     fn test_calibration_coefficient_calculation() {
-        let mut cal = PhCalibration::default();
-        
         // Setup two-point calibration
-        cal.point_4 = Some(PhCalibrationPoint {
-            ph_value: 4.0,
-            raw_value: 100.0,
-            temperature: 25.0,
-            timestamp: 0,
-        });
-        
-        cal.point_7 = Some(PhCalibrationPoint {
-            ph_value: 7.0,
-            raw_value: 200.0,
-            temperature: 25.0,
-            timestamp: 0,
-        });
+        let mut cal = PhCalibration {
+            point_4: Some(PhCalibrationPoint {
+                ph_value: 4.0,
+                raw_value: 100.0,
+                temperature: 25.0,
+                timestamp: 0,
+            }),
+            point_7: Some(PhCalibrationPoint {
+                ph_value: 7.0,
+                raw_value: 200.0,
+                temperature: 25.0,
+                timestamp: 0,
+            }),
+            ..Default::default()
+        };
         
         cal.calculate_coefficients();
         
@@ -285,13 +272,15 @@ mod ph_sensor_tests {
         let temp_dir = TempDir::new().unwrap();
         let cal_path = temp_dir.path().join("ph_calibration.json");
         
-        let mut calibration = PhCalibration::default();
-        calibration.point_7 = Some(PhCalibrationPoint {
-            ph_value: 7.0,
-            raw_value: 200.0,
-            temperature: 25.0,
-            timestamp: 1000,
-        });
+        let mut calibration = PhCalibration {
+            point_7: Some(PhCalibrationPoint {
+                ph_value: 7.0,
+                raw_value: 200.0,
+                temperature: 25.0,
+                timestamp: 1000,
+            }),
+            ..Default::default()
+        };
         calibration.calculate_coefficients();
         
         // Save calibration
@@ -312,7 +301,7 @@ mod ph_sensor_tests {
         let status = sensor.get_status();
         
         // Status should have default values
-        assert!(status.adjustment_suggestion.len() > 0);
+        assert!(!status.adjustment_suggestion.is_empty());
         assert_eq!(status.alert_level, PhAlertLevel::Normal);
         assert_eq!(status.trend, PhTrend::Stable);
     }
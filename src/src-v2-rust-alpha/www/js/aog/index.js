@@ -209,8 +209,8 @@ function savePumpSettings() {
         photo_cycle_start_hour: parseInt($('#photoCycleStart').val()),
         photo_cycle_end_hour: parseInt($('#photoCycleEnd').val()),
         safety_gpio_pin: $('#safetyPinSwitch').is(':checked') ? parseInt($('#safetyGpioPin').val()) : null,
-        pump_runtime_limit_seconds: parseInt($('#runtimeLimit').val()),
-        pump_cooldown_seconds: parseInt($('#cooldownPeriod').val())
+        pump_runtime_limit_seconds: $('#runtimeLimit').val() ? parseInt($('#runtimeLimit').val()) : null,
+        pump_cooldown_seconds: $('#cooldownPeriod').val() ? parseInt($('#cooldownPeriod').val()) : null
     };
     
     $.ajax({